@@ -1,14 +1,18 @@
 use crate::{
-    Error, ObjectStore as ObjectStoreTrait, Queue as QueueTrait, QueueMessage, Result, Signer,
-    TaskCapabilityClaims, TaskCapabilityIssueRequest,
+    Error, ObjectMeta, ObjectReader, ObjectStore as ObjectStoreTrait, Queue as QueueTrait,
+    QueueMessage, Result, Signer, TaskCapabilityClaims, TaskCapabilityIssueRequest,
 };
 use anyhow::Context;
 use async_trait::async_trait;
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use chrono::{DateTime, Utc};
 use serde_json::Value;
-use std::{path::Path, time::Duration};
+use std::{ops::Range, path::Path, time::Duration};
 
+use crate::multipart::{self, CompletedPart, MultipartBackend, MultipartConfig};
 use crate::{runtime::RuntimeInvoker, udf::UdfInvocationPayload};
 
 #[derive(Debug, Clone)]
@@ -205,18 +209,128 @@ impl QueueTrait for SqsQueue {
 #[derive(Debug, Clone)]
 pub struct S3ObjectStore {
     client: aws_sdk_s3::Client,
+    multipart: MultipartConfig,
 }
 
 impl S3ObjectStore {
     pub fn new(client: aws_sdk_s3::Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            multipart: MultipartConfig::default(),
+        }
     }
 
     pub async fn from_env() -> Result<Self> {
         let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-        Ok(Self {
-            client: aws_sdk_s3::Client::new(&config),
-        })
+        Ok(Self::new(aws_sdk_s3::Client::new(&config)))
+    }
+
+    /// Replace the default multipart settings used by `put_file`.
+    pub fn with_multipart(mut self, multipart: MultipartConfig) -> Result<Self> {
+        multipart.validate()?;
+        self.multipart = multipart;
+        Ok(self)
+    }
+}
+
+#[async_trait]
+impl MultipartBackend for S3ObjectStore {
+    async fn create_multipart(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+    ) -> Result<String> {
+        let resp = self
+            .client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .send()
+            .await
+            .with_context(|| format!("s3 CreateMultipartUpload bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        resp.upload_id()
+            .map(str::to_string)
+            .ok_or_else(|| Error::msg("s3 CreateMultipartUpload response missing upload id"))
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        bytes: Vec<u8>,
+        sha256: [u8; 32],
+    ) -> Result<String> {
+        let resp = self
+            .client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .checksum_sha256(BASE64.encode(sha256))
+            .body(ByteStream::from(bytes))
+            .send()
+            .await
+            .with_context(|| {
+                format!("s3 UploadPart bucket={bucket} key={key} part_number={part_number}")
+            })
+            .map_err(Error::from)?;
+        resp.e_tag()
+            .map(str::to_string)
+            .ok_or_else(|| Error::msg("s3 UploadPart response missing ETag"))
+    }
+
+    async fn complete_multipart(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> Result<()> {
+        let parts = parts
+            .iter()
+            .map(|part| {
+                aws_sdk_s3::types::CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(&part.etag)
+                    .checksum_sha256(BASE64.encode(part.sha256))
+                    .build()
+            })
+            .collect();
+        self.client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .with_context(|| format!("s3 CompleteMultipartUpload bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .with_context(|| format!("s3 AbortMultipartUpload bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(())
     }
 }
 
@@ -249,6 +363,24 @@ impl ObjectStoreTrait for S3ObjectStore {
         local_path: &Path,
         content_type: &str,
     ) -> Result<()> {
+        let size = tokio::fs::metadata(local_path)
+            .await
+            .with_context(|| format!("stat file path={}", local_path.display()))
+            .map_err(Error::from)?
+            .len();
+        if self.multipart.applies_to(size) {
+            return multipart::upload_file(
+                self,
+                &self.multipart,
+                bucket,
+                key,
+                local_path,
+                size,
+                content_type,
+            )
+            .await;
+        }
+
         let body = ByteStream::from_path(local_path)
             .await
             .with_context(|| format!("open file path={}", local_path.display()))
@@ -288,6 +420,132 @@ impl ObjectStoreTrait for S3ObjectStore {
             .to_vec();
         Ok(bytes)
     }
+
+    async fn get(&self, bucket: &str, key: &str) -> Result<ObjectReader> {
+        let resp = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .with_context(|| format!("s3 GetObject bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(Box::pin(resp.body.into_async_read()))
+    }
+
+    async fn get_range(&self, bucket: &str, key: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        if range.is_empty() {
+            return Err(Error::msg(format!("empty byte range {range:?}")));
+        }
+        let resp = match self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", range.start, range.end - 1))
+            .send()
+            .await
+        {
+            Ok(resp) => resp,
+            // S3 answers 416 when the range starts at or past the end of the object.
+            Err(err)
+                if err
+                    .raw_response()
+                    .is_some_and(|r| r.status().as_u16() == 416) =>
+            {
+                return Ok(Vec::new())
+            }
+            Err(err) => {
+                return Err(Error::from(anyhow::Error::from(err).context(format!(
+                    "s3 GetObject (range) bucket={bucket} key={key}"
+                ))))
+            }
+        };
+
+        let bytes = resp
+            .body
+            .collect()
+            .await
+            .context("s3 GetObject body collect")
+            .map_err(Error::from)?
+            .into_bytes()
+            .to_vec();
+        Ok(bytes)
+    }
+
+    async fn head(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        let resp = match self
+            .client
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(resp) => resp,
+            Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                return Ok(None)
+            }
+            Err(err) => {
+                return Err(Error::from(
+                    anyhow::Error::from(err)
+                        .context(format!("s3 HeadObject bucket={bucket} key={key}")),
+                ))
+            }
+        };
+        Ok(Some(ObjectMeta {
+            key: key.to_string(),
+            size: resp.content_length().unwrap_or(0).max(0) as u64,
+            last_modified: resp.last_modified().and_then(to_chrono),
+            etag: resp.e_tag().map(|etag| etag.trim_matches('"').to_string()),
+        }))
+    }
+
+    async fn list_prefix(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMeta>> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+        let mut objects = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page
+                .with_context(|| format!("s3 ListObjectsV2 bucket={bucket} prefix={prefix}"))
+                .map_err(Error::from)?;
+            for object in page.contents() {
+                let Some(key) = object.key() else { continue };
+                objects.push(ObjectMeta {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or(0).max(0) as u64,
+                    last_modified: object.last_modified().and_then(to_chrono),
+                    etag: object
+                        .e_tag()
+                        .map(|etag| etag.trim_matches('"').to_string()),
+                });
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn delete(&self, bucket: &str, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .with_context(|| format!("s3 DeleteObject bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(())
+    }
+}
+
+fn to_chrono(t: &aws_sdk_s3::primitives::DateTime) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(t.secs(), t.subsec_nanos())
 }
 
 #[derive(Debug, Clone, Default)]
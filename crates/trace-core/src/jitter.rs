@@ -0,0 +1,13 @@
+//! Jitter for retry backoff and heartbeat scheduling.
+
+use uuid::Uuid;
+
+/// A uniformly distributed value in `[0, 1)`.
+///
+/// Random v4 UUIDs are a good-enough entropy source for spreading retries and heartbeats, and
+/// avoid a dedicated RNG dependency. Not suitable for anything security-sensitive.
+pub fn unit() -> f64 {
+    // 53 random bits fill an f64 mantissa exactly.
+    let bits = Uuid::new_v4().as_u128() as u64 >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
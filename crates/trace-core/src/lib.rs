@@ -5,14 +5,15 @@
 //!
 //! # API notes
 //! `trace-core` is an internal crate (`publish = false`). Its public API currently uses a few
-//! third-party types (`uuid::Uuid`, `chrono::DateTime<Utc>`, `serde_json::Value`) as part of the
-//! Trace Lite contract.
+//! third-party types (`uuid::Uuid`, `chrono::DateTime<Utc>`, `serde_json::Value`,
+//! `jsonwebtoken::jwk::JwkSet`) as part of the Trace Lite contract.
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{fmt, path::Path, time::Duration};
+use std::{fmt, ops::Range, path::Path, pin::Pin, time::Duration};
+use tokio::io::AsyncRead;
 use uuid::Uuid;
 
 pub mod lite;
@@ -20,8 +21,13 @@ pub mod lite;
 #[cfg(feature = "aws")]
 pub mod aws;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 pub mod fixtures;
+pub mod jitter;
 pub mod manifest;
+pub mod multipart;
 pub mod query;
 pub mod runtime;
 pub mod udf;
@@ -127,6 +133,44 @@ pub trait ObjectStore: Send + Sync {
     ) -> Result<()>;
 
     async fn get_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>>;
+
+    /// Stream an object's body instead of buffering it.
+    async fn get(&self, bucket: &str, key: &str) -> Result<ObjectReader>;
+
+    /// Bytes `range.start..range.end` of an object; a range running past the end is truncated,
+    /// and one starting at or past the end is empty.
+    async fn get_range(&self, bucket: &str, key: &str, range: Range<u64>) -> Result<Vec<u8>>;
+
+    /// Object metadata, or `None` if the object does not exist.
+    async fn head(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>>;
+
+    /// Every object whose key starts with `prefix`, sorted by key.
+    async fn list_prefix(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMeta>>;
+
+    /// Delete an object. Deleting a missing object succeeds.
+    async fn delete(&self, bucket: &str, key: &str) -> Result<()>;
+
+    /// URI naming `bucket/key` (or a key prefix) in this store, e.g. for batch pointers.
+    fn object_uri(&self, bucket: &str, key: &str) -> String {
+        format!("s3://{bucket}/{key}")
+    }
+
+    /// Inverse of [`ObjectStore::object_uri`]: the `(bucket, key)` a URI names in this store.
+    fn locate_uri(&self, uri: &str) -> Result<(String, String)> {
+        lite::s3::parse_s3_uri(uri)
+    }
+}
+
+/// Streaming object body returned by [`ObjectStore::get`].
+pub type ObjectReader = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// Entity tag without surrounding quotes.
+    pub etag: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -146,6 +190,11 @@ pub trait Signer: Send + Sync {
 
     /// Verify and decode a task capability token.
     fn verify_task_capability(&self, token: &str) -> Result<TaskCapabilityClaims>;
+
+    /// Public keys that verify issued tokens. Empty for symmetric signers.
+    fn jwks(&self) -> jsonwebtoken::jwk::JwkSet {
+        jsonwebtoken::jwk::JwkSet { keys: Vec::new() }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -181,7 +230,7 @@ fn default_parquet_glob() -> String {
     "*.parquet".to_string()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "scheme", rename_all = "snake_case")]
 pub enum DatasetStorageRef {
     S3 {
@@ -199,6 +248,56 @@ pub enum DatasetStorageRef {
     },
 }
 
+impl DatasetStorageRef {
+    /// Parse a storage URI (`s3://bucket/prefix/` or `file:///abs/prefix/`) into a ref.
+    pub fn from_uri(uri: &str, glob: String) -> Result<Self> {
+        if let Some(rest) = uri.strip_prefix("s3://") {
+            let (bucket, prefix) = rest
+                .split_once('/')
+                .ok_or_else(|| Error::msg("s3 storage prefix missing key prefix"))?;
+            if bucket.is_empty() {
+                return Err(Error::msg("s3 storage prefix missing bucket"));
+            }
+            let prefix = prefix.trim_start_matches('/').trim_end_matches('/');
+            if prefix.is_empty() {
+                return Err(Error::msg("s3 storage prefix must not be empty"));
+            }
+            return Ok(Self::S3 {
+                bucket: bucket.to_string(),
+                prefix: format!("{prefix}/"),
+                glob,
+            });
+        }
+
+        if let Some(prefix) = uri.strip_prefix("file://") {
+            if !prefix.starts_with('/') {
+                return Err(Error::msg("file storage prefix must be absolute"));
+            }
+            let prefix = prefix.trim_end_matches('/');
+            return Ok(Self::File {
+                prefix: format!("{prefix}/"),
+                glob,
+            });
+        }
+
+        Err(Error::msg("unsupported storage prefix scheme"))
+    }
+
+    /// Storage URI of the prefix, as recorded in the dataset registry.
+    pub fn uri(&self) -> String {
+        match self {
+            Self::S3 { bucket, prefix, .. } => format!("s3://{bucket}/{prefix}"),
+            Self::File { prefix, .. } => format!("file://{prefix}"),
+        }
+    }
+
+    pub fn glob(&self) -> &str {
+        match self {
+            Self::S3 { glob, .. } | Self::File { glob, .. } => glob,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatasetPublication {
     pub dataset_uuid: Uuid,
@@ -223,3 +322,34 @@ impl S3Grants {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_uris_parse_into_refs() {
+        let s3 = DatasetStorageRef::from_uri("s3://bucket/a/b", "*.parquet".to_string()).unwrap();
+        assert_eq!(
+            s3,
+            DatasetStorageRef::S3 {
+                bucket: "bucket".to_string(),
+                prefix: "a/b/".to_string(),
+                glob: "*.parquet".to_string(),
+            }
+        );
+
+        let file = DatasetStorageRef::from_uri("file:///tmp/x/", "*.parquet".to_string()).unwrap();
+        assert_eq!(
+            file,
+            DatasetStorageRef::File {
+                prefix: "/tmp/x/".to_string(),
+                glob: "*.parquet".to_string(),
+            }
+        );
+
+        assert!(DatasetStorageRef::from_uri("s3://bucket/", "*".to_string()).is_err());
+        assert!(DatasetStorageRef::from_uri("file://relative", "*".to_string()).is_err());
+        assert!(DatasetStorageRef::from_uri("gs://bucket/x", "*".to_string()).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Directory-backed object store for single-node deployments.
+//!
+//! `bucket/key` maps to `{root}/{bucket}/{key}`. Keys are validated segment by segment (no `..`,
+//! absolute paths or empty segments) and resolved paths must stay under `root` after following
+//! symlinks. Writes go to a temporary file in the destination directory and are renamed into
+//! place, so readers (including DuckDB globbing the directory) never see partial objects.
+
+use crate::{Error, ObjectMeta, ObjectReader, ObjectStore as ObjectStoreTrait, Result};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Name prefix of in-flight writes; never a valid key segment and never listed.
+const TEMP_PREFIX: &str = ".trace-tmp-";
+
+#[derive(Clone, Debug)]
+pub struct FsObjectStore {
+    root: PathBuf,
+}
+
+impl FsObjectStore {
+    /// A store rooted at `root`, which is created if missing.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        std::fs::create_dir_all(root)
+            .with_context(|| format!("create object store root {}", root.display()))
+            .map_err(Error::from)?;
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("resolve object store root {}", root.display()))
+            .map_err(Error::from)?;
+        Ok(Self { root })
+    }
+
+    /// Canonical root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of `bucket/key`, after validating both. Does not touch the filesystem.
+    pub fn object_path(&self, bucket: &str, key: &str) -> Result<PathBuf> {
+        validate_bucket(bucket)?;
+        validate_key(key)?;
+        Ok(self.root.join(bucket).join(key))
+    }
+
+    /// Path of `bucket/key` whose existing ancestors resolve (through symlinks) under the root.
+    async fn resolved_path(&self, bucket: &str, key: &str) -> Result<PathBuf> {
+        let path = self.object_path(bucket, key)?;
+        let mut existing = path.as_path();
+        let resolved = loop {
+            match tokio::fs::canonicalize(existing).await {
+                Ok(resolved) => break resolved,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    existing = existing.parent().unwrap_or(&self.root);
+                }
+                Err(err) => {
+                    return Err(Error::from(
+                        anyhow::Error::new(err)
+                            .context(format!("resolve object path {}", existing.display())),
+                    ))
+                }
+            }
+        };
+        if !resolved.starts_with(&self.root) {
+            return Err(Error::msg(format!(
+                "object path escapes store root bucket={bucket} key={key}"
+            )));
+        }
+        Ok(path)
+    }
+
+    /// Write `source` to a temp file beside `bucket/key`, then rename it into place.
+    async fn write_atomic(&self, bucket: &str, key: &str, source: WriteSource<'_>) -> Result<()> {
+        let path = self.resolved_path(bucket, key).await?;
+        let dir = path.parent().expect("object path has a parent");
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("create directory {}", dir.display()))
+            .map_err(Error::from)?;
+        // Re-check now that the directory exists: a symlinked ancestor must not lead outside.
+        let path = self.resolved_path(bucket, key).await?;
+        let temp = dir.join(format!("{TEMP_PREFIX}{}", Uuid::new_v4()));
+
+        let written: std::io::Result<()> = async {
+            let mut file = tokio::fs::File::create(&temp).await?;
+            match source {
+                WriteSource::Bytes(bytes) => file.write_all(&bytes).await?,
+                WriteSource::File(local_path) => {
+                    let mut src = tokio::fs::File::open(local_path).await?;
+                    tokio::io::copy(&mut src, &mut file).await?;
+                }
+            }
+            file.sync_all().await?;
+            tokio::fs::rename(&temp, &path).await
+        }
+        .await;
+        if let Err(err) = written {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(Error::from(
+                anyhow::Error::new(err).context(format!("write object {}", path.display())),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn open(&self, bucket: &str, key: &str) -> Result<tokio::fs::File> {
+        let path = self.resolved_path(bucket, key).await?;
+        tokio::fs::File::open(&path)
+            .await
+            .with_context(|| format!("open object {}", path.display()))
+            .map_err(Error::from)
+    }
+}
+
+enum WriteSource<'a> {
+    Bytes(Vec<u8>),
+    File(&'a Path),
+}
+
+#[async_trait]
+impl ObjectStoreTrait for FsObjectStore {
+    async fn put_bytes(
+        &self,
+        bucket: &str,
+        key: &str,
+        bytes: Vec<u8>,
+        _content_type: &str,
+    ) -> Result<()> {
+        self.write_atomic(bucket, key, WriteSource::Bytes(bytes))
+            .await
+    }
+
+    async fn put_file(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &Path,
+        _content_type: &str,
+    ) -> Result<()> {
+        self.write_atomic(bucket, key, WriteSource::File(local_path))
+            .await
+    }
+
+    async fn get_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.open(bucket, key)
+            .await?
+            .read_to_end(&mut bytes)
+            .await
+            .with_context(|| format!("read object bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(bytes)
+    }
+
+    async fn get(&self, bucket: &str, key: &str) -> Result<ObjectReader> {
+        Ok(Box::pin(self.open(bucket, key).await?))
+    }
+
+    async fn get_range(&self, bucket: &str, key: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        if range.is_empty() {
+            return Err(Error::msg(format!("empty byte range {range:?}")));
+        }
+        let mut file = self.open(bucket, key).await?;
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .with_context(|| format!("seek object bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        let mut bytes = Vec::new();
+        file.take(range.end - range.start)
+            .read_to_end(&mut bytes)
+            .await
+            .with_context(|| format!("read object range bucket={bucket} key={key}"))
+            .map_err(Error::from)?;
+        Ok(bytes)
+    }
+
+    async fn head(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        let path = self.resolved_path(bucket, key).await?;
+        let meta = match tokio::fs::metadata(&path).await {
+            Ok(meta) if meta.is_file() => meta,
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(Error::from(
+                    anyhow::Error::new(err).context(format!("stat object {}", path.display())),
+                ))
+            }
+        };
+        Ok(Some(ObjectMeta {
+            key: key.to_string(),
+            size: meta.len(),
+            last_modified: meta.modified().ok().map(DateTime::<Utc>::from),
+            etag: None,
+        }))
+    }
+
+    async fn list_prefix(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMeta>> {
+        validate_bucket(bucket)?;
+        let bucket_dir = self.root.join(bucket);
+        let prefix = prefix.to_string();
+        let mut objects = tokio::task::spawn_blocking(move || list_dir(&bucket_dir, &prefix))
+            .await
+            .context("join list task")
+            .map_err(Error::from)??;
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn delete(&self, bucket: &str, key: &str) -> Result<()> {
+        let path = self.resolved_path(bucket, key).await?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::from(
+                anyhow::Error::new(err).context(format!("delete object {}", path.display())),
+            )),
+        }
+    }
+
+    fn object_uri(&self, bucket: &str, key: &str) -> String {
+        format!("file://{}/{bucket}/{key}", self.root.display())
+    }
+
+    fn locate_uri(&self, uri: &str) -> Result<(String, String)> {
+        let path = uri
+            .strip_prefix("file://")
+            .ok_or_else(|| Error::msg("filesystem object store requires a file:// uri"))?;
+        let root = format!("{}/", self.root.display());
+        let (bucket, key) = path
+            .strip_prefix(&root)
+            .and_then(|rest| rest.split_once('/'))
+            .ok_or_else(|| Error::msg(format!("uri is outside the object store root: {uri}")))?;
+        validate_bucket(bucket)?;
+        // Prefix URIs end with `/`; the segments before it must still be valid.
+        let trimmed = key.trim_end_matches('/');
+        if !trimmed.is_empty() {
+            validate_key(trimmed)?;
+        }
+        Ok((bucket.to_string(), key.to_string()))
+    }
+}
+
+/// Objects under `bucket_dir` whose key starts with `prefix`. Symlinks are not followed.
+fn list_dir(bucket_dir: &Path, prefix: &str) -> Result<Vec<ObjectMeta>> {
+    let mut objects = Vec::new();
+    let mut pending = vec![(bucket_dir.to_path_buf(), String::new())];
+    while let Some((dir, dir_key)) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(Error::from(
+                    anyhow::Error::new(err).context(format!("list directory {}", dir.display())),
+                ))
+            }
+        };
+        for entry in entries {
+            let entry = entry
+                .with_context(|| format!("list directory {}", dir.display()))
+                .map_err(Error::from)?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with(TEMP_PREFIX) {
+                continue;
+            }
+            let key = format!("{dir_key}{name}");
+            let file_type = entry
+                .file_type()
+                .with_context(|| format!("stat {}", entry.path().display()))
+                .map_err(Error::from)?;
+            if file_type.is_dir() {
+                let dir_prefix = format!("{key}/");
+                // Only descend where keys can still match the prefix.
+                if dir_prefix.starts_with(prefix) || prefix.starts_with(&dir_prefix) {
+                    pending.push((entry.path(), dir_prefix));
+                }
+            } else if file_type.is_file() && key.starts_with(prefix) {
+                let meta = entry
+                    .metadata()
+                    .with_context(|| format!("stat {}", entry.path().display()))
+                    .map_err(Error::from)?;
+                objects.push(ObjectMeta {
+                    key,
+                    size: meta.len(),
+                    last_modified: meta.modified().ok().map(DateTime::<Utc>::from),
+                    etag: None,
+                });
+            }
+        }
+    }
+    Ok(objects)
+}
+
+fn validate_bucket(bucket: &str) -> Result<()> {
+    if bucket.is_empty()
+        || bucket == "."
+        || bucket == ".."
+        || bucket.contains(['/', '\\', '\0'])
+        || bucket.starts_with(TEMP_PREFIX)
+    {
+        return Err(Error::msg(format!("invalid bucket name {bucket:?}")));
+    }
+    Ok(())
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && !key.contains(['\\', '\0'])
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && !segment.starts_with(TEMP_PREFIX)
+        });
+    if !valid {
+        return Err(Error::msg(format!("invalid object key {key:?}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> FsObjectStore {
+        let root = std::env::temp_dir().join(format!("trace-fs-store-{}", Uuid::new_v4()));
+        FsObjectStore::new(root).unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trips_objects() {
+        let store = temp_store();
+        store
+            .put_bytes("b", "ds/v1/a.parquet", b"0123456789".to_vec(), "x")
+            .await
+            .unwrap();
+        store
+            .put_bytes("b", "ds/v1/_manifest.json", b"{}".to_vec(), "x")
+            .await
+            .unwrap();
+        store
+            .put_bytes("b", "other/c", b"c".to_vec(), "x")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_bytes("b", "ds/v1/a.parquet").await.unwrap(),
+            b"0123456789"
+        );
+        assert_eq!(
+            store.get_range("b", "ds/v1/a.parquet", 2..5).await.unwrap(),
+            b"234"
+        );
+        assert_eq!(
+            store
+                .get_range("b", "ds/v1/a.parquet", 8..50)
+                .await
+                .unwrap(),
+            b"89"
+        );
+        let mut streamed = Vec::new();
+        store
+            .get("b", "ds/v1/a.parquet")
+            .await
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"0123456789");
+
+        let head = store.head("b", "ds/v1/a.parquet").await.unwrap().unwrap();
+        assert_eq!(head.size, 10);
+        assert!(store.head("b", "ds/v1/missing").await.unwrap().is_none());
+
+        let keys =
+            |objects: Vec<ObjectMeta>| objects.into_iter().map(|o| o.key).collect::<Vec<_>>();
+        assert_eq!(
+            keys(store.list_prefix("b", "ds/").await.unwrap()),
+            ["ds/v1/_manifest.json", "ds/v1/a.parquet"]
+        );
+        assert_eq!(keys(store.list_prefix("b", "").await.unwrap()).len(), 3);
+        assert!(store.list_prefix("missing", "").await.unwrap().is_empty());
+
+        store.delete("b", "ds/v1/a.parquet").await.unwrap();
+        store.delete("b", "ds/v1/a.parquet").await.unwrap();
+        assert!(store.get_bytes("b", "ds/v1/a.parquet").await.is_err());
+
+        let uri = store.object_uri("b", "ds/v1/");
+        assert_eq!(
+            store.locate_uri(&uri).unwrap(),
+            ("b".to_string(), "ds/v1/".to_string())
+        );
+        assert!(store.locate_uri("s3://b/ds/v1/").is_err());
+        assert!(store.locate_uri("file:///elsewhere/b/k").is_err());
+
+        std::fs::remove_dir_all(store.root()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fs_object_store_conforms() {
+        let store = temp_store();
+        crate::testing::conformance::object_store(&store, "bucket")
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(store.root()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_unsafe_paths() {
+        let store = temp_store();
+        for key in [
+            "../x",
+            "a/../../x",
+            "/etc/passwd",
+            "a//b",
+            "",
+            "a/.trace-tmp-1",
+        ] {
+            assert!(
+                store.put_bytes("b", key, Vec::new(), "x").await.is_err(),
+                "key {key:?}"
+            );
+        }
+        for bucket in ["", "..", "a/b"] {
+            assert!(
+                store.get_bytes(bucket, "k").await.is_err(),
+                "bucket {bucket:?}"
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            let outside = std::env::temp_dir().join(format!("trace-fs-outside-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&outside).unwrap();
+            std::fs::create_dir_all(store.root().join("b")).unwrap();
+            std::os::unix::fs::symlink(&outside, store.root().join("b/link")).unwrap();
+            let err = store
+                .put_bytes("b", "link/x", b"x".to_vec(), "x")
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("escapes"), "{err}");
+            assert!(!outside.join("x").exists());
+            std::fs::remove_dir_all(&outside).unwrap();
+        }
+
+        std::fs::remove_dir_all(store.root()).unwrap();
+    }
+}
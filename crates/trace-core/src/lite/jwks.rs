@@ -0,0 +1,492 @@
+//! JWT verification keys indexed by `kid`.
+//!
+//! A [`KeySet`] holds locally configured keys and, optionally, the keys in a JWKS document (a URL
+//! or a local file). Looking up an unknown `kid` re-fetches the JWKS (at most once per refresh interval), so an
+//! issuer rotates by publishing its next key before signing with it and dropping the old key once
+//! outstanding tokens have expired.
+
+use crate::{Error, Result};
+use anyhow::{anyhow, ensure, Context};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+    PublicKeyUse,
+};
+use jsonwebtoken::{decode_header, Algorithm, DecodingKey, Validation};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Minimum time between JWKS fetches triggered by unknown `kid`s.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// DER `SubjectPublicKeyInfo` header for an Ed25519 key; the 32-byte key follows.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+/// DER `SubjectPublicKeyInfo` header for a P-256 key; the 65-byte uncompressed point follows.
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// A key that verifies tokens signed with exactly one algorithm.
+#[derive(Clone)]
+pub struct VerificationKey {
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+}
+
+impl std::fmt::Debug for VerificationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerificationKey")
+            .field("algorithm", &self.algorithm)
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+impl VerificationKey {
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            key: DecodingKey::from_secret(secret),
+        }
+    }
+
+    /// Build a key from a public JWK. Symmetric (`oct`) and encryption keys are rejected.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self> {
+        let algorithm = jwk_algorithm(jwk)?;
+        let key = DecodingKey::from_jwk(jwk)
+            .context("decode jwk")
+            .map_err(Error::from)?;
+        Ok(Self { algorithm, key })
+    }
+
+    /// Validation pinned to this key's algorithm; callers add issuer and audience checks.
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.algorithm)
+    }
+}
+
+/// The algorithm a JWK verifies, from its key type and optional `alg`.
+fn jwk_algorithm(jwk: &Jwk) -> Result<Algorithm> {
+    if let Some(PublicKeyUse::Encryption) = jwk.common.public_key_use {
+        return Err(Error::msg("jwk is an encryption key"));
+    }
+    let by_key_type = match &jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(p) if p.curve == EllipticCurve::Ed25519 => {
+            Algorithm::EdDSA
+        }
+        AlgorithmParameters::EllipticCurve(p) if p.curve == EllipticCurve::P256 => Algorithm::ES256,
+        AlgorithmParameters::EllipticCurve(p) if p.curve == EllipticCurve::P384 => Algorithm::ES384,
+        AlgorithmParameters::RSA(_) => Algorithm::RS256,
+        AlgorithmParameters::OctetKey(_) => {
+            return Err(Error::msg("symmetric jwk keys are not accepted"))
+        }
+        _ => return Err(Error::msg("unsupported jwk curve")),
+    };
+
+    let Some(declared) = jwk.common.key_algorithm else {
+        return Ok(by_key_type);
+    };
+    let declared = declared
+        .to_string()
+        .parse::<Algorithm>()
+        .context("unsupported jwk alg")
+        .map_err(Error::from)?;
+    let compatible = match by_key_type {
+        Algorithm::RS256 => matches!(
+            declared,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+        ),
+        other => declared == other,
+    };
+    if !compatible {
+        return Err(Error::msg(format!(
+            "jwk alg {declared:?} does not match its key type"
+        )));
+    }
+    Ok(declared)
+}
+
+/// The `kid` from a token's header.
+pub fn token_kid(token: &str) -> Result<String> {
+    decode_header(token)
+        .context("decode jwt header")
+        .map_err(Error::from)?
+        .kid
+        .ok_or_else(|| Error::msg("missing jwt kid"))
+}
+
+/// Verification keys by `kid`: configured keys plus those loaded from an optional JWKS URL or file.
+#[derive(Default)]
+pub struct KeySet {
+    keys: HashMap<String, VerificationKey>,
+    published: Option<PublishedJwks>,
+}
+
+/// A JWKS document whose keys are reloaded on refresh.
+struct PublishedJwks {
+    source: JwksSource,
+    refresh_interval: Duration,
+    keys: RwLock<HashMap<String, VerificationKey>>,
+    /// Held across a fetch so concurrent misses wait for it instead of fetching again.
+    last_fetch: tokio::sync::Mutex<Option<Instant>>,
+}
+
+enum JwksSource {
+    Url { url: String, http: reqwest::Client },
+    File(PathBuf),
+}
+
+impl std::fmt::Debug for KeySet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut kids: Vec<&str> = self.keys.keys().map(String::as_str).collect();
+        kids.sort_unstable();
+        f.debug_struct("KeySet")
+            .field("kids", &kids)
+            .field(
+                "jwks",
+                &self.published.as_ref().map(|p| p.source.to_string()),
+            )
+            .finish()
+    }
+}
+
+impl std::fmt::Display for JwksSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Url { url, .. } => f.write_str(url),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl KeySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, kid: impl Into<String>, key: VerificationKey) -> Result<()> {
+        let kid = kid.into();
+        if self.keys.contains_key(&kid) {
+            return Err(Error::msg(format!("duplicate jwt kid {kid}")));
+        }
+        self.keys.insert(kid, key);
+        Ok(())
+    }
+
+    /// Add a public JWK under its `kid`.
+    pub fn insert_jwk(&mut self, jwk: &Jwk) -> Result<()> {
+        let kid = jwk
+            .common
+            .key_id
+            .as_deref()
+            .ok_or_else(|| Error::msg("jwk is missing kid"))?;
+        self.insert(kid, VerificationKey::from_jwk(jwk)?)
+    }
+
+    /// Also trust the keys published at `url`, fetched on first use of an unknown `kid`.
+    pub fn with_jwks_url(self, url: impl Into<String>, refresh_interval: Duration) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        self.with_source(
+            JwksSource::Url {
+                url: url.into(),
+                http,
+            },
+            refresh_interval,
+        )
+    }
+
+    /// Also trust the keys in a local JWKS file, re-read like a fetched JWKS.
+    pub fn with_jwks_file(self, path: impl Into<PathBuf>, refresh_interval: Duration) -> Self {
+        self.with_source(JwksSource::File(path.into()), refresh_interval)
+    }
+
+    fn with_source(mut self, source: JwksSource, refresh_interval: Duration) -> Self {
+        self.published = Some(PublishedJwks {
+            source,
+            refresh_interval,
+            keys: RwLock::new(HashMap::new()),
+            last_fetch: tokio::sync::Mutex::new(None),
+        });
+        self
+    }
+
+    /// True when no key could ever be found.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.published.is_none()
+    }
+
+    /// Whether a JWKS URL or file is configured (and so worth refreshing periodically).
+    pub fn has_jwks(&self) -> bool {
+        self.published.is_some()
+    }
+
+    /// A configured or already-fetched key. Never fetches.
+    pub fn get(&self, kid: &str) -> Option<VerificationKey> {
+        if let Some(key) = self.keys.get(kid) {
+            return Some(key.clone());
+        }
+        self.published
+            .as_ref()?
+            .keys
+            .read()
+            .expect("jwks poisoned")
+            .get(kid)
+            .cloned()
+    }
+
+    /// Like [`KeySet::get`], but re-fetches the JWKS on a miss unless it was fetched within the
+    /// refresh interval.
+    pub async fn resolve(&self, kid: &str) -> Result<VerificationKey> {
+        if let Some(key) = self.get(kid) {
+            return Ok(key);
+        }
+        let unknown = || Error::msg(format!("unknown jwt kid {kid}"));
+        let Some(published) = &self.published else {
+            return Err(unknown());
+        };
+
+        let mut last_fetch = published.last_fetch.lock().await;
+        // Another caller may have fetched while this one waited for the lock.
+        if let Some(key) = self.get(kid) {
+            return Ok(key);
+        }
+        if last_fetch.is_some_and(|at| at.elapsed() < published.refresh_interval) {
+            return Err(unknown());
+        }
+        *last_fetch = Some(Instant::now());
+        published.fetch().await?;
+        drop(last_fetch);
+
+        self.get(kid).ok_or_else(unknown)
+    }
+
+    /// Fetch the JWKS now, if one is configured. On error the previous keys are kept.
+    pub async fn refresh(&self) -> Result<()> {
+        let Some(published) = &self.published else {
+            return Ok(());
+        };
+        let mut last_fetch = published.last_fetch.lock().await;
+        *last_fetch = Some(Instant::now());
+        published.fetch().await
+    }
+}
+
+impl PublishedJwks {
+    /// Replace the fetched keys with the current JWKS. Keys without a `kid` or of an unsupported
+    /// type are skipped rather than failing the whole set.
+    async fn fetch(&self) -> Result<()> {
+        let jwks: JwkSet = match &self.source {
+            JwksSource::Url { url, http } => http
+                .get(url)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+                .with_context(|| format!("fetch jwks url={url}"))
+                .map_err(Error::from)?
+                .json()
+                .await
+                .with_context(|| format!("decode jwks url={url}"))
+                .map_err(Error::from)?,
+            JwksSource::File(path) => {
+                let bytes = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("read jwks file {}", path.display()))
+                    .map_err(Error::from)?;
+                serde_json::from_slice(&bytes)
+                    .with_context(|| format!("decode jwks file {}", path.display()))
+                    .map_err(Error::from)?
+            }
+        };
+
+        let keys = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let kid = jwk.common.key_id.clone()?;
+                Some((kid, VerificationKey::from_jwk(jwk).ok()?))
+            })
+            .collect();
+        *self.keys.write().expect("jwks poisoned") = keys;
+        Ok(())
+    }
+}
+
+/// Decode the body of a PEM block with the given label, e.g. `PUBLIC KEY`.
+pub(crate) fn pem_to_der(pem: &str, label: &str) -> Result<Vec<u8>> {
+    pem_to_der_inner(pem, label).map_err(Error::from)
+}
+
+fn pem_to_der_inner(pem: &str, label: &str) -> anyhow::Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let start = pem
+        .find(&begin)
+        .ok_or_else(|| anyhow!("expected a PEM `{label}` block"))?
+        + begin.len();
+    let len = pem[start..]
+        .find(&end)
+        .ok_or_else(|| anyhow!("unterminated PEM `{label}` block"))?;
+    let body: String = pem[start..start + len]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    STANDARD
+        .decode(body)
+        .with_context(|| format!("decode PEM `{label}` block"))
+}
+
+/// Public JWK for an Ed25519 key.
+pub(crate) fn ed25519_jwk(kid: &str, public_key: &[u8]) -> Jwk {
+    Jwk {
+        common: signature_key(kid, KeyAlgorithm::EdDSA),
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(public_key),
+        }),
+    }
+}
+
+/// Public JWK for a P-256 key given as an uncompressed point (`0x04 || x || y`).
+pub(crate) fn p256_jwk(kid: &str, point: &[u8]) -> Result<Jwk> {
+    if point.len() != 65 || point[0] != 0x04 {
+        return Err(Error::msg("expected an uncompressed P-256 point"));
+    }
+    Ok(Jwk {
+        common: signature_key(kid, KeyAlgorithm::ES256),
+        algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+            key_type: EllipticCurveKeyType::EC,
+            curve: EllipticCurve::P256,
+            x: URL_SAFE_NO_PAD.encode(&point[1..33]),
+            y: URL_SAFE_NO_PAD.encode(&point[33..]),
+        }),
+    })
+}
+
+fn signature_key(kid: &str, alg: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(alg),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Public JWK for an Ed25519 or P-256 `PUBLIC KEY` PEM (as written by `openssl pkey -pubout`).
+pub fn public_key_pem_to_jwk(kid: &str, pem: &str) -> Result<Jwk> {
+    let der = pem_to_der(pem, "PUBLIC KEY")?;
+    if let Some(key) = der.strip_prefix(&ED25519_SPKI_PREFIX[..]) {
+        if key.len() == 32 {
+            return Ok(ed25519_jwk(kid, key));
+        }
+    }
+    if let Some(point) = der.strip_prefix(&P256_SPKI_PREFIX[..]) {
+        return p256_jwk(kid, point);
+    }
+    Err(Error::msg(
+        "unsupported public key: expected an Ed25519 or P-256 SubjectPublicKeyInfo",
+    ))
+}
+
+/// Read public keys from a `kid=path.pem,kid=path.pem` list.
+pub fn read_public_keys(spec: &str) -> Result<Vec<Jwk>> {
+    read_public_keys_inner(spec).map_err(Error::from)
+}
+
+fn read_public_keys_inner(spec: &str) -> anyhow::Result<Vec<Jwk>> {
+    let mut jwks = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (kid, path) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `kid=path`, got `{entry}`"))?;
+        let (kid, path) = (kid.trim(), path.trim());
+        ensure!(!kid.is_empty(), "empty kid in `{entry}`");
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("read public key kid={kid} path={path}"))?;
+        jwks.push(
+            public_key_pem_to_jwk(kid, &pem)
+                .map_err(anyhow::Error::from)
+                .with_context(|| format!("parse public key kid={kid} path={path}"))?,
+        );
+    }
+    Ok(jwks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::jwk::{OctetKeyParameters, OctetKeyType};
+
+    #[test]
+    fn rejects_symmetric_and_mismatched_jwks() {
+        let secret = Jwk {
+            common: signature_key("k", KeyAlgorithm::HS256),
+            algorithm: AlgorithmParameters::OctetKey(OctetKeyParameters {
+                key_type: OctetKeyType::Octet,
+                value: URL_SAFE_NO_PAD.encode(b"secret"),
+            }),
+        };
+        let err = VerificationKey::from_jwk(&secret).unwrap_err();
+        assert!(err.to_string().contains("symmetric"), "{err}");
+
+        let mut mislabeled = ed25519_jwk("k", &[7; 32]);
+        mislabeled.common.key_algorithm = Some(KeyAlgorithm::ES256);
+        let err = VerificationKey::from_jwk(&mislabeled).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err}");
+
+        let ok = VerificationKey::from_jwk(&ed25519_jwk("k", &[7; 32])).unwrap();
+        assert_eq!(ok.algorithm, Algorithm::EdDSA);
+    }
+
+    #[tokio::test]
+    async fn jwks_file_is_reloaded_on_unknown_kid_at_most_once_per_interval() {
+        let path = std::env::temp_dir().join(format!("trace-jwks-{}.json", uuid::Uuid::new_v4()));
+        let write = |kid: &str| {
+            let jwks = JwkSet {
+                keys: vec![ed25519_jwk(kid, &[1; 32])],
+            };
+            std::fs::write(&path, serde_json::to_vec(&jwks).unwrap()).unwrap();
+        };
+        write("a");
+        let keys = KeySet::new().with_jwks_file(&path, Duration::from_secs(3600));
+        assert!(keys.get("a").is_none(), "loaded before first use");
+
+        keys.resolve("a").await.unwrap();
+        write("b");
+        let err = keys.resolve("b").await.unwrap_err();
+        assert!(err.to_string().contains("unknown jwt kid b"), "{err}");
+
+        keys.refresh().await.unwrap();
+        assert!(keys.get("b").is_some());
+        assert!(keys.get("a").is_none(), "rotated-out key still trusted");
+
+        std::fs::remove_file(&path).unwrap();
+        keys.refresh().await.unwrap_err();
+        assert!(keys.get("b").is_some(), "failed refresh dropped keys");
+    }
+
+    #[test]
+    fn public_key_specs_need_kid_and_path() {
+        let err = read_public_keys("just-a-path.pem").unwrap_err();
+        assert!(err.to_string().contains("kid=path"), "{err}");
+        assert!(read_public_keys(" , ").unwrap().is_empty());
+        assert!(read_public_keys("k=/nonexistent/key.pem").is_err());
+    }
+}
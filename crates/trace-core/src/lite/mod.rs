@@ -1,8 +1,11 @@
 //! Lite-mode implementations of `trace-core` interfaces.
 //!
-//! This module provides a minimal Postgres-backed queue, an S3-compatible object store client,
-//! and an HS256 task capability signer for local development and harness flows.
+//! This module provides a minimal Postgres-backed queue, a SigV4-signing S3-compatible object
+//! store client, a directory-backed object store, and a task capability signer (HS256, Ed25519 or
+//! ES256) with a `kid`-indexed verification key cache for local development and harness flows.
 
+pub mod fs;
+pub mod jwks;
 pub mod jwt;
 pub mod pgqueue;
 pub mod s3;
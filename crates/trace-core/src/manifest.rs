@@ -25,3 +25,162 @@ pub struct DatasetManifestV1 {
 impl DatasetManifestV1 {
     pub const VERSION: u32 = 1;
 }
+
+/// Dataset manifest with per-object statistics, so readers can skip objects outside a query's
+/// block or time range without opening them.
+///
+/// Same trust model as [`DatasetManifestV1`]: written by trusted ingestion code from the Parquet
+/// footers it produced, validated as untrusted input when consumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetManifestV2 {
+    /// Manifest schema version.
+    pub version: u32,
+
+    pub dataset_uuid: Uuid,
+    pub dataset_version: Uuid,
+
+    /// Parquet objects, sorted by key.
+    pub objects: Vec<ManifestObject>,
+}
+
+impl DatasetManifestV2 {
+    pub const VERSION: u32 = 2;
+}
+
+/// One Parquet object of a [`DatasetManifestV2`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestObject {
+    /// Object key (relative to the S3 bucket).
+    pub key: String,
+    pub size_bytes: u64,
+    pub row_count: u64,
+    /// Lowercase hex SHA-256 of the object bytes.
+    pub sha256: String,
+    /// `block_number` range, when every row group records statistics for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<ColumnRange>,
+    /// `timestamp` (unix seconds) range, when every row group records statistics for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<ColumnRange>,
+}
+
+/// Inclusive min/max of an integer column within one object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+/// A dataset manifest of any supported version.
+#[derive(Debug, Clone)]
+pub enum DatasetManifest {
+    V1(DatasetManifestV1),
+    V2(DatasetManifestV2),
+}
+
+impl DatasetManifest {
+    /// Decode a manifest, dispatching on its `version` field.
+    ///
+    /// Returns `Ok(None)` for a well-formed manifest of an unsupported version.
+    pub fn from_json(bytes: &[u8]) -> serde_json::Result<Option<Self>> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u32,
+        }
+
+        let Versioned { version } = serde_json::from_slice(bytes)?;
+        Ok(match version {
+            DatasetManifestV1::VERSION => Some(Self::V1(serde_json::from_slice(bytes)?)),
+            DatasetManifestV2::VERSION => Some(Self::V2(serde_json::from_slice(bytes)?)),
+            _ => None,
+        })
+    }
+
+    pub fn dataset_uuid(&self) -> Uuid {
+        match self {
+            Self::V1(m) => m.dataset_uuid,
+            Self::V2(m) => m.dataset_uuid,
+        }
+    }
+
+    pub fn dataset_version(&self) -> Uuid {
+        match self {
+            Self::V1(m) => m.dataset_version,
+            Self::V2(m) => m.dataset_version,
+        }
+    }
+
+    /// Parquet object keys, in manifest order.
+    pub fn parquet_keys(&self) -> Vec<&str> {
+        match self {
+            Self::V1(m) => m.parquet_keys.iter().map(String::as_str).collect(),
+            Self::V2(m) => m.objects.iter().map(|o| o.key.as_str()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_by_version() {
+        let dataset_uuid = Uuid::new_v4();
+        let dataset_version = Uuid::new_v4();
+
+        let v1 = serde_json::json!({
+            "version": 1,
+            "dataset_uuid": dataset_uuid,
+            "dataset_version": dataset_version,
+            "parquet_objects": ["p/a.parquet"],
+        });
+        let manifest = DatasetManifest::from_json(v1.to_string().as_bytes())
+            .unwrap()
+            .expect("v1 supported");
+        assert!(matches!(manifest, DatasetManifest::V1(_)));
+        assert_eq!(manifest.parquet_keys(), vec!["p/a.parquet"]);
+
+        let v2 = serde_json::json!({
+            "version": 2,
+            "dataset_uuid": dataset_uuid,
+            "dataset_version": dataset_version,
+            "objects": [{
+                "key": "p/b.parquet",
+                "size_bytes": 10,
+                "row_count": 2,
+                "sha256": "00".repeat(32),
+                "block_number": { "min": 5, "max": 6 },
+            }],
+        });
+        let manifest = DatasetManifest::from_json(v2.to_string().as_bytes())
+            .unwrap()
+            .expect("v2 supported");
+        assert_eq!(manifest.dataset_uuid(), dataset_uuid);
+        assert_eq!(manifest.dataset_version(), dataset_version);
+        let DatasetManifest::V2(v2) = manifest else {
+            panic!("expected v2");
+        };
+        assert_eq!(
+            v2.objects[0].block_number,
+            Some(ColumnRange { min: 5, max: 6 })
+        );
+        assert_eq!(v2.objects[0].timestamp, None);
+
+        let v3 = serde_json::json!({ "version": 3 });
+        assert!(DatasetManifest::from_json(v3.to_string().as_bytes())
+            .unwrap()
+            .is_none());
+
+        // Fields are strict per version.
+        let mixed = serde_json::json!({
+            "version": 2,
+            "dataset_uuid": dataset_uuid,
+            "dataset_version": dataset_version,
+            "parquet_keys": ["p/a.parquet"],
+        });
+        assert!(DatasetManifest::from_json(mixed.to_string().as_bytes()).is_err());
+    }
+}
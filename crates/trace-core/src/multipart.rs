@@ -0,0 +1,430 @@
+//! Multipart upload driver shared by the S3 object store backends.
+//!
+//! `put_file` switches to a multipart upload once a file is larger than
+//! [`MultipartConfig::threshold_bytes`]. Parts are read from disk one at a time per worker, uploaded
+//! `concurrency` at a time with a SHA-256 checksum each, and retried individually with jittered
+//! backoff. If any part still fails (or completion fails) the upload is aborted so no orphaned
+//! parts are billed.
+
+use crate::{jitter, Error, Result};
+use anyhow::Context;
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Smallest part S3 accepts (except for the last part).
+pub const MIN_PART_SIZE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Largest part S3 accepts.
+pub const MAX_PART_SIZE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Most parts one upload may have.
+pub const MAX_PARTS: u64 = 10_000;
+
+/// Most attempts per part `validate` accepts.
+pub const MAX_PART_ATTEMPTS: u32 = 10;
+
+const PART_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+const PART_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultipartConfig {
+    /// Files larger than this are uploaded in parts.
+    pub threshold_bytes: u64,
+    /// Size of every part but the last; raised for files that would need more than
+    /// [`MAX_PARTS`] parts.
+    pub part_size_bytes: u64,
+    /// Parts in flight at once (and so part buffers held in memory).
+    pub concurrency: usize,
+    /// Attempts per part, including the first.
+    pub part_attempts: u32,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self {
+            threshold_bytes: 64 * 1024 * 1024,
+            part_size_bytes: 16 * 1024 * 1024,
+            concurrency: 4,
+            part_attempts: 3,
+        }
+    }
+}
+
+impl MultipartConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(MIN_PART_SIZE_BYTES..=MAX_PART_SIZE_BYTES).contains(&self.part_size_bytes) {
+            return Err(Error::msg(format!(
+                "multipart part size must be between {MIN_PART_SIZE_BYTES} and {MAX_PART_SIZE_BYTES} bytes (got {})",
+                self.part_size_bytes
+            )));
+        }
+        if self.concurrency == 0 {
+            return Err(Error::msg("multipart concurrency must be at least 1"));
+        }
+        if !(1..=MAX_PART_ATTEMPTS).contains(&self.part_attempts) {
+            return Err(Error::msg(format!(
+                "multipart part attempts must be between 1 and {MAX_PART_ATTEMPTS} (got {})",
+                self.part_attempts
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `put_file` should upload a file of `size` bytes in parts.
+    pub fn applies_to(&self, size: u64) -> bool {
+        size > self.threshold_bytes
+    }
+
+    fn part_size_for(&self, size: u64) -> u64 {
+        self.part_size_bytes.max(size.div_ceil(MAX_PARTS))
+    }
+}
+
+/// One uploaded part, as listed when completing the upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CompletedPart {
+    /// 1-based.
+    pub part_number: i32,
+    pub etag: String,
+    pub sha256: [u8; 32],
+}
+
+/// The four multipart calls of an S3 backend.
+#[async_trait]
+pub(crate) trait MultipartBackend: Send + Sync {
+    /// Start an upload with SHA-256 part checksums; returns the upload id.
+    async fn create_multipart(&self, bucket: &str, key: &str, content_type: &str)
+        -> Result<String>;
+
+    /// Upload one part carrying `sha256` as its checksum; returns the part's ETag.
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        bytes: Vec<u8>,
+        sha256: [u8; 32],
+    ) -> Result<String>;
+
+    async fn complete_multipart(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> Result<()>;
+
+    async fn abort_multipart(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()>;
+}
+
+/// Upload `local_path` (`size` bytes) as `bucket/key` in parts, aborting the upload on failure.
+pub(crate) async fn upload_file<B: MultipartBackend + ?Sized>(
+    backend: &B,
+    cfg: &MultipartConfig,
+    bucket: &str,
+    key: &str,
+    local_path: &Path,
+    size: u64,
+    content_type: &str,
+) -> Result<()> {
+    let part_size = cfg.part_size_for(size);
+    let part_count = size.div_ceil(part_size).max(1);
+    let upload_id = backend.create_multipart(bucket, key, content_type).await?;
+
+    let upload = PartUpload {
+        backend,
+        cfg,
+        bucket,
+        key,
+        upload_id: &upload_id,
+        local_path,
+    };
+    let uploaded: Result<()> = async {
+        let mut parts: Vec<CompletedPart> = futures_util::stream::iter(0..part_count)
+            .map(|idx| {
+                let offset = idx * part_size;
+                upload_part_with_retry(
+                    &upload,
+                    FilePart {
+                        idx,
+                        offset,
+                        len: part_size.min(size - offset),
+                    },
+                )
+            })
+            .buffer_unordered(cfg.concurrency.max(1))
+            .try_collect()
+            .await?;
+        parts.sort_by_key(|part| part.part_number);
+        backend
+            .complete_multipart(bucket, key, &upload_id, &parts)
+            .await
+    }
+    .await;
+
+    if let Err(err) = uploaded {
+        let context = match backend.abort_multipart(bucket, key, &upload_id).await {
+            Ok(()) => format!("multipart upload aborted bucket={bucket} key={key}"),
+            Err(abort_err) => format!(
+                "multipart upload failed and abort failed bucket={bucket} key={key} upload_id={upload_id}: {abort_err}"
+            ),
+        };
+        return Err(Error::from(anyhow::Error::new(err).context(context)));
+    }
+    Ok(())
+}
+
+/// An upload in progress: where its parts go and the file they are read from.
+struct PartUpload<'a, B: ?Sized> {
+    backend: &'a B,
+    cfg: &'a MultipartConfig,
+    bucket: &'a str,
+    key: &'a str,
+    upload_id: &'a str,
+    local_path: &'a Path,
+}
+
+/// The byte range of the file uploaded as part `idx` (0-based).
+#[derive(Debug, Clone, Copy)]
+struct FilePart {
+    idx: u64,
+    offset: u64,
+    len: u64,
+}
+
+async fn upload_part_with_retry<B: MultipartBackend + ?Sized>(
+    upload: &PartUpload<'_, B>,
+    part: FilePart,
+) -> Result<CompletedPart> {
+    let PartUpload {
+        backend,
+        cfg,
+        bucket,
+        key,
+        upload_id,
+        local_path,
+    } = *upload;
+    let part_number = i32::try_from(part.idx + 1).map_err(|_| Error::msg("too many parts"))?;
+    let bytes = read_part(local_path, part.offset, part.len).await?;
+    let sha256: [u8; 32] = Sha256::digest(&bytes).into();
+
+    let attempts = cfg.part_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match backend
+            .upload_part(bucket, key, upload_id, part_number, bytes.clone(), sha256)
+            .await
+        {
+            Ok(etag) => {
+                return Ok(CompletedPart {
+                    part_number,
+                    etag,
+                    sha256,
+                })
+            }
+            Err(err) if attempt >= attempts => {
+                return Err(Error::from(anyhow::Error::new(err).context(format!(
+                    "upload part {part_number} failed after {attempts} attempts"
+                ))))
+            }
+            Err(_) => {
+                tokio::time::sleep(part_retry_delay(attempt, jitter::unit())).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Backoff after failed attempt `attempt` (1-based): doubling from the base delay, capped.
+///
+/// Half of the delay is fixed and half is scaled by `jitter` (in `[0, 1)`), so parts that fail
+/// together do not all retry at once.
+fn part_retry_delay(attempt: u32, jitter: f64) -> Duration {
+    let capped = 2_u32
+        .checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| PART_RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(PART_RETRY_MAX_DELAY, |delay| {
+            delay.min(PART_RETRY_MAX_DELAY)
+        });
+    let half = capped / 2;
+    half + half.mul_f64(jitter.clamp(0.0, 1.0))
+}
+
+async fn read_part(local_path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(local_path)
+        .await
+        .with_context(|| format!("open file path={}", local_path.display()))
+        .map_err(Error::from)?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .with_context(|| format!("seek file path={} offset={offset}", local_path.display()))
+        .map_err(Error::from)?;
+    let len = usize::try_from(len)
+        .context("part does not fit in memory")
+        .map_err(Error::from)?;
+    let mut bytes = vec![0; len];
+    file.read_exact(&mut bytes)
+        .await
+        .with_context(|| format!("read part path={} offset={offset}", local_path.display()))
+        .map_err(Error::from)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct FakeBackend {
+        parts: Mutex<BTreeMap<i32, Vec<u8>>>,
+        completed: Mutex<Option<Vec<u8>>>,
+        aborted: Mutex<bool>,
+        /// Part number -> failures left before it succeeds.
+        failures: Mutex<BTreeMap<i32, u32>>,
+    }
+
+    #[async_trait]
+    impl MultipartBackend for FakeBackend {
+        async fn create_multipart(&self, _: &str, _: &str, _: &str) -> Result<String> {
+            Ok("upload-1".to_string())
+        }
+
+        async fn upload_part(
+            &self,
+            _: &str,
+            _: &str,
+            upload_id: &str,
+            part_number: i32,
+            bytes: Vec<u8>,
+            sha256: [u8; 32],
+        ) -> Result<String> {
+            assert_eq!(upload_id, "upload-1");
+            assert_eq!(<[u8; 32]>::from(Sha256::digest(&bytes)), sha256);
+            if let Some(left) = self.failures.lock().unwrap().get_mut(&part_number) {
+                if *left > 0 {
+                    *left -= 1;
+                    return Err(Error::msg("injected part failure"));
+                }
+            }
+            self.parts.lock().unwrap().insert(part_number, bytes);
+            Ok(format!("\"etag-{part_number}\""))
+        }
+
+        async fn complete_multipart(
+            &self,
+            _: &str,
+            _: &str,
+            _: &str,
+            parts: &[CompletedPart],
+        ) -> Result<()> {
+            let uploaded = self.parts.lock().unwrap();
+            let numbers = parts.iter().map(|p| p.part_number).collect::<Vec<_>>();
+            assert_eq!(numbers, uploaded.keys().copied().collect::<Vec<_>>());
+            assert!(parts
+                .iter()
+                .all(|p| p.etag == format!("\"etag-{}\"", p.part_number)));
+            *self.completed.lock().unwrap() = Some(uploaded.values().flatten().copied().collect());
+            Ok(())
+        }
+
+        async fn abort_multipart(&self, _: &str, _: &str, _: &str) -> Result<()> {
+            *self.aborted.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    fn cfg() -> MultipartConfig {
+        MultipartConfig {
+            threshold_bytes: MIN_PART_SIZE_BYTES,
+            part_size_bytes: MIN_PART_SIZE_BYTES,
+            concurrency: 2,
+            part_attempts: 2,
+        }
+    }
+
+    fn write_temp_file(len: usize) -> (std::path::PathBuf, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("trace-multipart-{}", uuid::Uuid::new_v4()));
+        let bytes = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        std::fs::write(&path, &bytes).unwrap();
+        (path, bytes)
+    }
+
+    #[tokio::test]
+    async fn uploads_parts_in_order_and_retries_failed_parts() {
+        let len = 2 * MIN_PART_SIZE_BYTES as usize + 123;
+        let (path, bytes) = write_temp_file(len);
+        let backend = FakeBackend::default();
+        backend.failures.lock().unwrap().insert(2, 1);
+
+        upload_file(&backend, &cfg(), "b", "k", &path, len as u64, "x")
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(backend.parts.lock().unwrap().len(), 3);
+        assert_eq!(
+            backend.completed.lock().unwrap().as_deref(),
+            Some(&bytes[..])
+        );
+        assert!(!*backend.aborted.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn aborts_when_a_part_keeps_failing() {
+        let len = MIN_PART_SIZE_BYTES as usize + 1;
+        let (path, _) = write_temp_file(len);
+        let backend = FakeBackend::default();
+        backend.failures.lock().unwrap().insert(1, 2);
+
+        let err = upload_file(&backend, &cfg(), "b", "k", &path, len as u64, "x")
+            .await
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(format!("{err:#}").contains("aborted"), "{err:#}");
+        assert!(*backend.aborted.lock().unwrap());
+        assert!(backend.completed.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn grows_parts_to_stay_under_the_part_limit() {
+        let cfg = MultipartConfig::default();
+        let size = 500 * 1024 * 1024 * 1024;
+        assert_eq!(cfg.part_size_for(size), size.div_ceil(MAX_PARTS));
+        assert_eq!(cfg.part_size_for(1), cfg.part_size_bytes);
+        assert!(MultipartConfig {
+            part_size_bytes: 1024,
+            ..cfg
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn part_retry_delay_is_capped() {
+        assert_eq!(part_retry_delay(1, 1.0), PART_RETRY_BASE_DELAY);
+        assert_eq!(part_retry_delay(1, 0.0), PART_RETRY_BASE_DELAY / 2);
+        assert_eq!(part_retry_delay(2, 1.0), PART_RETRY_BASE_DELAY * 2);
+        for attempt in [MAX_PART_ATTEMPTS, 33, u32::MAX] {
+            assert_eq!(part_retry_delay(attempt, 1.0), PART_RETRY_MAX_DELAY);
+            assert_eq!(part_retry_delay(attempt, 0.0), PART_RETRY_MAX_DELAY / 2);
+        }
+        let cfg = MultipartConfig::default();
+        for part_attempts in [0, MAX_PART_ATTEMPTS + 1] {
+            assert!(MultipartConfig {
+                part_attempts,
+                ..cfg
+            }
+            .validate()
+            .is_err());
+        }
+    }
+}
@@ -1,373 +1,1166 @@
 use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+use sqlparser::ast::{
+    BinaryOperator, Distinct, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArgumentClause,
+    FunctionArguments, GroupByExpr, Ident, JoinConstraint, JoinOperator, JsonPathElem,
+    NamedWindowExpr, ObjectName, OrderBy, OrderByExpr, Query, Select, SelectItem, SetExpr,
+    Statement, Subscript, TableAlias, TableFactor, TableWithJoins, UnaryOperator, Value,
+    WindowFrameBound, WindowSpec, WindowType,
+};
+use sqlparser::dialect::DuckDbDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::{BTreeMap, BTreeSet};
 
 const MAX_STRING_LITERAL_BYTES: usize = 4096;
 
-/// Conservative denylist of functions that can read from (or otherwise touch) external systems.
+/// Functions untrusted SQL may call (lowercase, unqualified).
 ///
 /// Notes:
-/// - We intentionally key off *function call sites* (identifier followed by `(`, allowing
-///   whitespace/comments in between). This avoids false positives for column/table names.
-/// - This list is necessarily conservative. Query Service MUST also harden DuckDB (disable
-///   the `LocalFileSystem`, forbid extension installation) and run in a locked-down OS/network
-///   sandbox. In particular, if the service allows querying authorized remote Parquet datasets
-///   (HTTP/S3), the OS-level network policy must restrict egress to only the configured object
-///   store endpoints.
-const FORBIDDEN_FUNCTIONS: &[&str] = &[
-    // File / URL readers (table functions)
-    "READ_CSV",
-    "READ_CSV_AUTO",
-    "READ_PARQUET",
-    "PARQUET_SCAN",
-    "READ_JSON",
-    "READ_JSON_AUTO",
-    "JSON_SCAN",
-    "SQLITE_SCAN",
-    "POSTGRES_SCAN",
-    "MYSQL_SCAN",
-    "DELTA_SCAN",
-    "ICEBERG_SCAN",
-    // File-ish scalar helpers
-    "READ_FILE",
-    "READ_BLOB",
-    "WRITE_FILE",
-    "WRITE_BLOB",
-    // Environment/process introspection (defense-in-depth)
-    "GETENV",
+/// - This is an allowlist: anything not listed here is rejected, so new DuckDB table functions
+///   and file/URL readers (`read_csv`, `read_parquet`, `glob`, ...) fail closed by default.
+/// - Nothing here may touch the filesystem, network, environment, or DuckDB settings/catalog
+///   (e.g. `getenv`, `current_setting`, `duckdb_settings`, `query`). Query Service MUST still
+///   harden DuckDB (disable the `LocalFileSystem`, forbid extension installation) and restrict
+///   OS-level egress to the configured object store endpoints.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    // Aggregates
+    "any_value",
+    "approx_count_distinct",
+    "approx_quantile",
+    "arg_max",
+    "arg_min",
+    "array_agg",
+    "avg",
+    "bit_and",
+    "bit_or",
+    "bit_xor",
+    "bool_and",
+    "bool_or",
+    "count",
+    "count_if",
+    "first",
+    "last",
+    "list",
+    "max",
+    "max_by",
+    "mean",
+    "median",
+    "min",
+    "min_by",
+    "mode",
+    "product",
+    "quantile",
+    "quantile_cont",
+    "quantile_disc",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "string_agg",
+    "sum",
+    "var_pop",
+    "var_samp",
+    "variance",
+    // Window functions
+    "cume_dist",
+    "dense_rank",
+    "first_value",
+    "lag",
+    "last_value",
+    "lead",
+    "nth_value",
+    "ntile",
+    "percent_rank",
+    "rank",
+    "row_number",
+    // Conditionals
+    "coalesce",
+    "greatest",
+    "if",
+    "ifnull",
+    "least",
+    "nullif",
+    // Math
+    "abs",
+    "cbrt",
+    "ceil",
+    "ceiling",
+    "exp",
+    "floor",
+    "ln",
+    "log",
+    "log10",
+    "log2",
+    "mod",
+    "pow",
+    "power",
+    "round",
+    "sign",
+    "sqrt",
+    "trunc",
+    // Strings and bytes
+    "concat",
+    "concat_ws",
+    "contains",
+    "ends_with",
+    "left",
+    "length",
+    "lower",
+    "lpad",
+    "ltrim",
+    "octet_length",
+    "position",
+    "prefix",
+    "regexp_extract",
+    "regexp_matches",
+    "regexp_replace",
+    "repeat",
+    "replace",
+    "reverse",
+    "right",
+    "rpad",
+    "rtrim",
+    "split_part",
+    "starts_with",
+    "strlen",
+    "strpos",
+    "substr",
+    "substring",
+    "suffix",
+    "trim",
+    "upper",
+    // Encoding and hashing
+    "from_hex",
+    "hex",
+    "md5",
+    "sha256",
+    "to_hex",
+    "unhex",
+    // Dates and times
+    "current_date",
+    "current_timestamp",
+    "date_diff",
+    "date_part",
+    "date_trunc",
+    "datediff",
+    "datepart",
+    "datetrunc",
+    "epoch",
+    "epoch_ms",
+    "make_date",
+    "make_timestamp",
+    "now",
+    "strftime",
+    "strptime",
+    "to_timestamp",
+    // Lists
+    "array_length",
+    "len",
+    "list_contains",
+    "list_extract",
+    "list_value",
 ];
 
-const FORBIDDEN_KEYWORDS: &[&str] = &[
-    "ATTACH", "DETACH", "INSTALL", "LOAD", // DDL/DML/side effects
-    "ALTER", "ANALYZE", "CALL", "COPY", "CREATE", "DELETE", "DROP", "EXEC", "EXECUTE", "EXPORT",
-    "IMPORT", "INSERT", "MERGE", "PRAGMA", "RESET", "SET", "UPDATE", "VACUUM",
-    // Some engines support SELECT ... INTO (file/table). We treat INTO as a side effect.
-    "INTO",
-];
+/// What a validated query reads.
+///
+/// Collected from the parsed AST for authorization and auditing. Attribution of columns to
+/// relations is best effort: unqualified columns are only attributed when a single relation is in
+/// scope, and names that turn out to be select-list aliases are reported like columns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidatedQuery {
+    /// Base relations referenced in `FROM`/`JOIN` positions (lowercase; CTEs excluded).
+    pub relations: BTreeSet<String>,
+    /// Column references (lowercase). `*` is reported as a column named `*`.
+    pub columns: BTreeSet<ColumnRef>,
+    /// Every `FROM`/`JOIN` read of a base relation, in statement order.
+    pub scans: Vec<RelationScan>,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum State {
-    Normal,
-    SingleQuote,
-    DoubleQuote,
-    LineComment,
-    BlockComment,
+impl ValidatedQuery {
+    /// Bounds on `column` that hold for every scan of `relation`.
+    ///
+    /// `None` when `relation` is not scanned, or when any scan of it leaves `column` unbounded.
+    /// Otherwise the smallest range covering all scans (their bounds may be disjoint).
+    pub fn column_bounds(&self, relation: &str, column: &str) -> Option<IntBounds> {
+        let mut scans = self
+            .scans
+            .iter()
+            .filter(|scan| scan.relation.eq_ignore_ascii_case(relation))
+            .peekable();
+        scans.peek()?;
+
+        let mut hull: Option<IntBounds> = None;
+        for scan in scans {
+            let bounds = scan.bounds.get(&column.to_ascii_lowercase())?;
+            hull = Some(match hull {
+                None => *bounds,
+                Some(hull) => IntBounds {
+                    min: hull.min.zip(bounds.min).map(|(a, b)| a.min(b)),
+                    max: hull.max.zip(bounds.max).map(|(a, b)| a.max(b)),
+                },
+            });
+        }
+        hull.filter(|hull| hull.min.is_some() || hull.max.is_some())
+    }
+}
+
+/// A column reference, attributed to a base relation when it can be resolved.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ColumnRef {
+    pub relation: Option<String>,
+    pub column: String,
+}
+
+/// One `FROM`/`JOIN` read of a base relation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelationScan {
+    /// Relation name (lowercase).
+    pub relation: String,
+    /// Integer bounds per column (lowercase) that the enclosing `SELECT`'s `WHERE` clause places
+    /// on this scan.
+    ///
+    /// Only top-level `AND`ed comparisons (`<`, `<=`, `=`, `>=`, `>`, `BETWEEN`, `IN`) between a
+    /// column of this scan and integer literals count; anything else leaves the column unbounded.
+    /// Each counted comparison rejects `NULL`, so it holds for either side of an outer join.
+    /// Unqualified columns count only when the `FROM` clause binds a single relation.
+    pub bounds: BTreeMap<String, IntBounds>,
+}
+
+/// An inclusive integer range; `None` ends are unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IntBounds {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl IntBounds {
+    /// Whether any value in `[min, max]` is within these bounds.
+    pub fn overlaps(&self, min: i64, max: i64) -> bool {
+        self.min.is_none_or(|lo| max >= lo) && self.max.is_none_or(|hi| min <= hi)
+    }
+
+    fn intersect(&mut self, other: IntBounds) {
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
 }
 
 /// Fail-closed SQL validator for Query Service.
 ///
-/// v1 requirements:
-/// - Allow only a single `SELECT` (or `WITH ... SELECT`) statement
-/// - Reject extension install/load
-/// - Reject known unsafe external-reader functions (e.g. `read_csv(...)`)
-/// - Reject non-standard string-literal relations (e.g. `FROM 'file.csv'`)
-/// - Reject multi-statement batches
+/// v2 requirements:
+/// - Parse with a DuckDB-dialect SQL parser; allow only a single `SELECT` (or `WITH ... SELECT`)
+///   statement
+/// - Accept only the AST shapes handled below; anything else (`INSTALL`/`LOAD`/`ATTACH`,
+///   `SELECT ... INTO`, string-literal or table-function relations, lambdas, ...) is rejected
+/// - Resolve every function call against [`ALLOWED_FUNCTIONS`]
+/// - Reject schema-qualified relations and multi-statement batches
 ///
-/// This validator is intentionally conservative and does not try to be a full SQL parser.
-pub fn validate_sql(sql: &str) -> Result<()> {
+/// Returns the relations, columns, and scans the query references.
+pub fn validate_sql(sql: &str) -> Result<ValidatedQuery> {
+    validate(sql, None)
+}
+
+/// [`validate_sql`], plus: every relation in a `FROM`/`JOIN` position must be one of
+/// `allowed_relations` (compared case-insensitively) or a CTE defined by the statement.
+pub fn validate_sql_relations(sql: &str, allowed_relations: &[&str]) -> Result<ValidatedQuery> {
+    validate(sql, Some(allowed_relations))
+}
+
+/// Literal-free fingerprint of `sql`, for grouping audit records without storing the statement.
+///
+/// The statement is tokenized (DuckDB dialect) and normalized before hashing:
+/// - whitespace, comments, and trailing `;` are dropped,
+/// - literals (numbers, strings, placeholders) become `?`, and literal lists collapse to one `?`
+///   (`IN (1, 2, 3)` and `IN (4)` match),
+/// - keywords are uppercased and unquoted identifiers lowercased.
+///
+/// Returns the lowercase hex SHA-256 of the normalized token stream, or `None` if `sql` does not
+/// tokenize.
+pub fn sql_fingerprint(sql: &str) -> Option<String> {
+    let tokens = Tokenizer::new(&DuckDbDialect {}, sql).tokenize().ok()?;
+
+    let mut normalized: Vec<String> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        let text = match token {
+            Token::Whitespace(_) | Token::SemiColon | Token::EOF => continue,
+            Token::Word(word) if word.quote_style.is_some() => word.to_string(),
+            Token::Word(word) if word.keyword != Keyword::NoKeyword => {
+                word.value.to_ascii_uppercase()
+            }
+            Token::Word(word) => word.value.to_ascii_lowercase(),
+            Token::Number(..)
+            | Token::SingleQuotedString(_)
+            | Token::DoubleQuotedString(_)
+            | Token::TripleSingleQuotedString(_)
+            | Token::TripleDoubleQuotedString(_)
+            | Token::DollarQuotedString(_)
+            | Token::SingleQuotedByteStringLiteral(_)
+            | Token::DoubleQuotedByteStringLiteral(_)
+            | Token::TripleSingleQuotedByteStringLiteral(_)
+            | Token::TripleDoubleQuotedByteStringLiteral(_)
+            | Token::SingleQuotedRawStringLiteral(_)
+            | Token::DoubleQuotedRawStringLiteral(_)
+            | Token::TripleSingleQuotedRawStringLiteral(_)
+            | Token::TripleDoubleQuotedRawStringLiteral(_)
+            | Token::NationalStringLiteral(_)
+            | Token::EscapedStringLiteral(_)
+            | Token::UnicodeStringLiteral(_)
+            | Token::HexStringLiteral(_)
+            | Token::Placeholder(_) => {
+                if normalized.len() >= 2
+                    && normalized[normalized.len() - 1] == ","
+                    && normalized[normalized.len() - 2] == "?"
+                {
+                    normalized.pop();
+                    continue;
+                }
+                "?".to_string()
+            }
+            other => other.to_string(),
+        };
+        normalized.push(text);
+    }
+
+    let digest = Sha256::digest(normalized.join(" ").as_bytes());
+    Some(digest.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+fn validate(sql: &str, allowed_relations: Option<&[&str]>) -> Result<ValidatedQuery> {
     let sql = sql.trim();
     if sql.is_empty() {
         return Err(Error::msg("sql rejected: empty"));
     }
 
-    let bytes = sql.as_bytes();
-    let mut i = 0usize;
-    let mut state = State::Normal;
-    let mut first_keyword: Option<String> = None;
+    let dialect = DuckDbDialect {};
+
+    // The parser folds repeated `;` into one delimiter, so check statement boundaries on the
+    // token stream: at most one `;`, followed only by whitespace/comments.
+    let tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(|_| Error::msg("sql rejected: parse error"))?;
     let mut seen_semicolon = false;
+    for token in &tokens {
+        match token {
+            Token::Whitespace(_) => {}
+            Token::SemiColon if !seen_semicolon => seen_semicolon = true,
+            _ if seen_semicolon => return Err(Error::msg("sql rejected: multiple statements")),
+            _ => {}
+        }
+    }
 
-    // Minimal context to reject DuckDB-style FROM 'file.csv' and similar.
-    // We track whether we're in the FROM clause at the top level and whether
-    // the next token must be a relation/table factor.
-    let mut paren_depth: i32 = 0;
-    let mut in_from_clause = false;
-    let mut expects_relation = false;
+    // Parser errors can echo fragments of the statement; keep them out of the error.
+    let statements =
+        Parser::parse_sql(&dialect, sql).map_err(|_| Error::msg("sql rejected: parse error"))?;
+    let query = match statements.as_slice() {
+        [] => return Err(Error::msg("sql rejected: no statement")),
+        [Statement::Query(query)] => query,
+        [_] => return Err(Error::msg("sql rejected: only SELECT allowed")),
+        _ => return Err(Error::msg("sql rejected: multiple statements")),
+    };
 
-    let mut string_literal = String::new();
-    let mut quoted_ident = String::new();
+    let mut walker = Walker {
+        allowed_relations,
+        ctes: Vec::new(),
+        scopes: Vec::new(),
+        out: ValidatedQuery::default(),
+    };
+    walker.query(query)?;
+    Ok(walker.out)
+}
 
-    while i < bytes.len() {
-        let b = bytes[i];
+/// A name visible in a `FROM` scope.
+struct Binding {
+    name: String,
+    /// Base relation behind the binding (`None` for CTEs and derived tables).
+    relation: Option<String>,
+    /// Index of the binding's entry in `ValidatedQuery::scans` (base relations only).
+    scan: Option<usize>,
+}
 
-        match state {
-            State::Normal => {
-                if seen_semicolon {
-                    if b.is_ascii_whitespace() {
-                        i += 1;
-                        continue;
-                    }
-                    if b == b'-' && bytes.get(i + 1) == Some(&b'-') {
-                        state = State::LineComment;
-                        i += 2;
-                        continue;
-                    }
-                    if b == b'/' && bytes.get(i + 1) == Some(&b'*') {
-                        state = State::BlockComment;
-                        i += 2;
-                        continue;
-                    }
-                    return Err(Error::msg("sql rejected: multiple statements"));
+struct Walker<'a> {
+    allowed_relations: Option<&'a [&'a str]>,
+    /// CTE names visible at the current point (lowercase).
+    ctes: Vec<String>,
+    /// `FROM` scopes, innermost last.
+    scopes: Vec<Vec<Binding>>,
+    out: ValidatedQuery,
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::msg(format!("sql rejected: unsupported {what}"))
+}
+
+fn lower(ident: &Ident) -> String {
+    ident.value.to_ascii_lowercase()
+}
+
+impl Walker<'_> {
+    fn query(&mut self, query: &Query) -> Result<()> {
+        if !query.limit_by.is_empty()
+            || !query.locks.is_empty()
+            || query.for_clause.is_some()
+            || query.settings.is_some()
+            || query.format_clause.is_some()
+        {
+            return Err(unsupported("clause"));
+        }
+
+        let ctes_len = self.ctes.len();
+        let scopes_len = self.scopes.len();
+
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                if cte.from.is_some() {
+                    return Err(unsupported("clause"));
                 }
+                self.ctes.push(lower(&cte.alias.name));
+                self.query(&cte.query)?;
+            }
+        }
 
-                if b.is_ascii_whitespace() {
-                    i += 1;
-                    continue;
+        // A plain SELECT body leaves its scope open so ORDER BY can resolve its columns.
+        self.set_expr(&query.body)?;
+
+        if let Some(order_by) = &query.order_by {
+            self.order_by(order_by)?;
+        }
+        if let Some(limit) = &query.limit {
+            self.expr(limit)?;
+        }
+        if let Some(offset) = &query.offset {
+            self.expr(&offset.value)?;
+        }
+        if let Some(fetch) = &query.fetch {
+            if let Some(quantity) = &fetch.quantity {
+                self.expr(quantity)?;
+            }
+        }
+
+        self.scopes.truncate(scopes_len);
+        self.ctes.truncate(ctes_len);
+        Ok(())
+    }
+
+    fn set_expr(&mut self, body: &SetExpr) -> Result<()> {
+        match body {
+            SetExpr::Select(select) => self.select(select),
+            SetExpr::Query(query) => self.query(query),
+            SetExpr::SetOperation { left, right, .. } => {
+                let scopes_len = self.scopes.len();
+                self.set_expr(left)?;
+                self.scopes.truncate(scopes_len);
+                self.set_expr(right)?;
+                self.scopes.truncate(scopes_len);
+                Ok(())
+            }
+            SetExpr::Values(values) => {
+                for row in &values.rows {
+                    self.exprs(row)?;
                 }
-                if b == b'-' && bytes.get(i + 1) == Some(&b'-') {
-                    state = State::LineComment;
-                    i += 2;
-                    continue;
+                Ok(())
+            }
+            SetExpr::Insert(_) | SetExpr::Update(_) | SetExpr::Table(_) => {
+                Err(Error::msg("sql rejected: only SELECT allowed"))
+            }
+        }
+    }
+
+    fn select(&mut self, select: &Select) -> Result<()> {
+        if select.into.is_some() {
+            return Err(Error::msg("sql rejected: SELECT INTO"));
+        }
+        if select.top.is_some()
+            || !select.lateral_views.is_empty()
+            || select.prewhere.is_some()
+            || !select.cluster_by.is_empty()
+            || !select.distribute_by.is_empty()
+            || !select.sort_by.is_empty()
+            || select.value_table_mode.is_some()
+            || select.connect_by.is_some()
+        {
+            return Err(unsupported("clause"));
+        }
+
+        self.scopes.push(Vec::new());
+        for table in &select.from {
+            self.table_with_joins(table)?;
+        }
+
+        if let Some(Distinct::On(exprs)) = &select.distinct {
+            self.exprs(exprs)?;
+        }
+        for item in &select.projection {
+            match item {
+                SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                    self.expr(expr)?
                 }
-                if b == b'/' && bytes.get(i + 1) == Some(&b'*') {
-                    state = State::BlockComment;
-                    i += 2;
-                    continue;
+                SelectItem::QualifiedWildcard(name, _) => self.qualified_wildcard(name),
+                SelectItem::Wildcard(_) => self.wildcard(),
+            }
+        }
+        if let Some(selection) = &select.selection {
+            self.expr(selection)?;
+            self.bound_scans(selection);
+        }
+        if let GroupByExpr::Expressions(exprs, _) = &select.group_by {
+            self.exprs(exprs)?;
+        }
+        if let Some(having) = &select.having {
+            self.expr(having)?;
+        }
+        for window in &select.named_window {
+            if let NamedWindowExpr::WindowSpec(spec) = &window.1 {
+                self.window_spec(spec)?;
+            }
+        }
+        if let Some(qualify) = &select.qualify {
+            self.expr(qualify)?;
+        }
+        Ok(())
+    }
+
+    fn table_with_joins(&mut self, table: &TableWithJoins) -> Result<()> {
+        self.table_factor(&table.relation)?;
+        for join in &table.joins {
+            self.table_factor(&join.relation)?;
+            let constraint = match &join.join_operator {
+                JoinOperator::Inner(c)
+                | JoinOperator::LeftOuter(c)
+                | JoinOperator::RightOuter(c)
+                | JoinOperator::FullOuter(c)
+                | JoinOperator::Semi(c)
+                | JoinOperator::LeftSemi(c)
+                | JoinOperator::RightSemi(c)
+                | JoinOperator::Anti(c)
+                | JoinOperator::LeftAnti(c)
+                | JoinOperator::RightAnti(c) => Some(c),
+                JoinOperator::AsOf {
+                    match_condition,
+                    constraint,
+                } => {
+                    self.expr(match_condition)?;
+                    Some(constraint)
                 }
-                if b == b';' {
-                    seen_semicolon = true;
-                    i += 1;
-                    continue;
+                JoinOperator::CrossJoin => None,
+                JoinOperator::CrossApply | JoinOperator::OuterApply => {
+                    return Err(unsupported("join"))
                 }
-                if b == b'(' {
-                    paren_depth += 1;
-                    if expects_relation {
-                        expects_relation = false;
+            };
+            match constraint {
+                Some(JoinConstraint::On(expr)) => self.expr(expr)?,
+                Some(JoinConstraint::Using(columns)) => {
+                    for column in columns {
+                        self.column(column);
                     }
-                    i += 1;
-                    continue;
                 }
-                if b == b')' {
-                    paren_depth = (paren_depth - 1).max(0);
-                    i += 1;
-                    continue;
+                Some(JoinConstraint::Natural) | Some(JoinConstraint::None) | None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn table_factor(&mut self, factor: &TableFactor) -> Result<()> {
+        match factor {
+            TableFactor::Table {
+                name,
+                alias,
+                args,
+                with_hints,
+                version,
+                with_ordinality,
+                partitions,
+                json_path,
+            } => {
+                if args.is_some() {
+                    return Err(Error::msg("sql rejected: table function relation"));
                 }
-                if b == b',' {
-                    // Only treat commas as relation separators at top-level of a FROM clause.
-                    if in_from_clause && paren_depth == 0 {
-                        expects_relation = true;
-                    }
-                    i += 1;
-                    continue;
+                if !with_hints.is_empty()
+                    || version.is_some()
+                    || *with_ordinality
+                    || !partitions.is_empty()
+                    || json_path.is_some()
+                {
+                    return Err(unsupported("relation"));
                 }
-                if b == b'\'' {
-                    state = State::SingleQuote;
-                    string_literal.clear();
-                    i += 1;
-                    continue;
+                let [ident] = name.0.as_slice() else {
+                    return Err(Error::msg("sql rejected: qualified relation"));
+                };
+                // DuckDB reads `FROM 'file.csv'` as a file scan; the parser keeps it as a
+                // single-quoted table name.
+                if !matches!(ident.quote_style, None | Some('"')) {
+                    return Err(Error::msg("sql rejected: string literal relation"));
                 }
-                if b == b'"' {
-                    state = State::DoubleQuote;
-                    quoted_ident.clear();
-                    if expects_relation {
-                        expects_relation = false;
+                let relation = lower(ident);
+
+                let base = if self.ctes.contains(&relation) {
+                    None
+                } else {
+                    if let Some(allowed) = self.allowed_relations {
+                        if !allowed.iter().any(|r| r.eq_ignore_ascii_case(&relation)) {
+                            return Err(Error::msg("sql rejected: unknown relation"));
+                        }
                     }
-                    i += 1;
-                    continue;
+                    self.out.relations.insert(relation.clone());
+                    Some(relation.clone())
+                };
+                let scan = base.as_ref().map(|relation| {
+                    self.out.scans.push(RelationScan {
+                        relation: relation.clone(),
+                        bounds: BTreeMap::new(),
+                    });
+                    self.out.scans.len() - 1
+                });
+                let name = alias.as_ref().map_or(relation, |a| lower(&a.name));
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push(Binding {
+                        name,
+                        relation: base,
+                        scan,
+                    });
                 }
+                Ok(())
+            }
+            TableFactor::Derived {
+                subquery, alias, ..
+            } => {
+                self.query(subquery)?;
+                self.bind_alias(alias);
+                Ok(())
+            }
+            TableFactor::NestedJoin {
+                table_with_joins,
+                alias,
+            } => {
+                self.table_with_joins(table_with_joins)?;
+                self.bind_alias(alias);
+                Ok(())
+            }
+            TableFactor::TableFunction { .. } | TableFactor::Function { .. } => {
+                Err(Error::msg("sql rejected: table function relation"))
+            }
+            _ => Err(unsupported("relation")),
+        }
+    }
 
-                if b.is_ascii_alphabetic() || b == b'_' {
-                    let start = i;
-                    i += 1;
-                    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_')
-                    {
-                        i += 1;
-                    }
+    fn bind_alias(&mut self, alias: &Option<TableAlias>) {
+        if let (Some(alias), Some(scope)) = (alias, self.scopes.last_mut()) {
+            scope.push(Binding {
+                name: lower(&alias.name),
+                relation: None,
+                scan: None,
+            });
+        }
+    }
 
-                    let token = match std::str::from_utf8(&bytes[start..i]) {
-                        Ok(s) => s,
-                        Err(_) => return Err(Error::msg("sql rejected: invalid utf-8")),
+    /// Record the integer bounds `selection` places on the scans of the innermost `FROM` scope.
+    fn bound_scans(&mut self, selection: &Expr) {
+        let mut conjuncts = vec![selection];
+        while let Some(expr) = conjuncts.pop() {
+            match expr {
+                Expr::BinaryOp {
+                    left,
+                    op: BinaryOperator::And,
+                    right,
+                } => {
+                    conjuncts.push(left);
+                    conjuncts.push(right);
+                }
+                Expr::Nested(inner) => conjuncts.push(inner),
+                _ => {
+                    let Some((column, bounds)) = comparison_bounds(expr) else {
+                        continue;
                     };
-                    let token_upper = token.to_ascii_uppercase();
+                    let Some((scan, name)) = self.scope_column(column) else {
+                        continue;
+                    };
+                    self.out.scans[scan]
+                        .bounds
+                        .entry(name)
+                        .or_default()
+                        .intersect(bounds);
+                }
+            }
+        }
+    }
 
-                    if first_keyword.is_none() {
-                        first_keyword = Some(token_upper.clone());
-                    }
+    /// Resolve a column expression to a scan bound in the innermost `FROM` scope.
+    fn scope_column(&self, column: &Expr) -> Option<(usize, String)> {
+        let scope = self.scopes.last()?;
+        match column {
+            Expr::Identifier(column) => match scope.as_slice() {
+                [only] => Some((only.scan?, lower(column))),
+                _ => None,
+            },
+            Expr::CompoundIdentifier(idents) => match idents.as_slice() {
+                [qualifier, column] => {
+                    let qualifier = lower(qualifier);
+                    let binding = scope.iter().find(|b| b.name == qualifier)?;
+                    Some((binding.scan?, lower(column)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 
-                    // Track top-level FROM clause state so we can reject
-                    // non-standard `FROM 'file.csv'`.
-                    if paren_depth == 0 {
-                        match token_upper.as_str() {
-                            "FROM" => {
-                                in_from_clause = true;
-                                expects_relation = true;
-                            }
-                            "JOIN" => {
-                                if in_from_clause {
-                                    expects_relation = true;
-                                }
-                            }
-                            // Heuristic: these keywords end the FROM clause in common SQL dialects.
-                            "WHERE" | "GROUP" | "HAVING" | "QUALIFY" | "WINDOW" | "ORDER"
-                            | "LIMIT" => {
-                                in_from_clause = false;
-                                expects_relation = false;
-                            }
-                            _ => {
-                                if expects_relation {
-                                    expects_relation = false;
-                                }
-                            }
-                        }
-                    }
+    fn lookup(&self, qualifier: &str) -> Option<&Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .find(|b| b.name == qualifier)
+    }
 
-                    if FORBIDDEN_KEYWORDS
-                        .iter()
-                        .any(|kw| kw.eq_ignore_ascii_case(&token_upper))
-                    {
-                        return Err(Error::msg("sql rejected: forbidden keyword"));
-                    }
+    /// Record an unqualified column, attributing it to the innermost scope's only relation.
+    fn column(&mut self, column: &Ident) {
+        let relation = match self.scopes.last().map(Vec::as_slice) {
+            Some([only]) => only.relation.clone(),
+            _ => None,
+        };
+        self.out.columns.insert(ColumnRef {
+            relation,
+            column: lower(column),
+        });
+    }
 
-                    // Deny known unsafe function call sites (e.g. read_csv(...)).
-                    if FORBIDDEN_FUNCTIONS
-                        .iter()
-                        .any(|f| f.eq_ignore_ascii_case(&token_upper))
-                        && looks_like_function_call(bytes, i)
-                    {
-                        return Err(Error::msg("sql rejected: forbidden function"));
+    fn compound_identifier(&mut self, idents: &[Ident]) {
+        match idents {
+            [] => {}
+            [column] => self.column(column),
+            [qualifier, column, ..] => {
+                let relation = self.lookup(&lower(qualifier)).map(|b| b.relation.clone());
+                match relation {
+                    Some(relation) => {
+                        self.out.columns.insert(ColumnRef {
+                            relation,
+                            column: lower(column),
+                        });
                     }
-
-                    continue;
+                    // Not a relation name: struct field access on a column.
+                    None => self.column(qualifier),
                 }
-
-                // Other characters are ignored for validation purposes.
-                i += 1;
             }
-            State::SingleQuote => {
-                if b == b'\'' {
-                    if bytes.get(i + 1) == Some(&b'\'') {
-                        if string_literal.len() < MAX_STRING_LITERAL_BYTES {
-                            string_literal.push('\'');
-                        } else {
-                            return Err(Error::msg("sql rejected: string literal too long"));
-                        }
-                        i += 2;
-                        continue;
-                    }
+        }
+    }
 
-                    if in_from_clause && expects_relation {
-                        // Reject non-standard table factor syntax like:
-                        //   SELECT * FROM 'file.csv'
-                        return Err(Error::msg("sql rejected: string literal relation"));
-                    }
+    fn wildcard(&mut self) {
+        let relations: Vec<Option<String>> = self
+            .scopes
+            .last()
+            .map(|scope| scope.iter().map(|b| b.relation.clone()).collect())
+            .unwrap_or_default();
+        if relations.is_empty() {
+            self.out.columns.insert(ColumnRef {
+                relation: None,
+                column: "*".to_string(),
+            });
+        }
+        for relation in relations {
+            self.out.columns.insert(ColumnRef {
+                relation,
+                column: "*".to_string(),
+            });
+        }
+    }
 
-                    state = State::Normal;
-                    i += 1;
-                    continue;
+    fn qualified_wildcard(&mut self, name: &ObjectName) {
+        let relation = name
+            .0
+            .last()
+            .and_then(|q| self.lookup(&lower(q)))
+            .and_then(|b| b.relation.clone());
+        self.out.columns.insert(ColumnRef {
+            relation,
+            column: "*".to_string(),
+        });
+    }
+
+    fn order_by(&mut self, order_by: &OrderBy) -> Result<()> {
+        if order_by.interpolate.is_some() {
+            return Err(unsupported("clause"));
+        }
+        self.order_by_exprs(&order_by.exprs)
+    }
+
+    fn order_by_exprs(&mut self, exprs: &[OrderByExpr]) -> Result<()> {
+        for order in exprs {
+            if order.with_fill.is_some() {
+                return Err(unsupported("clause"));
+            }
+            self.expr(&order.expr)?;
+        }
+        Ok(())
+    }
+
+    fn window_spec(&mut self, spec: &WindowSpec) -> Result<()> {
+        self.exprs(&spec.partition_by)?;
+        self.order_by_exprs(&spec.order_by)?;
+        if let Some(frame) = &spec.window_frame {
+            for bound in std::iter::once(&frame.start_bound).chain(frame.end_bound.as_ref()) {
+                match bound {
+                    WindowFrameBound::Preceding(Some(expr))
+                    | WindowFrameBound::Following(Some(expr)) => self.expr(expr)?,
+                    _ => {}
                 }
+            }
+        }
+        Ok(())
+    }
 
-                if string_literal.len() < MAX_STRING_LITERAL_BYTES {
-                    string_literal.push(b as char);
-                } else {
-                    return Err(Error::msg("sql rejected: string literal too long"));
+    fn function(&mut self, function: &Function) -> Result<()> {
+        let allowed = match function.name.0.as_slice() {
+            [name] => ALLOWED_FUNCTIONS.contains(&lower(name).as_str()),
+            _ => false,
+        };
+        if !allowed {
+            return Err(Error::msg("sql rejected: function not allowed"));
+        }
+        if !matches!(function.parameters, FunctionArguments::None) {
+            return Err(unsupported("expression"));
+        }
+
+        match &function.args {
+            FunctionArguments::None => {}
+            FunctionArguments::Subquery(query) => self.query(query)?,
+            FunctionArguments::List(list) => {
+                for arg in &list.args {
+                    let arg = match arg {
+                        FunctionArg::Named { arg, .. }
+                        | FunctionArg::ExprNamed { arg, .. }
+                        | FunctionArg::Unnamed(arg) => arg,
+                    };
+                    match arg {
+                        FunctionArgExpr::Expr(expr) => self.expr(expr)?,
+                        FunctionArgExpr::QualifiedWildcard(name) => self.qualified_wildcard(name),
+                        // `count(*)` reads no column values.
+                        FunctionArgExpr::Wildcard => {}
+                    }
                 }
-                i += 1;
-            }
-            State::DoubleQuote => {
-                if b == b'"' {
-                    if bytes.get(i + 1) == Some(&b'"') {
-                        if quoted_ident.len() < MAX_STRING_LITERAL_BYTES {
-                            quoted_ident.push('"');
-                        } else {
-                            return Err(Error::msg("sql rejected: quoted identifier too long"));
-                        }
-                        i += 2;
-                        continue;
+                for clause in &list.clauses {
+                    match clause {
+                        FunctionArgumentClause::OrderBy(exprs) => self.order_by_exprs(exprs)?,
+                        FunctionArgumentClause::Limit(expr) => self.expr(expr)?,
+                        _ => {}
                     }
+                }
+            }
+        }
 
-                    // Quoted identifiers can be used as function names in many SQL dialects.
-                    // Treat these as potential function call sites.
-                    let ident_upper = quoted_ident.to_ascii_uppercase();
-                    if FORBIDDEN_FUNCTIONS
-                        .iter()
-                        .any(|f| f.eq_ignore_ascii_case(&ident_upper))
-                        && looks_like_function_call(bytes, i + 1)
-                    {
-                        return Err(Error::msg("sql rejected: forbidden function"));
-                    }
+        if let Some(filter) = &function.filter {
+            self.expr(filter)?;
+        }
+        if let Some(WindowType::WindowSpec(spec)) = &function.over {
+            self.window_spec(spec)?;
+        }
+        self.order_by_exprs(&function.within_group)
+    }
 
-                    state = State::Normal;
-                    i += 1;
-                    continue;
-                }
+    fn exprs(&mut self, exprs: &[Expr]) -> Result<()> {
+        for expr in exprs {
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
 
-                if quoted_ident.len() < MAX_STRING_LITERAL_BYTES {
-                    quoted_ident.push(b as char);
-                } else {
-                    return Err(Error::msg("sql rejected: quoted identifier too long"));
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Identifier(ident) => {
+                self.column(ident);
+                Ok(())
+            }
+            Expr::CompoundIdentifier(idents) => {
+                self.compound_identifier(idents);
+                Ok(())
+            }
+            Expr::Wildcard(_) => {
+                self.wildcard();
+                Ok(())
+            }
+            Expr::QualifiedWildcard(name, _) => {
+                self.qualified_wildcard(name);
+                Ok(())
+            }
+            Expr::Value(value) => check_value(value),
+            Expr::TypedString { value, .. } => check_literal(value),
+            Expr::Function(function) => self.function(function),
+
+            Expr::IsFalse(e)
+            | Expr::IsNotFalse(e)
+            | Expr::IsTrue(e)
+            | Expr::IsNotTrue(e)
+            | Expr::IsNull(e)
+            | Expr::IsNotNull(e)
+            | Expr::IsUnknown(e)
+            | Expr::IsNotUnknown(e)
+            | Expr::Nested(e)
+            | Expr::UnaryOp { expr: e, .. }
+            | Expr::Cast { expr: e, .. }
+            | Expr::Extract { expr: e, .. }
+            | Expr::Ceil { expr: e, .. }
+            | Expr::Floor { expr: e, .. }
+            | Expr::Collate { expr: e, .. }
+            | Expr::CompositeAccess { expr: e, .. }
+            | Expr::Named { expr: e, .. } => self.expr(e),
+
+            Expr::IsDistinctFrom(a, b)
+            | Expr::IsNotDistinctFrom(a, b)
+            | Expr::BinaryOp {
+                left: a, right: b, ..
+            }
+            | Expr::AnyOp {
+                left: a, right: b, ..
+            }
+            | Expr::AllOp {
+                left: a, right: b, ..
+            }
+            | Expr::AtTimeZone {
+                timestamp: a,
+                time_zone: b,
+            }
+            | Expr::Position { expr: a, r#in: b }
+            | Expr::InUnnest {
+                expr: a,
+                array_expr: b,
+                ..
+            }
+            | Expr::Like {
+                expr: a,
+                pattern: b,
+                ..
+            }
+            | Expr::ILike {
+                expr: a,
+                pattern: b,
+                ..
+            }
+            | Expr::SimilarTo {
+                expr: a,
+                pattern: b,
+                ..
+            }
+            | Expr::RLike {
+                expr: a,
+                pattern: b,
+                ..
+            } => {
+                self.expr(a)?;
+                self.expr(b)
+            }
+
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                self.expr(expr)?;
+                self.expr(low)?;
+                self.expr(high)
+            }
+            Expr::InList { expr, list, .. } => {
+                self.expr(expr)?;
+                self.exprs(list)
+            }
+            Expr::InSubquery { expr, subquery, .. } => {
+                self.expr(expr)?;
+                self.query(subquery)
+            }
+            Expr::Exists { subquery, .. } | Expr::Subquery(subquery) => self.query(subquery),
+            Expr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+                ..
+            } => {
+                self.expr(expr)?;
+                for e in [substring_from, substring_for].into_iter().flatten() {
+                    self.expr(e)?;
                 }
-                i += 1;
+                Ok(())
             }
-            State::LineComment => {
-                if b == b'\n' {
-                    state = State::Normal;
+            Expr::Trim {
+                expr,
+                trim_what,
+                trim_characters,
+                ..
+            } => {
+                self.expr(expr)?;
+                if let Some(what) = trim_what {
+                    self.expr(what)?;
+                }
+                if let Some(characters) = trim_characters {
+                    self.exprs(characters)?;
                 }
-                i += 1;
+                Ok(())
             }
-            State::BlockComment => {
-                if b == b'*' && bytes.get(i + 1) == Some(&b'/') {
-                    state = State::Normal;
-                    i += 2;
-                    continue;
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                if let Some(operand) = operand {
+                    self.expr(operand)?;
+                }
+                self.exprs(conditions)?;
+                self.exprs(results)?;
+                if let Some(else_result) = else_result {
+                    self.expr(else_result)?;
+                }
+                Ok(())
+            }
+            Expr::GroupingSets(sets) | Expr::Cube(sets) | Expr::Rollup(sets) => {
+                for set in sets {
+                    self.exprs(set)?;
+                }
+                Ok(())
+            }
+            Expr::Tuple(exprs) | Expr::Struct { values: exprs, .. } => self.exprs(exprs),
+            Expr::Array(array) => self.exprs(&array.elem),
+            Expr::Dictionary(fields) => {
+                for field in fields {
+                    self.expr(&field.value)?;
                 }
-                i += 1;
+                Ok(())
             }
+            Expr::Map(map) => {
+                for entry in &map.entries {
+                    self.expr(&entry.key)?;
+                    self.expr(&entry.value)?;
+                }
+                Ok(())
+            }
+            Expr::Interval(interval) => self.expr(&interval.value),
+            Expr::MapAccess { column, keys } => {
+                self.expr(column)?;
+                for key in keys {
+                    self.expr(&key.key)?;
+                }
+                Ok(())
+            }
+            Expr::Subscript { expr, subscript } => {
+                self.expr(expr)?;
+                match subscript.as_ref() {
+                    Subscript::Index { index } => self.expr(index),
+                    Subscript::Slice {
+                        lower_bound,
+                        upper_bound,
+                        stride,
+                    } => {
+                        for e in [lower_bound, upper_bound, stride].into_iter().flatten() {
+                            self.expr(e)?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Expr::JsonAccess { value, path } => {
+                self.expr(value)?;
+                for elem in &path.path {
+                    if let JsonPathElem::Bracket { key } = elem {
+                        self.expr(key)?;
+                    }
+                }
+                Ok(())
+            }
+
+            // Lambdas, method calls, and dialect-specific forms are not needed for dataset queries.
+            _ => Err(unsupported("expression")),
         }
     }
+}
 
-    match state {
-        State::Normal | State::LineComment => {}
-        State::SingleQuote => return Err(Error::msg("sql rejected: unterminated string literal")),
-        State::DoubleQuote => {
-            return Err(Error::msg("sql rejected: unterminated quoted identifier"));
+/// The column and bounds of a `column <op> literal` comparison (either way around).
+fn comparison_bounds(expr: &Expr) -> Option<(&Expr, IntBounds)> {
+    let bounds = |min, max| IntBounds { min, max };
+    match expr {
+        Expr::BinaryOp { left, op, right } => {
+            let (column, op, value) = match (int_literal(left), int_literal(right)) {
+                (None, Some(value)) => (left.as_ref(), op.clone(), value),
+                (Some(value), None) => {
+                    let flipped = match op {
+                        BinaryOperator::Lt => BinaryOperator::Gt,
+                        BinaryOperator::LtEq => BinaryOperator::GtEq,
+                        BinaryOperator::Gt => BinaryOperator::Lt,
+                        BinaryOperator::GtEq => BinaryOperator::LtEq,
+                        other => other.clone(),
+                    };
+                    (right.as_ref(), flipped, value)
+                }
+                _ => return None,
+            };
+            let range = match op {
+                BinaryOperator::Eq => bounds(Some(value), Some(value)),
+                BinaryOperator::Lt => bounds(None, Some(value.checked_sub(1)?)),
+                BinaryOperator::LtEq => bounds(None, Some(value)),
+                BinaryOperator::Gt => bounds(Some(value.checked_add(1)?), None),
+                BinaryOperator::GtEq => bounds(Some(value), None),
+                _ => return None,
+            };
+            Some((column, range))
         }
-        State::BlockComment => return Err(Error::msg("sql rejected: unterminated block comment")),
+        Expr::Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        } => Some((
+            expr,
+            bounds(Some(int_literal(low)?), Some(int_literal(high)?)),
+        )),
+        Expr::InList {
+            expr,
+            list,
+            negated: false,
+        } => {
+            let values = list.iter().map(int_literal).collect::<Option<Vec<_>>>()?;
+            Some((
+                expr,
+                bounds(values.iter().min().copied(), values.iter().max().copied()),
+            ))
+        }
+        _ => None,
     }
+}
 
-    let first = first_keyword.ok_or_else(|| Error::msg("sql rejected: no statement"))?;
-    if first != "SELECT" && first != "WITH" {
-        return Err(Error::msg("sql rejected: only SELECT allowed"));
+fn int_literal(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Value(Value::Number(n, _)) => n.parse().ok(),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => int_literal(expr)?.checked_neg(),
+        Expr::UnaryOp {
+            op: UnaryOperator::Plus,
+            expr,
+        } => int_literal(expr),
+        Expr::Nested(expr) => int_literal(expr),
+        _ => None,
     }
-
-    Ok(())
 }
 
-fn looks_like_function_call(bytes: &[u8], mut i: usize) -> bool {
-    // Skip whitespace and comments to find the next significant token.
-    while i < bytes.len() {
-        let b = bytes[i];
-
-        if b.is_ascii_whitespace() {
-            i += 1;
-            continue;
-        }
-        if b == b'-' && bytes.get(i + 1) == Some(&b'-') {
-            // Line comment
-            i += 2;
-            while i < bytes.len() && bytes[i] != b'\n' {
-                i += 1;
-            }
-            continue;
-        }
-        if b == b'/' && bytes.get(i + 1) == Some(&b'*') {
-            // Block comment
-            i += 2;
-            while i + 1 < bytes.len() {
-                if bytes[i] == b'*' && bytes[i + 1] == b'/' {
-                    i += 2;
-                    break;
-                }
-                i += 1;
-            }
-            continue;
-        }
-
-        return b == b'(';
+fn check_value(value: &Value) -> Result<()> {
+    match value {
+        Value::Placeholder(_) => Err(unsupported("expression")),
+        Value::Number(..) | Value::Boolean(_) | Value::Null => Ok(()),
+        other => check_literal(&other.to_string()),
     }
+}
 
-    false
+fn check_literal(value: &str) -> Result<()> {
+    if value.len() > MAX_STRING_LITERAL_BYTES {
+        return Err(Error::msg("sql rejected: string literal too long"));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::validate_sql;
+    use super::{sql_fingerprint, validate_sql, validate_sql_relations, ColumnRef, IntBounds};
 
     fn assert_rejected(sql: &str) {
         assert!(validate_sql(sql).is_err(), "expected rejection: {sql}");
@@ -420,4 +1213,225 @@ mod tests {
         assert_rejected("SELECT * FROM t, 'local.csv'");
         assert_rejected("SELECT * FROM t JOIN 'local.csv' ON 1=1");
     }
+
+    #[test]
+    fn rejects_nested_string_literal_relations() {
+        assert_rejected("SELECT * FROM (SELECT * FROM 'local.csv') t");
+        assert_rejected("SELECT * FROM t WHERE x IN (SELECT x FROM 'local.csv')");
+    }
+
+    fn assert_relations_rejected(sql: &str, allowed: &[&str]) {
+        assert!(
+            validate_sql_relations(sql, allowed).is_err(),
+            "expected rejection: {sql}"
+        );
+    }
+
+    #[test]
+    fn allows_only_listed_relations() {
+        let allowed = ["logs", "txs", "labels"];
+        validate_sql_relations("SELECT * FROM logs", &allowed).unwrap();
+        validate_sql_relations("SELECT * FROM LOGS l", &allowed).unwrap();
+        validate_sql_relations("SELECT * FROM \"logs\"", &allowed).unwrap();
+        validate_sql_relations(
+            "SELECT l.*, t.hash FROM logs l JOIN txs AS t ON l.tx = t.hash \
+             LEFT JOIN labels b USING (address)",
+            &allowed,
+        )
+        .unwrap();
+        validate_sql_relations("SELECT * FROM logs, txs WHERE logs.tx = txs.hash", &allowed)
+            .unwrap();
+        validate_sql_relations(
+            "SELECT * FROM (SELECT * FROM logs) s WHERE s.tx IN (SELECT hash FROM txs)",
+            &allowed,
+        )
+        .unwrap();
+        validate_sql_relations(
+            "WITH a AS (SELECT * FROM logs), \"b\" AS (SELECT * FROM a) SELECT * FROM b",
+            &allowed,
+        )
+        .unwrap();
+        validate_sql_relations(
+            "SELECT x FROM logs UNION ALL SELECT y FROM txs ORDER BY 1",
+            &allowed,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rejects_unlisted_relations() {
+        let allowed = ["dataset"];
+        assert_relations_rejected("SELECT * FROM other", &allowed);
+        assert_relations_rejected("SELECT * FROM dataset JOIN other ON true", &allowed);
+        assert_relations_rejected("SELECT * FROM dataset, other", &allowed);
+        assert_relations_rejected("SELECT * FROM (SELECT * FROM other) o", &allowed);
+        assert_relations_rejected("SELECT * FROM dataset UNION SELECT * FROM other", &allowed);
+        assert_relations_rejected("SELECT * FROM \"other\"", &allowed);
+    }
+
+    #[test]
+    fn rejects_catalog_and_table_function_relations() {
+        let allowed = ["dataset"];
+        assert_relations_rejected("SELECT * FROM information_schema.tables", &allowed);
+        assert_relations_rejected("SELECT * FROM main.dataset", &allowed);
+        assert_relations_rejected("SELECT * FROM duckdb_settings()", &allowed);
+        assert_relations_rejected("SELECT * FROM dataset (x)", &allowed);
+        assert_relations_rejected("SELECT * FROM range(10)", &allowed);
+    }
+
+    #[test]
+    fn rejects_functions_outside_allowlist() {
+        assert_rejected("SELECT current_setting('s3_secret_access_key')");
+        assert_rejected("SELECT glob('/etc/*')");
+        assert_rejected("SELECT main.lower('x')");
+        assert_rejected("SELECT list_transform([1, 2], x -> getenv('HOME'))");
+        assert_rejected("SELECT count(*) FILTER (WHERE read_text('x') = '') FROM t");
+        assert_rejected("SELECT 1 INTO t");
+        assert_rejected("SELECT * FROM t FOR UPDATE");
+
+        validate_sql(
+            "SELECT date_trunc('day', ts), count(*), sum(value) FILTER (WHERE ok), \
+             row_number() OVER (PARTITION BY a ORDER BY ts) FROM t GROUP BY 1 HAVING count(*) > 1",
+        )
+        .unwrap();
+        validate_sql("SELECT CAST(x AS BIGINT), coalesce(y, 0), CASE WHEN z THEN 1 END FROM t")
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_relations_and_columns() {
+        let allowed = ["logs", "txs"];
+        let validated = validate_sql_relations(
+            "WITH recent AS (SELECT * FROM txs WHERE block_number > 10) \
+             SELECT l.address, r.hash, count(*) FROM logs l JOIN recent r ON l.tx = r.hash \
+             WHERE topic0 IS NOT NULL GROUP BY 1, 2",
+            &allowed,
+        )
+        .unwrap();
+
+        assert_eq!(
+            validated.relations.iter().collect::<Vec<_>>(),
+            vec!["logs", "txs"]
+        );
+
+        let col = |relation: Option<&str>, column: &str| ColumnRef {
+            relation: relation.map(str::to_string),
+            column: column.to_string(),
+        };
+        for expected in [
+            col(Some("txs"), "*"),
+            col(Some("txs"), "block_number"),
+            col(Some("logs"), "address"),
+            col(Some("logs"), "tx"),
+            col(None, "hash"),
+            col(None, "topic0"),
+        ] {
+            assert!(
+                validated.columns.contains(&expected),
+                "missing {expected:?} in {:?}",
+                validated.columns
+            );
+        }
+    }
+
+    #[test]
+    fn reports_integer_bounds_per_scan() {
+        let bounds = |sql: &str, relation: &str| {
+            validate_sql_relations(sql, &["blocks", "logs"])
+                .unwrap()
+                .column_bounds(relation, "block_number")
+        };
+        let range = |min, max| Some(IntBounds { min, max });
+
+        assert_eq!(
+            bounds(
+                "SELECT * FROM blocks WHERE block_number >= 100 AND block_number < 200",
+                "blocks"
+            ),
+            range(Some(100), Some(199))
+        );
+        assert_eq!(
+            bounds(
+                "SELECT * FROM blocks b WHERE (b.block_number BETWEEN 5 AND 9) AND miner = 'x'",
+                "blocks"
+            ),
+            range(Some(5), Some(9))
+        );
+        assert_eq!(
+            bounds(
+                "SELECT * FROM blocks WHERE 10 > block_number AND block_number IN (-3, 4)",
+                "blocks"
+            ),
+            range(Some(-3), Some(4))
+        );
+
+        // Every scan must be bounded; the result covers all of them.
+        assert_eq!(
+            bounds(
+                "SELECT * FROM blocks WHERE block_number < 10 \
+                 UNION ALL SELECT * FROM blocks WHERE block_number = 50",
+                "blocks"
+            ),
+            range(None, Some(50))
+        );
+        assert_eq!(
+            bounds(
+                "SELECT * FROM blocks WHERE block_number = 1 \
+                 AND hash IN (SELECT hash FROM blocks)",
+                "blocks"
+            ),
+            None
+        );
+
+        // Joins: qualified columns bound their own scan; unqualified ones are ambiguous.
+        let sql = "SELECT * FROM blocks b LEFT JOIN logs l ON l.block_number = b.block_number \
+                   WHERE b.block_number > 7 AND block_number < 9";
+        assert_eq!(bounds(sql, "blocks"), range(Some(8), None));
+        assert_eq!(bounds(sql, "logs"), None);
+
+        // Disjunctions, non-literal comparisons, and derived tables are not bounds.
+        for sql in [
+            "SELECT * FROM blocks WHERE block_number = 1 OR block_number = 2",
+            "SELECT * FROM blocks WHERE block_number + 1 = 2",
+            "SELECT * FROM blocks WHERE block_number NOT BETWEEN 1 AND 2",
+            "SELECT * FROM blocks WHERE block_number > 1.5",
+            "SELECT * FROM (SELECT * FROM blocks) t WHERE t.block_number = 1",
+            "SELECT * FROM blocks",
+        ] {
+            assert_eq!(bounds(sql, "blocks"), None, "{sql}");
+        }
+        assert_eq!(bounds("SELECT * FROM blocks", "logs"), None);
+
+        assert!(IntBounds {
+            min: Some(10),
+            max: None
+        }
+        .overlaps(0, 10));
+        assert!(!IntBounds {
+            min: None,
+            max: Some(9)
+        }
+        .overlaps(10, 20));
+    }
+
+    #[test]
+    fn fingerprint_ignores_literals_and_formatting() {
+        let fp = |sql: &str| sql_fingerprint(sql).expect("fingerprint");
+
+        let base = fp("SELECT a, count(*) FROM logs WHERE block = 10 AND addr IN ('0x1', '0x2')");
+        assert_eq!(base.len(), 64);
+        assert!(base.bytes().all(|b| b.is_ascii_hexdigit()));
+
+        assert_eq!(
+            base,
+            fp("select A, COUNT(*)\n  from logs -- note\n where block = 99 and addr in ('0x3');")
+        );
+        assert_ne!(base, fp("SELECT a, count(*) FROM logs WHERE block > 10"));
+        assert_ne!(
+            base,
+            fp("SELECT b, count(*) FROM logs WHERE block = 10 AND addr IN ('0x1')")
+        );
+        assert_ne!(fp("SELECT \"A\" FROM t"), fp("SELECT a FROM t"));
+        assert!(!base.contains("0x1"));
+    }
 }
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Time source for in-memory backends and the conformance suites.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Let `duration` pass: a real sleep, or an instant step for manual clocks.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Wall-clock time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("clock poisoned");
+        *now += chrono::Duration::from_std(duration).expect("duration out of range");
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("clock poisoned") = now;
+    }
+}
+
+impl Default for ManualClock {
+    /// Starts at 2025-01-01T00:00:00Z.
+    fn default() -> Self {
+        Self::new(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap())
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("clock poisoned")
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
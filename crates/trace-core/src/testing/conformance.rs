@@ -0,0 +1,237 @@
+//! Behavior shared by every `Queue` and `ObjectStore` backend.
+//!
+//! Each suite runs against a live backend and returns the first divergence as an error. They
+//! only touch a fresh, randomly named queue or key prefix, so they can run against shared
+//! Postgres or S3 instances.
+
+use super::Clock;
+use crate::{Error, ObjectStore, Queue, Result};
+use anyhow::ensure;
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+/// Visibility timeout and requeue delay used by [`queue`].
+const QUEUE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Extra time past a deadline before expecting a message back (absorbs wall-clock jitter).
+const QUEUE_MARGIN: Duration = Duration::from_millis(250);
+
+/// Check visibility timeouts, delivery counts, delayed availability, requeue, ack and ordering.
+///
+/// `clock` must be the time source `queue` schedules against: a `ManualClock` for in-memory
+/// queues, `SystemClock` for Postgres-backed ones.
+pub async fn queue(queue: &dyn Queue, clock: &dyn Clock) -> Result<()> {
+    queue_inner(queue, clock).await.map_err(Error::from)
+}
+
+async fn queue_inner(queue: &dyn Queue, clock: &dyn Clock) -> anyhow::Result<()> {
+    let name = format!("conformance-{}", Uuid::new_v4());
+    let other = format!("{name}-other");
+    let receive = |max: i64| queue.receive(&name, max, QUEUE_TIMEOUT);
+    let past = |secs: i64| clock.now() - chrono::Duration::seconds(secs);
+
+    ensure!(receive(10).await?.is_empty(), "new queue is not empty");
+
+    // Ordered by `available_at`, not publish order.
+    queue.publish(&name, json!({"n": 2}), past(1)).await?;
+    queue.publish(&name, json!({"n": 1}), past(2)).await?;
+    queue.publish(&other, json!({"n": 0}), past(3)).await?;
+
+    let first = receive(1).await?;
+    ensure!(first.len() == 1, "receive(max=1) returned {}", first.len());
+    let first = &first[0];
+    ensure!(
+        first.payload == json!({"n": 1}),
+        "out of order: {}",
+        first.payload
+    );
+    ensure!(first.queue_name == name, "queue_name={}", first.queue_name);
+    ensure!(first.deliveries == 1, "deliveries={}", first.deliveries);
+
+    // The received message stays hidden; the other one is still available.
+    let second = receive(10).await?;
+    ensure!(second.len() == 1, "in-flight message was redelivered");
+    ensure!(
+        second[0].payload == json!({"n": 2}),
+        "payload={}",
+        second[0].payload
+    );
+    queue.ack(&second[0].ack_token).await?;
+
+    // Unacked messages come back after the visibility timeout with a higher delivery count.
+    clock.sleep(QUEUE_TIMEOUT + QUEUE_MARGIN).await;
+    let redelivered = receive(10).await?;
+    ensure!(
+        redelivered.len() == 1,
+        "expected one redelivery after visibility timeout, got {}",
+        redelivered.len()
+    );
+    ensure!(
+        redelivered[0].payload == json!({"n": 1}),
+        "acked message was redelivered"
+    );
+    ensure!(
+        redelivered[0].deliveries == 2,
+        "deliveries={}",
+        redelivered[0].deliveries
+    );
+
+    // Requeued messages wait out the delay, then are delivered again.
+    queue
+        .nack_or_requeue(&redelivered[0].ack_token, QUEUE_TIMEOUT)
+        .await?;
+    ensure!(
+        receive(10).await?.is_empty(),
+        "requeued message delivered before its delay"
+    );
+    clock.sleep(QUEUE_TIMEOUT + QUEUE_MARGIN).await;
+    let requeued = receive(10).await?;
+    ensure!(requeued.len() == 1, "requeued message not delivered");
+    ensure!(
+        requeued[0].deliveries == 3,
+        "deliveries={}",
+        requeued[0].deliveries
+    );
+
+    // Acks are idempotent and final.
+    queue.ack(&requeued[0].ack_token).await?;
+    queue.ack(&requeued[0].ack_token).await?;
+    clock.sleep(QUEUE_TIMEOUT + QUEUE_MARGIN).await;
+    ensure!(
+        receive(10).await?.is_empty(),
+        "acked message was redelivered"
+    );
+
+    // Delayed publishes are not receivable before `available_at`.
+    let available_at = clock.now() + chrono::Duration::from_std(QUEUE_TIMEOUT)?;
+    queue.publish(&name, json!({"n": 3}), available_at).await?;
+    ensure!(
+        receive(10).await?.is_empty(),
+        "delayed message delivered early"
+    );
+    clock.sleep(QUEUE_TIMEOUT + QUEUE_MARGIN).await;
+    let delayed = receive(10).await?;
+    ensure!(delayed.len() == 1, "delayed message not delivered");
+    ensure!(
+        delayed[0].deliveries == 1,
+        "deliveries={}",
+        delayed[0].deliveries
+    );
+    queue.ack(&delayed[0].ack_token).await?;
+
+    // Queues are isolated by name.
+    let others = queue.receive(&other, 10, QUEUE_TIMEOUT).await?;
+    ensure!(
+        others.len() == 1 && others[0].payload == json!({"n": 0}),
+        "message leaked between queues"
+    );
+    queue.ack(&others[0].ack_token).await?;
+
+    Ok(())
+}
+
+/// Check put/get round trips, ranges, streaming, metadata, listing, overwrite and delete.
+pub async fn object_store(store: &dyn ObjectStore, bucket: &str) -> Result<()> {
+    object_store_inner(store, bucket).await.map_err(Error::from)
+}
+
+async fn object_store_inner(store: &dyn ObjectStore, bucket: &str) -> anyhow::Result<()> {
+    let prefix = format!("conformance/{}/", Uuid::new_v4());
+    let key = |leaf: &str| format!("{prefix}{leaf}");
+    let listed = |objects: Vec<crate::ObjectMeta>| {
+        objects
+            .into_iter()
+            .map(|o| (o.key, o.size))
+            .collect::<Vec<_>>()
+    };
+
+    ensure!(
+        store.head(bucket, &key("a")).await?.is_none(),
+        "head of missing object returned metadata"
+    );
+    ensure!(
+        store.get_bytes(bucket, &key("a")).await.is_err(),
+        "get of missing object succeeded"
+    );
+    ensure!(
+        store.list_prefix(bucket, &prefix).await?.is_empty(),
+        "fresh prefix is not empty"
+    );
+
+    store
+        .put_bytes(bucket, &key("a"), b"hello world".to_vec(), "text/plain")
+        .await?;
+    ensure!(store.get_bytes(bucket, &key("a")).await? == b"hello world");
+    ensure!(
+        store.get_range(bucket, &key("a"), 0..5).await? == b"hello",
+        "range 0..5"
+    );
+    ensure!(
+        store.get_range(bucket, &key("a"), 6..100).await? == b"world",
+        "range past the end is not truncated"
+    );
+    ensure!(
+        store
+            .get_range(bucket, &key("a"), 100..200)
+            .await?
+            .is_empty(),
+        "range starting past the end is not empty"
+    );
+    let mut streamed = Vec::new();
+    store
+        .get(bucket, &key("a"))
+        .await?
+        .read_to_end(&mut streamed)
+        .await?;
+    ensure!(streamed == b"hello world", "streamed body differs");
+    let head = store.head(bucket, &key("a")).await?;
+    ensure!(
+        head.as_ref().map(|m| (m.key.as_str(), m.size)) == Some((key("a").as_str(), 11)),
+        "head={head:?}"
+    );
+
+    store
+        .put_bytes(bucket, &key("a"), b"bye".to_vec(), "text/plain")
+        .await?;
+    ensure!(
+        store.get_bytes(bucket, &key("a")).await? == b"bye",
+        "overwrite not visible"
+    );
+
+    let local = std::env::temp_dir().join(format!("trace-conformance-{}", Uuid::new_v4()));
+    tokio::fs::write(&local, b"from file").await?;
+    let put = store
+        .put_file(bucket, &key("b"), &local, "application/octet-stream")
+        .await;
+    let _ = tokio::fs::remove_file(&local).await;
+    put?;
+    ensure!(store.get_bytes(bucket, &key("b")).await? == b"from file");
+
+    store
+        .put_bytes(bucket, &key("sub/c"), b"c".to_vec(), "text/plain")
+        .await?;
+    let all = listed(store.list_prefix(bucket, &prefix).await?);
+    ensure!(
+        all == [(key("a"), 3), (key("b"), 9), (key("sub/c"), 1)],
+        "list_prefix={all:?}"
+    );
+    let sub = listed(store.list_prefix(bucket, &key("sub/")).await?);
+    ensure!(sub == [(key("sub/c"), 1)], "nested list_prefix={sub:?}");
+
+    store.delete(bucket, &key("a")).await?;
+    store.delete(bucket, &key("a")).await?;
+    ensure!(
+        store.head(bucket, &key("a")).await?.is_none(),
+        "deleted object still present"
+    );
+    let remaining = listed(store.list_prefix(bucket, &prefix).await?);
+    ensure!(
+        remaining == [(key("b"), 9), (key("sub/c"), 1)],
+        "list_prefix after delete={remaining:?}"
+    );
+
+    store.delete(bucket, &key("b")).await?;
+    store.delete(bucket, &key("sub/c")).await?;
+    Ok(())
+}
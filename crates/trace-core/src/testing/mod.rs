@@ -0,0 +1,18 @@
+//! In-memory implementations of `trace-core` interfaces for hermetic tests.
+//!
+//! Enabled with the `testing` feature. [`MemoryQueue`] follows `PgQueue` semantics (visibility
+//! timeouts, delivery counts, delayed availability) against a [`Clock`], so tests can step time
+//! with [`ManualClock`] instead of sleeping. [`MemoryObjectStore`] can inject put failures and
+//! latency, and [`TestSigner`] issues deterministic capability tokens. The [`conformance`] suites
+//! pin down the behavior every `Queue` and `ObjectStore` backend must share.
+
+mod clock;
+pub mod conformance;
+mod object_store;
+mod queue;
+mod signer;
+
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use object_store::MemoryObjectStore;
+pub use queue::MemoryQueue;
+pub use signer::TestSigner;
@@ -0,0 +1,237 @@
+use crate::{Error, ObjectMeta, ObjectReader, ObjectStore, Result};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// In-memory object store with fault injection.
+///
+/// [`MemoryObjectStore::fail_nth_put`] makes a later `put_bytes` / `put_file` call fail without
+/// storing anything, and [`MemoryObjectStore::set_latency`] delays every operation.
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    objects: Mutex<BTreeMap<(String, String), StoredObject>>,
+    faults: Mutex<Faults>,
+}
+
+struct StoredObject {
+    bytes: Vec<u8>,
+    last_modified: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Faults {
+    puts: usize,
+    fail_puts: Vec<usize>,
+    latency: Duration,
+}
+
+impl MemoryObjectStore {
+    /// Fail the `n`th put (1-based) counted from now.
+    pub fn fail_nth_put(&self, n: usize) {
+        let mut faults = self.faults.lock().expect("faults poisoned");
+        let target = faults.puts + n.max(1);
+        faults.fail_puts.push(target);
+    }
+
+    /// Delay every operation by `latency` (zero disables).
+    pub fn set_latency(&self, latency: Duration) {
+        self.faults.lock().expect("faults poisoned").latency = latency;
+    }
+
+    /// Put calls attempted so far, including failed ones.
+    pub fn put_count(&self) -> usize {
+        self.faults.lock().expect("faults poisoned").puts
+    }
+
+    /// Stored objects as `bucket/key`, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        self.objects
+            .lock()
+            .expect("objects poisoned")
+            .keys()
+            .map(|(bucket, key)| format!("{bucket}/{key}"))
+            .collect()
+    }
+
+    async fn delay(&self) {
+        let latency = self.faults.lock().expect("faults poisoned").latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
+    /// Count a put and fail it if it was marked with `fail_nth_put`.
+    async fn begin_put(&self, bucket: &str, key: &str) -> Result<()> {
+        self.delay().await;
+        let mut faults = self.faults.lock().expect("faults poisoned");
+        faults.puts += 1;
+        let put = faults.puts;
+        let before = faults.fail_puts.len();
+        faults.fail_puts.retain(|&n| n != put);
+        if faults.fail_puts.len() != before {
+            return Err(Error::msg(format!(
+                "injected put failure bucket={bucket} key={key}"
+            )));
+        }
+        Ok(())
+    }
+
+    fn insert(&self, bucket: &str, key: &str, bytes: Vec<u8>) {
+        self.objects.lock().expect("objects poisoned").insert(
+            (bucket.to_string(), key.to_string()),
+            StoredObject {
+                bytes,
+                last_modified: Utc::now(),
+            },
+        );
+    }
+
+    fn read<T>(&self, bucket: &str, key: &str, f: impl FnOnce(&StoredObject) -> T) -> Option<T> {
+        self.objects
+            .lock()
+            .expect("objects poisoned")
+            .get(&(bucket.to_string(), key.to_string()))
+            .map(f)
+    }
+
+    fn read_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>> {
+        self.read(bucket, key, |o| o.bytes.clone())
+            .ok_or_else(|| Error::msg(format!("object not found bucket={bucket} key={key}")))
+    }
+}
+
+fn meta(key: &str, object: &StoredObject) -> ObjectMeta {
+    ObjectMeta {
+        key: key.to_string(),
+        size: object.bytes.len() as u64,
+        last_modified: Some(object.last_modified),
+        etag: Some(
+            Sha256::digest(&object.bytes)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+        ),
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MemoryObjectStore {
+    async fn put_bytes(
+        &self,
+        bucket: &str,
+        key: &str,
+        bytes: Vec<u8>,
+        _content_type: &str,
+    ) -> Result<()> {
+        self.begin_put(bucket, key).await?;
+        self.insert(bucket, key, bytes);
+        Ok(())
+    }
+
+    async fn put_file(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &Path,
+        _content_type: &str,
+    ) -> Result<()> {
+        self.begin_put(bucket, key).await?;
+        let bytes = tokio::fs::read(local_path)
+            .await
+            .with_context(|| format!("read {}", local_path.display()))
+            .map_err(Error::from)?;
+        self.insert(bucket, key, bytes);
+        Ok(())
+    }
+
+    async fn get_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>> {
+        self.delay().await;
+        self.read_bytes(bucket, key)
+    }
+
+    async fn get(&self, bucket: &str, key: &str) -> Result<ObjectReader> {
+        self.delay().await;
+        Ok(Box::pin(std::io::Cursor::new(
+            self.read_bytes(bucket, key)?,
+        )))
+    }
+
+    async fn get_range(&self, bucket: &str, key: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        self.delay().await;
+        if range.is_empty() {
+            return Err(Error::msg(format!("empty byte range {range:?}")));
+        }
+        let bytes = self.read_bytes(bucket, key)?;
+        let len = bytes.len() as u64;
+        Ok(bytes[range.start.min(len) as usize..range.end.min(len) as usize].to_vec())
+    }
+
+    async fn head(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        self.delay().await;
+        Ok(self.read(bucket, key, |o| meta(key, o)))
+    }
+
+    async fn list_prefix(&self, bucket: &str, prefix: &str) -> Result<Vec<ObjectMeta>> {
+        self.delay().await;
+        Ok(self
+            .objects
+            .lock()
+            .expect("objects poisoned")
+            .iter()
+            .filter(|((b, key), _)| b == bucket && key.starts_with(prefix))
+            .map(|((_, key), object)| meta(key, object))
+            .collect())
+    }
+
+    async fn delete(&self, bucket: &str, key: &str) -> Result<()> {
+        self.delay().await;
+        self.objects
+            .lock()
+            .expect("objects poisoned")
+            .remove(&(bucket.to_string(), key.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::conformance;
+
+    #[tokio::test]
+    async fn memory_object_store_conforms() {
+        conformance::object_store(&MemoryObjectStore::default(), "bucket")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn injected_put_failure_stores_nothing() {
+        let store = MemoryObjectStore::default();
+        store.put_bytes("b", "k0", vec![0], "x").await.unwrap();
+        store.fail_nth_put(2);
+        store.put_bytes("b", "k1", vec![1], "x").await.unwrap();
+        let err = store.put_bytes("b", "k2", vec![2], "x").await.unwrap_err();
+        assert!(err.to_string().contains("injected put failure"), "{err}");
+        store.put_bytes("b", "k2", vec![2], "x").await.unwrap();
+
+        assert_eq!(store.put_count(), 4);
+        assert_eq!(store.keys(), ["b/k0", "b/k1", "b/k2"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latency_delays_operations() {
+        let store = MemoryObjectStore::default();
+        store.set_latency(Duration::from_secs(5));
+        let started = tokio::time::Instant::now();
+        store.put_bytes("b", "k", vec![1], "x").await.unwrap();
+        store.get_bytes("b", "k").await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+    }
+}
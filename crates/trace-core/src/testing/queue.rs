@@ -0,0 +1,183 @@
+use super::{Clock, SystemClock};
+use crate::{Error, Queue, QueueMessage, Result};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// In-memory queue with `PgQueue` semantics.
+///
+/// Messages become receivable at `available_at`, are hidden for the visibility timeout on each
+/// receive (incrementing `deliveries`), and are ordered by `available_at` then publish order.
+/// `nack_or_requeue` makes a message available again after the delay. Ack tokens are message ids.
+pub struct MemoryQueue {
+    clock: Arc<dyn Clock>,
+    messages: Mutex<Vec<StoredMessage>>,
+}
+
+struct StoredMessage {
+    message_id: Uuid,
+    queue_name: String,
+    payload: Value,
+    available_at: DateTime<Utc>,
+    invisible_until: Option<DateTime<Utc>>,
+    deliveries: i32,
+}
+
+impl MemoryQueue {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            messages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Messages in `queue` that have not been acked, whether or not they are visible.
+    pub fn depth(&self, queue: &str) -> usize {
+        self.messages
+            .lock()
+            .expect("queue poisoned")
+            .iter()
+            .filter(|m| m.queue_name == queue)
+            .count()
+    }
+
+    /// Payloads of the unacked messages in `queue`, in receive order.
+    pub fn payloads(&self, queue: &str) -> Vec<Value> {
+        let mut messages: Vec<_> = self
+            .messages
+            .lock()
+            .expect("queue poisoned")
+            .iter()
+            .filter(|m| m.queue_name == queue)
+            .map(|m| (m.available_at, m.payload.clone()))
+            .collect();
+        messages.sort_by_key(|(available_at, _)| *available_at);
+        messages.into_iter().map(|(_, payload)| payload).collect()
+    }
+}
+
+impl Default for MemoryQueue {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[async_trait]
+impl Queue for MemoryQueue {
+    async fn publish(
+        &self,
+        queue: &str,
+        payload: Value,
+        available_at: DateTime<Utc>,
+    ) -> Result<String> {
+        let message_id = Uuid::new_v4();
+        self.messages
+            .lock()
+            .expect("queue poisoned")
+            .push(StoredMessage {
+                message_id,
+                queue_name: queue.to_string(),
+                payload,
+                available_at,
+                invisible_until: None,
+                deliveries: 0,
+            });
+        Ok(message_id.to_string())
+    }
+
+    async fn receive(
+        &self,
+        queue: &str,
+        max: i64,
+        visibility_timeout: Duration,
+    ) -> Result<Vec<QueueMessage>> {
+        let now = self.clock.now();
+        let invisible_until = after(now, visibility_timeout);
+        let mut messages = self.messages.lock().expect("queue poisoned");
+
+        // The Vec is in publish order, so a stable sort gives `available_at, created_at`.
+        let mut ready: Vec<usize> = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| {
+                m.queue_name == queue
+                    && m.available_at <= now
+                    && m.invisible_until.is_none_or(|t| t <= now)
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        ready.sort_by_key(|&idx| messages[idx].available_at);
+        ready.truncate(usize::try_from(max).unwrap_or(0));
+
+        Ok(ready
+            .into_iter()
+            .map(|idx| {
+                let m = &mut messages[idx];
+                m.invisible_until = Some(invisible_until);
+                m.deliveries += 1;
+                QueueMessage {
+                    ack_token: m.message_id.to_string(),
+                    message_id: m.message_id.to_string(),
+                    queue_name: m.queue_name.clone(),
+                    payload: m.payload.clone(),
+                    deliveries: m.deliveries,
+                }
+            })
+            .collect())
+    }
+
+    async fn ack(&self, ack_token: &str) -> Result<()> {
+        let message_id = parse_ack_token(ack_token)?;
+        self.messages
+            .lock()
+            .expect("queue poisoned")
+            .retain(|m| m.message_id != message_id);
+        Ok(())
+    }
+
+    async fn nack_or_requeue(&self, ack_token: &str, delay: Duration) -> Result<()> {
+        let message_id = parse_ack_token(ack_token)?;
+        let available_at = after(self.clock.now(), delay);
+        if let Some(m) = self
+            .messages
+            .lock()
+            .expect("queue poisoned")
+            .iter_mut()
+            .find(|m| m.message_id == message_id)
+        {
+            m.available_at = available_at;
+            m.invisible_until = None;
+        }
+        Ok(())
+    }
+}
+
+fn parse_ack_token(ack_token: &str) -> Result<Uuid> {
+    Uuid::parse_str(ack_token)
+        .context("parse ack_token as uuid")
+        .map_err(Error::from)
+}
+
+fn after(now: DateTime<Utc>, d: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(d)
+        .ok()
+        .and_then(|d| now.checked_add_signed(d))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{conformance, ManualClock};
+
+    #[tokio::test]
+    async fn memory_queue_conforms() {
+        let clock = Arc::new(ManualClock::default());
+        let queue = MemoryQueue::new(clock.clone());
+        conformance::queue(&queue, clock.as_ref()).await.unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use super::{Clock, ManualClock};
+use crate::{Error, Result, Signer, TaskCapabilityClaims, TaskCapabilityIssueRequest};
+use anyhow::Context;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Deterministic HS256 capability signer whose `iat`/`exp` come from a [`Clock`].
+///
+/// The same request at the same clock time always yields the same token, and expiry is checked
+/// against the clock rather than wall time, so tests can issue a token and step past its TTL.
+pub struct TestSigner {
+    clock: Arc<dyn Clock>,
+    ttl: Duration,
+}
+
+impl TestSigner {
+    pub const ISSUER: &'static str = "trace-test";
+    pub const AUDIENCE: &'static str = "trace-test";
+    pub const KID: &'static str = "test";
+    pub const SECRET: &'static str = "trace-test-secret";
+
+    /// A signer issuing tokens valid for five minutes of `clock` time.
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            ttl: Duration::from_secs(300),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl Default for TestSigner {
+    fn default() -> Self {
+        Self::new(Arc::new(ManualClock::default()))
+    }
+}
+
+impl Signer for TestSigner {
+    fn issue_task_capability(&self, req: &TaskCapabilityIssueRequest) -> Result<String> {
+        let now = self.clock.now().timestamp();
+        let iat: usize = now.try_into().unwrap_or(0);
+        let exp = iat.saturating_add(usize::try_from(self.ttl.as_secs()).unwrap_or(usize::MAX));
+
+        let task_id = req.task_id;
+        let claims = TaskCapabilityClaims {
+            iss: Self::ISSUER.to_string(),
+            aud: Self::AUDIENCE.to_string(),
+            sub: format!("task:{task_id}"),
+            exp,
+            iat,
+            org_id: req.org_id,
+            task_id: req.task_id,
+            attempt: req.attempt,
+            datasets: req.datasets.clone(),
+            s3: req.s3.clone(),
+        };
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(Self::KID.to_string());
+        encode(
+            &header,
+            &claims,
+            &EncodingKey::from_secret(Self::SECRET.as_bytes()),
+        )
+        .context("encode task capability token")
+        .map_err(Error::from)
+    }
+
+    fn verify_task_capability(&self, token: &str) -> Result<TaskCapabilityClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[Self::ISSUER]);
+        validation.set_audience(&[Self::AUDIENCE]);
+        // Expiry is checked below against the clock.
+        validation.validate_exp = false;
+
+        let claims = decode::<TaskCapabilityClaims>(
+            token,
+            &DecodingKey::from_secret(Self::SECRET.as_bytes()),
+            &validation,
+        )
+        .context("verify jwt")
+        .map_err(Error::from)?
+        .claims;
+
+        let now: usize = self.clock.now().timestamp().try_into().unwrap_or(0);
+        if claims.exp <= now {
+            return Err(Error::msg("task capability token expired"));
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::S3Grants;
+    use uuid::Uuid;
+
+    #[test]
+    fn tokens_are_deterministic_and_expire_on_the_clock() {
+        let clock = Arc::new(ManualClock::default());
+        let signer = TestSigner::new(clock.clone()).with_ttl(Duration::from_secs(60));
+        let req = TaskCapabilityIssueRequest {
+            org_id: Uuid::nil(),
+            task_id: Uuid::from_u128(1),
+            attempt: 1,
+            datasets: Vec::new(),
+            s3: S3Grants::empty(),
+        };
+
+        let token = signer.issue_task_capability(&req).unwrap();
+        assert_eq!(token, signer.issue_task_capability(&req).unwrap());
+        let claims = signer.verify_task_capability(&token).unwrap();
+        assert_eq!(claims.task_id, req.task_id);
+        assert_eq!(claims.exp - claims.iat, 60);
+
+        clock.advance(Duration::from_secs(59));
+        signer.verify_task_capability(&token).unwrap();
+        clock.advance(Duration::from_secs(1));
+        let err = signer.verify_task_capability(&token).unwrap_err();
+        assert!(err.to_string().contains("expired"), "{err}");
+    }
+}
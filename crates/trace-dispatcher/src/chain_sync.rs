@@ -1,5 +1,5 @@
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Row};
 use std::collections::BTreeMap;
 use uuid::Uuid;
@@ -184,6 +184,7 @@ pub async fn apply_chain_sync_yaml(
         validate_stream(stream)?;
 
         let config_hash = format!("cryo_ingest.{}:v1", stream.cryo_dataset_name);
+        let dataset_uuid = derive_dataset_uuid(org_id, doc.chain_id, dataset_key)?;
 
         sqlx::query(
             r#"
@@ -195,14 +196,16 @@ pub async fn apply_chain_sync_yaml(
               config_hash,
               chunk_size,
               max_inflight,
+              dataset_uuid,
               updated_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now())
             ON CONFLICT (job_id, dataset_key) DO UPDATE SET
               cryo_dataset_name = EXCLUDED.cryo_dataset_name,
               rpc_pool = EXCLUDED.rpc_pool,
               config_hash = EXCLUDED.config_hash,
               chunk_size = EXCLUDED.chunk_size,
               max_inflight = EXCLUDED.max_inflight,
+              dataset_uuid = EXCLUDED.dataset_uuid,
               updated_at = now()
             "#,
         )
@@ -213,6 +216,7 @@ pub async fn apply_chain_sync_yaml(
         .bind(&config_hash)
         .bind(stream.chunk_size)
         .bind(stream.max_inflight)
+        .bind(dataset_uuid)
         .execute(&mut *tx)
         .await
         .with_context(|| format!("upsert stream {dataset_key}"))?;
@@ -283,6 +287,140 @@ pub async fn set_chain_sync_enabled(
         .context("read updated job_id")
 }
 
+/// Selects which failed ranges [`retry_failed_ranges`] reschedules.
+///
+/// When set, `from_block`/`to_block` select ranges fully contained in `[from_block, to_block)`.
+#[derive(Debug, Clone, Default)]
+pub struct RetryRangesFilter {
+    pub dataset_key: Option<String>,
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetriedRange {
+    pub dataset_key: String,
+    pub range_start: i64,
+    pub range_end: i64,
+    pub task_id: Uuid,
+    pub attempt: i64,
+}
+
+/// Reschedule failed chain_sync ranges under a fresh task attempt.
+///
+/// Each range returns to `scheduled` and its task is re-queued with `attempt + 1` and a fresh
+/// retry budget; a wake-up is written to the outbox in the same transaction. Ranges are
+/// rescheduled regardless of `max_inflight` (this is an explicit operator action).
+pub async fn retry_failed_ranges(
+    pool: &PgPool,
+    task_wakeup_queue: &str,
+    job_id: Uuid,
+    filter: &RetryRangesFilter,
+) -> anyhow::Result<Vec<RetriedRange>> {
+    if let (Some(from), Some(to)) = (filter.from_block, filter.to_block) {
+        anyhow::ensure!(to > from, "--to must be > --from");
+    }
+
+    let mut tx = pool.begin().await.context("begin retry ranges tx")?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT dataset_key, range_start, range_end, task_id
+        FROM state.chain_sync_scheduled_ranges
+        WHERE job_id = $1
+          AND status = 'failed'
+          AND ($2::text IS NULL OR dataset_key = $2)
+          AND ($3::bigint IS NULL OR range_start >= $3)
+          AND ($4::bigint IS NULL OR range_end <= $4)
+        ORDER BY dataset_key, range_start
+        FOR UPDATE
+        "#,
+    )
+    .bind(job_id)
+    .bind(filter.dataset_key.as_deref())
+    .bind(filter.from_block)
+    .bind(filter.to_block)
+    .fetch_all(&mut *tx)
+    .await
+    .context("select failed chain_sync ranges")?;
+
+    let mut out = Vec::with_capacity(rows.len());
+    for row in rows {
+        let dataset_key: String = row.try_get("dataset_key").context("dataset_key")?;
+        let range_start: i64 = row.try_get("range_start").context("range_start")?;
+        let range_end: i64 = row.try_get("range_end").context("range_end")?;
+        let task_id: Uuid = row.try_get("task_id").context("task_id")?;
+
+        // `retry_base_attempt = attempt` reads the pre-update value: the new attempt is the first
+        // of a fresh retry budget.
+        let attempt: Option<i64> = sqlx::query_scalar(
+            r#"
+            UPDATE state.tasks
+            SET attempt = attempt + 1,
+                retry_base_attempt = attempt,
+                status = 'queued',
+                lease_token = NULL,
+                lease_expires_at = NULL,
+                updated_at = now()
+            WHERE task_id = $1
+              AND status = 'failed'
+            RETURNING attempt
+            "#,
+        )
+        .bind(task_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .with_context(|| format!("requeue task_id={task_id}"))?;
+
+        let Some(attempt) = attempt else {
+            anyhow::bail!(
+                "task for failed range is not in failed state: task_id={task_id} dataset_key={dataset_key} range=[{range_start}, {range_end})"
+            );
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE state.chain_sync_scheduled_ranges
+            SET status = 'scheduled',
+                last_error = NULL,
+                updated_at = now()
+            WHERE task_id = $1
+            "#,
+        )
+        .bind(task_id)
+        .execute(&mut *tx)
+        .await
+        .with_context(|| format!("reschedule range task_id={task_id}"))?;
+
+        let outbox_id = crate::outbox_id_for_task_wakeup(task_id, attempt);
+        let wakeup_payload = serde_json::json!({ "task_id": task_id });
+        sqlx::query(
+            r#"
+            INSERT INTO state.outbox (outbox_id, topic, payload, available_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (outbox_id) DO NOTHING
+            "#,
+        )
+        .bind(outbox_id)
+        .bind(task_wakeup_queue)
+        .bind(wakeup_payload)
+        .execute(&mut *tx)
+        .await
+        .context("insert outbox wakeup")?;
+
+        out.push(RetriedRange {
+            dataset_key,
+            range_start,
+            range_end,
+            task_id,
+            attempt,
+        });
+    }
+
+    tx.commit().await.context("commit retry ranges")?;
+    Ok(out)
+}
+
 pub fn derive_dataset_uuid(org_id: Uuid, chain_id: i64, dataset_key: &str) -> anyhow::Result<Uuid> {
     anyhow::ensure!(chain_id > 0, "chain_id must be > 0");
     validate_stream_key(dataset_key)?;
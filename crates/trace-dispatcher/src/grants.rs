@@ -0,0 +1,177 @@
+//! Per-task capability grants.
+//!
+//! A task's capability token only covers what the task itself declares:
+//! - Reads: the payload's `inputs` (`[{dataset_uuid, dataset_version}]`), each pinned to the
+//!   storage location registered for that version in `state.dataset_versions`.
+//! - Writes: the attempt's output prefix `{artifact_root}batches/{task_id}/{attempt}/`. A
+//!   `cryo_ingest` task may also write its block range's version-addressed prefix, since dataset
+//!   versions are deterministic across attempts (see [`cryo_range_prefix`]).
+//!
+//! The dispatcher rejects buffered batches and dataset publications outside the write grants.
+
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{Postgres, Row, Transaction};
+use trace_core::{DatasetGrant, DatasetStorageRef, S3Grants};
+use uuid::Uuid;
+
+/// Object key prefix (relative to the artifact root) a task attempt writes its outputs under.
+pub fn attempt_output_prefix(task_id: Uuid, attempt: i64) -> String {
+    format!("batches/{task_id}/{attempt}/")
+}
+
+/// Object key prefix (relative to the artifact root) holding every dataset version of a
+/// `cryo_ingest` block range; versions are published at `{prefix}{dataset_version}/`.
+pub fn cryo_range_prefix(
+    chain_id: i64,
+    dataset_uuid: Uuid,
+    range_start: i64,
+    range_end: i64,
+) -> String {
+    format!("cryo/{chain_id}/{dataset_uuid}/{range_start}_{range_end}/")
+}
+
+/// Whether `uri` names an object (or prefix) under one of the granted write prefixes.
+///
+/// Prefixes are directory prefixes, and `.`/`..` segments are rejected so a URI cannot climb out
+/// of its prefix on stores that resolve paths.
+pub fn write_allowed(grants: &S3Grants, uri: &str) -> bool {
+    grants.write_prefixes.iter().any(|prefix| {
+        prefix.ends_with('/')
+            && uri
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.split('/').all(|seg| seg != "." && seg != ".."))
+    })
+}
+
+#[derive(Debug)]
+pub(crate) struct TaskGrants {
+    pub datasets: Vec<DatasetGrant>,
+    pub s3: S3Grants,
+}
+
+#[derive(Debug)]
+pub(crate) enum GrantError {
+    /// `inputs` is not a list of `{dataset_uuid, dataset_version}`, or a `cryo_ingest` payload
+    /// has no block range.
+    InvalidPayload(serde_json::Error),
+    /// An input names a dataset version that has not been published (yet).
+    Unpublished {
+        dataset_uuid: Uuid,
+        dataset_version: Uuid,
+    },
+    Internal(anyhow::Error),
+}
+
+impl std::fmt::Display for GrantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPayload(err) => write!(f, "invalid task payload: {err}"),
+            Self::Unpublished {
+                dataset_uuid,
+                dataset_version,
+            } => write!(
+                f,
+                "input dataset {dataset_uuid} version {dataset_version} is not published"
+            ),
+            Self::Internal(err) => write!(f, "{err:#}"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TaskInput {
+    dataset_uuid: Uuid,
+    dataset_version: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct CryoRange {
+    chain_id: i64,
+    dataset_uuid: Uuid,
+    range_start: i64,
+    range_end: i64,
+}
+
+/// Grants for `attempt` of a task with `payload`, resolved inside the claim transaction.
+pub(crate) async fn resolve_task_grants(
+    tx: &mut Transaction<'_, Postgres>,
+    artifact_root: &str,
+    task_id: Uuid,
+    attempt: i64,
+    payload: &Value,
+) -> Result<TaskGrants, GrantError> {
+    let inputs: Vec<TaskInput> = match payload.get("inputs") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(inputs) => {
+            serde_json::from_value(inputs.clone()).map_err(GrantError::InvalidPayload)?
+        }
+    };
+
+    let mut datasets = Vec::with_capacity(inputs.len());
+    let mut read_prefixes = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let row = sqlx::query(
+            r#"
+            SELECT storage_prefix, storage_glob
+            FROM state.dataset_versions
+            WHERE dataset_version = $1
+              AND dataset_uuid = $2
+            "#,
+        )
+        .bind(input.dataset_version)
+        .bind(input.dataset_uuid)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(|err| GrantError::Internal(err.into()))?;
+
+        let Some(row) = row else {
+            return Err(GrantError::Unpublished {
+                dataset_uuid: input.dataset_uuid,
+                dataset_version: input.dataset_version,
+            });
+        };
+        let storage_prefix: String = row
+            .try_get("storage_prefix")
+            .map_err(|err| GrantError::Internal(err.into()))?;
+        let storage_glob: String = row
+            .try_get("storage_glob")
+            .map_err(|err| GrantError::Internal(err.into()))?;
+        let storage_ref = DatasetStorageRef::from_uri(&storage_prefix, storage_glob)
+            .map_err(|err| GrantError::Internal(err.into()))?;
+
+        read_prefixes.push(storage_ref.uri());
+        datasets.push(DatasetGrant {
+            dataset_uuid: input.dataset_uuid,
+            dataset_version: input.dataset_version,
+            storage_ref: Some(storage_ref),
+        });
+    }
+
+    let root = artifact_root.trim_end_matches('/');
+    let mut write_prefixes = vec![format!(
+        "{root}/{}",
+        attempt_output_prefix(task_id, attempt)
+    )];
+    if payload.get("operator").and_then(Value::as_str) == Some("cryo_ingest") {
+        let range: CryoRange =
+            serde_json::from_value(payload.clone()).map_err(GrantError::InvalidPayload)?;
+        write_prefixes.push(format!(
+            "{root}/{}",
+            cryo_range_prefix(
+                range.chain_id,
+                range.dataset_uuid,
+                range.range_start,
+                range.range_end
+            )
+        ));
+    }
+
+    Ok(TaskGrants {
+        datasets,
+        s3: S3Grants {
+            read_prefixes,
+            write_prefixes,
+        },
+    })
+}
@@ -1,15 +1,16 @@
 //! Trace dispatcher service (Lite mode).
 //!
 //! Exposes task-scoped endpoints for claiming work, emitting buffered outputs via an outbox, and
-//! tracking lease heartbeats. This crate is intentionally small and designed to be reused by the
-//! harness while freezing the dispatcher semantics.
+//! tracking lease heartbeats. Claims require a worker token (see [`worker_auth`]). Capability
+//! verification keys are published at `/.well-known/jwks.json`. This crate is intentionally small
+//! and designed to be reused by the harness while freezing the dispatcher semantics.
 
 use anyhow::Context;
 use axum::{
     extract::State,
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
-    routing::post,
+    routing::{get, post},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
@@ -19,17 +20,25 @@ use sqlx::{PgPool, Row};
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{net::TcpListener, sync::watch, task::JoinHandle};
 use trace_core::{
-    DatasetGrant, DatasetPublication, DatasetStorageRef, Queue as QueueTrait, S3Grants,
-    Signer as SignerTrait, TaskCapabilityIssueRequest,
+    DatasetPublication, Queue as QueueTrait, Signer as SignerTrait, TaskCapabilityClaims,
+    TaskCapabilityIssueRequest,
 };
 use uuid::Uuid;
 
 pub const TASK_CAPABILITY_HEADER: &str = "X-Trace-Task-Capability";
+pub const WORKER_TOKEN_HEADER: &str = "X-Trace-Worker-Token";
 
 pub mod chain_sync;
+pub mod grants;
 pub mod head_observer;
+pub mod outbox;
 pub mod planner;
+pub mod retry;
 pub mod status;
+pub mod worker_auth;
+
+const OUTBOX_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const OUTBOX_SWEEP_BATCH: i64 = 1000;
 
 // UUIDv5 namespace for deterministic outbox message IDs (task fencing/idempotency).
 const OUTBOX_NAMESPACE: Uuid = Uuid::from_bytes([
@@ -46,11 +55,22 @@ pub struct DispatcherConfig {
     pub task_wakeup_queue: String,
     pub buffer_queue: String,
 
-    /// Dataset grants to embed in issued task capability tokens (lite/harness default).
-    pub default_datasets: Vec<DatasetGrant>,
+    /// Object store URI that per-attempt write grants are rooted at, e.g. `s3://{bucket}/`
+    /// (see [`grants`]).
+    pub artifact_root: String,
+
+    /// Bounded retry/backoff policy for retryable failures and expired leases.
+    pub retry: retry::TaskRetryConfig,
+
+    /// Per-row retry/backoff policy for outbox publishes; exhausted rows are marked `dead`.
+    pub outbox_retry: retry::RetryPolicy,
 
-    /// S3 grants to embed in issued task capability tokens.
-    pub default_s3: S3Grants,
+    /// How long `sent` outbox rows are kept (their `outbox_id` is the dedupe key); 0 keeps them
+    /// forever. Rows whose task is still running are kept regardless.
+    pub outbox_sent_retention_secs: u64,
+
+    /// Workers allowed to call `/internal/task-claim`, and the operators each may claim.
+    pub worker_auth: worker_auth::WorkerAuth,
 }
 
 #[derive(Clone)]
@@ -61,6 +81,15 @@ struct AppState {
     queue: Arc<dyn QueueTrait>,
 }
 
+/// Background loops a [`DispatcherServer`] runs next to its HTTP server.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackgroundLoops {
+    pub outbox: bool,
+    /// Chain sync planner and head observer; runs only together with `outbox`.
+    pub chain_sync_planner: bool,
+    pub lease_reaper: bool,
+}
+
 #[derive(Debug)]
 pub struct DispatcherServer {
     pub addr: SocketAddr,
@@ -75,9 +104,7 @@ impl DispatcherServer {
         capability: Arc<dyn SignerTrait>,
         queue: Arc<dyn QueueTrait>,
         bind: SocketAddr,
-        enable_outbox: bool,
-        enable_chain_sync_planner: bool,
-        enable_lease_reaper: bool,
+        loops: BackgroundLoops,
     ) -> anyhow::Result<Self> {
         let listener = TcpListener::bind(bind)
             .await
@@ -99,9 +126,7 @@ impl DispatcherServer {
             state,
             shutdown_tx.clone(),
             shutdown_rx,
-            enable_outbox,
-            enable_chain_sync_planner,
-            enable_lease_reaper,
+            loops,
         ));
 
         Ok(Self {
@@ -124,12 +149,10 @@ async fn run_dispatcher(
     state: Arc<AppState>,
     shutdown_tx: watch::Sender<bool>,
     shutdown_rx: watch::Receiver<bool>,
-    enable_outbox: bool,
-    enable_chain_sync_planner: bool,
-    enable_lease_reaper: bool,
+    loops: BackgroundLoops,
 ) -> anyhow::Result<()> {
     let mut bg = Vec::<JoinHandle<anyhow::Result<()>>>::new();
-    if enable_outbox && enable_chain_sync_planner {
+    if loops.outbox && loops.chain_sync_planner {
         bg.push(tokio::spawn(chain_sync_planner_loop(
             state.clone(),
             shutdown_rx.clone(),
@@ -139,13 +162,13 @@ async fn run_dispatcher(
             shutdown_rx.clone(),
         )));
     }
-    if enable_outbox {
+    if loops.outbox {
         bg.push(tokio::spawn(outbox_drain_loop(
             state.clone(),
             shutdown_rx.clone(),
         )));
     }
-    if enable_lease_reaper {
+    if loops.lease_reaper {
         bg.push(tokio::spawn(lease_reaper_loop(
             state.clone(),
             shutdown_rx.clone(),
@@ -180,9 +203,15 @@ fn build_router(state: Arc<AppState>) -> Router {
         .route("/v1/task/heartbeat", post(task_heartbeat))
         .route("/v1/task/buffer-publish", post(task_buffer_publish))
         .route("/v1/task/complete", post(task_complete))
+        .route("/.well-known/jwks.json", get(jwks))
         .with_state(state)
 }
 
+/// Public keys for task capability tokens, so verifiers need not share the signing secret.
+async fn jwks(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.capability.jwks())
+}
+
 #[derive(Debug, Deserialize)]
 struct TaskClaimRequest {
     task_id: Uuid,
@@ -200,8 +229,11 @@ struct TaskClaimResponse {
 
 async fn task_claim(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<TaskClaimRequest>,
 ) -> ApiResult<Json<TaskClaimResponse>> {
+    let worker = require_worker(&state.cfg.worker_auth, &headers, req.task_id)?;
+
     let now = Utc::now();
     let lease_secs = state.cfg.lease_duration_secs.min(i64::MAX as u64) as i64;
     let lease_expires_at = now + chrono::Duration::seconds(lease_secs);
@@ -223,7 +255,7 @@ async fn task_claim(
 
     let row = sqlx::query(
         r#"
-        SELECT attempt, status, lease_expires_at, payload
+        SELECT attempt, status, payload
         FROM state.tasks
         WHERE task_id = $1
         FOR UPDATE
@@ -234,75 +266,110 @@ async fn task_claim(
     .await
     .map_err(ApiError::internal)?;
 
+    let current_attempt: i64 = row.try_get("attempt").map_err(ApiError::internal)?;
     let status: String = row.try_get("status").map_err(ApiError::internal)?;
-    let current_lease_expires_at: Option<DateTime<Utc>> = row
-        .try_get("lease_expires_at")
-        .map_err(ApiError::internal)?;
-
-    let (attempt, work_payload) = match status.as_str() {
-        "queued" => {
-            let row = sqlx::query(
-                r#"
-                UPDATE state.tasks
-                SET status = 'running',
-                    lease_token = $2,
-                    lease_expires_at = $3,
-                    updated_at = now()
-                WHERE task_id = $1
-                RETURNING attempt, payload
-                "#,
+    let payload: Value = row.try_get("payload").map_err(ApiError::internal)?;
+    let operator = payload.get("operator").and_then(Value::as_str);
+
+    // A `running` task is never re-claimed here, even once its lease expired: the lease reaper
+    // moves it back to `queued` under the retry policy (budget, backoff) first.
+    let claim = if !worker.operators.allows(operator) {
+        Err((
+            "operator_denied",
+            ApiError::forbidden("worker may not claim this operator"),
+        ))
+    } else {
+        match status.as_str() {
+            "queued" => Ok(0_i64),
+            "running" => Err(("conflict", ApiError::conflict("task already leased"))),
+            _ => Err(("conflict", ApiError::conflict("task not claimable"))),
+        }
+    };
+    let attempt = match claim {
+        Ok(increment) => current_attempt + increment,
+        Err((outcome, err)) => {
+            return Err(
+                deny_task_claim(&state, tx, worker, req.task_id, operator, outcome, err).await,
             )
-            .bind(req.task_id)
-            .bind(lease_token)
-            .bind(lease_expires_at)
-            .fetch_one(&mut *tx)
-            .await
-            .map_err(ApiError::internal)?;
-
-            let attempt: i64 = row.try_get("attempt").map_err(ApiError::internal)?;
-            let payload: Value = row.try_get("payload").map_err(ApiError::internal)?;
-            (attempt, payload)
         }
-        "running" => {
-            let lease_active = current_lease_expires_at.is_some_and(|t| t > now);
-            if lease_active {
-                return Err(ApiError::conflict("task already leased"));
-            }
+    };
 
-            let row = sqlx::query(
-                r#"
-                UPDATE state.tasks
-                SET attempt = attempt + 1,
-                    status = 'running',
-                    lease_token = $2,
-                    lease_expires_at = $3,
-                    updated_at = now()
-                WHERE task_id = $1
-                RETURNING attempt, payload
-                "#,
+    let grants = match grants::resolve_task_grants(
+        &mut tx,
+        &state.cfg.artifact_root,
+        req.task_id,
+        attempt,
+        &payload,
+    )
+    .await
+    {
+        Ok(grants) => grants,
+        Err(grants::GrantError::Internal(err)) => return Err(ApiError::internal(err)),
+        Err(err) => {
+            tracing::warn!(
+                event = "harness.dispatcher.task_claim.grants_unresolved",
+                task_id = %req.task_id,
+                error = %err,
+                "cannot resolve task grants"
+            );
+            let api_err = match err {
+                grants::GrantError::Unpublished { .. } => {
+                    ApiError::unavailable("task inputs not published")
+                }
+                _ => ApiError::bad_request("invalid task payload"),
+            };
+            return Err(deny_task_claim(
+                &state,
+                tx,
+                worker,
+                req.task_id,
+                operator,
+                "inputs_unresolved",
+                api_err,
             )
-            .bind(req.task_id)
-            .bind(lease_token)
-            .bind(lease_expires_at)
-            .fetch_one(&mut *tx)
-            .await
-            .map_err(ApiError::internal)?;
-
-            let attempt: i64 = row.try_get("attempt").map_err(ApiError::internal)?;
-            let payload: Value = row.try_get("payload").map_err(ApiError::internal)?;
-            (attempt, payload)
+            .await);
         }
-        _ => return Err(ApiError::conflict("task not claimable")),
     };
 
+    sqlx::query(
+        r#"
+        UPDATE state.tasks
+        SET attempt = $4,
+            status = 'running',
+            lease_token = $2,
+            lease_expires_at = $3,
+            updated_at = now()
+        WHERE task_id = $1
+        "#,
+    )
+    .bind(req.task_id)
+    .bind(lease_token)
+    .bind(lease_expires_at)
+    .bind(attempt)
+    .execute(&mut *tx)
+    .await
+    .map_err(ApiError::internal)?;
+
+    // Granted claims are audited atomically with the lease: no audit row, no capability.
+    audit_task_claim(
+        &mut *tx,
+        worker,
+        req.task_id,
+        operator,
+        Some(attempt),
+        "granted",
+    )
+    .await
+    .map_err(ApiError::internal)?;
+
     tx.commit().await.map_err(ApiError::internal)?;
 
     let capability_req = TaskCapabilityIssueRequest {
         org_id: state.cfg.org_id,
         task_id: req.task_id,
         attempt,
-        datasets: state.cfg.default_datasets.clone(),
-        s3: state.cfg.default_s3.clone(),
+        datasets: grants.datasets,
+        s3: grants.s3,
     };
 
     let capability_token = state
@@ -316,7 +383,7 @@ async fn task_claim(
         lease_token,
         lease_expires_at,
         capability_token,
-        work_payload,
+        work_payload: payload,
     }))
 }
 
@@ -401,12 +468,17 @@ async fn task_buffer_publish(
     headers: HeaderMap,
     Json(req): Json<BufferPublishRequest>,
 ) -> ApiResult<StatusCode> {
-    require_task_capability(
+    let claims = require_task_capability(
         state.capability.as_ref(),
         &headers,
         req.fence.task_id,
         req.fence.attempt,
     )?;
+    if !grants::write_allowed(&claims.s3, &req.batch_uri) {
+        return Err(ApiError::forbidden(
+            "batch_uri outside granted write prefixes",
+        ));
+    }
 
     // IMPORTANT: fencing + side-effect writes must be *atomic*.
     let mut tx = state.pool.begin().await.map_err(ApiError::internal)?;
@@ -476,6 +548,10 @@ struct CompleteRequest {
     /// in the state DB on successful completion.
     #[serde(default)]
     datasets_published: Vec<DatasetPublication>,
+
+    /// Optional error reason for non-success outcomes (persisted as `state.tasks.last_error`).
+    #[serde(default)]
+    error: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -491,12 +567,21 @@ async fn task_complete(
     headers: HeaderMap,
     Json(req): Json<CompleteRequest>,
 ) -> ApiResult<StatusCode> {
-    require_task_capability(
+    let claims = require_task_capability(
         state.capability.as_ref(),
         &headers,
         req.fence.task_id,
         req.fence.attempt,
     )?;
+    if req
+        .datasets_published
+        .iter()
+        .any(|pubd| !grants::write_allowed(&claims.s3, &pubd.storage_ref.uri()))
+    {
+        return Err(ApiError::forbidden(
+            "dataset publication outside granted write prefixes",
+        ));
+    }
 
     let mut tx = state.pool.begin().await.map_err(ApiError::internal)?;
 
@@ -543,12 +628,12 @@ async fn task_complete(
     .await
     .map_err(ApiError::internal)?;
 
-    if chain_sync_range.is_some() {
+    if let Some(range_row) = &chain_sync_range {
         enforce_chain_sync_completion_rules(
             &req.outcome,
             &req.datasets_published,
             &current_payload,
-            chain_sync_range.as_ref().unwrap(),
+            range_row,
         )?;
     } else if req.datasets_published.len() > 1 {
         return Err(ApiError::bad_request(
@@ -580,10 +665,12 @@ async fn task_complete(
             .map_err(ApiError::internal)?;
         }
         TaskOutcome::FatalError => {
+            let reason = req.error.as_deref().unwrap_or("fatal_error");
             sqlx::query(
                 r#"
                 UPDATE state.tasks
                 SET status = 'failed',
+                    last_error = $2,
                     lease_token = NULL,
                     lease_expires_at = NULL,
                     updated_at = now()
@@ -591,44 +678,34 @@ async fn task_complete(
                 "#,
             )
             .bind(req.fence.task_id)
+            .bind(retry::truncate_error(reason))
             .execute(&mut *tx)
             .await
             .map_err(ApiError::internal)?;
+
+            if chain_sync_range.is_some() {
+                mark_chain_sync_range_failed(&mut tx, req.fence.task_id, reason)
+                    .await
+                    .map_err(ApiError::internal)?;
+            }
         }
         TaskOutcome::RetryableError => {
-            let row = sqlx::query(
-                r#"
-                UPDATE state.tasks
-                SET attempt = attempt + 1,
-                    status = 'queued',
-                    lease_token = NULL,
-                    lease_expires_at = NULL,
-                    updated_at = now()
-                WHERE task_id = $1
-                RETURNING attempt
-                "#,
+            let reason = req.error.as_deref().unwrap_or("retryable_error");
+            let decision = retry::retry_or_fail_task(
+                &mut tx,
+                &state.cfg.retry,
+                &state.cfg.task_wakeup_queue,
+                req.fence.task_id,
+                req.fence.attempt,
+                &current_payload,
+                reason,
             )
-            .bind(req.fence.task_id)
-            .fetch_one(&mut *tx)
             .await
             .map_err(ApiError::internal)?;
 
-            let new_attempt: i64 = row.try_get("attempt").map_err(ApiError::internal)?;
-            let outbox_id = outbox_id_for_task_wakeup(req.fence.task_id, new_attempt);
-            let payload = serde_json::json!({ "task_id": req.fence.task_id });
-            sqlx::query(
-                r#"
-                INSERT INTO state.outbox (outbox_id, topic, payload, available_at)
-                VALUES ($1, $2, $3, now())
-                ON CONFLICT (outbox_id) DO NOTHING
-                "#,
-            )
-            .bind(outbox_id)
-            .bind(&state.cfg.task_wakeup_queue)
-            .bind(payload)
-            .execute(&mut *tx)
-            .await
-            .map_err(ApiError::internal)?;
+            if decision == retry::RetryDecision::Skipped {
+                return Err(ApiError::conflict("stale task fence"));
+            }
         }
     }
 
@@ -701,15 +778,8 @@ async fn register_dataset_publications(
     datasets: &[DatasetPublication],
 ) -> ApiResult<()> {
     for pubd in datasets {
-        let (storage_prefix, storage_glob) = match &pubd.storage_ref {
-            DatasetStorageRef::S3 {
-                bucket,
-                prefix,
-                glob,
-                ..
-            } => (format!("s3://{bucket}/{prefix}"), glob.clone()),
-            DatasetStorageRef::File { prefix, glob } => (format!("file://{prefix}"), glob.clone()),
-        };
+        let storage_prefix = pubd.storage_ref.uri();
+        let storage_glob = pubd.storage_ref.glob();
 
         let inserted = sqlx::query(
             r#"
@@ -728,7 +798,7 @@ async fn register_dataset_publications(
         .bind(pubd.dataset_version)
         .bind(pubd.dataset_uuid)
         .bind(&storage_prefix)
-        .bind(&storage_glob)
+        .bind(storage_glob)
         .bind(&pubd.config_hash)
         .bind(pubd.range_start)
         .bind(pubd.range_end)
@@ -800,6 +870,32 @@ async fn mark_chain_sync_range_completed(
     Ok(())
 }
 
+/// Fail a task's chain_sync range terminally so it stops holding a `max_inflight` slot.
+///
+/// No-op for tasks that are not chain_sync ranges.
+pub(crate) async fn mark_chain_sync_range_failed(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    task_id: Uuid,
+    reason: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE state.chain_sync_scheduled_ranges
+        SET status = 'failed',
+            last_error = $2,
+            updated_at = now()
+        WHERE task_id = $1
+          AND status = 'scheduled'
+        "#,
+    )
+    .bind(task_id)
+    .bind(retry::truncate_error(reason))
+    .execute(&mut **tx)
+    .await
+    .with_context(|| format!("mark chain_sync range failed task_id={task_id}"))?;
+    Ok(())
+}
+
 fn outbox_id_for_buffer_publish(task_id: Uuid, attempt: i64, batch_uri: &str) -> Uuid {
     let name = format!("buffer_publish:{task_id}:{attempt}:{batch_uri}");
     Uuid::new_v5(&OUTBOX_NAMESPACE, name.as_bytes())
@@ -815,16 +911,19 @@ async fn outbox_drain_loop(
     mut shutdown_rx: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
     let interval = Duration::from_millis(state.cfg.outbox_poll_ms);
+    let retention = Duration::from_secs(state.cfg.outbox_sent_retention_secs);
+    let mut next_sweep = tokio::time::Instant::now();
 
     loop {
         if *shutdown_rx.borrow() {
             return Ok(());
         }
 
-        if let Err(err) = drain_outbox_once(
+        if let Err(err) = outbox::drain_outbox_once(
             &state.pool,
             state.queue.as_ref(),
             state.cfg.outbox_batch_size,
+            &state.cfg.outbox_retry,
         )
         .await
         {
@@ -835,6 +934,24 @@ async fn outbox_drain_loop(
             );
         }
 
+        // A zero retention disables the sweeper (sent rows are kept forever).
+        if !retention.is_zero() && tokio::time::Instant::now() >= next_sweep {
+            next_sweep = tokio::time::Instant::now() + OUTBOX_SWEEP_INTERVAL;
+            match outbox::sweep_sent_outbox_once(&state.pool, retention, OUTBOX_SWEEP_BATCH).await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::debug!(
+                    event = "harness.dispatcher.outbox_sweep.deleted",
+                    deleted,
+                    "swept sent outbox rows"
+                ),
+                Err(err) => tracing::warn!(
+                    event = "harness.dispatcher.outbox_sweep.error",
+                    error = %err,
+                    "outbox sweep error"
+                ),
+            }
+        }
+
         tokio::select! {
             _ = tokio::time::sleep(interval) => {}
             _ = shutdown_rx.changed() => {}
@@ -842,54 +959,6 @@ async fn outbox_drain_loop(
     }
 }
 
-async fn drain_outbox_once(pool: &PgPool, queue: &dyn QueueTrait, max: i64) -> anyhow::Result<()> {
-    let mut tx = pool.begin().await.context("begin outbox drain tx")?;
-
-    let rows = sqlx::query(
-        r#"
-        SELECT outbox_id, topic, payload, available_at
-        FROM state.outbox
-        WHERE status = 'pending'
-          AND available_at <= now()
-        ORDER BY available_at, created_at
-        LIMIT $1
-        FOR UPDATE SKIP LOCKED
-        "#,
-    )
-    .bind(max)
-    .fetch_all(&mut *tx)
-    .await
-    .context("select pending outbox")?;
-
-    for row in rows {
-        let outbox_id: Uuid = row.try_get("outbox_id")?;
-        let topic: String = row.try_get("topic")?;
-        let payload: Value = row.try_get("payload")?;
-        let available_at: DateTime<Utc> = row.try_get("available_at")?;
-
-        let _message_id = queue
-            .publish(&topic, payload, available_at)
-            .await
-            .with_context(|| format!("publish outbox_id={outbox_id} to queue={topic}"))?;
-
-        sqlx::query(
-            r#"
-            UPDATE state.outbox
-            SET status = 'sent',
-                updated_at = now()
-            WHERE outbox_id = $1
-            "#,
-        )
-        .bind(outbox_id)
-        .execute(&mut *tx)
-        .await
-        .with_context(|| format!("mark outbox_id={outbox_id} sent"))?;
-    }
-
-    tx.commit().await.context("commit outbox drain")?;
-    Ok(())
-}
-
 async fn lease_reaper_loop(
     state: Arc<AppState>,
     mut shutdown_rx: watch::Receiver<bool>,
@@ -921,7 +990,7 @@ async fn reap_expired_leases_once(pool: &PgPool, cfg: &DispatcherConfig) -> anyh
 
     let rows = sqlx::query(
         r#"
-        SELECT task_id, attempt
+        SELECT task_id, attempt, payload
         FROM state.tasks
         WHERE status = 'running'
           AND lease_expires_at IS NOT NULL
@@ -937,47 +1006,18 @@ async fn reap_expired_leases_once(pool: &PgPool, cfg: &DispatcherConfig) -> anyh
     for row in rows {
         let task_id: Uuid = row.try_get("task_id")?;
         let attempt: i64 = row.try_get("attempt")?;
-        let new_attempt = attempt + 1;
-
-        let updated = sqlx::query(
-            r#"
-            UPDATE state.tasks
-            SET attempt = $2,
-                status = 'queued',
-                lease_token = NULL,
-                lease_expires_at = NULL,
-                updated_at = now()
-            WHERE task_id = $1
-              AND attempt = $3
-              AND status = 'running'
-            "#,
-        )
-        .bind(task_id)
-        .bind(new_attempt)
-        .bind(attempt)
-        .execute(&mut *tx)
-        .await
-        .with_context(|| format!("mark task_id={task_id} for retry"))?;
-
-        if updated.rows_affected() == 0 {
-            continue;
-        }
+        let payload: Value = row.try_get("payload")?;
 
-        let outbox_id = outbox_id_for_task_wakeup(task_id, new_attempt);
-        let payload = serde_json::json!({ "task_id": task_id });
-        sqlx::query(
-            r#"
-            INSERT INTO state.outbox (outbox_id, topic, payload, available_at)
-            VALUES ($1, $2, $3, now())
-            ON CONFLICT (outbox_id) DO NOTHING
-            "#,
+        retry::retry_or_fail_task(
+            &mut tx,
+            &cfg.retry,
+            &cfg.task_wakeup_queue,
+            task_id,
+            attempt,
+            &payload,
+            "lease expired",
         )
-        .bind(outbox_id)
-        .bind(&cfg.task_wakeup_queue)
-        .bind(payload)
-        .execute(&mut *tx)
-        .await
-        .with_context(|| format!("enqueue retry wakeup task_id={task_id} attempt={new_attempt}"))?;
+        .await?;
     }
 
     tx.commit().await.context("commit lease reaper")?;
@@ -1021,6 +1061,13 @@ impl ApiError {
         }
     }
 
+    fn unavailable(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message,
+        }
+    }
+
     fn internal<E: std::fmt::Display>(err: E) -> Self {
         tracing::error!(
             event = "harness.dispatcher.internal_error",
@@ -1110,12 +1157,118 @@ impl IntoResponse for ApiError {
     }
 }
 
+/// Roll back a refused claim (discarding the placeholder row for an unknown task) and audit it.
+///
+/// An audit failure is logged but does not mask the refusal.
+async fn deny_task_claim(
+    state: &AppState,
+    tx: sqlx::Transaction<'_, sqlx::Postgres>,
+    worker: &worker_auth::WorkerCredential,
+    task_id: Uuid,
+    operator: Option<&str>,
+    outcome: &str,
+    err: ApiError,
+) -> ApiError {
+    if let Err(rollback_err) = tx.rollback().await {
+        return ApiError::internal(rollback_err);
+    }
+    if let Err(audit_err) =
+        audit_task_claim(&state.pool, worker, task_id, operator, None, outcome).await
+    {
+        tracing::error!(
+            event = "harness.dispatcher.task_claim.audit_error",
+            worker_id = %worker.worker_id,
+            task_id = %task_id,
+            error = %audit_err,
+            "failed to record task claim audit row"
+        );
+    }
+    err
+}
+
+/// Authenticate the worker calling `/internal/task-claim` from its worker token.
+fn require_worker<'a>(
+    auth: &'a worker_auth::WorkerAuth,
+    headers: &HeaderMap,
+    task_id: Uuid,
+) -> ApiResult<&'a worker_auth::WorkerCredential> {
+    let token = headers
+        .get(WORKER_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    let worker = token.and_then(|token| auth.authenticate(token));
+
+    let Some(worker) = worker else {
+        tracing::warn!(
+            event = "harness.dispatcher.task_claim",
+            outcome = "unauthenticated",
+            task_id = %task_id,
+            token_present = token.is_some(),
+            "rejected unauthenticated task claim"
+        );
+        return Err(ApiError::unauthorized(if token.is_some() {
+            "invalid worker token"
+        } else {
+            "missing worker token"
+        }));
+    };
+    Ok(worker)
+}
+
+/// Record a claim attempt by an authenticated worker (`state.task_claim_audit`) and log it.
+async fn audit_task_claim<'e, E>(
+    executor: E,
+    worker: &worker_auth::WorkerCredential,
+    task_id: Uuid,
+    operator: Option<&str>,
+    attempt: Option<i64>,
+    outcome: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    if outcome == "operator_denied" {
+        tracing::warn!(
+            event = "harness.dispatcher.task_claim",
+            outcome,
+            worker_id = %worker.worker_id,
+            task_id = %task_id,
+            operator,
+            "task claim denied"
+        );
+    } else {
+        tracing::info!(
+            event = "harness.dispatcher.task_claim",
+            outcome,
+            worker_id = %worker.worker_id,
+            task_id = %task_id,
+            operator,
+            attempt,
+            "task claim"
+        );
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO state.task_claim_audit (worker_id, task_id, operator, attempt, outcome)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(&worker.worker_id)
+    .bind(task_id)
+    .bind(operator)
+    .bind(attempt)
+    .bind(outcome)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
 fn require_task_capability(
     signer: &dyn SignerTrait,
     headers: &HeaderMap,
     task_id: Uuid,
     attempt: i64,
-) -> ApiResult<()> {
+) -> ApiResult<TaskCapabilityClaims> {
     let token = headers
         .get(TASK_CAPABILITY_HEADER)
         .and_then(|v| v.to_str().ok())
@@ -1139,5 +1292,5 @@ fn require_task_capability(
         return Err(ApiError::forbidden("capability does not match request"));
     }
 
-    Ok(())
+    Ok(claims)
 }
@@ -0,0 +1,290 @@
+//! Outbox draining, retention, and dead-row operations.
+//!
+//! Rows are published independently: a failed publish records `attempts`/`last_error` and backs
+//! the row off via `available_at` without blocking the rest of the batch. After the retry budget
+//! is exhausted the row is marked `dead` and left for an operator to inspect and re-arm.
+//!
+//! Rows are claimed (`locked_until`) in a short transaction and published outside it, so no row
+//! lock is held across a network publish.
+
+use crate::retry::{self, RetryPolicy};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::time::Duration;
+use trace_core::{jitter, Queue as QueueTrait};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainResult {
+    pub sent: u64,
+    pub retried: u64,
+    pub dead: u64,
+}
+
+/// How long a drainer's claim on a row lasts; another drainer may publish the row after this.
+///
+/// Must comfortably exceed one publish, or a slow publish is duplicated.
+pub const OUTBOX_CLAIM_TTL: Duration = Duration::from_secs(60);
+
+/// Publish up to `max` due `pending` rows.
+///
+/// Publish failures are handled per row; only state DB errors abort the drain.
+pub async fn drain_outbox_once(
+    pool: &PgPool,
+    queue: &dyn QueueTrait,
+    max: i64,
+    retry_policy: &RetryPolicy,
+) -> anyhow::Result<DrainResult> {
+    let rows = sqlx::query(
+        r#"
+        UPDATE state.outbox
+        SET locked_until = now() + make_interval(secs => $2)
+        WHERE outbox_id IN (
+          SELECT outbox_id
+          FROM state.outbox
+          WHERE status = 'pending'
+            AND available_at <= now()
+            AND (locked_until IS NULL OR locked_until <= now())
+          ORDER BY available_at, created_at
+          LIMIT $1
+          FOR UPDATE SKIP LOCKED
+        )
+        RETURNING outbox_id, topic, payload, available_at, attempts
+        "#,
+    )
+    .bind(max)
+    .bind(OUTBOX_CLAIM_TTL.as_secs_f64())
+    .fetch_all(pool)
+    .await
+    .context("claim pending outbox")?;
+
+    let mut out = DrainResult::default();
+    for row in rows {
+        let outbox_id: Uuid = row.try_get("outbox_id")?;
+        let topic: String = row.try_get("topic")?;
+        let payload: Value = row.try_get("payload")?;
+        let available_at: DateTime<Utc> = row.try_get("available_at")?;
+        let attempts: i32 = row.try_get("attempts")?;
+
+        let err = match queue.publish(&topic, payload, available_at).await {
+            Ok(_message_id) => {
+                sqlx::query(
+                    r#"
+                    UPDATE state.outbox
+                    SET status = 'sent',
+                        attempts = attempts + 1,
+                        locked_until = NULL,
+                        updated_at = now()
+                    WHERE outbox_id = $1
+                    "#,
+                )
+                .bind(outbox_id)
+                .execute(pool)
+                .await
+                .with_context(|| format!("mark outbox_id={outbox_id} sent"))?;
+                out.sent += 1;
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        let attempts = attempts.saturating_add(1);
+        let reason = format!("{err:#}");
+        let reason = retry::truncate_error(&reason);
+
+        if retry_policy.allows_retry_after(i64::from(attempts)) {
+            let delay = retry_policy.backoff(i64::from(attempts) + 1, jitter::unit());
+            let next_available_at = Utc::now()
+                + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+
+            tracing::warn!(
+                event = "harness.dispatcher.outbox_publish.error",
+                %outbox_id,
+                topic = %topic,
+                attempts,
+                error = reason,
+                "outbox publish failed; backing off"
+            );
+
+            sqlx::query(
+                r#"
+                UPDATE state.outbox
+                SET attempts = $2,
+                    last_error = $3,
+                    available_at = $4,
+                    locked_until = NULL,
+                    updated_at = now()
+                WHERE outbox_id = $1
+                "#,
+            )
+            .bind(outbox_id)
+            .bind(attempts)
+            .bind(reason)
+            .bind(next_available_at)
+            .execute(pool)
+            .await
+            .with_context(|| format!("back off outbox_id={outbox_id}"))?;
+            out.retried += 1;
+        } else {
+            tracing::error!(
+                event = "harness.dispatcher.outbox_publish.dead",
+                %outbox_id,
+                topic = %topic,
+                attempts,
+                error = reason,
+                "outbox publish failed; marking row dead"
+            );
+
+            sqlx::query(
+                r#"
+                UPDATE state.outbox
+                SET status = 'dead',
+                    attempts = $2,
+                    last_error = $3,
+                    locked_until = NULL,
+                    updated_at = now()
+                WHERE outbox_id = $1
+                "#,
+            )
+            .bind(outbox_id)
+            .bind(attempts)
+            .bind(reason)
+            .execute(pool)
+            .await
+            .with_context(|| format!("mark outbox_id={outbox_id} dead"))?;
+            out.dead += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Delete up to `max` `sent` rows last updated more than `retention` ago.
+///
+/// Sent rows only exist for idempotency: producers derive `outbox_id` from the side effect and
+/// insert with `ON CONFLICT (outbox_id) DO NOTHING`, so deleting a row lets a replay publish again.
+/// A row whose task (`payload.task_id`) is still `running` is therefore kept whatever its age: a
+/// buffer publish is fenced only by the running attempt, and a heartbeating attempt can outlive
+/// any fixed retention. Task wake-ups are keyed by `(task_id, attempt)` and written once per
+/// attempt, so they cannot be replayed once sent.
+pub async fn sweep_sent_outbox_once(
+    pool: &PgPool,
+    retention: Duration,
+    max: i64,
+) -> anyhow::Result<u64> {
+    let retention_secs = i64::try_from(retention.as_secs()).unwrap_or(i64::MAX);
+    let deleted = sqlx::query(
+        r#"
+        DELETE FROM state.outbox
+        WHERE outbox_id IN (
+          SELECT o.outbox_id
+          FROM state.outbox o
+          WHERE o.status = 'sent'
+            AND o.updated_at < now() - make_interval(secs => $1::double precision)
+            AND NOT EXISTS (
+              SELECT 1
+              FROM state.tasks t
+              WHERE t.task_id = (o.payload->>'task_id')::uuid
+                AND t.status = 'running'
+            )
+          ORDER BY o.updated_at
+          LIMIT $2
+          FOR UPDATE SKIP LOCKED
+        )
+        "#,
+    )
+    .bind(retention_secs as f64)
+    .bind(max)
+    .execute(pool)
+    .await
+    .context("delete sent outbox rows")?;
+
+    Ok(deleted.rows_affected())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadOutboxRow {
+    pub outbox_id: Uuid,
+    pub topic: String,
+    pub payload: Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// List `dead` rows, most recently failed first.
+pub async fn list_dead_outbox(
+    pool: &PgPool,
+    topic: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<Vec<DeadOutboxRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT outbox_id, topic, payload, attempts, last_error, created_at, updated_at
+        FROM state.outbox
+        WHERE status = 'dead'
+          AND ($1::text IS NULL OR topic = $1)
+        ORDER BY updated_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(topic)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .context("list dead outbox rows")?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(DeadOutboxRow {
+                outbox_id: row.try_get("outbox_id").context("outbox_id")?,
+                topic: row.try_get("topic").context("topic")?,
+                payload: row.try_get("payload").context("payload")?,
+                attempts: row.try_get("attempts").context("attempts")?,
+                last_error: row.try_get("last_error").context("last_error")?,
+                created_at: row.try_get("created_at").context("created_at")?,
+                updated_at: row.try_get("updated_at").context("updated_at")?,
+            })
+        })
+        .collect()
+}
+
+/// Re-arm `dead` rows: back to `pending` with a fresh retry budget, due immediately.
+///
+/// Selects by `outbox_id` and/or `topic`; at least one must be provided. `last_error` is kept for
+/// reference until the row is next attempted.
+pub async fn rearm_dead_outbox(
+    pool: &PgPool,
+    outbox_id: Option<Uuid>,
+    topic: Option<&str>,
+) -> anyhow::Result<Vec<Uuid>> {
+    anyhow::ensure!(
+        outbox_id.is_some() || topic.is_some(),
+        "re-arm requires an outbox_id or topic"
+    );
+
+    let ids = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        UPDATE state.outbox
+        SET status = 'pending',
+            attempts = 0,
+            available_at = now(),
+            updated_at = now()
+        WHERE status = 'dead'
+          AND ($1::uuid IS NULL OR outbox_id = $1)
+          AND ($2::text IS NULL OR topic = $2)
+        RETURNING outbox_id
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(topic)
+    .fetch_all(pool)
+    .await
+    .context("re-arm dead outbox rows")?;
+
+    Ok(ids)
+}
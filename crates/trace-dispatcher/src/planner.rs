@@ -159,9 +159,11 @@ pub async fn planner_tick_once_scoped(
                         chain_id,
                         &rpc_pool,
                         &dataset_key,
-                        from_block,
-                        tail_lag,
-                        max_age as i64,
+                        FollowHeadWindow {
+                            from_block,
+                            tail_lag,
+                            max_head_age_seconds: max_age as i64,
+                        },
                     )
                     .await?
                 }
@@ -180,28 +182,33 @@ pub async fn planner_tick_once_scoped(
                 continue;
             };
 
-            out.scheduled_ranges += plan_stream_once(
-                pool,
-                task_wakeup_queue,
+            let plan = StreamPlan {
                 job_id,
                 org_id,
                 chain_id,
                 from_block,
-                eligible_exclusive,
-                &dataset_key,
-                &cryo_dataset_name,
-                &rpc_pool,
-                &config_hash,
-                chunk_size.unwrap_or(1_000),
-                max_inflight.unwrap_or(10),
-            )
-            .await?;
+                dataset_key: &dataset_key,
+                cryo_dataset_name: &cryo_dataset_name,
+                rpc_pool: &rpc_pool,
+                config_hash: &config_hash,
+                chunk_size: chunk_size.unwrap_or(1_000),
+                max_inflight: max_inflight.unwrap_or(10),
+            };
+            out.scheduled_ranges +=
+                plan_stream_once(pool, task_wakeup_queue, &plan, eligible_exclusive).await?;
         }
     }
 
     Ok(out)
 }
 
+/// Planning window of a `follow_head` stream.
+struct FollowHeadWindow {
+    from_block: i64,
+    tail_lag: i64,
+    max_head_age_seconds: i64,
+}
+
 async fn observed_head_eligible_exclusive(
     pool: &PgPool,
     job_id: Uuid,
@@ -209,10 +216,13 @@ async fn observed_head_eligible_exclusive(
     chain_id: i64,
     rpc_pool: &str,
     dataset_key: &str,
-    from_block: i64,
-    tail_lag: i64,
-    max_head_age_seconds: i64,
+    window: FollowHeadWindow,
 ) -> anyhow::Result<Option<i64>> {
+    let FollowHeadWindow {
+        from_block,
+        tail_lag,
+        max_head_age_seconds,
+    } = window;
     let row_opt = sqlx::query(
         r#"
         SELECT head_block, observed_at
@@ -260,22 +270,38 @@ async fn observed_head_eligible_exclusive(
     Ok(Some(eligible_exclusive))
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn plan_stream_once(
-    pool: &PgPool,
-    task_wakeup_queue: &str,
+/// One stream of a chain_sync job, as the planner schedules it.
+struct StreamPlan<'a> {
     job_id: Uuid,
     org_id: Uuid,
     chain_id: i64,
     from_block: i64,
-    eligible_exclusive: i64,
-    dataset_key: &str,
-    cryo_dataset_name: &str,
-    rpc_pool: &str,
-    config_hash: &str,
+    dataset_key: &'a str,
+    cryo_dataset_name: &'a str,
+    rpc_pool: &'a str,
+    config_hash: &'a str,
     chunk_size: i64,
     max_inflight: i64,
+}
+
+async fn plan_stream_once(
+    pool: &PgPool,
+    task_wakeup_queue: &str,
+    stream: &StreamPlan<'_>,
+    eligible_exclusive: i64,
 ) -> anyhow::Result<i64> {
+    let &StreamPlan {
+        job_id,
+        org_id,
+        chain_id,
+        from_block,
+        dataset_key,
+        cryo_dataset_name,
+        rpc_pool,
+        config_hash,
+        chunk_size,
+        max_inflight,
+    } = stream;
     let dataset_uuid = crate::chain_sync::derive_dataset_uuid(org_id, chain_id, dataset_key)
         .with_context(|| {
             format!(
@@ -332,7 +358,7 @@ async fn plan_stream_once(
         FROM state.chain_sync_scheduled_ranges
         WHERE job_id = $1
           AND dataset_key = $2
-          AND status <> 'completed'
+          AND status = 'scheduled'
         "#,
     )
     .bind(job_id)
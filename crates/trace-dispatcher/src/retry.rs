@@ -0,0 +1,249 @@
+//! Bounded task retries.
+//!
+//! Retryable failures (worker-reported `retryable_error` and expired leases) re-queue the task
+//! under a new attempt with an exponential backoff written into the wakeup outbox `available_at`.
+//! Once the per-operator attempt budget is exhausted the task moves to the terminal `failed`
+//! state with the last error reason recorded (and its chain_sync range, if any, is failed too).
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{Postgres, Row, Transaction};
+use std::{collections::BTreeMap, time::Duration};
+use trace_core::jitter;
+use uuid::Uuid;
+
+/// Max bytes of an error reason persisted on `state.tasks.last_error`.
+pub const MAX_TASK_ERROR_BYTES: usize = 1024;
+
+/// Retry policy for a single operator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts allowed (including the first). Values below 1 are treated as 1.
+    pub max_attempts: i64,
+    /// Backoff before the second attempt; doubles for every subsequent attempt.
+    pub base_delay: Duration,
+    /// Upper bound on the (pre-jitter) backoff.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff to apply before `next_attempt` is made available.
+    ///
+    /// Uses "equal jitter": half of the capped exponential delay is fixed and the other half is
+    /// scaled by `jitter` (expected in `[0, 1)`), so retries never fire immediately but still
+    /// spread out under correlated failures.
+    pub fn backoff(&self, next_attempt: i64, jitter: f64) -> Duration {
+        let exp = u32::try_from(next_attempt.saturating_sub(2).clamp(0, 32)).unwrap_or(32);
+        let capped = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(exp))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        let half = capped / 2;
+        half + half.mul_f64(jitter.clamp(0.0, 1.0))
+    }
+
+    /// Whether a task that just failed `attempt` may be retried.
+    pub fn allows_retry_after(&self, attempt: i64) -> bool {
+        attempt < self.max_attempts.max(1)
+    }
+}
+
+/// Task retry configuration: a default policy plus per-operator overrides.
+///
+/// The operator is read from the task payload's `operator` field (e.g. `cryo_ingest`).
+#[derive(Clone, Debug, Default)]
+pub struct TaskRetryConfig {
+    pub default: RetryPolicy,
+    pub per_operator: BTreeMap<String, RetryPolicy>,
+}
+
+impl TaskRetryConfig {
+    pub fn policy_for(&self, operator: Option<&str>) -> &RetryPolicy {
+        operator
+            .and_then(|op| self.per_operator.get(op))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Parse `op=N,op2=M` into per-operator `max_attempts` overrides.
+pub fn parse_operator_max_attempts(spec: &str) -> anyhow::Result<BTreeMap<String, i64>> {
+    let mut out = BTreeMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((op, n)) = entry.split_once('=') else {
+            anyhow::bail!("invalid operator retry entry `{entry}` (expected `operator=N`)");
+        };
+        let op = op.trim();
+        if op.is_empty() {
+            anyhow::bail!("invalid operator retry entry `{entry}` (empty operator)");
+        }
+        let n = n
+            .trim()
+            .parse::<i64>()
+            .with_context(|| format!("parse max attempts for operator `{op}`"))?;
+        if n < 1 {
+            anyhow::bail!("max attempts for operator `{op}` must be >= 1");
+        }
+        out.insert(op.to_string(), n);
+    }
+    Ok(out)
+}
+
+/// Outcome of [`retry_or_fail_task`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RetryDecision {
+    Retried {
+        attempt: i64,
+        available_at: DateTime<Utc>,
+    },
+    Failed,
+    /// The task was no longer at `attempt`/`running` (raced with another writer).
+    Skipped,
+}
+
+/// Re-queue a failed `running` attempt with backoff, or fail it terminally when the operator's
+/// attempt budget is exhausted.
+///
+/// Must run inside the caller's transaction so the state transition and the wakeup outbox row
+/// commit atomically.
+pub(crate) async fn retry_or_fail_task(
+    tx: &mut Transaction<'_, Postgres>,
+    retry: &TaskRetryConfig,
+    task_wakeup_queue: &str,
+    task_id: Uuid,
+    attempt: i64,
+    payload: &Value,
+    reason: &str,
+) -> anyhow::Result<RetryDecision> {
+    let operator = payload.get("operator").and_then(Value::as_str);
+    let policy = retry.policy_for(operator);
+    let reason = truncate_error(reason);
+
+    // Operator-initiated reschedules (e.g. `chain-sync retry-ranges`) start a fresh budget.
+    let retry_base_attempt: i64 = sqlx::query_scalar(
+        r#"
+        SELECT retry_base_attempt
+        FROM state.tasks
+        WHERE task_id = $1
+        "#,
+    )
+    .bind(task_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .with_context(|| format!("fetch retry_base_attempt task_id={task_id}"))?
+    .unwrap_or(0);
+
+    if !policy.allows_retry_after(attempt - retry_base_attempt) {
+        let updated = sqlx::query(
+            r#"
+            UPDATE state.tasks
+            SET status = 'failed',
+                last_error = $3,
+                lease_token = NULL,
+                lease_expires_at = NULL,
+                updated_at = now()
+            WHERE task_id = $1
+              AND attempt = $2
+              AND status = 'running'
+            "#,
+        )
+        .bind(task_id)
+        .bind(attempt)
+        .bind(reason)
+        .execute(&mut **tx)
+        .await
+        .with_context(|| format!("mark task_id={task_id} failed"))?;
+
+        if updated.rows_affected() == 0 {
+            return Ok(RetryDecision::Skipped);
+        }
+
+        crate::mark_chain_sync_range_failed(tx, task_id, reason).await?;
+
+        tracing::warn!(
+            event = "trace.dispatcher.task.retries_exhausted",
+            %task_id,
+            attempt,
+            operator = operator.unwrap_or(""),
+            error = reason,
+            "task failed after exhausting retries"
+        );
+        return Ok(RetryDecision::Failed);
+    }
+
+    let row = sqlx::query(
+        r#"
+        UPDATE state.tasks
+        SET attempt = attempt + 1,
+            status = 'queued',
+            last_error = $3,
+            lease_token = NULL,
+            lease_expires_at = NULL,
+            updated_at = now()
+        WHERE task_id = $1
+          AND attempt = $2
+          AND status = 'running'
+        RETURNING attempt
+        "#,
+    )
+    .bind(task_id)
+    .bind(attempt)
+    .bind(reason)
+    .fetch_optional(&mut **tx)
+    .await
+    .with_context(|| format!("mark task_id={task_id} for retry"))?;
+
+    let Some(row) = row else {
+        return Ok(RetryDecision::Skipped);
+    };
+    let new_attempt: i64 = row.try_get("attempt")?;
+
+    let delay = policy.backoff(new_attempt - retry_base_attempt, jitter::unit());
+    let available_at = Utc::now()
+        + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::seconds(0));
+
+    let outbox_id = crate::outbox_id_for_task_wakeup(task_id, new_attempt);
+    let wakeup = serde_json::json!({ "task_id": task_id });
+    sqlx::query(
+        r#"
+        INSERT INTO state.outbox (outbox_id, topic, payload, available_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (outbox_id) DO NOTHING
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(task_wakeup_queue)
+    .bind(wakeup)
+    .bind(available_at)
+    .execute(&mut **tx)
+    .await
+    .with_context(|| format!("enqueue retry wakeup task_id={task_id} attempt={new_attempt}"))?;
+
+    Ok(RetryDecision::Retried {
+        attempt: new_attempt,
+        available_at,
+    })
+}
+
+pub(crate) fn truncate_error(reason: &str) -> &str {
+    if reason.len() <= MAX_TASK_ERROR_BYTES {
+        return reason;
+    }
+    let mut end = MAX_TASK_ERROR_BYTES;
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    &reason[..end]
+}
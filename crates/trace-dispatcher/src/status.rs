@@ -24,9 +24,23 @@ pub struct ChainSyncStreamStatus {
     pub next_block: i64,
     pub inflight: i64,
     pub completed: i64,
+    pub failed: i64,
 
     pub observed_head: Option<i64>,
     pub eligible_exclusive: Option<i64>,
+
+    /// Ranges that failed terminally; reschedule with `chain-sync retry-ranges`.
+    pub failed_ranges: Vec<FailedRangeStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedRangeStatus {
+    pub range_start: i64,
+    pub range_end: i64,
+    pub task_id: Uuid,
+    pub attempt: Option<i64>,
+    pub error: Option<String>,
+    pub failed_at: DateTime<Utc>,
 }
 
 pub async fn fetch_chain_sync_status(
@@ -61,7 +75,13 @@ pub async fn fetch_chain_sync_status(
         WITH inflight AS (
           SELECT job_id, dataset_key, count(*)::bigint AS cnt
           FROM state.chain_sync_scheduled_ranges
-          WHERE status <> 'completed'
+          WHERE status = 'scheduled'
+          GROUP BY job_id, dataset_key
+        ),
+        failed AS (
+          SELECT job_id, dataset_key, count(*)::bigint AS cnt
+          FROM state.chain_sync_scheduled_ranges
+          WHERE status = 'failed'
           GROUP BY job_id, dataset_key
         ),
         completed AS (
@@ -77,6 +97,7 @@ pub async fn fetch_chain_sync_status(
           c.next_block,
           COALESCE(i.cnt, 0) AS inflight,
           COALESCE(d.cnt, 0) AS completed,
+          COALESCE(f.cnt, 0) AS failed,
           h.head_block AS head_block,
           h.observed_at AS head_observed_at
         FROM state.chain_sync_streams s
@@ -89,6 +110,9 @@ pub async fn fetch_chain_sync_status(
         LEFT JOIN completed d
           ON d.job_id = s.job_id
          AND d.dataset_key = s.dataset_key
+        LEFT JOIN failed f
+          ON f.job_id = s.job_id
+         AND f.dataset_key = s.dataset_key
         LEFT JOIN state.chain_head_observations h
           ON h.org_id = $2
          AND h.chain_id = $3
@@ -104,6 +128,39 @@ pub async fn fetch_chain_sync_status(
     .await
     .context("fetch chain_sync stream status")?;
 
+    let failed_rows = sqlx::query(
+        r#"
+        SELECT r.dataset_key, r.range_start, r.range_end, r.task_id, t.attempt, r.last_error,
+               r.updated_at
+        FROM state.chain_sync_scheduled_ranges r
+        LEFT JOIN state.tasks t
+          ON t.task_id = r.task_id
+        WHERE r.job_id = $1
+          AND r.status = 'failed'
+        ORDER BY r.dataset_key, r.range_start
+        "#,
+    )
+    .bind(job_id)
+    .fetch_all(pool)
+    .await
+    .context("fetch chain_sync failed ranges")?;
+
+    let mut failed_by_stream = std::collections::HashMap::<String, Vec<FailedRangeStatus>>::new();
+    for row in failed_rows {
+        let dataset_key: String = row.try_get("dataset_key").context("dataset_key")?;
+        failed_by_stream
+            .entry(dataset_key)
+            .or_default()
+            .push(FailedRangeStatus {
+                range_start: row.try_get("range_start").context("range_start")?,
+                range_end: row.try_get("range_end").context("range_end")?,
+                task_id: row.try_get("task_id").context("task_id")?,
+                attempt: row.try_get("attempt").context("attempt")?,
+                error: row.try_get("last_error").context("last_error")?,
+                failed_at: row.try_get("updated_at").context("updated_at")?,
+            });
+    }
+
     let now = Utc::now();
     let streams = rows
         .into_iter()
@@ -116,6 +173,8 @@ pub async fn fetch_chain_sync_status(
             let next_block: i64 = row.try_get("next_block").context("next_block")?;
             let inflight: i64 = row.try_get("inflight").context("inflight")?;
             let completed: i64 = row.try_get("completed").context("completed")?;
+            let failed: i64 = row.try_get("failed").context("failed")?;
+            let failed_ranges = failed_by_stream.remove(&dataset_key).unwrap_or_default();
             let head_block: Option<i64> = row.try_get("head_block").context("head_block")?;
             let head_observed_at: Option<DateTime<Utc>> = row
                 .try_get("head_observed_at")
@@ -141,8 +200,10 @@ pub async fn fetch_chain_sync_status(
                 next_block,
                 inflight,
                 completed,
+                failed,
                 observed_head,
                 eligible_exclusive,
+                failed_ranges,
             })
         })
         .collect::<anyhow::Result<Vec<_>>>()?;
@@ -182,3 +243,64 @@ fn compute_follow_head_window(
     let eligible = (head_block + 1).saturating_sub(tail_lag.max(0));
     (Some(head_block), Some(from_block.max(eligible)))
 }
+
+#[derive(Debug, Serialize)]
+pub struct TaskStatus {
+    pub task_id: Uuid,
+    pub status: String,
+    pub attempt: i64,
+    pub operator: Option<String>,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub async fn fetch_task_status(pool: &PgPool, task_id: Uuid) -> anyhow::Result<TaskStatus> {
+    let row = sqlx::query(
+        r#"
+        SELECT task_id, status, attempt, payload->>'operator' AS operator, last_error, updated_at
+        FROM state.tasks
+        WHERE task_id = $1
+        "#,
+    )
+    .bind(task_id)
+    .fetch_one(pool)
+    .await
+    .context("fetch task")?;
+
+    task_status_from_row(&row)
+}
+
+/// List tasks in `status` (e.g. `failed`), most recently updated first.
+pub async fn fetch_tasks_by_status(
+    pool: &PgPool,
+    status: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<TaskStatus>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT task_id, status, attempt, payload->>'operator' AS operator, last_error, updated_at
+        FROM state.tasks
+        WHERE status = $1
+        ORDER BY updated_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(status)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .context("list tasks by status")?;
+
+    rows.iter().map(task_status_from_row).collect()
+}
+
+fn task_status_from_row(row: &sqlx::postgres::PgRow) -> anyhow::Result<TaskStatus> {
+    Ok(TaskStatus {
+        task_id: row.try_get("task_id").context("task_id")?,
+        status: row.try_get("status").context("status")?,
+        attempt: row.try_get("attempt").context("attempt")?,
+        operator: row.try_get("operator").context("operator")?,
+        last_error: row.try_get("last_error").context("last_error")?,
+        updated_at: row.try_get("updated_at").context("updated_at")?,
+    })
+}
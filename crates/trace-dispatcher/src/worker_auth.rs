@@ -0,0 +1,134 @@
+//! Worker identity for `/internal/task-claim`.
+//!
+//! Workers authenticate with an `X-Trace-Worker-Token` header. Each credential names a worker and
+//! the operators (the task payload's `operator` field) it may claim, so a leaked token for one
+//! worker pool cannot mint capabilities for another pool's tasks. Tokens are only kept as SHA-256
+//! digests. Deployments that use mTLS terminate client certificates at the ingress and forward a
+//! per-worker token; the dispatcher itself only checks tokens.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// Operators a worker may claim tasks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperatorAllowlist {
+    /// Any operator, including tasks whose payload names none.
+    Any,
+    Only(BTreeSet<String>),
+}
+
+impl OperatorAllowlist {
+    pub fn allows(&self, operator: Option<&str>) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Only(ops) => operator.is_some_and(|op| ops.contains(op)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkerCredential {
+    pub worker_id: String,
+    pub operators: OperatorAllowlist,
+    token_sha256: [u8; 32],
+}
+
+impl std::fmt::Debug for WorkerCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerCredential")
+            .field("worker_id", &self.worker_id)
+            .field("operators", &self.operators)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The set of workers allowed to claim tasks. An empty set rejects every claim.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerAuth {
+    credentials: Vec<WorkerCredential>,
+}
+
+impl WorkerAuth {
+    /// Parse `worker_id=token:op1|op2,...` (`*` allows any operator).
+    ///
+    /// Tokens may not contain `,`; a `:` is allowed since the operator list is split off the
+    /// last one.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut auth = Self::default();
+        for entry in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((worker_id, rest)) = entry.split_once('=') else {
+                anyhow::bail!("invalid worker credential entry (expected `worker_id=token:ops`)");
+            };
+            let worker_id = worker_id.trim();
+            let Some((token, ops)) = rest.rsplit_once(':') else {
+                anyhow::bail!("worker credential for `{worker_id}` has no operator allowlist");
+            };
+            let operators = match ops.trim() {
+                "*" => OperatorAllowlist::Any,
+                ops => OperatorAllowlist::Only(
+                    ops.split('|')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                ),
+            };
+            auth = auth.with_worker(worker_id, token.trim(), operators)?;
+        }
+        Ok(auth)
+    }
+
+    pub fn with_worker(
+        mut self,
+        worker_id: &str,
+        token: &str,
+        operators: OperatorAllowlist,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !worker_id.is_empty(),
+            "worker credential has an empty worker id"
+        );
+        anyhow::ensure!(
+            !token.is_empty(),
+            "worker credential for `{worker_id}` has an empty token"
+        );
+        if let OperatorAllowlist::Only(ops) = &operators {
+            anyhow::ensure!(
+                !ops.is_empty(),
+                "worker credential for `{worker_id}` allows no operators"
+            );
+        }
+        anyhow::ensure!(
+            self.credentials.iter().all(|c| c.worker_id != worker_id),
+            "duplicate worker credential for `{worker_id}`"
+        );
+
+        let token_sha256: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        anyhow::ensure!(
+            self.credentials
+                .iter()
+                .all(|c| c.token_sha256 != token_sha256),
+            "worker `{worker_id}` reuses another worker's token"
+        );
+        self.credentials.push(WorkerCredential {
+            worker_id: worker_id.to_string(),
+            operators,
+            token_sha256,
+        });
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.credentials.is_empty()
+    }
+
+    /// The worker presenting `token`, if any.
+    ///
+    /// Digests are compared rather than raw tokens, so match timing reveals nothing usable about
+    /// the configured tokens.
+    pub fn authenticate(&self, token: &str) -> Option<&WorkerCredential> {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        self.credentials.iter().find(|c| c.token_sha256 == digest)
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::Context;
+use reqwest::StatusCode;
+use serde_json::Value;
+use sqlx::PgPool;
+use trace_core::{DatasetStorageRef, S3Grants};
+use trace_dispatcher::grants::write_allowed;
+use trace_dispatcher::{BackgroundLoops, TASK_CAPABILITY_HEADER, WORKER_TOKEN_HEADER};
+use uuid::Uuid;
+
+mod common;
+
+use common::{
+    connect_and_migrate, start_dispatcher, test_capability, test_config, ARTIFACT_ROOT,
+    WORKER_TOKEN,
+};
+
+async fn insert_task(pool: &PgPool, payload: Value) -> anyhow::Result<Uuid> {
+    let task_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO state.tasks (task_id, status, payload)
+        VALUES ($1, 'queued', $2)
+        "#,
+    )
+    .bind(task_id)
+    .bind(payload)
+    .execute(pool)
+    .await?;
+    Ok(task_id)
+}
+
+async fn claim(
+    client: &reqwest::Client,
+    base: &str,
+    task_id: Uuid,
+) -> anyhow::Result<reqwest::Response> {
+    Ok(client
+        .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, WORKER_TOKEN)
+        .json(&serde_json::json!({ "task_id": task_id }))
+        .send()
+        .await?)
+}
+
+async fn post_task(
+    client: &reqwest::Client,
+    base: &str,
+    path: &str,
+    claim: &Value,
+    body: Value,
+) -> anyhow::Result<StatusCode> {
+    let mut body = body;
+    body["task_id"] = claim["task_id"].clone();
+    body["attempt"] = claim["attempt"].clone();
+    body["lease_token"] = claim["lease_token"].clone();
+    Ok(client
+        .post(format!("{base}{path}"))
+        .header(
+            TASK_CAPABILITY_HEADER,
+            claim["capability_token"]
+                .as_str()
+                .context("capability_token")?,
+        )
+        .json(&body)
+        .send()
+        .await?
+        .status())
+}
+
+fn publication(dataset_uuid: Uuid, uri: &str) -> anyhow::Result<Value> {
+    Ok(serde_json::json!({
+        "dataset_uuid": dataset_uuid,
+        "dataset_version": Uuid::new_v4(),
+        "storage_ref": DatasetStorageRef::from_uri(uri, "*.parquet".to_string())?,
+        "config_hash": "grants_test",
+        "range_start": 0,
+        "range_end": 10,
+    }))
+}
+
+#[tokio::test]
+async fn capabilities_grant_only_declared_inputs_and_attempt_outputs() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let capability = test_capability()?;
+    let server = start_dispatcher(&pool, test_config()?, BackgroundLoops::default()).await?;
+    let base = format!("http://{}", server.addr);
+    let client = reqwest::Client::new();
+
+    let dataset_uuid = Uuid::new_v4();
+    let dataset_version = Uuid::new_v4();
+    let storage_prefix = format!("s3://trace-dispatcher-test/datasets/{dataset_version}/");
+    sqlx::query(
+        r#"
+        INSERT INTO state.dataset_versions (
+          dataset_version, dataset_uuid, storage_prefix, config_hash, range_start, range_end
+        ) VALUES ($1, $2, $3, 'grants_test', 0, 10)
+        "#,
+    )
+    .bind(dataset_version)
+    .bind(dataset_uuid)
+    .bind(&storage_prefix)
+    .execute(&pool)
+    .await?;
+
+    let task_id = insert_task(
+        &pool,
+        serde_json::json!({
+            "operator": "udf",
+            "inputs": [{ "dataset_uuid": dataset_uuid, "dataset_version": dataset_version }],
+        }),
+    )
+    .await?;
+    let claimed: Value = claim(&client, &base, task_id)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let claims = capability.verify(claimed["capability_token"].as_str().context("token")?)?;
+    assert_eq!(claims.datasets.len(), 1);
+    assert_eq!(claims.datasets[0].dataset_version, dataset_version);
+    assert_eq!(
+        claims.datasets[0].storage_ref.as_ref().map(|r| r.uri()),
+        Some(storage_prefix.clone())
+    );
+    assert_eq!(claims.s3.read_prefixes, vec![storage_prefix]);
+    let output_prefix = format!("{ARTIFACT_ROOT}batches/{task_id}/1/");
+    assert_eq!(claims.s3.write_prefixes, vec![output_prefix.clone()]);
+
+    let batch = |uri: String| {
+        serde_json::json!({
+            "batch_uri": uri,
+            "content_type": "application/jsonl",
+            "batch_size_bytes": 1,
+            "dedupe_scope": "grants_test",
+        })
+    };
+    let other_task = format!("{ARTIFACT_ROOT}batches/{}/1/out.jsonl", Uuid::new_v4());
+    let climbing = format!("{output_prefix}../../../elsewhere.jsonl");
+    for uri in [other_task, climbing] {
+        assert_eq!(
+            post_task(
+                &client,
+                &base,
+                "/v1/task/buffer-publish",
+                &claimed,
+                batch(uri)
+            )
+            .await?,
+            StatusCode::FORBIDDEN
+        );
+    }
+    assert_eq!(
+        post_task(
+            &client,
+            &base,
+            "/v1/task/buffer-publish",
+            &claimed,
+            batch(format!("{output_prefix}out.jsonl"))
+        )
+        .await?,
+        StatusCode::OK
+    );
+
+    let outside = publication(Uuid::new_v4(), "s3://trace-dispatcher-test/datasets/x/")?;
+    assert_eq!(
+        post_task(
+            &client,
+            &base,
+            "/v1/task/complete",
+            &claimed,
+            serde_json::json!({ "outcome": "success", "datasets_published": [outside] }),
+        )
+        .await?,
+        StatusCode::FORBIDDEN
+    );
+    let inside = publication(Uuid::new_v4(), &format!("{output_prefix}dataset/"))?;
+    assert_eq!(
+        post_task(
+            &client,
+            &base,
+            "/v1/task/complete",
+            &claimed,
+            serde_json::json!({ "outcome": "success", "datasets_published": [inside] }),
+        )
+        .await?,
+        StatusCode::OK
+    );
+
+    // Inputs that are not published yet leave the task queued for a later claim.
+    let pending = insert_task(
+        &pool,
+        serde_json::json!({
+            "inputs": [{ "dataset_uuid": Uuid::new_v4(), "dataset_version": Uuid::new_v4() }],
+        }),
+    )
+    .await?;
+    assert_eq!(
+        claim(&client, &base, pending).await?.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    let status: String = sqlx::query_scalar("SELECT status FROM state.tasks WHERE task_id = $1")
+        .bind(pending)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(status, "queued");
+
+    // cryo_ingest tasks may also publish under their block range's version-addressed prefix.
+    let cryo_dataset = Uuid::new_v4();
+    let cryo_task = insert_task(
+        &pool,
+        serde_json::json!({
+            "operator": "cryo_ingest",
+            "chain_id": 1,
+            "dataset_uuid": cryo_dataset,
+            "range_start": 0,
+            "range_end": 10,
+            "config_hash": "grants_test",
+        }),
+    )
+    .await?;
+    let cryo_claim: Value = claim(&client, &base, cryo_task)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let cryo_pub = publication(
+        cryo_dataset,
+        &format!(
+            "{ARTIFACT_ROOT}cryo/1/{cryo_dataset}/0_10/{}/",
+            Uuid::new_v4()
+        ),
+    )?;
+    assert_eq!(
+        post_task(
+            &client,
+            &base,
+            "/v1/task/complete",
+            &cryo_claim,
+            serde_json::json!({ "outcome": "success", "datasets_published": [cryo_pub] }),
+        )
+        .await?,
+        StatusCode::OK
+    );
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[test]
+fn write_prefixes_are_directories() {
+    let grants = S3Grants {
+        read_prefixes: Vec::new(),
+        write_prefixes: vec![
+            "s3://bucket/batches/t/1/".to_string(),
+            "s3://bucket/unterminated".to_string(),
+        ],
+    };
+    assert!(write_allowed(&grants, "s3://bucket/batches/t/1/out.jsonl"));
+    assert!(write_allowed(&grants, "s3://bucket/batches/t/1/"));
+    assert!(!write_allowed(
+        &grants,
+        "s3://bucket/batches/t/10/out.jsonl"
+    ));
+    assert!(!write_allowed(
+        &grants,
+        "s3://bucket/batches/t/1/../2/out.jsonl"
+    ));
+    assert!(!write_allowed(
+        &grants,
+        "s3://bucket/unterminated-sibling/x"
+    ));
+    assert!(!write_allowed(&grants, "s3://other/batches/t/1/out.jsonl"));
+}
@@ -0,0 +1,298 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::time::Duration;
+use trace_core::lite::pgqueue::PgQueue;
+use trace_core::{Queue as QueueTrait, QueueMessage};
+use trace_dispatcher::outbox::{
+    drain_outbox_once, list_dead_outbox, rearm_dead_outbox, sweep_sent_outbox_once,
+};
+use trace_dispatcher::retry::RetryPolicy;
+use uuid::Uuid;
+
+mod common;
+
+use common::connect_and_migrate;
+
+/// Delegates to `PgQueue` but fails every publish to `poison_topic`.
+struct PoisonQueue {
+    inner: PgQueue,
+    poison_topic: String,
+}
+
+#[async_trait]
+impl QueueTrait for PoisonQueue {
+    async fn publish(
+        &self,
+        queue: &str,
+        payload: Value,
+        available_at: DateTime<Utc>,
+    ) -> trace_core::Result<String> {
+        if queue == self.poison_topic {
+            return Err(trace_core::Error::msg("queue unavailable"));
+        }
+        self.inner.publish(queue, payload, available_at).await
+    }
+
+    async fn receive(
+        &self,
+        queue: &str,
+        max: i64,
+        visibility_timeout: Duration,
+    ) -> trace_core::Result<Vec<QueueMessage>> {
+        self.inner.receive(queue, max, visibility_timeout).await
+    }
+
+    async fn ack(&self, ack_token: &str) -> trace_core::Result<()> {
+        self.inner.ack(ack_token).await
+    }
+
+    async fn nack_or_requeue(&self, ack_token: &str, delay: Duration) -> trace_core::Result<()> {
+        self.inner.nack_or_requeue(ack_token, delay).await
+    }
+}
+
+async fn insert_outbox(pool: &PgPool, topic: &str) -> anyhow::Result<Uuid> {
+    let outbox_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO state.outbox (outbox_id, topic, payload)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(outbox_id)
+    .bind(topic)
+    .bind(serde_json::json!({ "n": 1 }))
+    .execute(pool)
+    .await?;
+    Ok(outbox_id)
+}
+
+async fn outbox_row(
+    pool: &PgPool,
+    outbox_id: Uuid,
+) -> anyhow::Result<(String, i32, Option<String>)> {
+    let row = sqlx::query(
+        r#"
+        SELECT status, attempts, last_error
+        FROM state.outbox
+        WHERE outbox_id = $1
+        "#,
+    )
+    .bind(outbox_id)
+    .fetch_one(pool)
+    .await?;
+    Ok((
+        row.try_get("status")?,
+        row.try_get("attempts")?,
+        row.try_get("last_error")?,
+    ))
+}
+
+#[tokio::test]
+async fn poison_row_goes_dead_without_blocking_others_and_can_be_rearmed() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let poison_topic = format!("outbox_poison_test_{}", Uuid::new_v4());
+    let ok_topic = format!("outbox_ok_test_{}", Uuid::new_v4());
+    let queue = PoisonQueue {
+        inner: PgQueue::new(pool.clone()),
+        poison_topic: poison_topic.clone(),
+    };
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    // The poison row is older, so it is selected first in every batch.
+    let poison_id = insert_outbox(&pool, &poison_topic).await?;
+    let ok_id = insert_outbox(&pool, &ok_topic).await?;
+
+    let first = drain_outbox_once(&pool, &queue, 1000, &policy).await?;
+    anyhow::ensure!(first.retried >= 1, "expected a retry, got {first:?}");
+
+    let (status, attempts, last_error) = outbox_row(&pool, ok_id).await?;
+    anyhow::ensure!(status == "sent", "ok row should be sent, got {status}");
+    anyhow::ensure!(attempts == 1, "ok row attempts: {attempts}");
+    anyhow::ensure!(last_error.is_none());
+
+    let (status, attempts, last_error) = outbox_row(&pool, poison_id).await?;
+    anyhow::ensure!(
+        status == "pending",
+        "poison row should back off, got {status}"
+    );
+    anyhow::ensure!(attempts == 1, "poison row attempts: {attempts}");
+    anyhow::ensure!(
+        last_error
+            .as_deref()
+            .is_some_and(|e| e.contains("queue unavailable")),
+        "unexpected last_error: {last_error:?}"
+    );
+
+    for _ in 0..10 {
+        if outbox_row(&pool, poison_id).await?.0 == "dead" {
+            break;
+        }
+        drain_outbox_once(&pool, &queue, 1000, &policy).await?;
+    }
+    let (status, attempts, _) = outbox_row(&pool, poison_id).await?;
+    anyhow::ensure!(status == "dead", "poison row should be dead, got {status}");
+    anyhow::ensure!(attempts == 3, "poison row attempts: {attempts}");
+
+    let dead = list_dead_outbox(&pool, Some(&poison_topic), 10).await?;
+    anyhow::ensure!(dead.len() == 1 && dead[0].outbox_id == poison_id);
+
+    anyhow::ensure!(
+        rearm_dead_outbox(&pool, None, None).await.is_err(),
+        "re-arm without a selector must be rejected"
+    );
+    let rearmed = rearm_dead_outbox(&pool, None, Some(&poison_topic)).await?;
+    anyhow::ensure!(rearmed == vec![poison_id], "rearmed: {rearmed:?}");
+    let (status, attempts, _) = outbox_row(&pool, poison_id).await?;
+    anyhow::ensure!(status == "pending" && attempts == 0);
+
+    // Once the queue recovers the re-armed row is delivered.
+    let healthy = PgQueue::new(pool.clone());
+    for _ in 0..10 {
+        if outbox_row(&pool, poison_id).await?.0 == "sent" {
+            break;
+        }
+        drain_outbox_once(&pool, &healthy, 1000, &policy).await?;
+    }
+    anyhow::ensure!(outbox_row(&pool, poison_id).await?.0 == "sent");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sweeper_deletes_only_aged_sent_rows() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let topic = format!("outbox_sweep_test_{}", Uuid::new_v4());
+
+    let aged = insert_outbox(&pool, &topic).await?;
+    let fresh = insert_outbox(&pool, &topic).await?;
+    let pending = insert_outbox(&pool, &topic).await?;
+    // An aged row whose task is still running: its attempt could replay the publish.
+    let task_id = Uuid::new_v4();
+    let running = insert_outbox(&pool, &topic).await?;
+    sqlx::query("UPDATE state.outbox SET payload = $2 WHERE outbox_id = $1")
+        .bind(running)
+        .bind(serde_json::json!({ "task_id": task_id, "attempt": 1 }))
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO state.tasks (task_id, status, attempt, lease_token, lease_expires_at)
+        VALUES ($1, 'running', 1, $2, now() + interval '5 minutes')
+        "#,
+    )
+    .bind(task_id)
+    .bind(Uuid::new_v4())
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE state.outbox
+        SET status = 'sent',
+            updated_at = CASE WHEN outbox_id = $1 THEN now() ELSE now() - interval '2 hours' END
+        WHERE outbox_id = ANY($2)
+        "#,
+    )
+    .bind(fresh)
+    .bind(vec![aged, fresh, running])
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        "UPDATE state.outbox SET updated_at = now() - interval '2 hours' WHERE outbox_id = $1",
+    )
+    .bind(pending)
+    .execute(&pool)
+    .await?;
+
+    let deleted = sweep_sent_outbox_once(&pool, Duration::from_secs(3600), 10_000).await?;
+    anyhow::ensure!(deleted >= 1, "expected the aged row to be swept");
+
+    let remaining = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT outbox_id
+        FROM state.outbox
+        WHERE topic = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(&topic)
+    .fetch_all(&pool)
+    .await?;
+    anyhow::ensure!(
+        !remaining.contains(&aged)
+            && remaining.contains(&fresh)
+            && remaining.contains(&pending)
+            && remaining.contains(&running),
+        "remaining: {remaining:?}"
+    );
+
+    // Once the task is no longer running, its row is swept too.
+    sqlx::query("UPDATE state.tasks SET status = 'succeeded' WHERE task_id = $1")
+        .bind(task_id)
+        .execute(&pool)
+        .await?;
+    sweep_sent_outbox_once(&pool, Duration::from_secs(3600), 10_000).await?;
+    let swept =
+        sqlx::query_scalar::<_, i64>("SELECT count(*) FROM state.outbox WHERE outbox_id = $1")
+            .bind(running)
+            .fetch_one(&pool)
+            .await?;
+    anyhow::ensure!(swept == 0, "row of finished task was not swept");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn claimed_rows_are_skipped_until_the_claim_lapses() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let topic = format!("outbox_claim_test_{}", Uuid::new_v4());
+    let queue = PgQueue::new(pool.clone());
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(10),
+    };
+
+    // Another drainer claimed the row and is publishing it.
+    let claimed = insert_outbox(&pool, &topic).await?;
+    sqlx::query(
+        "UPDATE state.outbox SET locked_until = now() + interval '1 minute' WHERE outbox_id = $1",
+    )
+    .bind(claimed)
+    .execute(&pool)
+    .await?;
+    drain_outbox_once(&pool, &queue, 10_000, &policy).await?;
+    let (status, attempts, _) = outbox_row(&pool, claimed).await?;
+    anyhow::ensure!(
+        status == "pending" && attempts == 0,
+        "claimed row was drained: {status} {attempts}"
+    );
+
+    // That drainer died: once the claim lapses, the row is published and the claim cleared.
+    sqlx::query(
+        "UPDATE state.outbox SET locked_until = now() - interval '1 second' WHERE outbox_id = $1",
+    )
+    .bind(claimed)
+    .execute(&pool)
+    .await?;
+    drain_outbox_once(&pool, &queue, 10_000, &policy).await?;
+    let (status, attempts, _) = outbox_row(&pool, claimed).await?;
+    anyhow::ensure!(
+        status == "sent" && attempts == 1,
+        "lapsed claim not drained: {status} {attempts}"
+    );
+    let locked_until: Option<DateTime<Utc>> =
+        sqlx::query_scalar("SELECT locked_until FROM state.outbox WHERE outbox_id = $1")
+            .bind(claimed)
+            .fetch_one(&pool)
+            .await?;
+    anyhow::ensure!(locked_until.is_none(), "claim not cleared");
+
+    Ok(())
+}
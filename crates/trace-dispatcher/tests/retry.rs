@@ -0,0 +1,343 @@
+use anyhow::Context;
+use std::{collections::BTreeMap, time::Duration};
+use trace_dispatcher::chain_sync::{apply_chain_sync_yaml, retry_failed_ranges, RetryRangesFilter};
+use trace_dispatcher::planner::planner_tick_once_scoped;
+use trace_dispatcher::retry::{RetryPolicy, TaskRetryConfig};
+use trace_dispatcher::status::{fetch_chain_sync_status, fetch_task_status};
+use trace_dispatcher::{
+    BackgroundLoops, DispatcherConfig, TASK_CAPABILITY_HEADER, WORKER_TOKEN_HEADER,
+};
+use uuid::Uuid;
+
+mod common;
+
+use common::{connect_and_migrate, start_dispatcher, test_config, WORKER_TOKEN};
+
+fn lease_reaper_only() -> BackgroundLoops {
+    BackgroundLoops {
+        lease_reaper: true,
+        ..BackgroundLoops::default()
+    }
+}
+
+async fn claim(
+    client: &reqwest::Client,
+    base: &str,
+    task_id: Uuid,
+) -> anyhow::Result<serde_json::Value> {
+    Ok(client
+        .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, WORKER_TOKEN)
+        .json(&serde_json::json!({ "task_id": task_id }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?)
+}
+
+async fn complete(
+    client: &reqwest::Client,
+    base: &str,
+    claim: &serde_json::Value,
+    outcome: &str,
+    error: &str,
+) -> anyhow::Result<()> {
+    client
+        .post(format!("{base}/v1/task/complete"))
+        .header(
+            TASK_CAPABILITY_HEADER,
+            claim["capability_token"].as_str().context("capability")?,
+        )
+        .json(&serde_json::json!({
+            "task_id": claim["task_id"],
+            "attempt": claim["attempt"],
+            "lease_token": claim["lease_token"],
+            "outcome": outcome,
+            "error": error,
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[test]
+fn backoff_is_exponential_capped_and_jittered() -> anyhow::Result<()> {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(1_000),
+    };
+
+    anyhow::ensure!(policy.backoff(2, 0.0) == Duration::from_millis(50));
+    anyhow::ensure!(policy.backoff(2, 1.0) == Duration::from_millis(100));
+    anyhow::ensure!(policy.backoff(3, 0.0) == Duration::from_millis(100));
+    anyhow::ensure!(policy.backoff(4, 1.0) == Duration::from_millis(400));
+    anyhow::ensure!(policy.backoff(40, 1.0) == Duration::from_millis(1_000));
+    anyhow::ensure!(policy.backoff(i64::MAX, 0.5) == Duration::from_millis(750));
+
+    anyhow::ensure!(policy.allows_retry_after(9));
+    anyhow::ensure!(!policy.allows_retry_after(10));
+    Ok(())
+}
+
+#[test]
+fn parses_operator_overrides() -> anyhow::Result<()> {
+    let parsed = trace_dispatcher::retry::parse_operator_max_attempts(" cryo_ingest=10, udf=2 ,")?;
+    anyhow::ensure!(parsed.get("cryo_ingest") == Some(&10));
+    anyhow::ensure!(parsed.get("udf") == Some(&2));
+    anyhow::ensure!(trace_dispatcher::retry::parse_operator_max_attempts("udf").is_err());
+    anyhow::ensure!(trace_dispatcher::retry::parse_operator_max_attempts("udf=0").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_back_off_then_fail_terminally() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+
+    let default_policy = RetryPolicy {
+        max_attempts: 2,
+        base_delay: Duration::from_secs(10),
+        max_delay: Duration::from_secs(10),
+    };
+    let mut per_operator = BTreeMap::new();
+    per_operator.insert(
+        "no_retry_op".to_string(),
+        RetryPolicy {
+            max_attempts: 1,
+            ..default_policy.clone()
+        },
+    );
+
+    let task_wakeup_queue = format!("task_wakeup_retry_test_{}", Uuid::new_v4());
+    let server = start_dispatcher(
+        &pool,
+        DispatcherConfig {
+            task_wakeup_queue: task_wakeup_queue.clone(),
+            retry: TaskRetryConfig {
+                default: default_policy,
+                per_operator,
+            },
+            ..test_config()?
+        },
+        lease_reaper_only(),
+    )
+    .await?;
+
+    // Expired leases: one operator without retry budget, one default task that may retry once.
+    let exhausted_task = Uuid::new_v4();
+    let retried_task = Uuid::new_v4();
+    for (task_id, payload) in [
+        (
+            exhausted_task,
+            serde_json::json!({ "operator": "no_retry_op" }),
+        ),
+        (retried_task, serde_json::json!({})),
+    ] {
+        sqlx::query(
+            r#"
+            INSERT INTO state.tasks (task_id, attempt, status, lease_token, lease_expires_at, payload)
+            VALUES ($1, 1, 'running', $2, now() - interval '1 minute', $3)
+            "#,
+        )
+        .bind(task_id)
+        .bind(Uuid::new_v4())
+        .bind(payload)
+        .execute(&pool)
+        .await
+        .context("insert expired task")?;
+    }
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let exhausted = fetch_task_status(&pool, exhausted_task).await?;
+        let retried = fetch_task_status(&pool, retried_task).await?;
+        if exhausted.status == "failed" && retried.status == "queued" {
+            anyhow::ensure!(exhausted.attempt == 1, "failed task must keep its attempt");
+            anyhow::ensure!(
+                exhausted.last_error.as_deref() == Some("lease expired"),
+                "unexpected last_error: {:?}",
+                exhausted.last_error
+            );
+            anyhow::ensure!(retried.attempt == 2, "retried task must advance attempt");
+            break;
+        }
+        if tokio::time::Instant::now() > deadline {
+            anyhow::bail!("timed out waiting for lease reaper");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let backoff_secs: f64 = sqlx::query_scalar(
+        r#"
+        SELECT EXTRACT(EPOCH FROM (available_at - created_at))::float8
+        FROM state.outbox
+        WHERE topic = $1
+          AND payload->>'task_id' = $2
+        "#,
+    )
+    .bind(&task_wakeup_queue)
+    .bind(retried_task.to_string())
+    .fetch_one(&pool)
+    .await
+    .context("fetch retry wakeup")?;
+    anyhow::ensure!(
+        (4.0..=11.0).contains(&backoff_secs),
+        "expected jittered backoff in [5s, 10s], got {backoff_secs}s"
+    );
+
+    let no_wakeups: i64 = sqlx::query_scalar(
+        r#"
+        SELECT count(*)
+        FROM state.outbox
+        WHERE topic = $1
+          AND payload->>'task_id' = $2
+        "#,
+    )
+    .bind(&task_wakeup_queue)
+    .bind(exhausted_task.to_string())
+    .fetch_one(&pool)
+    .await?;
+    anyhow::ensure!(no_wakeups == 0, "exhausted task must not be woken up");
+
+    // The second attempt reports a retryable error: the budget is exhausted, so it fails.
+    let base = format!("http://{}", server.addr);
+    let client = reqwest::Client::new();
+    let claim2 = claim(&client, &base, retried_task).await?;
+    anyhow::ensure!(claim2["attempt"].as_i64() == Some(2), "expected attempt 2");
+    complete(&client, &base, &claim2, "retryable_error", "rpc timeout").await?;
+
+    let retried = fetch_task_status(&pool, retried_task).await?;
+    anyhow::ensure!(
+        retried.status == "failed",
+        "expected failed, got {}",
+        retried.status
+    );
+    anyhow::ensure!(retried.last_error.as_deref() == Some("rpc timeout"));
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn fatal_range_frees_inflight_slot_and_can_be_retried() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let task_wakeup_queue = format!("task_wakeup_retry_ranges_test_{}", Uuid::new_v4());
+
+    let org_id = Uuid::new_v4();
+    let chain_id = ((Uuid::new_v4().as_u128() % 1_000_000) as i64) + 1;
+    let name = format!("retry_ranges_test_{}", Uuid::new_v4());
+    let yaml = format!(
+        r#"
+kind: chain_sync
+name: {name}
+chain_id: {chain_id}
+mode:
+  kind: fixed_target
+  from_block: 0
+  to_block: 2000
+streams:
+  blocks:
+    cryo_dataset_name: blocks
+    rpc_pool: standard
+    chunk_size: 1000
+    max_inflight: 1
+"#
+    );
+    let applied = apply_chain_sync_yaml(&pool, org_id, &yaml).await?;
+
+    let r1 = planner_tick_once_scoped(&pool, &task_wakeup_queue, Some(org_id)).await?;
+    anyhow::ensure!(r1.scheduled_ranges == 1, "expected 1 scheduled range");
+
+    let first_task: Uuid = sqlx::query_scalar(
+        r#"
+        SELECT task_id
+        FROM state.chain_sync_scheduled_ranges
+        WHERE job_id = $1
+          AND range_start = 0
+        "#,
+    )
+    .bind(applied.job_id)
+    .fetch_one(&pool)
+    .await?;
+
+    let server = start_dispatcher(
+        &pool,
+        DispatcherConfig {
+            task_wakeup_queue: task_wakeup_queue.clone(),
+            ..test_config()?
+        },
+        lease_reaper_only(),
+    )
+    .await?;
+    let base = format!("http://{}", server.addr);
+    let client = reqwest::Client::new();
+
+    let claim1 = claim(&client, &base, first_task).await?;
+    complete(
+        &client,
+        &base,
+        &claim1,
+        "fatal_error",
+        "cryo exited with status 1",
+    )
+    .await?;
+
+    let status = fetch_chain_sync_status(&pool, applied.job_id).await?;
+    let stream = &status.streams[0];
+    anyhow::ensure!(stream.failed == 1, "expected 1 failed range");
+    anyhow::ensure!(
+        stream.inflight == 0,
+        "failed range must not count as inflight"
+    );
+    anyhow::ensure!(
+        stream.failed_ranges[0].error.as_deref() == Some("cryo exited with status 1"),
+        "unexpected failed range: {:?}",
+        stream.failed_ranges
+    );
+
+    // The failed range no longer holds the only max_inflight slot.
+    let r2 = planner_tick_once_scoped(&pool, &task_wakeup_queue, Some(org_id)).await?;
+    anyhow::ensure!(
+        r2.scheduled_ranges == 1,
+        "expected planner to use freed slot"
+    );
+
+    let retried = retry_failed_ranges(
+        &pool,
+        &task_wakeup_queue,
+        applied.job_id,
+        &RetryRangesFilter {
+            dataset_key: Some("blocks".to_string()),
+            from_block: Some(0),
+            to_block: Some(1000),
+        },
+    )
+    .await?;
+    anyhow::ensure!(retried.len() == 1, "expected 1 retried range");
+    anyhow::ensure!(retried[0].task_id == first_task);
+    anyhow::ensure!(retried[0].attempt == 2, "expected fresh attempt 2");
+
+    let status = fetch_chain_sync_status(&pool, applied.job_id).await?;
+    anyhow::ensure!(status.streams[0].failed == 0, "expected no failed ranges");
+    anyhow::ensure!(
+        status.streams[0].inflight == 2,
+        "expected 2 inflight ranges"
+    );
+
+    let claim2 = claim(&client, &base, first_task).await?;
+    anyhow::ensure!(claim2["attempt"].as_i64() == Some(2), "expected attempt 2");
+
+    let again = retry_failed_ranges(
+        &pool,
+        &task_wakeup_queue,
+        applied.job_id,
+        &RetryRangesFilter::default(),
+    )
+    .await?;
+    anyhow::ensure!(again.is_empty(), "nothing left to retry");
+
+    server.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::{PgPool, Row};
+use std::collections::BTreeSet;
+use trace_dispatcher::worker_auth::{OperatorAllowlist, WorkerAuth};
+use trace_dispatcher::{BackgroundLoops, DispatcherConfig, WORKER_TOKEN_HEADER};
+use uuid::Uuid;
+
+mod common;
+
+use common::{connect_and_migrate, start_dispatcher, test_config};
+
+const CRYO_TOKEN: &str = "cryo-worker-test-token";
+const ADMIN_TOKEN: &str = "admin-worker-test-token";
+
+async fn claim_status(
+    client: &reqwest::Client,
+    base: &str,
+    token: Option<&str>,
+    task_id: Uuid,
+) -> anyhow::Result<StatusCode> {
+    let mut req = client.post(format!("{base}/internal/task-claim"));
+    if let Some(token) = token {
+        req = req.header(WORKER_TOKEN_HEADER, token);
+    }
+    Ok(req
+        .json(&serde_json::json!({ "task_id": task_id }))
+        .send()
+        .await?
+        .status())
+}
+
+async fn audit_rows(pool: &PgPool, task_id: Uuid) -> anyhow::Result<Vec<(String, String)>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT worker_id, outcome
+        FROM state.task_claim_audit
+        WHERE task_id = $1
+        ORDER BY audit_id
+        "#,
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|r| Ok((r.try_get("worker_id")?, r.try_get("outcome")?)))
+        .collect()
+}
+
+#[tokio::test]
+async fn task_claims_require_a_worker_allowed_for_the_operator() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let worker_auth = WorkerAuth::default()
+        .with_worker(
+            "cryo",
+            CRYO_TOKEN,
+            OperatorAllowlist::Only(BTreeSet::from(["cryo_ingest".to_string()])),
+        )?
+        .with_worker("admin", ADMIN_TOKEN, OperatorAllowlist::Any)?;
+    let server = start_dispatcher(
+        &pool,
+        DispatcherConfig {
+            worker_auth,
+            ..test_config()?
+        },
+        BackgroundLoops::default(),
+    )
+    .await?;
+    let base = format!("http://{}", server.addr);
+    let client = reqwest::Client::new();
+
+    let udf_task = Uuid::new_v4();
+    let cryo_task = Uuid::new_v4();
+    let cryo_payload = serde_json::json!({
+        "operator": "cryo_ingest",
+        "chain_id": 1,
+        "dataset_uuid": Uuid::new_v4(),
+        "range_start": 0,
+        "range_end": 10,
+    });
+    for (task_id, payload) in [
+        (udf_task, serde_json::json!({ "operator": "udf" })),
+        (cryo_task, cryo_payload),
+    ] {
+        sqlx::query(
+            r#"
+            INSERT INTO state.tasks (task_id, status, payload)
+            VALUES ($1, 'queued', $2)
+            "#,
+        )
+        .bind(task_id)
+        .bind(payload)
+        .execute(&pool)
+        .await?;
+    }
+
+    assert_eq!(
+        claim_status(&client, &base, None, cryo_task).await?,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        claim_status(&client, &base, Some("not-a-worker"), cryo_task).await?,
+        StatusCode::UNAUTHORIZED
+    );
+    // Anonymous callers have no worker to attribute and never reach the audit table.
+    assert!(audit_rows(&pool, cryo_task).await?.is_empty());
+
+    assert_eq!(
+        claim_status(&client, &base, Some(CRYO_TOKEN), udf_task).await?,
+        StatusCode::FORBIDDEN
+    );
+    let status: String = sqlx::query_scalar("SELECT status FROM state.tasks WHERE task_id = $1")
+        .bind(udf_task)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(status, "queued", "denied claim must not lease the task");
+
+    assert_eq!(
+        claim_status(&client, &base, Some(CRYO_TOKEN), cryo_task).await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        claim_status(&client, &base, Some(ADMIN_TOKEN), cryo_task).await?,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        claim_status(&client, &base, Some(ADMIN_TOKEN), udf_task).await?,
+        StatusCode::OK
+    );
+
+    // Unknown tasks carry no operator, so only an unrestricted worker may create-and-claim them.
+    let unknown_task = Uuid::new_v4();
+    assert_eq!(
+        claim_status(&client, &base, Some(CRYO_TOKEN), unknown_task).await?,
+        StatusCode::FORBIDDEN
+    );
+    let placeholder: Option<Uuid> =
+        sqlx::query_scalar("SELECT task_id FROM state.tasks WHERE task_id = $1")
+            .bind(unknown_task)
+            .fetch_optional(&pool)
+            .await?;
+    assert!(
+        placeholder.is_none(),
+        "denied claim left a placeholder task"
+    );
+
+    let pairs = |rows: &[(&str, &str)]| -> Vec<(String, String)> {
+        rows.iter()
+            .map(|(w, o)| (w.to_string(), o.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        audit_rows(&pool, udf_task).await?,
+        pairs(&[("cryo", "operator_denied"), ("admin", "granted")])
+    );
+    assert_eq!(
+        audit_rows(&pool, cryo_task).await?,
+        pairs(&[("cryo", "granted"), ("admin", "conflict")])
+    );
+    assert_eq!(
+        audit_rows(&pool, unknown_task).await?,
+        pairs(&[("cryo", "operator_denied")])
+    );
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn expired_leases_are_left_to_the_lease_reaper() -> anyhow::Result<()> {
+    let pool = connect_and_migrate().await?;
+    let worker_auth =
+        WorkerAuth::default().with_worker("admin", ADMIN_TOKEN, OperatorAllowlist::Any)?;
+    let server = start_dispatcher(
+        &pool,
+        DispatcherConfig {
+            worker_auth,
+            ..test_config()?
+        },
+        BackgroundLoops::default(),
+    )
+    .await?;
+    let base = format!("http://{}", server.addr);
+    let client = reqwest::Client::new();
+
+    let task_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO state.tasks (task_id, attempt, status, lease_token, lease_expires_at, payload)
+        VALUES ($1, 1, 'running', $2, now() - interval '1 minute', '{}'::jsonb)
+        "#,
+    )
+    .bind(task_id)
+    .bind(Uuid::new_v4())
+    .execute(&pool)
+    .await?;
+
+    // A redelivered wakeup must not bypass the retry budget by re-claiming the hung attempt.
+    assert_eq!(
+        claim_status(&client, &base, Some(ADMIN_TOKEN), task_id).await?,
+        StatusCode::CONFLICT
+    );
+    let attempt: i64 = sqlx::query_scalar("SELECT attempt FROM state.tasks WHERE task_id = $1")
+        .bind(task_id)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(attempt, 1, "conflicting claim must not advance the attempt");
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[test]
+fn worker_token_specs_parse_operator_allowlists() -> anyhow::Result<()> {
+    let auth = WorkerAuth::parse("cryo=tok:en:cryo_ingest|backfill, admin=other:*")?;
+    let cryo = auth.authenticate("tok:en").context("cryo token")?;
+    assert_eq!(cryo.worker_id, "cryo");
+    assert!(cryo.operators.allows(Some("backfill")));
+    assert!(!cryo.operators.allows(Some("udf")));
+    assert!(!cryo.operators.allows(None));
+    let admin = auth.authenticate("other").context("admin token")?;
+    assert!(admin.operators.allows(None));
+    assert!(auth.authenticate("tok").is_none());
+
+    assert!(WorkerAuth::parse("cryo=tok").is_err());
+    assert!(WorkerAuth::parse("cryo=:*").is_err());
+    assert!(WorkerAuth::parse("cryo=tok:|").is_err());
+    assert!(WorkerAuth::parse("a=tok:*,a=other:*").is_err());
+    assert!(WorkerAuth::parse("a=tok:*,b=tok:*").is_err());
+    assert!(WorkerAuth::parse("")?.is_empty());
+    Ok(())
+}
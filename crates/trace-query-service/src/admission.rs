@@ -0,0 +1,334 @@
+//! Admission control for synchronous queries.
+//!
+//! Every `/v1/query` and `/v1/task/query` request opens its own DuckDB connection (and spill
+//! directory), so the number running at once is bounded per org: a request takes one of the org's
+//! `QUERY_SERVICE_ORG_MAX_CONCURRENT_QUERIES` slots, or waits in a bounded per-org queue for up to
+//! `QUERY_SERVICE_ADMISSION_QUEUE_TIMEOUT_SECS`. User queries are additionally rate limited per
+//! JWT `sub` with a token bucket. Rejections surface as `429` with `Retry-After`.
+//!
+//! Async jobs (`/v1/query/jobs`) take the same org slots while they run, but wait for one without
+//! the queue bound or timeout: they are already accepted. Their submission is rate limited like a
+//! user query, and `QUERY_SERVICE_MAX_RUNNING_JOBS` bounds them across all orgs.
+
+use crate::config::QueryServiceConfig;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+// Rate limit buckets kept before idle (full) ones are pruned.
+const MAX_IDLE_USER_BUCKETS: usize = 10_000;
+
+/// Admission limits, from [`QueryServiceConfig`].
+#[derive(Debug, Clone, Copy)]
+pub struct AdmissionLimits {
+    pub max_concurrent_per_org: usize,
+    pub max_queued_per_org: usize,
+    pub queue_timeout: Duration,
+    /// Sustained user query rate per `sub`; `0` disables rate limiting.
+    pub user_rate_per_minute: u32,
+    pub user_burst: u32,
+}
+
+impl AdmissionLimits {
+    pub fn from_config(cfg: &QueryServiceConfig) -> Self {
+        Self {
+            max_concurrent_per_org: cfg.org_max_concurrent_queries,
+            max_queued_per_org: cfg.org_max_queued_queries,
+            queue_timeout: Duration::from_secs(cfg.admission_queue_timeout_secs),
+            user_rate_per_minute: cfg.user_query_rate_per_minute,
+            user_burst: cfg.user_query_burst,
+        }
+    }
+}
+
+/// Why a request was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rejection {
+    /// The org's queue was already full.
+    QueueFull { retry_after: Duration },
+    /// The request waited the full queue timeout without getting a slot.
+    QueueTimeout { retry_after: Duration },
+    /// The caller's `sub` exhausted its rate limit.
+    RateLimited { retry_after: Duration },
+}
+
+impl Rejection {
+    fn reason(self) -> &'static str {
+        match self {
+            Self::QueueFull { .. } => "queue_full",
+            Self::QueueTimeout { .. } => "queue_timeout",
+            Self::RateLimited { .. } => "rate_limited",
+        }
+    }
+}
+
+/// Point-in-time admission counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AdmissionStats {
+    /// Queries currently holding a slot.
+    pub running: u64,
+    /// Queries currently waiting for a slot.
+    pub queued: u64,
+    pub rejected_queue_full: u64,
+    pub rejected_queue_timeout: u64,
+    pub rejected_rate_limited: u64,
+}
+
+/// Shared admission state for the service.
+#[derive(Clone)]
+pub struct AdmissionController {
+    limits: AdmissionLimits,
+    orgs: Arc<Mutex<HashMap<Uuid, Arc<OrgSlots>>>>,
+    users: Arc<Mutex<HashMap<String, TokenBucket>>>,
+    counters: Arc<Counters>,
+}
+
+struct OrgSlots {
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Counters {
+    running: AtomicU64,
+    queued: AtomicU64,
+    rejected_queue_full: AtomicU64,
+    rejected_queue_timeout: AtomicU64,
+    rejected_rate_limited: AtomicU64,
+}
+
+/// A held query slot; released on drop.
+pub(crate) struct AdmissionPermit {
+    _permit: OwnedSemaphorePermit,
+    counters: Arc<Counters>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.counters.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Leaves the org queue even if the waiting request is dropped (client disconnect).
+struct QueuedGuard<'a> {
+    org: &'a OrgSlots,
+    counters: &'a Counters,
+}
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.org.queued.fetch_sub(1, Ordering::Relaxed);
+        self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl AdmissionController {
+    pub fn new(limits: AdmissionLimits) -> Self {
+        Self {
+            limits,
+            orgs: Arc::new(Mutex::new(HashMap::new())),
+            users: Arc::new(Mutex::new(HashMap::new())),
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        let c = &self.counters;
+        AdmissionStats {
+            running: c.running.load(Ordering::Relaxed),
+            queued: c.queued.load(Ordering::Relaxed),
+            rejected_queue_full: c.rejected_queue_full.load(Ordering::Relaxed),
+            rejected_queue_timeout: c.rejected_queue_timeout.load(Ordering::Relaxed),
+            rejected_rate_limited: c.rejected_rate_limited.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Take one of `org_id`'s query slots, queueing for one if none is free.
+    pub(crate) async fn admit(&self, org_id: Uuid) -> Result<AdmissionPermit, Rejection> {
+        let org = self.org(org_id);
+        if let Ok(permit) = org.permits.clone().try_acquire_owned() {
+            return Ok(self.permit(permit));
+        }
+
+        if org.queued.fetch_add(1, Ordering::Relaxed) >= self.limits.max_queued_per_org {
+            org.queued.fetch_sub(1, Ordering::Relaxed);
+            return Err(self.reject(
+                org_id,
+                Rejection::QueueFull {
+                    retry_after: self.limits.queue_timeout,
+                },
+            ));
+        }
+        let queue_depth = self.counters.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let _queued = QueuedGuard {
+            org: &org,
+            counters: &self.counters,
+        };
+        tracing::debug!(
+            event = "query_service.admission.queued",
+            org_id = %org_id,
+            queue_depth,
+            "query queued for admission"
+        );
+
+        match tokio::time::timeout(
+            self.limits.queue_timeout,
+            org.permits.clone().acquire_owned(),
+        )
+        .await
+        {
+            Ok(Ok(permit)) => Ok(self.permit(permit)),
+            // The semaphore is never closed; treat it like a timeout regardless.
+            Ok(Err(_)) | Err(_) => Err(self.reject(
+                org_id,
+                Rejection::QueueTimeout {
+                    retry_after: self.limits.queue_timeout,
+                },
+            )),
+        }
+    }
+
+    /// Take one of `org_id`'s query slots for an async job, waiting for as long as it takes.
+    pub(crate) async fn admit_job(&self, org_id: Uuid) -> AdmissionPermit {
+        let permit = self
+            .org(org_id)
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("admission semaphore is never closed");
+        self.permit(permit)
+    }
+
+    /// Spend one of `sub`'s rate limit tokens.
+    pub(crate) fn check_rate(&self, org_id: Uuid, sub: &str) -> Result<(), Rejection> {
+        let rate_per_minute = self.limits.user_rate_per_minute;
+        if rate_per_minute == 0 {
+            return Ok(());
+        }
+        let rate = f64::from(rate_per_minute) / 60.0;
+        let burst = f64::from(self.limits.user_burst.max(1));
+        let now = Instant::now();
+
+        let mut users = self.users.lock().expect("rate limit map poisoned");
+        if users.len() >= MAX_IDLE_USER_BUCKETS {
+            users.retain(|_, bucket| bucket.refilled(now, rate, burst) < burst);
+        }
+        let bucket = users.entry(sub.to_string()).or_insert(TokenBucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.refilled(now, rate, burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / rate);
+        drop(users);
+
+        Err(self.reject(org_id, Rejection::RateLimited { retry_after }))
+    }
+
+    fn org(&self, org_id: Uuid) -> Arc<OrgSlots> {
+        self.orgs
+            .lock()
+            .expect("admission org map poisoned")
+            .entry(org_id)
+            .or_insert_with(|| {
+                Arc::new(OrgSlots {
+                    permits: Arc::new(Semaphore::new(self.limits.max_concurrent_per_org.max(1))),
+                    queued: AtomicUsize::new(0),
+                })
+            })
+            .clone()
+    }
+
+    fn permit(&self, permit: OwnedSemaphorePermit) -> AdmissionPermit {
+        self.counters.running.fetch_add(1, Ordering::Relaxed);
+        AdmissionPermit {
+            _permit: permit,
+            counters: self.counters.clone(),
+        }
+    }
+
+    fn reject(&self, org_id: Uuid, rejection: Rejection) -> Rejection {
+        let counter = match rejection {
+            Rejection::QueueFull { .. } => &self.counters.rejected_queue_full,
+            Rejection::QueueTimeout { .. } => &self.counters.rejected_queue_timeout,
+            Rejection::RateLimited { .. } => &self.counters.rejected_rate_limited,
+        };
+        let rejected = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::info!(
+            event = "query_service.admission.rejected",
+            org_id = %org_id,
+            reason = rejection.reason(),
+            rejected,
+            queue_depth = self.counters.queued.load(Ordering::Relaxed),
+            running = self.counters.running.load(Ordering::Relaxed),
+            "query not admitted"
+        );
+        rejection
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refilled(&self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * rate).min(burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_slot_no_queue() -> AdmissionController {
+        AdmissionController::new(AdmissionLimits {
+            max_concurrent_per_org: 1,
+            max_queued_per_org: 0,
+            queue_timeout: Duration::from_secs(1),
+            user_rate_per_minute: 0,
+            user_burst: 0,
+        })
+    }
+
+    #[tokio::test]
+    async fn jobs_wait_for_org_slots_outside_the_queue_bound() {
+        let admission = one_slot_no_queue();
+        let org_id = Uuid::new_v4();
+
+        let query = admission.admit(org_id).await.unwrap();
+        let job = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.admit_job(org_id).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            !job.is_finished(),
+            "job ran beside a query holding the org's only slot"
+        );
+        assert_eq!(admission.stats().rejected_queue_full, 0);
+
+        drop(query);
+        let job_slot = job.await.unwrap();
+        assert_eq!(admission.stats().running, 1);
+        assert!(matches!(
+            admission.admit(org_id).await,
+            Err(Rejection::QueueFull { .. })
+        ));
+        assert!(admission.admit(Uuid::new_v4()).await.is_ok());
+
+        drop(job_slot);
+        assert_eq!(admission.stats().running, 0);
+    }
+}
@@ -0,0 +1,94 @@
+//! Task capability revocation (`/v1/task/query`).
+//!
+//! A capability token stays valid for `TASK_CAPABILITY_TTL_SECS`, but the attempt it was issued
+//! to can end sooner: the task is canceled, the lease is reaped, or the task is retried under a
+//! new attempt. Before running a task query, the Query Service checks that the token's
+//! `(task_id, attempt)` is still the task's current running attempt with an unexpired lease in
+//! `state.tasks`, and rejects stale attempts with `401`.
+//!
+//! Lookups are cached for `QUERY_SERVICE_TASK_ATTEMPT_CACHE_MS`, which bounds how long a revoked
+//! attempt may keep querying. Without a state DB the attempt cannot be checked, so every task
+//! query is rejected with `503`.
+
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Cached lookups kept before expired ones are pruned.
+const MAX_CACHED_ATTEMPTS: usize = 10_000;
+
+// `(task_id, attempt)`
+type AttemptKey = (Uuid, i64);
+
+/// Recent `(task_id, attempt)` liveness lookups.
+#[derive(Clone)]
+pub struct AttemptCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<AttemptKey, (Instant, bool)>>>,
+}
+
+impl AttemptCache {
+    /// A cache keeping lookups for `ttl`; a zero `ttl` checks the state DB on every query.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Whether `attempt` is the current running attempt of `task_id`.
+    pub(crate) async fn is_current(
+        &self,
+        pool: &PgPool,
+        task_id: Uuid,
+        attempt: i64,
+    ) -> anyhow::Result<bool> {
+        if let Some(current) = self.get(task_id, attempt) {
+            return Ok(current);
+        }
+
+        let current: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+              SELECT 1
+              FROM state.tasks
+              WHERE task_id = $1
+                AND attempt = $2
+                AND status = 'running'
+                AND lease_expires_at > now()
+            )
+            "#,
+        )
+        .bind(task_id)
+        .bind(attempt)
+        .fetch_one(pool)
+        .await
+        .context("look up task attempt")?;
+
+        self.insert(task_id, attempt, current);
+        Ok(current)
+    }
+
+    fn get(&self, task_id: Uuid, attempt: i64) -> Option<bool> {
+        let entries = self.entries.lock().expect("attempt cache poisoned");
+        entries
+            .get(&(task_id, attempt))
+            .filter(|(checked_at, _)| checked_at.elapsed() < self.ttl)
+            .map(|(_, current)| *current)
+    }
+
+    fn insert(&self, task_id: Uuid, attempt: i64, current: bool) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("attempt cache poisoned");
+        if entries.len() >= MAX_CACHED_ATTEMPTS {
+            let ttl = self.ttl;
+            entries.retain(|_, (checked_at, _)| checked_at.elapsed() < ttl);
+        }
+        entries.insert((task_id, attempt), (Instant::now(), current));
+    }
+}
@@ -1,4 +1,9 @@
 use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use trace_core::lite::s3::{S3Config, S3Credentials};
+use trace_core::multipart::MultipartConfig;
+use uuid::Uuid;
 
 /// Query Service configuration (Lite/harness defaults).
 #[derive(Parser, Clone)]
@@ -11,10 +16,24 @@ pub struct QueryServiceConfig {
     )]
     pub data_database_url: String,
 
+    /// Postgres state DB connection string for dataset registry and task attempt lookups.
+    ///
+    /// When set, user queries resolve a dataset to all of its published range versions for the
+    /// current `config_hash` instead of only the version carried in the grant. Required to serve
+    /// `/v1/task/query`, which rejects every request (`503`) when the token's attempt cannot be
+    /// checked.
+    #[arg(long, env = "STATE_DATABASE_URL")]
+    pub state_database_url: Option<String>,
+
     /// Bind address for the HTTP server.
     #[arg(long, env = "QUERY_SERVICE_BIND", default_value = "127.0.0.1:8090")]
     pub bind: String,
 
+    /// Bind address for internal endpoints (`/internal/admission`); unset serves none of them.
+    /// Keep it off the public ingress.
+    #[arg(long, env = "QUERY_SERVICE_INTERNAL_BIND")]
+    pub internal_bind: Option<String>,
+
     /// Task capability token issuer.
     #[arg(
         long,
@@ -47,10 +66,29 @@ pub struct QueryServiceConfig {
     #[arg(long, env = "TASK_CAPABILITY_NEXT_SECRET")]
     pub task_capability_next_secret: Option<String>,
 
+    /// Dispatcher public keys as `kid=path.pem,...`. When this or `TASK_CAPABILITY_JWKS_URL` is
+    /// set, capabilities are verified with public keys only and the HMAC settings are ignored.
+    #[arg(long, env = "TASK_CAPABILITY_PUBLIC_KEYS")]
+    pub task_capability_public_keys: Option<String>,
+
+    /// Dispatcher JWKS (e.g. `http://127.0.0.1:8080/.well-known/jwks.json`), re-fetched when a
+    /// token names an unknown `kid`.
+    #[arg(long, env = "TASK_CAPABILITY_JWKS_URL")]
+    pub task_capability_jwks_url: Option<String>,
+
     /// Task capability token TTL in seconds.
     #[arg(long, env = "TASK_CAPABILITY_TTL_SECS", default_value_t = 300)]
     pub task_capability_ttl_secs: u64,
 
+    /// How long a task attempt liveness lookup is cached (`0` checks every task query); bounds how
+    /// long a revoked attempt's capability may keep querying.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_TASK_ATTEMPT_CACHE_MS",
+        default_value_t = 1000
+    )]
+    pub task_attempt_cache_ms: u64,
+
     /// User JWT issuer (Lite dev-only; `POST /v1/query`).
     #[arg(long, env = "USER_JWT_ISS", default_value = "trace-lite")]
     pub user_jwt_iss: String,
@@ -75,6 +113,36 @@ pub struct QueryServiceConfig {
     #[arg(long, env = "USER_JWT_NEXT_SECRET")]
     pub user_jwt_next_secret: Option<String>,
 
+    /// Identity provider JWKS URL. When this or `USER_JWT_JWKS_FILE` is set, user JWTs are
+    /// verified with the JWKS keys (limited to `USER_JWT_ALGORITHMS`) and the HMAC settings are
+    /// ignored.
+    #[arg(long, env = "USER_JWT_JWKS_URL")]
+    pub user_jwt_jwks_url: Option<String>,
+
+    /// Local JWKS file used like `USER_JWT_JWKS_URL`.
+    #[arg(long, env = "USER_JWT_JWKS_FILE")]
+    pub user_jwt_jwks_file: Option<String>,
+
+    /// Seconds between background JWKS reloads (unknown `kid`s also trigger a reload).
+    #[arg(long, env = "USER_JWT_JWKS_REFRESH_SECS", default_value_t = 300)]
+    pub user_jwt_jwks_refresh_secs: u64,
+
+    /// Comma-separated signature algorithms accepted from JWKS keys.
+    #[arg(long, env = "USER_JWT_ALGORITHMS", default_value = "RS256,ES256")]
+    pub user_jwt_algorithms: String,
+
+    /// Claim holding the caller's org id: a dotted path or a JSON pointer (`/...`).
+    #[arg(long, env = "USER_JWT_ORG_CLAIM", default_value = "org_id")]
+    pub user_jwt_org_claim: String,
+
+    /// Claim holding the caller's dataset grants (absent means none).
+    #[arg(long, env = "USER_JWT_DATASETS_CLAIM", default_value = "datasets")]
+    pub user_jwt_datasets_claim: String,
+
+    /// Claim holding the caller's S3 read grants (absent means none).
+    #[arg(long, env = "USER_JWT_S3_CLAIM", default_value = "s3")]
+    pub user_jwt_s3_claim: String,
+
     /// MinIO/S3 endpoint for Parquet dataset objects (Lite mode).
     #[arg(long, env = "S3_ENDPOINT", default_value = "http://localhost:9000")]
     pub s3_endpoint: String,
@@ -95,19 +163,171 @@ pub struct QueryServiceConfig {
     )]
     pub max_manifest_objects: usize,
 
-    /// S3 access key for DuckDB httpfs S3 access (Lite mode; defaults match `harness/docker-compose.yml`).
+    /// Max range versions attached as one dataset view (registry-resolved user queries).
+    #[arg(long, env = "QUERY_SERVICE_MAX_VIEW_VERSIONS", default_value_t = 1024)]
+    pub max_view_versions: usize,
+
+    /// Default max rows per query result (requests may ask for fewer via `limit`).
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_MAX_RESULT_ROWS",
+        default_value_t = 1_000_000
+    )]
+    pub max_result_rows: u64,
+
+    /// Default max encoded bytes per query result.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_MAX_RESULT_BYTES",
+        default_value_t = 268_435_456
+    )]
+    pub max_result_bytes: u64,
+
+    /// Per-org result ceilings, as JSON: `{"<org_id>": {"max_rows": N, "max_bytes": N}}`.
+    ///
+    /// Either key may be omitted to keep the default for that org.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_ORG_RESULT_LIMITS",
+        default_value = "{}",
+        value_parser = parse_org_result_limits
+    )]
+    pub org_result_limits: OrgResultLimits,
+
+    /// Deadline for a `/v1/query` request, in seconds; the DuckDB query is interrupted after it.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_USER_QUERY_TIMEOUT_SECS",
+        default_value_t = 60
+    )]
+    pub user_query_timeout_secs: u64,
+
+    /// Deadline for a `/v1/task/query` request, in seconds.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_TASK_QUERY_TIMEOUT_SECS",
+        default_value_t = 300
+    )]
+    pub task_query_timeout_secs: u64,
+
+    /// Deadline for an async query job once it starts running, in seconds.
+    #[arg(long, env = "QUERY_SERVICE_JOB_TIMEOUT_SECS", default_value_t = 3600)]
+    pub job_timeout_secs: u64,
+
+    /// DuckDB `memory_limit` for each query connection (DuckDB size syntax, e.g. `1GB`).
+    #[arg(long, env = "QUERY_SERVICE_DUCKDB_MEMORY_LIMIT", default_value = "1GB")]
+    pub duckdb_memory_limit: String,
+
+    /// DuckDB `threads` for each query connection.
+    #[arg(long, env = "QUERY_SERVICE_DUCKDB_THREADS", default_value_t = 2)]
+    pub duckdb_threads: usize,
+
+    /// DuckDB `max_temp_directory_size` (spill limit) for each query connection.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_DUCKDB_MAX_TEMP_DIRECTORY_SIZE",
+        default_value = "4GB"
+    )]
+    pub duckdb_max_temp_directory_size: String,
+
+    /// Bucket that async query job results (`/v1/query/jobs`) are written to.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_RESULTS_BUCKET",
+        default_value = "trace-harness"
+    )]
+    pub results_bucket: String,
+
+    /// Key prefix for async query job results: `{prefix}/{org_id}/{job_id}/result.parquet`.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_RESULTS_PREFIX",
+        default_value = "query-results"
+    )]
+    pub results_prefix: String,
+
+    /// How long a succeeded job's result is served, in seconds.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_JOB_RESULT_TTL_SECS",
+        default_value_t = 86_400
+    )]
+    pub job_result_ttl_secs: u64,
+
+    /// Max async query jobs executing at once; further jobs stay queued.
+    #[arg(long, env = "QUERY_SERVICE_MAX_RUNNING_JOBS", default_value_t = 2)]
+    pub max_running_jobs: usize,
+
+    /// How long an instance owns its queued or running jobs without renewing them, in seconds.
+    /// Leases are renewed every third of this; jobs whose lease lapses are failed as interrupted.
+    #[arg(long, env = "QUERY_SERVICE_JOB_LEASE_SECS", default_value_t = 30)]
+    pub job_lease_secs: u64,
+
+    /// Max synchronous queries (`/v1/query`, `/v1/task/query`) running at once per org.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_ORG_MAX_CONCURRENT_QUERIES",
+        default_value_t = 4
+    )]
+    pub org_max_concurrent_queries: usize,
+
+    /// Max synchronous queries waiting for a slot per org; further requests get `429`.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_ORG_MAX_QUEUED_QUERIES",
+        default_value_t = 16
+    )]
+    pub org_max_queued_queries: usize,
+
+    /// How long a queued query waits for a slot before it gets `429`, in seconds.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_ADMISSION_QUEUE_TIMEOUT_SECS",
+        default_value_t = 10
+    )]
+    pub admission_queue_timeout_secs: u64,
+
+    /// Sustained `/v1/query` and `/v1/query/jobs` requests per minute per user `sub` (`0` disables).
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_USER_QUERY_RATE_PER_MINUTE",
+        default_value_t = 60
+    )]
+    pub user_query_rate_per_minute: u32,
+
+    /// Requests a user `sub` may make in a burst above the sustained rate.
+    #[arg(long, env = "QUERY_SERVICE_USER_QUERY_BURST", default_value_t = 10)]
+    pub user_query_burst: u32,
+
+    /// Dataset versions whose schema (`/v1/datasets/{dataset_id}/schema`) is cached (`0` disables).
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_SCHEMA_CACHE_ENTRIES",
+        default_value_t = 1024
+    )]
+    pub schema_cache_entries: usize,
+
+    /// Max Parquet row groups per dataset version whose statistics a schema response includes.
+    #[arg(
+        long,
+        env = "QUERY_SERVICE_SCHEMA_MAX_ROW_GROUPS",
+        default_value_t = 1000
+    )]
+    pub schema_max_row_groups: usize,
+
+    /// S3 access key for DuckDB httpfs and manifest reads (Lite mode; defaults match `harness/docker-compose.yml`).
     #[arg(long, env = "S3_ACCESS_KEY", default_value = "trace")]
     pub s3_access_key: String,
 
-    /// S3 secret key for DuckDB httpfs S3 access (Lite mode).
+    /// S3 secret key for DuckDB httpfs and manifest reads (Lite mode).
     #[arg(long, env = "S3_SECRET_KEY", default_value = "tracepassword")]
     pub s3_secret_key: String,
 
-    /// S3 region for DuckDB httpfs S3 access (Lite mode).
+    /// S3 region for DuckDB httpfs and manifest reads (Lite mode).
     #[arg(long, env = "S3_REGION", default_value = "us-east-1")]
     pub s3_region: String,
 
-    /// S3 URL style for DuckDB (`path` for MinIO; `vhost` for AWS).
+    /// S3 URL style for DuckDB and manifest reads (`path` for MinIO; `vhost` for AWS).
     #[arg(long, env = "S3_URL_STYLE", default_value = "path")]
     pub s3_url_style: String,
 
@@ -130,6 +350,10 @@ impl std::fmt::Debug for QueryServiceConfig {
         let s3_secret_key = "<redacted>";
         f.debug_struct("QueryServiceConfig")
             .field("data_database_url", &"<redacted>")
+            .field(
+                "state_database_url",
+                &self.state_database_url.as_ref().map(|_| "<redacted>"),
+            )
             .field("bind", &self.bind)
             .field("task_capability_iss", &self.task_capability_iss)
             .field("task_capability_aud", &self.task_capability_aud)
@@ -137,16 +361,66 @@ impl std::fmt::Debug for QueryServiceConfig {
             .field("task_capability_secret", &"<redacted>")
             .field("task_capability_next_kid", &self.task_capability_next_kid)
             .field("task_capability_next_secret", &task_capability_next_secret)
+            .field(
+                "task_capability_public_keys",
+                &self.task_capability_public_keys,
+            )
+            .field("task_capability_jwks_url", &self.task_capability_jwks_url)
             .field("task_capability_ttl_secs", &self.task_capability_ttl_secs)
+            .field("task_attempt_cache_ms", &self.task_attempt_cache_ms)
             .field("user_jwt_iss", &self.user_jwt_iss)
             .field("user_jwt_aud", &self.user_jwt_aud)
             .field("user_jwt_kid", &self.user_jwt_kid)
             .field("user_jwt_secret", &"<redacted>")
             .field("user_jwt_next_kid", &self.user_jwt_next_kid)
             .field("user_jwt_next_secret", &user_jwt_next_secret)
+            .field("user_jwt_jwks_url", &self.user_jwt_jwks_url)
+            .field("user_jwt_jwks_file", &self.user_jwt_jwks_file)
+            .field(
+                "user_jwt_jwks_refresh_secs",
+                &self.user_jwt_jwks_refresh_secs,
+            )
+            .field("user_jwt_algorithms", &self.user_jwt_algorithms)
+            .field("user_jwt_org_claim", &self.user_jwt_org_claim)
+            .field("user_jwt_datasets_claim", &self.user_jwt_datasets_claim)
+            .field("user_jwt_s3_claim", &self.user_jwt_s3_claim)
             .field("s3_endpoint", &self.s3_endpoint)
             .field("max_manifest_bytes", &self.max_manifest_bytes)
             .field("max_manifest_objects", &self.max_manifest_objects)
+            .field("max_view_versions", &self.max_view_versions)
+            .field("max_result_rows", &self.max_result_rows)
+            .field("max_result_bytes", &self.max_result_bytes)
+            .field("org_result_limits", &self.org_result_limits)
+            .field("user_query_timeout_secs", &self.user_query_timeout_secs)
+            .field("task_query_timeout_secs", &self.task_query_timeout_secs)
+            .field("job_timeout_secs", &self.job_timeout_secs)
+            .field("duckdb_memory_limit", &self.duckdb_memory_limit)
+            .field("duckdb_threads", &self.duckdb_threads)
+            .field(
+                "duckdb_max_temp_directory_size",
+                &self.duckdb_max_temp_directory_size,
+            )
+            .field("results_bucket", &self.results_bucket)
+            .field("results_prefix", &self.results_prefix)
+            .field("job_result_ttl_secs", &self.job_result_ttl_secs)
+            .field("max_running_jobs", &self.max_running_jobs)
+            .field("job_lease_secs", &self.job_lease_secs)
+            .field(
+                "org_max_concurrent_queries",
+                &self.org_max_concurrent_queries,
+            )
+            .field("org_max_queued_queries", &self.org_max_queued_queries)
+            .field(
+                "admission_queue_timeout_secs",
+                &self.admission_queue_timeout_secs,
+            )
+            .field(
+                "user_query_rate_per_minute",
+                &self.user_query_rate_per_minute,
+            )
+            .field("user_query_burst", &self.user_query_burst)
+            .field("schema_cache_entries", &self.schema_cache_entries)
+            .field("schema_max_row_groups", &self.schema_max_row_groups)
             .field("s3_access_key", &"<redacted>")
             .field("s3_secret_key", &s3_secret_key)
             .field("s3_region", &self.s3_region)
@@ -161,4 +435,53 @@ impl QueryServiceConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         Ok(Self::parse_from(["trace-query-service"]))
     }
+
+    /// Signed object store settings for manifest reads and job result uploads.
+    pub fn s3_config(&self) -> anyhow::Result<S3Config> {
+        Ok(S3Config {
+            endpoint: self.s3_endpoint.clone(),
+            region: self.s3_region.clone(),
+            url_style: self.s3_url_style.parse()?,
+            credentials: Some(S3Credentials {
+                access_key: self.s3_access_key.clone(),
+                secret_key: self.s3_secret_key.clone(),
+            }),
+            multipart: MultipartConfig::default(),
+        })
+    }
+
+    /// Result ceilings for `org_id`: its override where set, else the service defaults.
+    pub fn result_limits(&self, org_id: Uuid) -> ResultLimits {
+        let org = self.org_result_limits.0.get(&org_id);
+        ResultLimits {
+            max_rows: org.and_then(|o| o.max_rows).unwrap_or(self.max_result_rows),
+            max_bytes: org
+                .and_then(|o| o.max_bytes)
+                .unwrap_or(self.max_result_bytes),
+        }
+    }
+}
+
+/// Row and byte ceilings applied to one query result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultLimits {
+    pub max_rows: u64,
+    pub max_bytes: u64,
+}
+
+/// Per-org overrides of the default [`ResultLimits`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OrgResultLimits(pub BTreeMap<Uuid, OrgResultLimitOverride>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrgResultLimitOverride {
+    #[serde(default)]
+    pub max_rows: Option<u64>,
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+}
+
+fn parse_org_result_limits(value: &str) -> Result<OrgResultLimits, String> {
+    serde_json::from_str(value).map_err(|err| format!("invalid org result limits: {err}"))
 }
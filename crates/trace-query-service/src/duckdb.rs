@@ -1,15 +1,30 @@
 use crate::config::QueryServiceConfig;
+use crate::values;
 use anyhow::Context;
-use duckdb::{Config, Connection};
+use duckdb::arrow::datatypes::SchemaRef;
+use duckdb::arrow::record_batch::RecordBatch;
+use duckdb::{Config, Connection, InterruptHandle};
+use serde::Serialize;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum DuckDbQueryError {
     Attach(anyhow::Error),
     Query(anyhow::Error),
+    /// The query ran past its deadline and was interrupted.
+    Timeout(Duration),
+    /// DuckDB hit its memory or temp directory limit.
+    ResourceExhausted(anyhow::Error),
+    /// The caller went away (request dropped or stream closed) and the query was interrupted.
+    Canceled,
 }
 
 impl std::fmt::Display for DuckDbQueryError {
@@ -17,6 +32,13 @@ impl std::fmt::Display for DuckDbQueryError {
         match self {
             DuckDbQueryError::Attach(err) => write!(f, "duckdb attach failed: {err}"),
             DuckDbQueryError::Query(err) => write!(f, "duckdb query failed: {err}"),
+            DuckDbQueryError::Timeout(timeout) => {
+                write!(f, "duckdb query timed out after {timeout:?}")
+            }
+            DuckDbQueryError::ResourceExhausted(err) => {
+                write!(f, "duckdb query exceeded resource limits: {err}")
+            }
+            DuckDbQueryError::Canceled => write!(f, "duckdb query canceled"),
         }
     }
 }
@@ -33,25 +55,136 @@ pub struct QueryColumn {
 pub struct QueryResultSet {
     pub columns: Vec<QueryColumn>,
     pub rows: Vec<Vec<Value>>,
+    /// Set when a row or byte ceiling cut the result short.
+    pub truncated: bool,
+    /// Parquet files behind each attached view, keyed by view name (empty when not measured).
+    pub scans: BTreeMap<String, ScanStats>,
+}
+
+/// Size of the Parquet file set a view covers.
+///
+/// This is an upper bound on what DuckDB read: row-group and column pruning are not accounted for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub files: i64,
+    pub bytes: i64,
+}
+
+/// Outcome of a streamed query.
+#[derive(Debug, Clone, Default)]
+pub struct StreamSummary {
+    /// Rows written to the sink.
+    pub rows: u64,
+    /// Parquet files behind each attached view, keyed by view name.
+    pub scans: BTreeMap<String, ScanStats>,
+}
+
+/// Columns and Parquet metadata of one dataset view, from file footers only.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParquetSchema {
+    pub columns: Vec<SchemaColumn>,
+    pub row_count: i64,
+    pub files: i64,
+    /// Row groups in file order, capped at the caller's `max_row_groups`.
+    pub row_groups: Vec<RowGroupStats>,
+    /// Set when `row_groups` was cut short by the cap.
+    pub row_groups_truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaColumn {
+    pub name: String,
+    pub r#type: String,
+    /// `false` only when the column is `REQUIRED` in every file.
+    pub nullable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowGroupStats {
+    /// Index of the row group's file within the view (files sorted by name).
+    pub file: i64,
+    pub row_group: i64,
+    pub rows: i64,
+    pub bytes: i64,
+    pub columns: Vec<ColumnChunkStats>,
+}
+
+/// Footer statistics of one column chunk; absent when the writer did not record them.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnChunkStats {
+    pub path: String,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub null_count: Option<i64>,
+}
+
+/// Encodes a streamed result, one DuckDB Arrow batch at a time.
+pub trait BatchSink: Send {
+    fn write(&mut self, batch: &RecordBatch) -> anyhow::Result<()>;
+
+    /// Write any trailing output (footers, buffered bytes).
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// Builds the [`BatchSink`] for a result once its schema is known.
+pub type MakeBatchSink =
+    Box<dyn FnOnce(SchemaRef) -> anyhow::Result<Box<dyn BatchSink>> + Send + 'static>;
+
+/// A dataset attached as a named temp view.
+#[derive(Debug, Clone)]
+pub struct DatasetView {
+    /// Relation name visible to SQL (a validated request alias).
+    pub name: String,
+    pub source: DatasetViewSource,
+}
+
+#[derive(Debug, Clone)]
+pub enum DatasetViewSource {
+    /// Remote Parquet object URIs, read via `httpfs`.
+    S3ParquetUris(Vec<String>),
+    /// Local Parquet file globs (one per dataset version).
+    FileScans(Vec<String>),
+}
+
+/// Resource limits applied to every DuckDB connection, before its configuration is locked.
+#[derive(Debug, Clone)]
+pub struct DuckDbLimits {
+    /// DuckDB `memory_limit` (e.g. `1GB`).
+    pub memory_limit: String,
+    pub threads: usize,
+    /// DuckDB `max_temp_directory_size`; bounds spill to the per-query temp directory.
+    pub max_temp_directory_size: String,
+}
+
+impl DuckDbLimits {
+    pub fn from_config(cfg: &QueryServiceConfig) -> Self {
+        Self {
+            memory_limit: cfg.duckdb_memory_limit.clone(),
+            threads: cfg.duckdb_threads.max(1),
+            max_temp_directory_size: cfg.duckdb_max_temp_directory_size.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DuckDbSandbox {
-    _private: (),
+    limits: DuckDbLimits,
 }
 
 impl DuckDbSandbox {
-    pub fn new() -> Self {
-        Self { _private: () }
+    pub fn new(limits: DuckDbLimits) -> Self {
+        Self { limits }
     }
 
-    pub async fn query(&self, sql: String, max_rows: usize) -> anyhow::Result<QueryResultSet> {
+    pub async fn query(&self, sql: String, max_rows: u64) -> anyhow::Result<QueryResultSet> {
+        let limits = self.limits.clone();
         let handle: JoinHandle<anyhow::Result<QueryResultSet>> =
             tokio::task::spawn_blocking(move || {
-                let (conn, _spill_dir) = open_in_memory(false).context("open duckdb in-memory")?;
+                let (conn, _spill_dir) =
+                    open_in_memory(&limits, false).context("open duckdb in-memory")?;
                 apply_hardening(&conn).context("apply duckdb hardening")?;
                 lock_down_local_filesystem(&conn).context("lock down local filesystem")?;
-                run_query(&conn, &sql, max_rows).context("run query")
+                run_query(&conn, &sql, max_rows, u64::MAX).context("run query")
             });
 
         handle
@@ -60,86 +193,401 @@ impl DuckDbSandbox {
             .context("duckdb query failed")
     }
 
-    pub async fn query_with_s3_parquet_uris(
+    /// Attach each of `views` as a named temp view and run `sql` against them.
+    ///
+    /// Collects at most `max_rows` rows and roughly `max_bytes` of JSON; `truncated` is set when
+    /// either ceiling cut the result short. The query is interrupted after `timeout`, or as soon as
+    /// the returned future is dropped.
+    pub async fn query_with_dataset_views(
         &self,
         cfg: &QueryServiceConfig,
-        parquet_uris: Vec<String>,
+        views: Vec<DatasetView>,
         sql: String,
-        max_rows: usize,
+        max_rows: u64,
+        max_bytes: u64,
+        timeout: Duration,
     ) -> Result<QueryResultSet, DuckDbQueryError> {
+        let deadline = Instant::now() + timeout;
         let cfg = cfg.clone();
-        let parquet_uris = parquet_uris.clone();
+        let limits = self.limits.clone();
+        let canceller = QueryCanceller::default();
+        let worker_canceller = canceller.clone();
         let handle: JoinHandle<Result<QueryResultSet, DuckDbQueryError>> =
             tokio::task::spawn_blocking(move || {
-                let (conn, _spill_dir) = open_in_memory(true)
-                    .context("open duckdb in-memory")
-                    .map_err(DuckDbQueryError::Attach)?;
-                apply_hardening(&conn)
-                    .context("apply duckdb hardening")
-                    .map_err(DuckDbQueryError::Attach)?;
-
-                // Attach the dataset as a TEMP VIEW over remote Parquet so DuckDB can apply
-                // Parquet predicate/projection pushdown. This requires `httpfs` and network
-                // access; the security model relies on:
-                // - `trace_core::query::validate_sql` (untrusted SQL gate)
-                // - dataset grants from the task capability token (authz)
-                // - OS/container egress controls (allowlist object store endpoints)
-                (|| -> anyhow::Result<()> {
-                    load_parquet(&conn).context("load parquet")?;
-                    load_httpfs(&conn).context("load httpfs")?;
-                    configure_s3(&conn, &cfg).context("configure s3")?;
-                    lock_down_local_filesystem(&conn).context("lock down local filesystem")?;
-
-                    attach_parquet_dataset_view_list(&conn, &parquet_uris)
-                        .context("attach parquet dataset")?;
-                    Ok(())
-                })()
-                .map_err(DuckDbQueryError::Attach)?;
-
-                run_query(&conn, &sql, max_rows)
+                let canceller = worker_canceller;
+                let (conn, _spill_dir) = open_dataset_views(&cfg, &limits, &views, &canceller)?;
+
+                let mut results = run_query(&conn, &sql, max_rows, max_bytes)
                     .context("run query")
-                    .map_err(DuckDbQueryError::Query)
+                    .map_err(|err| canceller.execution_error(err))?;
+                results.scans = measure_scans(&conn, &views);
+                Ok(results)
             });
 
-        handle.await.map_err(|err| {
-            DuckDbQueryError::Query(anyhow::Error::new(err).context("join duckdb worker"))
-        })?
+        join_worker(handle, canceller, deadline, timeout).await
     }
 
-    pub async fn query_with_file_scan(
+    /// Attach `view` and read its column types and Parquet footer metadata (no SQL from callers).
+    ///
+    /// Row-group statistics are collected for at most `max_row_groups` row groups; row and file
+    /// counts always cover the whole view.
+    pub async fn describe_dataset_view(
         &self,
-        scan: String,
+        cfg: &QueryServiceConfig,
+        view: DatasetView,
+        max_row_groups: usize,
+        timeout: Duration,
+    ) -> Result<ParquetSchema, DuckDbQueryError> {
+        let deadline = Instant::now() + timeout;
+        let cfg = cfg.clone();
+        let limits = self.limits.clone();
+        let canceller = QueryCanceller::default();
+        let worker_canceller = canceller.clone();
+        let handle: JoinHandle<Result<ParquetSchema, DuckDbQueryError>> =
+            tokio::task::spawn_blocking(move || {
+                let canceller = worker_canceller;
+                let views = std::slice::from_ref(&view);
+                let (conn, _spill_dir) = open_dataset_views(&cfg, &limits, views, &canceller)?;
+
+                describe_parquet(&conn, &view, max_row_groups)
+                    .context("describe parquet dataset")
+                    .map_err(|err| canceller.execution_error(err))
+            });
+
+        join_worker(handle, canceller, deadline, timeout).await
+    }
+
+    /// Attach `views`, run `sql`, and write up to `max_rows` rows to the sink built by `make_sink`.
+    ///
+    /// Returns once the query has executed and the sink is created, so attach and execution
+    /// errors surface before any output is written; [`QueryStream::finish`] resolves when the
+    /// last batch has been written. DuckDB materializes the result (spilling to the per-query temp
+    /// directory) before the first batch is read.
+    ///
+    /// `timeout` covers the whole query, including writing the result. Dropping the future or the
+    /// returned stream interrupts the query; a sink write failure (e.g. the client went away)
+    /// stops it at the next batch.
+    pub async fn stream_with_dataset_views(
+        &self,
+        cfg: &QueryServiceConfig,
+        views: Vec<DatasetView>,
         sql: String,
-        max_rows: usize,
-    ) -> Result<QueryResultSet, DuckDbQueryError> {
-        let handle: JoinHandle<Result<QueryResultSet, DuckDbQueryError>> =
+        max_rows: u64,
+        make_sink: MakeBatchSink,
+        timeout: Duration,
+    ) -> Result<QueryStream, DuckDbQueryError> {
+        let deadline = Instant::now() + timeout;
+        let cfg = cfg.clone();
+        let limits = self.limits.clone();
+        let canceller = QueryCanceller::default();
+        let guard = CancelOnDrop::new(canceller.clone());
+        let worker_canceller = canceller.clone();
+        let (started_tx, started_rx) = oneshot::channel();
+        let handle: JoinHandle<Result<StreamSummary, DuckDbQueryError>> =
             tokio::task::spawn_blocking(move || {
-                let (conn, _spill_dir) = open_in_memory(true)
-                    .context("open duckdb in-memory")
-                    .map_err(DuckDbQueryError::Attach)?;
-                apply_hardening(&conn)
-                    .context("apply duckdb hardening")
-                    .map_err(DuckDbQueryError::Attach)?;
-
-                (|| -> anyhow::Result<()> {
-                    load_parquet(&conn).context("load parquet")?;
-                    lock_configuration(&conn).context("lock configuration")?;
-                    attach_parquet_dataset_view(&conn, &scan).context("attach parquet dataset")?;
-                    Ok(())
-                })()
-                .map_err(DuckDbQueryError::Attach)?;
-
-                run_query(&conn, &sql, max_rows)
-                    .context("run query")
-                    .map_err(DuckDbQueryError::Query)
+                let canceller = worker_canceller;
+                let (conn, _spill_dir) = open_dataset_views(&cfg, &limits, &views, &canceller)?;
+
+                let mut stmt = conn
+                    .prepare(&sql)
+                    .context("prepare")
+                    .map_err(|err| canceller.execution_error(err))?;
+                let mut batches = stmt
+                    .query_arrow([])
+                    .context("query")
+                    .map_err(|err| canceller.execution_error(err))?;
+                let mut sink = make_sink(batches.get_schema())
+                    .context("create result sink")
+                    .map_err(DuckDbQueryError::Query)?;
+                let _ = started_tx.send(());
+
+                let mut rows = 0u64;
+                for batch in batches.by_ref() {
+                    let remaining = max_rows - rows;
+                    if remaining == 0 {
+                        break;
+                    }
+                    let batch = if batch.num_rows() as u64 > remaining {
+                        batch.slice(0, remaining as usize)
+                    } else {
+                        batch
+                    };
+                    sink.write(&batch)
+                        .context("write result batch")
+                        .map_err(|err| canceller.sink_error(err))?;
+                    rows += batch.num_rows() as u64;
+                }
+                sink.finish()
+                    .context("finish result")
+                    .map_err(|err| canceller.sink_error(err))?;
+                drop(batches);
+
+                Ok(StreamSummary {
+                    rows,
+                    scans: measure_scans(&conn, &views),
+                })
             });
 
-        handle.await.map_err(|err| {
-            DuckDbQueryError::Query(anyhow::Error::new(err).context("join duckdb worker"))
-        })?
+        match tokio::time::timeout_at(deadline, started_rx).await {
+            Ok(Ok(())) => {
+                return Ok(QueryStream {
+                    worker: handle,
+                    canceller,
+                    guard,
+                    deadline,
+                    timeout,
+                })
+            }
+            Ok(Err(_)) => {}
+            Err(_) => return Err(timed_out(&canceller, timeout)),
+        }
+        // The worker exited before starting the stream; surface its error.
+        guard.disarm();
+        match handle.await {
+            Ok(Err(err)) => Err(err),
+            Ok(Ok(_)) => Err(DuckDbQueryError::Query(anyhow::anyhow!(
+                "duckdb worker exited before streaming"
+            ))),
+            Err(err) => Err(DuckDbQueryError::Query(
+                anyhow::Error::new(err).context("join duckdb worker"),
+            )),
+        }
+    }
+}
+
+/// A streamed query whose result is being written to its sink.
+///
+/// Dropping it before [`QueryStream::finish`] completes interrupts the query.
+pub struct QueryStream {
+    worker: JoinHandle<Result<StreamSummary, DuckDbQueryError>>,
+    canceller: QueryCanceller,
+    guard: CancelOnDrop,
+    deadline: Instant,
+    timeout: Duration,
+}
+
+impl QueryStream {
+    /// Wait for the last batch to be written (or the deadline to pass).
+    pub async fn finish(self) -> Result<StreamSummary, DuckDbQueryError> {
+        let QueryStream {
+            worker,
+            canceller,
+            guard,
+            deadline,
+            timeout,
+        } = self;
+        let result = join_worker(worker, canceller, deadline, timeout).await;
+        guard.disarm();
+        result
     }
 }
 
+/// Interrupts the DuckDB connection owned by a blocking worker.
+///
+/// The worker registers its connection once open; cancelling before that makes registration fail,
+/// so the worker never starts the query.
+#[derive(Clone, Default)]
+struct QueryCanceller {
+    state: Arc<Mutex<CancelState>>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    interrupt: Option<Arc<InterruptHandle>>,
+    canceled: bool,
+}
+
+impl QueryCanceller {
+    fn register(&self, conn: &Connection) -> Result<(), DuckDbQueryError> {
+        let mut state = self.state.lock().expect("cancel state poisoned");
+        if state.canceled {
+            return Err(DuckDbQueryError::Canceled);
+        }
+        state.interrupt = Some(conn.interrupt_handle());
+        Ok(())
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock().expect("cancel state poisoned");
+        state.canceled = true;
+        if let Some(interrupt) = &state.interrupt {
+            interrupt.interrupt();
+        }
+    }
+
+    fn is_canceled(&self) -> bool {
+        self.state.lock().expect("cancel state poisoned").canceled
+    }
+
+    /// Classify an error from executing the untrusted query.
+    fn execution_error(&self, err: anyhow::Error) -> DuckDbQueryError {
+        if self.is_canceled() {
+            DuckDbQueryError::Canceled
+        } else if is_resource_exhausted(&err) {
+            DuckDbQueryError::ResourceExhausted(err)
+        } else {
+            DuckDbQueryError::Query(err)
+        }
+    }
+
+    /// Classify an error from writing to the result sink.
+    fn sink_error(&self, err: anyhow::Error) -> DuckDbQueryError {
+        let closed = err.chain().any(|cause| {
+            cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|io| io.kind() == std::io::ErrorKind::BrokenPipe)
+        });
+        if closed || self.is_canceled() {
+            DuckDbQueryError::Canceled
+        } else {
+            DuckDbQueryError::Query(err)
+        }
+    }
+}
+
+/// DuckDB reports both `memory_limit` and `max_temp_directory_size` overruns as out-of-memory
+/// errors; the C API does not expose the error type, so match the message prefix.
+fn is_resource_exhausted(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.to_string().contains("Out of Memory Error"))
+}
+
+/// Cancels the query when dropped, unless disarmed.
+struct CancelOnDrop(Option<QueryCanceller>);
+
+impl CancelOnDrop {
+    fn new(canceller: QueryCanceller) -> Self {
+        Self(Some(canceller))
+    }
+
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(canceller) = self.0.take() {
+            canceller.cancel();
+        }
+    }
+}
+
+/// Await a DuckDB worker until `deadline`, interrupting it on timeout or if this future is dropped.
+async fn join_worker<T>(
+    worker: JoinHandle<Result<T, DuckDbQueryError>>,
+    canceller: QueryCanceller,
+    deadline: Instant,
+    timeout: Duration,
+) -> Result<T, DuckDbQueryError> {
+    let guard = CancelOnDrop::new(canceller.clone());
+    let joined = tokio::time::timeout_at(deadline, worker).await;
+    guard.disarm();
+    match joined {
+        Ok(Ok(result)) => result,
+        Ok(Err(err)) => Err(DuckDbQueryError::Query(
+            anyhow::Error::new(err).context("join duckdb worker"),
+        )),
+        Err(_) => Err(timed_out(&canceller, timeout)),
+    }
+}
+
+fn timed_out(canceller: &QueryCanceller, timeout: Duration) -> DuckDbQueryError {
+    canceller.cancel();
+    tracing::info!(
+        event = "query_service.duckdb.timeout",
+        timeout_ms = timeout.as_millis() as u64,
+        "duckdb query timed out"
+    );
+    DuckDbQueryError::Timeout(timeout)
+}
+
+/// Open a hardened connection with each of `views` attached as a named temp view.
+///
+/// The connection is registered with `canceller` before anything runs on it.
+fn open_dataset_views(
+    cfg: &QueryServiceConfig,
+    limits: &DuckDbLimits,
+    views: &[DatasetView],
+    canceller: &QueryCanceller,
+) -> Result<(Connection, SpillDir), DuckDbQueryError> {
+    let (conn, spill_dir) = open_in_memory(limits, true)
+        .context("open duckdb in-memory")
+        .map_err(DuckDbQueryError::Attach)?;
+    canceller.register(&conn)?;
+    apply_hardening(&conn)
+        .context("apply duckdb hardening")
+        .map_err(DuckDbQueryError::Attach)?;
+
+    // Attach each dataset as a TEMP VIEW over Parquet so DuckDB can apply Parquet
+    // predicate/projection pushdown. Remote datasets require `httpfs` and network
+    // access; the security model relies on:
+    // - `trace_core::query::validate_sql_relations` (untrusted SQL gate; only the
+    //   attached view names are admitted as relations)
+    // - dataset grants from the capability/user token (authz)
+    // - OS/container egress controls (allowlist object store endpoints)
+    (|| -> anyhow::Result<()> {
+        if views.is_empty() {
+            anyhow::bail!("no datasets to attach");
+        }
+        let any_s3 = views
+            .iter()
+            .any(|v| matches!(v.source, DatasetViewSource::S3ParquetUris(_)));
+        let any_file = views
+            .iter()
+            .any(|v| matches!(v.source, DatasetViewSource::FileScans(_)));
+
+        load_parquet(&conn).context("load parquet")?;
+        if any_s3 {
+            load_httpfs(&conn).context("load httpfs")?;
+            configure_s3(&conn, cfg).context("configure s3")?;
+        }
+        // File-backed views are read through the LocalFileSystem, so it can only be
+        // disabled when every view is remote.
+        if any_file {
+            lock_configuration(&conn).context("lock configuration")?;
+        } else {
+            lock_down_local_filesystem(&conn).context("lock down local filesystem")?;
+        }
+
+        for view in views {
+            attach_parquet_dataset_view_list(&conn, &view.name, view_uris(view))
+                .with_context(|| format!("attach parquet dataset {}", view.name))?;
+        }
+        Ok(())
+    })()
+    .map_err(|err| {
+        if canceller.is_canceled() {
+            DuckDbQueryError::Canceled
+        } else {
+            DuckDbQueryError::Attach(err)
+        }
+    })?;
+
+    Ok((conn, spill_dir))
+}
+
+/// Measure the files behind each of `views`.
+///
+/// Best effort: a failed measurement leaves the audit metrics empty but does not fail an otherwise
+/// successful query.
+fn measure_scans(conn: &Connection, views: &[DatasetView]) -> BTreeMap<String, ScanStats> {
+    let mut scans = BTreeMap::new();
+    for view in views {
+        match scan_stats(conn, view) {
+            Ok(stats) => {
+                scans.insert(view.name.clone(), stats);
+            }
+            Err(err) => tracing::warn!(
+                event = "query_service.duckdb.scan_stats_failed",
+                error = ?err,
+                "duckdb scan stats failed"
+            ),
+        }
+    }
+    scans
+}
+
 fn apply_hardening(conn: &Connection) -> anyhow::Result<()> {
     // Fail-closed: if we can't apply these settings, refuse to run queries.
     //
@@ -166,17 +614,30 @@ impl Drop for SpillDir {
     }
 }
 
-fn open_in_memory(external_access: bool) -> anyhow::Result<(Connection, SpillDir)> {
+fn open_in_memory(
+    limits: &DuckDbLimits,
+    external_access: bool,
+) -> anyhow::Result<(Connection, SpillDir)> {
     let spill_dir = create_spill_dir().context("create duckdb spill dir")?;
     let spill_dir_str = spill_dir.path.to_string_lossy().to_string();
 
+    // Resource limits are set at open, so they are in place before `lock_configuration`.
     let config = Config::default()
         .enable_autoload_extension(false)
         .context("disable extension autoload")?
         .enable_external_access(external_access)
         .context("set external access")?
         .with("temp_directory", spill_dir_str)
-        .context("set duckdb temp_directory")?;
+        .context("set duckdb temp_directory")?
+        .max_memory(&limits.memory_limit)
+        .context("set duckdb memory_limit")?
+        .threads(limits.threads as i64)
+        .context("set duckdb threads")?
+        .with(
+            "max_temp_directory_size",
+            limits.max_temp_directory_size.as_str(),
+        )
+        .context("set duckdb max_temp_directory_size")?;
 
     let conn =
         Connection::open_in_memory_with_flags(config).context("open in-memory connection")?;
@@ -280,121 +741,244 @@ fn escape_sql_string(value: &str) -> String {
     value.replace('\'', "''")
 }
 
-fn attach_parquet_dataset_view(conn: &Connection, scan: &str) -> anyhow::Result<()> {
-    let scan = escape_sql_string(scan);
+/// Attach Parquet URIs or file globs (one per dataset version) as the temp view `name`.
+///
+/// `name` must already be a validated alias; it is quoted here regardless.
+fn attach_parquet_dataset_view_list(
+    conn: &Connection,
+    name: &str,
+    uris: &[String],
+) -> anyhow::Result<()> {
+    if uris.is_empty() {
+        anyhow::bail!("empty parquet uri list");
+    }
+
+    let list = sql_string_list(uris);
+
+    let name = name.replace('"', "\"\"");
     let create =
-        format!("CREATE OR REPLACE TEMP VIEW dataset AS SELECT * FROM read_parquet('{scan}');");
+        format!("CREATE OR REPLACE TEMP VIEW \"{name}\" AS SELECT * FROM read_parquet({list});");
     conn.execute_batch(&create)
         .context("create temp dataset view")?;
     Ok(())
 }
 
-fn attach_parquet_dataset_view_list(conn: &Connection, uris: &[String]) -> anyhow::Result<()> {
-    if uris.is_empty() {
-        anyhow::bail!("empty parquet uri list");
+/// Count the files and bytes behind `view` (trusted SQL; sizes come from file metadata only).
+fn scan_stats(conn: &Connection, view: &DatasetView) -> anyhow::Result<ScanStats> {
+    let list = sql_string_list(view_uris(view));
+
+    let (files, bytes): (i64, Option<i64>) = conn
+        .query_row(
+            &format!("SELECT count(*), sum(size)::BIGINT FROM read_blob({list});"),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .context("read blob sizes")?;
+    Ok(ScanStats {
+        files,
+        bytes: bytes.unwrap_or(0),
+    })
+}
+
+/// Read the column types of `view` and the footer metadata of the files behind it.
+fn describe_parquet(
+    conn: &Connection,
+    view: &DatasetView,
+    max_row_groups: usize,
+) -> anyhow::Result<ParquetSchema> {
+    let list = sql_string_list(view_uris(view));
+    let name = view.name.replace('"', "\"\"");
+
+    let mut stmt = conn
+        .prepare(&format!("DESCRIBE SELECT * FROM \"{name}\";"))
+        .context("prepare describe")?;
+    let described = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .context("describe view")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("read describe rows")?;
+
+    let required = required_columns(conn, &list)?;
+    let columns = described
+        .into_iter()
+        .map(|(name, r#type)| SchemaColumn {
+            nullable: !required.get(&name).copied().unwrap_or(false),
+            name,
+            r#type,
+        })
+        .collect();
+
+    let (files, row_count): (i64, Option<i64>) = conn
+        .query_row(
+            &format!(
+                "SELECT count(DISTINCT file_name), sum(rows)::BIGINT FROM (\
+                 SELECT DISTINCT file_name, row_group_id, row_group_num_rows AS rows \
+                 FROM parquet_metadata({list}));"
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .context("count parquet rows")?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT dense_rank() OVER (ORDER BY file_name) - 1, row_group_id, \
+             row_group_num_rows, row_group_bytes, path_in_schema, stats_min_value, \
+             stats_max_value, stats_null_count \
+             FROM parquet_metadata({list}) ORDER BY file_name, row_group_id, column_id;"
+        ))
+        .context("prepare parquet metadata")?;
+    let mut rows = stmt.query([]).context("read parquet metadata")?;
+    let mut row_groups: Vec<RowGroupStats> = Vec::new();
+    let mut row_groups_truncated = false;
+    while let Some(row) = rows.next().context("read parquet metadata row")? {
+        let (file, row_group): (i64, i64) = (row.get(0)?, row.get(1)?);
+        let current = row_groups
+            .last()
+            .is_some_and(|rg| rg.file == file && rg.row_group == row_group);
+        if !current {
+            if row_groups.len() >= max_row_groups {
+                row_groups_truncated = true;
+                break;
+            }
+            row_groups.push(RowGroupStats {
+                file,
+                row_group,
+                rows: row.get(2)?,
+                bytes: row.get(3)?,
+                columns: Vec::new(),
+            });
+        }
+        if let Some(rg) = row_groups.last_mut() {
+            rg.columns.push(ColumnChunkStats {
+                path: row.get(4)?,
+                min: row.get(5)?,
+                max: row.get(6)?,
+                null_count: row.get(7)?,
+            });
+        }
     }
 
+    Ok(ParquetSchema {
+        columns,
+        row_count: row_count.unwrap_or(0),
+        files,
+        row_groups,
+        row_groups_truncated,
+    })
+}
+
+/// Top-level columns by name, `true` when the column is `REQUIRED` in every file of `list`.
+fn required_columns(conn: &Connection, list: &str) -> anyhow::Result<HashMap<String, bool>> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT file_name, name, repetition_type, num_children FROM parquet_schema({list});"
+        ))
+        .context("prepare parquet schema")?;
+    let mut rows = stmt.query([]).context("read parquet schema")?;
+
+    // `parquet_schema` lists each file's schema tree depth-first. The root (the only element
+    // without a repetition type) is listed for some files and not others, so it is skipped;
+    // `pending` holds the children still to come at each open nested level.
+    let mut required = HashMap::new();
+    let mut file = String::new();
+    let mut pending: Vec<i64> = Vec::new();
+    while let Some(row) = rows.next().context("read parquet schema row")? {
+        let row_file: String = row.get(0)?;
+        let repetition: Option<String> = row.get(2)?;
+        if row_file != file || repetition.is_none() {
+            file = row_file;
+            pending.clear();
+            if repetition.is_none() {
+                continue;
+            }
+        }
+        if pending.is_empty() {
+            let name: String = row.get(1)?;
+            let is_required = repetition.as_deref() == Some("REQUIRED");
+            *required.entry(name).or_insert(true) &= is_required;
+        } else if let Some(top) = pending.last_mut() {
+            *top -= 1;
+        }
+        let children: Option<i64> = row.get(3)?;
+        if let Some(children) = children.filter(|n| *n > 0) {
+            pending.push(children);
+        }
+        while pending.last() == Some(&0) {
+            pending.pop();
+        }
+    }
+    Ok(required)
+}
+
+fn view_uris(view: &DatasetView) -> &[String] {
+    match &view.source {
+        DatasetViewSource::S3ParquetUris(uris) => uris,
+        DatasetViewSource::FileScans(scans) => scans,
+    }
+}
+
+fn sql_string_list<'a>(values: impl IntoIterator<Item = &'a String>) -> String {
     let mut list = String::from("[");
-    for (idx, uri) in uris.iter().enumerate() {
+    for (idx, value) in values.into_iter().enumerate() {
         if idx > 0 {
             list.push_str(", ");
         }
         list.push('\'');
-        list.push_str(&escape_sql_string(uri));
+        list.push_str(&escape_sql_string(value));
         list.push('\'');
     }
     list.push(']');
-
-    let create =
-        format!("CREATE OR REPLACE TEMP VIEW dataset AS SELECT * FROM read_parquet({list});");
-    conn.execute_batch(&create)
-        .context("create temp dataset view")?;
-    Ok(())
+    list
 }
 
-fn run_query(conn: &Connection, sql: &str, max_rows: usize) -> anyhow::Result<QueryResultSet> {
+fn run_query(
+    conn: &Connection,
+    sql: &str,
+    max_rows: u64,
+    max_bytes: u64,
+) -> anyhow::Result<QueryResultSet> {
     let mut stmt = conn.prepare(sql).context("prepare")?;
     let mut rows = Vec::new();
-    let mut result_rows = stmt.query([]).context("query")?;
-
-    // NOTE: DuckDB statement schema is only available after execution.
-    let stmt_ref = result_rows
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("missing statement metadata"))?;
-
-    let column_count = stmt_ref.column_count();
-    let mut columns = Vec::with_capacity(column_count);
-    for idx in 0..column_count {
-        let name = stmt_ref
-            .column_name(idx)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|_| format!("col_{idx}"));
-        let decl = format!("{:?}", stmt_ref.column_type(idx));
-        columns.push(QueryColumn { name, r#type: decl });
-    }
-
-    while let Some(row) = result_rows.next().context("next row")? {
-        let mut out = Vec::with_capacity(column_count);
-        for idx in 0..column_count {
-            let v = row.get_ref(idx).context("get column")?;
-            out.push(value_ref_to_json(v));
-        }
-        rows.push(out);
-        if rows.len() >= max_rows {
-            break;
-        }
-    }
-
-    Ok(QueryResultSet { columns, rows })
-}
-
-fn value_ref_to_json(value: duckdb::types::ValueRef<'_>) -> Value {
-    match value {
-        duckdb::types::ValueRef::Null => Value::Null,
-        duckdb::types::ValueRef::Boolean(b) => Value::Bool(b),
-        duckdb::types::ValueRef::TinyInt(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::SmallInt(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::Int(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::BigInt(i) => Value::Number(i.into()),
-        duckdb::types::ValueRef::HugeInt(i) => Value::String(i.to_string()),
-        duckdb::types::ValueRef::UTinyInt(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::USmallInt(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::UInt(i) => Value::Number((i as i64).into()),
-        duckdb::types::ValueRef::UBigInt(i) => Value::String(i.to_string()),
-        duckdb::types::ValueRef::Float(f) => serde_json::Number::from_f64(f as f64)
-            .map(Value::Number)
-            .unwrap_or(Value::Null),
-        duckdb::types::ValueRef::Double(f) => serde_json::Number::from_f64(f)
-            .map(Value::Number)
-            .unwrap_or(Value::Null),
-        duckdb::types::ValueRef::Decimal(d) => Value::String(d.to_string()),
-        duckdb::types::ValueRef::Timestamp(_, v) => Value::Number(v.into()),
-        duckdb::types::ValueRef::Text(bytes) => {
-            Value::String(String::from_utf8_lossy(bytes).into_owned())
+    let mut batches = stmt.query_arrow([]).context("query")?;
+    let schema = batches.get_schema();
+
+    let mut bytes = 0u64;
+    let mut truncated = false;
+    'batches: for batch in batches.by_ref() {
+        for idx in 0..batch.num_rows() {
+            if rows.len() as u64 >= max_rows {
+                truncated = true;
+                break 'batches;
+            }
+            let out = values::row_json(&batch, idx);
+            bytes = bytes.saturating_add(serde_json::to_vec(&out).map_or(0, |b| b.len() as u64));
+            if bytes > max_bytes {
+                truncated = true;
+                break 'batches;
+            }
+            rows.push(out);
         }
-        duckdb::types::ValueRef::Blob(bytes) => Value::Array(
-            bytes
-                .iter()
-                .map(|b| Value::Number((*b as i64).into()))
-                .collect(),
-        ),
-        duckdb::types::ValueRef::Date32(v) => Value::Number((v as i64).into()),
-        duckdb::types::ValueRef::Time64(_, v) => Value::Number(v.into()),
-        duckdb::types::ValueRef::Interval {
-            months,
-            days,
-            nanos,
-        } => Value::Object(
-            [
-                ("months".to_string(), Value::Number((months as i64).into())),
-                ("days".to_string(), Value::Number((days as i64).into())),
-                ("nanos".to_string(), Value::Number(nanos.into())),
-            ]
-            .into_iter()
-            .collect(),
-        ),
-        duckdb::types::ValueRef::List(_, _) => Value::String("<list>".to_string()),
-        duckdb::types::ValueRef::Enum(_, _) => Value::String("<enum>".to_string()),
-        _ => Value::String("<unsupported>".to_string()),
     }
+    drop(batches);
+
+    // Logical types come from the prepared statement; the Arrow schema alone conflates some types.
+    let columns = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| QueryColumn {
+            name: field.name().clone(),
+            r#type: values::column_type_name(field, Some(&stmt.column_logical_type(idx))),
+        })
+        .collect();
+
+    Ok(QueryResultSet {
+        columns,
+        rows,
+        truncated,
+        scans: BTreeMap::new(),
+    })
 }
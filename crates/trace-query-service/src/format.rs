@@ -0,0 +1,406 @@
+//! Query result formats (`Accept` negotiation and streaming encoders).
+//!
+//! JSON (the default) is buffered into a single `{columns, rows, truncated}` document. The Arrow IPC
+//! stream, Parquet, CSV, and NDJSON formats are encoded from DuckDB's Arrow record batches and sent
+//! as a chunked body while the batches are read.
+//!
+//! DuckDB's `duckdb::arrow` is a different major version from the `arrow`/`parquet` crates used for
+//! encoding, so batches cross over through the Arrow C Data Interface ([`import_batch`]).
+
+use crate::duckdb::BatchSink;
+use crate::values;
+use crate::ApiError;
+use anyhow::Context;
+use arrow::array::{RecordBatch, StructArray};
+use arrow::csv::WriterBuilder as CsvWriterBuilder;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::ffi::{FFI_ArrowArray, FFI_ArrowSchema};
+use arrow::ipc::writer::StreamWriter;
+use arrow::json::LineDelimitedWriter;
+use axum::http::{header, HeaderMap};
+use duckdb::arrow as duckdb_arrow;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Body chunks handed from the DuckDB worker to the HTTP response.
+pub(crate) type ChunkSender = mpsc::Sender<Result<Vec<u8>, io::Error>>;
+
+// Encoded bytes buffered before a chunk is handed to the response body.
+const CHUNK_BYTES: usize = 64 * 1024;
+// Parquet buffers a whole row group before writing it; keep groups small so output keeps flowing.
+const PARQUET_ROW_GROUP_ROWS: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResultFormat {
+    Json,
+    ArrowStream,
+    Parquet,
+    Csv,
+    Ndjson,
+}
+
+impl ResultFormat {
+    /// Pick the format from the request's `Accept` header.
+    ///
+    /// A missing or wildcard `Accept` selects JSON. Among the supported media types the highest `q`
+    /// wins (ties go to the first listed); `q=0` excludes a type. Nothing supported is 406.
+    pub(crate) fn negotiate(headers: &HeaderMap) -> Result<Self, ApiError> {
+        let Some(accept) = headers.get(header::ACCEPT) else {
+            return Ok(Self::Json);
+        };
+        let accept = accept
+            .to_str()
+            .map_err(|_| ApiError::not_acceptable("unsupported result format"))?;
+        if accept.trim().is_empty() {
+            return Ok(Self::Json);
+        }
+
+        let mut best: Option<(Self, f32)> = None;
+        for range in accept.split(',') {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim();
+            let mut q = 1.0_f32;
+            for param in parts {
+                if let Some((name, value)) = param.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        q = value.trim().parse().unwrap_or(0.0);
+                    }
+                }
+            }
+
+            let Some(format) = Self::from_media_type(media_type) else {
+                continue;
+            };
+            if q <= 0.0 {
+                continue;
+            }
+            if !matches!(best, Some((_, best_q)) if best_q >= q) {
+                best = Some((format, q));
+            }
+        }
+
+        best.map(|(format, _)| format)
+            .ok_or_else(|| ApiError::not_acceptable("unsupported result format"))
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.to_ascii_lowercase().as_str() {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "application/vnd.apache.arrow.stream" => Some(Self::ArrowStream),
+            "application/vnd.apache.parquet" | "application/x-parquet" => Some(Self::Parquet),
+            "text/csv" => Some(Self::Csv),
+            "application/x-ndjson" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::ArrowStream => "application/vnd.apache.arrow.stream",
+            Self::Parquet => "application/vnd.apache.parquet",
+            Self::Csv => "text/csv",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// Create the encoder for a streamed format, writing the result described by `schema` to `out`.
+    pub(crate) fn encoder(
+        self,
+        schema: duckdb_arrow::datatypes::SchemaRef,
+        out: ChunkWriter,
+    ) -> anyhow::Result<Box<dyn BatchSink>> {
+        let schema = import_schema(&schema).context("import result schema")?;
+        let encoder = match self {
+            Self::Json => anyhow::bail!("json results are not streamed"),
+            Self::ArrowStream => Encoder::ArrowStream(
+                StreamWriter::try_new(out, &schema).context("start arrow ipc stream")?,
+            ),
+            Self::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+                    .build();
+                Encoder::Parquet(Box::new(
+                    ArrowWriter::try_new(out, schema.clone(), Some(props))
+                        .context("start parquet writer")?,
+                ))
+            }
+            Self::Csv => {
+                let mut writer = CsvWriterBuilder::new().with_header(true).build(out);
+                // Write the header even when the result is empty.
+                writer
+                    .write(&RecordBatch::new_empty(schema.clone()))
+                    .context("write csv header")?;
+                Encoder::Csv(Box::new(writer))
+            }
+            Self::Ndjson => Encoder::Ndjson(LineDelimitedWriter::new(out)),
+        };
+        Ok(Box::new(EncodingSink { schema, encoder }))
+    }
+}
+
+/// `io::Write` adapter that sends encoded output to the response body in [`CHUNK_BYTES`] chunks.
+///
+/// Fails the write once more than `max_bytes` have been written, or once the client has gone away.
+pub(crate) struct ChunkWriter {
+    tx: ChunkSender,
+    buf: Vec<u8>,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl ChunkWriter {
+    pub(crate) fn new(tx: ChunkSender, max_bytes: u64) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(CHUNK_BYTES),
+            written: 0,
+            max_bytes,
+        }
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_BYTES));
+        // Runs on the DuckDB worker thread; blocking here applies backpressure from the client.
+        self.tx.blocking_send(Ok(chunk)).map_err(|_| {
+            tracing::debug!(
+                event = "query_service.result.client_disconnected",
+                "result stream closed by client"
+            );
+            io::Error::new(io::ErrorKind::BrokenPipe, "result stream closed")
+        })
+    }
+
+    /// Send any buffered output.
+    fn finish(mut self) -> io::Result<()> {
+        self.send_chunk()
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.written = self.written.saturating_add(data.len() as u64);
+        if self.written > self.max_bytes {
+            tracing::info!(
+                event = "query_service.result.byte_limit_exceeded",
+                max_bytes = self.max_bytes,
+                "query result exceeded byte limit"
+            );
+            return Err(io::Error::other("result byte limit exceeded"));
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.send_chunk()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Chunk boundaries are chosen by size; encoder flushes are not forwarded.
+        Ok(())
+    }
+}
+
+enum Encoder {
+    ArrowStream(StreamWriter<ChunkWriter>),
+    Parquet(Box<ArrowWriter<ChunkWriter>>),
+    Csv(Box<arrow::csv::Writer<ChunkWriter>>),
+    Ndjson(LineDelimitedWriter<ChunkWriter>),
+}
+
+struct EncodingSink {
+    schema: SchemaRef,
+    encoder: Encoder,
+}
+
+impl BatchSink for EncodingSink {
+    fn write(&mut self, batch: &duckdb_arrow::record_batch::RecordBatch) -> anyhow::Result<()> {
+        let batch = import_batch(batch, &self.schema).context("import result batch")?;
+        match &mut self.encoder {
+            Encoder::ArrowStream(writer) => writer.write(&batch)?,
+            Encoder::Parquet(writer) => writer.write(&batch)?,
+            Encoder::Csv(writer) => writer.write(&batch)?,
+            Encoder::Ndjson(writer) => writer.write(&batch)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        let out = match self.encoder {
+            Encoder::ArrowStream(writer) => writer.into_inner()?,
+            // Writes the Parquet footer.
+            Encoder::Parquet(writer) => writer.into_inner()?,
+            Encoder::Csv(writer) => writer.into_inner(),
+            Encoder::Ndjson(mut writer) => {
+                writer.finish()?;
+                writer.into_inner()
+            }
+        };
+        out.finish().context("send final result chunk")
+    }
+}
+
+/// Re-import a DuckDB (`duckdb::arrow`) schema as an `arrow` schema.
+fn import_schema(schema: &duckdb_arrow::datatypes::Schema) -> anyhow::Result<SchemaRef> {
+    let mut exported = duckdb_arrow::ffi::FFI_ArrowSchema::try_from(schema)?;
+    // SAFETY: both crates define `FFI_ArrowSchema` as the `#[repr(C)]` `ArrowSchema` struct of the
+    // Arrow C Data Interface. `from_raw` moves the exported value out and leaves a released (empty)
+    // struct behind, so the schema is released exactly once, by the imported value.
+    let imported =
+        unsafe { FFI_ArrowSchema::from_raw(&mut exported as *mut _ as *mut FFI_ArrowSchema) };
+    Ok(Arc::new(Schema::try_from(&imported)?))
+}
+
+/// Re-import a DuckDB (`duckdb::arrow`) record batch as an `arrow` record batch with `schema`.
+fn import_batch(
+    batch: &duckdb_arrow::record_batch::RecordBatch,
+    schema: &SchemaRef,
+) -> anyhow::Result<RecordBatch> {
+    let data = duckdb_arrow::array::Array::into_data(duckdb_arrow::array::StructArray::from(
+        batch.clone(),
+    ));
+    let (mut array, mut array_schema) = duckdb_arrow::ffi::to_ffi(&data)?;
+    // SAFETY: as in `import_schema`; `FFI_ArrowArray` is the C Data Interface `ArrowArray` struct in
+    // both crates, and `from_raw` takes ownership of the exported buffers.
+    let (array, array_schema) = unsafe {
+        (
+            FFI_ArrowArray::from_raw(&mut array as *mut _ as *mut FFI_ArrowArray),
+            FFI_ArrowSchema::from_raw(&mut array_schema as *mut _ as *mut FFI_ArrowSchema),
+        )
+    };
+    // SAFETY: `array` and `array_schema` were exported together from valid array data above.
+    let data = unsafe { arrow::ffi::from_ffi(array, &array_schema)? };
+    let columns = StructArray::from(data).columns().to_vec();
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Re-export an `arrow` schema as a DuckDB (`duckdb::arrow`) schema; the inverse of [`import_schema`].
+fn export_schema(schema: &Schema) -> anyhow::Result<duckdb_arrow::datatypes::Schema> {
+    let mut exported = FFI_ArrowSchema::try_from(schema)?;
+    // SAFETY: as in `import_schema`, with the crates swapped.
+    let imported = unsafe {
+        duckdb_arrow::ffi::FFI_ArrowSchema::from_raw(
+            &mut exported as *mut _ as *mut duckdb_arrow::ffi::FFI_ArrowSchema,
+        )
+    };
+    Ok(duckdb_arrow::datatypes::Schema::try_from(&imported)?)
+}
+
+/// Re-export an `arrow` record batch as a DuckDB (`duckdb::arrow`) record batch; the inverse of
+/// [`import_batch`].
+fn export_batch(batch: &RecordBatch) -> anyhow::Result<duckdb_arrow::record_batch::RecordBatch> {
+    let data = arrow::array::Array::into_data(StructArray::from(batch.clone()));
+    let (mut array, mut array_schema) = arrow::ffi::to_ffi(&data)?;
+    // SAFETY: as in `import_batch`, with the crates swapped.
+    let (array, array_schema) = unsafe {
+        (
+            duckdb_arrow::ffi::FFI_ArrowArray::from_raw(
+                &mut array as *mut _ as *mut duckdb_arrow::ffi::FFI_ArrowArray,
+            ),
+            duckdb_arrow::ffi::FFI_ArrowSchema::from_raw(
+                &mut array_schema as *mut _ as *mut duckdb_arrow::ffi::FFI_ArrowSchema,
+            ),
+        )
+    };
+    // SAFETY: `array` and `array_schema` were exported together from valid array data above.
+    let data = unsafe { duckdb_arrow::ffi::from_ffi(array, &array_schema)? };
+    Ok(duckdb_arrow::array::StructArray::from(data).into())
+}
+
+/// SQL type names for the columns of a stored result (see [`values::column_type_name`]).
+pub(crate) fn column_type_names(schema: &Schema) -> anyhow::Result<Vec<String>> {
+    let schema = export_schema(schema)?;
+    Ok(schema
+        .fields()
+        .iter()
+        .map(|field| values::column_type_name(field, None))
+        .collect())
+}
+
+/// Render `batch` as JSON rows (one array per row, columns in schema order).
+///
+/// Used to page through stored Parquet results; values render as in synchronous JSON responses.
+pub(crate) fn json_rows(batch: &RecordBatch) -> anyhow::Result<Vec<Vec<serde_json::Value>>> {
+    let batch = export_batch(batch)?;
+    Ok((0..batch.num_rows())
+        .map(|row| values::row_json(&batch, row))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn negotiate(accept: Option<&str>) -> Option<ResultFormat> {
+        let mut headers = HeaderMap::new();
+        if let Some(accept) = accept {
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        }
+        ResultFormat::negotiate(&headers).ok()
+    }
+
+    #[test]
+    fn negotiate_defaults_to_json() {
+        assert_eq!(negotiate(None), Some(ResultFormat::Json));
+        assert_eq!(negotiate(Some("")), Some(ResultFormat::Json));
+        assert_eq!(negotiate(Some("*/*")), Some(ResultFormat::Json));
+        assert_eq!(
+            negotiate(Some("application/json")),
+            Some(ResultFormat::Json)
+        );
+    }
+
+    #[test]
+    fn negotiate_selects_streamed_formats() {
+        assert_eq!(
+            negotiate(Some("application/vnd.apache.arrow.stream")),
+            Some(ResultFormat::ArrowStream)
+        );
+        assert_eq!(
+            negotiate(Some("application/x-parquet")),
+            Some(ResultFormat::Parquet)
+        );
+        assert_eq!(
+            negotiate(Some("Text/CSV; charset=utf-8")),
+            Some(ResultFormat::Csv)
+        );
+        assert_eq!(
+            negotiate(Some("application/x-ndjson")),
+            Some(ResultFormat::Ndjson)
+        );
+    }
+
+    #[test]
+    fn negotiate_honors_quality() {
+        assert_eq!(
+            negotiate(Some("application/json;q=0.5, text/csv")),
+            Some(ResultFormat::Csv)
+        );
+        assert_eq!(
+            negotiate(Some("text/csv, */*;q=0.1")),
+            Some(ResultFormat::Csv)
+        );
+        assert_eq!(
+            negotiate(Some("text/csv;q=0, application/x-ndjson;q=0.2")),
+            Some(ResultFormat::Ndjson)
+        );
+        assert_eq!(
+            negotiate(Some("text/csv, application/x-ndjson")),
+            Some(ResultFormat::Csv)
+        );
+    }
+
+    #[test]
+    fn negotiate_rejects_unsupported_types() {
+        assert_eq!(negotiate(Some("text/html")), None);
+        assert_eq!(negotiate(Some("text/csv;q=0")), None);
+        assert_eq!(negotiate(Some("application/xml, image/*")), None);
+    }
+}
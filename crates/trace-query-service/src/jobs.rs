@@ -0,0 +1,1004 @@
+//! Asynchronous query jobs (`/v1/query/jobs`, ADR 0005).
+//!
+//! A job is authorized, gated, and resolved exactly like `POST /v1/query`, then runs in the
+//! background: the result is encoded as one Parquet object and uploaded through
+//! `trace_core::ObjectStore` under `QUERY_SERVICE_RESULTS_PREFIX`. Job state lives in
+//! `data.query_results` (no raw SQL; the row carries the SQL fingerprint).
+//!
+//! Jobs run in the process that accepted them, which owns the row and renews its lease
+//! ([`renew_job_leases`]). A job whose owner stopped renewing is failed by any instance
+//! ([`fail_interrupted_jobs`]); succeeded results stop being served after
+//! `QUERY_SERVICE_JOB_RESULT_TTL_SECS` ([`expire_query_jobs`]).
+
+use crate::duckdb::{DatasetView, DuckDbQueryError, MakeBatchSink, ScanStats};
+use crate::format::{self, ChunkWriter, ResultFormat};
+use crate::{
+    duckdb_error_to_api, ApiError, AppState, GrantedQuery, QueryColumnResponse,
+    STREAM_BUFFER_CHUNKS,
+};
+use anyhow::Context;
+use bytes::Buf;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use parquet::arrow::arrow_reader::{
+    ArrowReaderMetadata, ArrowReaderOptions, ParquetRecordBatchReaderBuilder,
+};
+use parquet::errors::{ParquetError, Result as ParquetResult};
+use parquet::file::metadata::{ParquetMetaData, ParquetMetaDataReader};
+use parquet::file::reader::{ChunkReader, Length};
+use parquet::file::FOOTER_SIZE;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use trace_core::lite::s3::parse_s3_uri;
+use trace_core::ObjectStore as ObjectStoreTrait;
+use uuid::Uuid;
+
+/// Background executor state shared by the job handlers.
+#[derive(Clone)]
+pub struct QueryJobs {
+    instance_id: Uuid,
+    lease: Duration,
+    permits: Arc<Semaphore>,
+    /// Cancellation senders for jobs that are queued or running in this process.
+    cancels: Arc<Mutex<HashMap<Uuid, oneshot::Sender<()>>>>,
+}
+
+impl QueryJobs {
+    pub fn new(max_running: usize, lease: Duration) -> Self {
+        Self {
+            instance_id: Uuid::new_v4(),
+            lease,
+            permits: Arc::new(Semaphore::new(max_running.max(1))),
+            cancels: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Owner id this process records on the jobs it accepts.
+    pub fn instance_id(&self) -> Uuid {
+        self.instance_id
+    }
+
+    fn register(&self, job_id: Uuid) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.cancels
+            .lock()
+            .expect("job cancel map poisoned")
+            .insert(job_id, tx);
+        rx
+    }
+
+    fn unregister(&self, job_id: Uuid) -> Option<oneshot::Sender<()>> {
+        self.cancels
+            .lock()
+            .expect("job cancel map poisoned")
+            .remove(&job_id)
+    }
+}
+
+/// Job status as stored in `data.query_results.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryJobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Canceled,
+    Expired,
+}
+
+impl QueryJobStatus {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        Ok(match value {
+            "queued" => Self::Queued,
+            "running" => Self::Running,
+            "succeeded" => Self::Succeeded,
+            "failed" => Self::Failed,
+            "canceled" => Self::Canceled,
+            "expired" => Self::Expired,
+            other => anyhow::bail!("unknown query job status {other:?}"),
+        })
+    }
+}
+
+/// Response to `POST /v1/query/jobs`.
+#[derive(Debug, Serialize)]
+pub struct QueryJobAccepted {
+    pub job_id: Uuid,
+    pub status: QueryJobStatus,
+}
+
+/// A query accepted by `POST /v1/query/jobs`: granted, gated, and with its views resolved.
+pub(crate) struct QueryJob {
+    pub(crate) org_id: Uuid,
+    pub(crate) user_sub: String,
+    pub(crate) query: GrantedQuery,
+    pub(crate) views: Vec<DatasetView>,
+}
+
+/// Record `job` as queued and start it in the background; returns the job id.
+pub(crate) async fn submit(state: &Arc<AppState>, job: QueryJob) -> Result<Uuid, ApiError> {
+    let job_id = job.query.audit.query_id();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO data.query_results (
+          id, org_id, user_sub, mode, status, sql_fingerprint, output_format, owner_id,
+          lease_expires_at
+        ) VALUES ($1, $2, $3, 'batch', 'queued', $4, 'parquet', $5, now() + make_interval(secs => $6))
+        "#,
+    )
+    .bind(job_id)
+    .bind(job.org_id)
+    .bind(&job.user_sub)
+    .bind(job.query.audit.sql_fingerprint())
+    .bind(state.jobs.instance_id)
+    .bind(state.jobs.lease.as_secs_f64())
+    .execute(&state.data_pool)
+    .await;
+    if let Err(err) = inserted {
+        tracing::warn!(
+            event = "query_service.query_job.insert_failed",
+            error = %err,
+            "query job insert failed"
+        );
+        let GrantedQuery {
+            audit,
+            datasets,
+            validated,
+            ..
+        } = job.query;
+        return Err(audit
+            .failed(
+                &state.data_pool,
+                &datasets,
+                &validated,
+                ApiError::internal("query job insert failed"),
+            )
+            .await);
+    }
+
+    let cancel = state.jobs.register(job_id);
+    tokio::spawn(run(state.clone(), job_id, job, cancel));
+    Ok(job_id)
+}
+
+/// Cancel a queued or running job owned by `user_sub`. Returns `false` if no such job is active.
+pub(crate) async fn cancel(
+    state: &AppState,
+    job_id: Uuid,
+    org_id: Uuid,
+    user_sub: &str,
+) -> Result<bool, ApiError> {
+    let canceled = sqlx::query(
+        r#"
+        UPDATE data.query_results
+        SET status = 'canceled', finished_at = now(), updated_at = now()
+        WHERE id = $1 AND org_id = $2 AND user_sub = $3 AND status IN ('queued', 'running')
+        "#,
+    )
+    .bind(job_id)
+    .bind(org_id)
+    .bind(user_sub)
+    .execute(&state.data_pool)
+    .await
+    .map_err(|err| {
+        tracing::warn!(
+            event = "query_service.query_job.update_failed",
+            error = %err,
+            "query job update failed"
+        );
+        ApiError::internal("query job update failed")
+    })?
+    .rows_affected()
+        > 0;
+
+    if canceled {
+        if let Some(tx) = state.jobs.unregister(job_id) {
+            let _ = tx.send(());
+        }
+        tracing::info!(
+            event = "query_service.query_job.canceled",
+            %job_id,
+            "query job canceled"
+        );
+    }
+    Ok(canceled)
+}
+
+struct JobOutput {
+    rows: u64,
+    bytes: u64,
+    location: String,
+    scans: BTreeMap<String, ScanStats>,
+}
+
+/// Why a job failed; `code` is stored in `query_results.error_code`.
+struct JobFailure {
+    code: &'static str,
+    error: ApiError,
+}
+
+impl JobFailure {
+    fn internal(code: &'static str, message: &'static str) -> Self {
+        Self {
+            code,
+            error: ApiError::internal(message),
+        }
+    }
+
+    fn query(err: DuckDbQueryError) -> Self {
+        let code = match &err {
+            DuckDbQueryError::Timeout(_) => "timeout",
+            DuckDbQueryError::ResourceExhausted(_) => "resource_exhausted",
+            DuckDbQueryError::Canceled => "canceled",
+            DuckDbQueryError::Attach(_) | DuckDbQueryError::Query(_) => "query_failed",
+        };
+        Self {
+            code,
+            error: duckdb_error_to_api(err),
+        }
+    }
+}
+
+async fn run(state: Arc<AppState>, job_id: Uuid, job: QueryJob, cancel: oneshot::Receiver<()>) {
+    let QueryJob {
+        org_id,
+        query,
+        views,
+        ..
+    } = job;
+    let GrantedQuery {
+        audit,
+        datasets,
+        validated,
+        sql,
+        limit,
+        max_bytes,
+        timeout,
+        ..
+    } = query;
+    let pool = state.data_pool.clone();
+    let started = Instant::now();
+    let spool = SpoolFile::new(job_id);
+
+    let outcome = tokio::select! {
+        outcome = execute(
+            &state,
+            job_id,
+            org_id,
+            views,
+            sql,
+            ResultBounds { limit, max_bytes, timeout },
+            &spool.path,
+        ) => {
+            Some(outcome)
+        }
+        _ = cancel => None,
+    };
+    state.jobs.unregister(job_id);
+    drop(spool);
+
+    let duration_ms = i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX);
+    match outcome {
+        // `cancel` already marked the row.
+        None => {
+            let _ = audit
+                .failed(
+                    &pool,
+                    &datasets,
+                    &validated,
+                    ApiError::internal("query job canceled"),
+                )
+                .await;
+        }
+        Some(Ok(output)) => {
+            let marked = sqlx::query(
+                r#"
+                UPDATE data.query_results
+                SET status = 'succeeded',
+                    output_location = $2,
+                    row_count = $3,
+                    bytes = $4,
+                    duration_ms = $5,
+                    finished_at = now(),
+                    updated_at = now(),
+                    expires_at = now() + make_interval(secs => $6)
+                WHERE id = $1 AND status = 'running'
+                "#,
+            )
+            .bind(job_id)
+            .bind(&output.location)
+            .bind(i64::try_from(output.rows).unwrap_or(i64::MAX))
+            .bind(i64::try_from(output.bytes).unwrap_or(i64::MAX))
+            .bind(duration_ms)
+            .bind(state.cfg.job_result_ttl_secs as f64)
+            .execute(&pool)
+            .await;
+            if let Err(err) = marked {
+                tracing::warn!(
+                    event = "query_service.query_job.update_failed",
+                    error = %err,
+                    "query job update failed"
+                );
+            }
+            let _ = audit
+                .succeeded(&pool, &datasets, &validated, output.rows, &output.scans)
+                .await;
+            tracing::info!(
+                event = "query_service.query_job.succeeded",
+                %job_id,
+                rows = output.rows,
+                bytes = output.bytes,
+                "query job succeeded"
+            );
+        }
+        Some(Err(failure)) => {
+            let marked = sqlx::query(
+                r#"
+                UPDATE data.query_results
+                SET status = 'failed',
+                    error_code = $2,
+                    error_message = $3,
+                    duration_ms = $4,
+                    finished_at = now(),
+                    updated_at = now()
+                WHERE id = $1 AND status IN ('queued', 'running')
+                "#,
+            )
+            .bind(job_id)
+            .bind(failure.code)
+            .bind(failure.error.message)
+            .bind(duration_ms)
+            .execute(&pool)
+            .await;
+            if let Err(err) = marked {
+                tracing::warn!(
+                    event = "query_service.query_job.update_failed",
+                    error = %err,
+                    "query job update failed"
+                );
+            }
+            let _ = audit
+                .failed(&pool, &datasets, &validated, failure.error)
+                .await;
+            tracing::info!(
+                event = "query_service.query_job.failed",
+                %job_id,
+                error_code = failure.code,
+                "query job failed"
+            );
+        }
+    }
+}
+
+/// Row, byte, and time bounds for one job's query.
+struct ResultBounds {
+    limit: u64,
+    max_bytes: u64,
+    timeout: Duration,
+}
+
+async fn execute(
+    state: &AppState,
+    job_id: Uuid,
+    org_id: Uuid,
+    views: Vec<DatasetView>,
+    sql: String,
+    bounds: ResultBounds,
+    spool: &Path,
+) -> Result<JobOutput, JobFailure> {
+    // The org slot comes first, so one org's waiting jobs never hold executor permits.
+    let _slot = state.admission.admit_job(org_id).await;
+    let _permit = state
+        .jobs
+        .permits
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|_| JobFailure::internal("executor_closed", "query job executor closed"))?;
+
+    let started = sqlx::query(
+        r#"
+        UPDATE data.query_results
+        SET status = 'running', started_at = now(), updated_at = now()
+        WHERE id = $1 AND status = 'queued'
+        "#,
+    )
+    .bind(job_id)
+    .execute(&state.data_pool)
+    .await
+    .map_err(|err| {
+        tracing::warn!(
+            event = "query_service.query_job.update_failed",
+            error = %err,
+            "query job update failed"
+        );
+        JobFailure::internal("state_update_failed", "query job update failed")
+    })?;
+    if started.rows_affected() == 0 {
+        // Canceled while queued.
+        return Err(JobFailure::internal("canceled", "query job canceled"));
+    }
+
+    let (tx, mut rx) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    let writer = ChunkWriter::new(tx, bounds.max_bytes);
+    let make_sink: MakeBatchSink =
+        Box::new(move |schema| ResultFormat::Parquet.encoder(schema, writer));
+    let stream = state
+        .duckdb
+        .stream_with_dataset_views(
+            &state.cfg,
+            views,
+            sql,
+            bounds.limit,
+            make_sink,
+            bounds.timeout,
+        )
+        .await
+        .map_err(JobFailure::query)?;
+
+    let spooled = async {
+        let mut file = tokio::fs::File::create(spool)
+            .await
+            .context("create result spool")?;
+        while let Some(chunk) = rx.recv().await {
+            file.write_all(&chunk?)
+                .await
+                .context("write result spool")?;
+        }
+        file.flush().await.context("flush result spool")?;
+        anyhow::Ok(())
+    }
+    .await;
+
+    let summary = stream.finish().await.map_err(JobFailure::query)?;
+    spooled.map_err(|err| {
+        tracing::warn!(
+            event = "query_service.query_job.spool_failed",
+            error = ?err,
+            "query job result spool failed"
+        );
+        JobFailure::internal("spool_failed", "query job result write failed")
+    })?;
+
+    let bytes = tokio::fs::metadata(spool)
+        .await
+        .map(|m| m.len())
+        .unwrap_or_default();
+    let key = result_key(&state.cfg.results_prefix, org_id, job_id);
+    state
+        .object_store
+        .put_file(
+            &state.cfg.results_bucket,
+            &key,
+            spool,
+            ResultFormat::Parquet.content_type(),
+        )
+        .await
+        .map_err(|err| {
+            tracing::warn!(
+                event = "query_service.query_job.upload_failed",
+                error = %err,
+                "query job result upload failed"
+            );
+            JobFailure::internal("upload_failed", "query job result upload failed")
+        })?;
+
+    Ok(JobOutput {
+        rows: summary.rows,
+        bytes,
+        location: format!("s3://{}/{key}", state.cfg.results_bucket),
+        scans: summary.scans,
+    })
+}
+
+/// Object key of a job's result: `{prefix}/{org_id}/{job_id}/result.parquet`.
+fn result_key(prefix: &str, org_id: Uuid, job_id: Uuid) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        format!("{org_id}/{job_id}/result.parquet")
+    } else {
+        format!("{prefix}/{org_id}/{job_id}/result.parquet")
+    }
+}
+
+/// Local file the result is encoded into before upload; removed on drop.
+struct SpoolFile {
+    path: PathBuf,
+}
+
+impl SpoolFile {
+    fn new(job_id: Uuid) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("trace-query-job-{job_id}.parquet")),
+        }
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A job as seen by its owner.
+#[derive(Debug, Serialize)]
+pub struct QueryJobResponse {
+    pub job_id: Uuid,
+    pub status: QueryJobStatus,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When a succeeded job's result stops being served.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub row_count: Option<i64>,
+    /// Size of the Parquet result object.
+    pub result_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// One page of result rows (succeeded jobs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<QueryJobPage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryJobPage {
+    pub offset: u64,
+    pub columns: Vec<QueryColumnResponse>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Offset of the next page; absent on the last page.
+    pub next_offset: Option<u64>,
+}
+
+/// Load `job_id` if it belongs to `user_sub` in `org_id`.
+pub(crate) async fn load(
+    pool: &PgPool,
+    job_id: Uuid,
+    org_id: Uuid,
+    user_sub: &str,
+) -> Result<Option<(QueryJobResponse, Option<String>)>, ApiError> {
+    let row = sqlx::query(
+        r#"
+        SELECT status, created_at, started_at, finished_at, expires_at, row_count, bytes,
+               error_message, output_location, (expires_at IS NOT NULL AND expires_at <= now()) AS expired
+        FROM data.query_results
+        WHERE id = $1 AND org_id = $2 AND user_sub = $3
+        "#,
+    )
+    .bind(job_id)
+    .bind(org_id)
+    .bind(user_sub)
+    .fetch_optional(pool)
+    .await
+    .map_err(|err| {
+        tracing::warn!(
+            event = "query_service.query_job.load_failed",
+            error = %err,
+            "query job load failed"
+        );
+        ApiError::internal("query job lookup failed")
+    })?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let decode = || -> anyhow::Result<(QueryJobResponse, Option<String>)> {
+        let mut status = QueryJobStatus::parse(row.try_get("status")?)?;
+        // The sweeper may not have run yet.
+        if status == QueryJobStatus::Succeeded && row.try_get::<bool, _>("expired")? {
+            status = QueryJobStatus::Expired;
+        }
+        Ok((
+            QueryJobResponse {
+                job_id,
+                status,
+                created_at: row.try_get("created_at")?,
+                started_at: row.try_get("started_at")?,
+                finished_at: row.try_get("finished_at")?,
+                expires_at: row.try_get("expires_at")?,
+                row_count: row.try_get("row_count")?,
+                result_bytes: row.try_get("bytes")?,
+                error: row.try_get("error_message")?,
+                page: None,
+            },
+            row.try_get("output_location")?,
+        ))
+    };
+    decode().map(Some).map_err(|err| {
+        tracing::warn!(
+            event = "query_service.query_job.load_failed",
+            error = ?err,
+            "query job decode failed"
+        );
+        ApiError::internal("query job lookup failed")
+    })
+}
+
+/// Read rows `[offset, offset + limit)` of the Parquet result at `location`.
+///
+/// Only the footer and the row groups holding those rows are fetched, so a page costs about one
+/// row group of the result rather than the whole object.
+pub(crate) async fn read_page(
+    state: &AppState,
+    location: &str,
+    offset: u64,
+    limit: u64,
+) -> Result<QueryJobPage, ApiError> {
+    let unavailable = |err: &dyn std::fmt::Display| {
+        tracing::warn!(
+            event = "query_service.query_job.result_unavailable",
+            error = %err,
+            "query job result unavailable"
+        );
+        ApiError::internal("query job result unavailable")
+    };
+    let (bucket, key) = parse_s3_uri(location).map_err(|err| unavailable(&err))?;
+    let result = fetch_page_ranges(state.object_store.as_ref(), &bucket, &key, offset, limit)
+        .await
+        .map_err(|err| unavailable(&format!("{err:#}")))?;
+
+    tokio::task::spawn_blocking(move || decode_page(result, offset, limit))
+        .await
+        .map_err(|err| unavailable(&err))?
+        .map_err(|err| unavailable(&format!("{err:#}")))
+}
+
+/// The parts of a Parquet result needed for one page: its metadata and the bytes of the row
+/// groups overlapping the page.
+struct PageRanges {
+    metadata: ParquetMetaData,
+    /// Indexes of the fetched row groups, in file order.
+    row_groups: Vec<usize>,
+    /// Rows of the first fetched row group that precede the page.
+    skip: u64,
+    /// Rows in the whole result.
+    total: u64,
+    chunks: FetchedRanges,
+}
+
+async fn fetch_page_ranges(
+    store: &dyn ObjectStoreTrait,
+    bucket: &str,
+    key: &str,
+    offset: u64,
+    limit: u64,
+) -> anyhow::Result<PageRanges> {
+    let size = store
+        .head(bucket, key)
+        .await?
+        .context("result object missing")?
+        .size;
+    let footer_size = FOOTER_SIZE as u64;
+    anyhow::ensure!(size >= footer_size, "result object too small: {size} bytes");
+    let tail: [u8; FOOTER_SIZE] = store
+        .get_range(bucket, key, size - footer_size..size)
+        .await?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("short parquet footer read"))?;
+    let metadata_len = ParquetMetaDataReader::decode_footer_tail(&tail)
+        .context("decode parquet footer")?
+        .metadata_length() as u64;
+    anyhow::ensure!(
+        metadata_len <= size - footer_size,
+        "parquet metadata length {metadata_len} exceeds object size {size}"
+    );
+    let metadata_end = size - footer_size;
+    let metadata = ParquetMetaDataReader::decode_metadata(
+        &store
+            .get_range(bucket, key, metadata_end - metadata_len..metadata_end)
+            .await?,
+    )
+    .context("decode parquet metadata")?;
+
+    let end = offset.saturating_add(limit);
+    let mut row_groups = Vec::new();
+    let mut skip = 0;
+    let mut first_row = 0_u64;
+    let mut ranges = Vec::new();
+    for (idx, row_group) in metadata.row_groups().iter().enumerate() {
+        let rows = u64::try_from(row_group.num_rows()).unwrap_or(0);
+        if first_row < end && first_row + rows > offset {
+            if row_groups.is_empty() {
+                skip = offset - first_row;
+            }
+            row_groups.push(idx);
+            let (start, stop) = row_group
+                .columns()
+                .iter()
+                .fold((u64::MAX, 0), |acc, column| {
+                    let (start, len) = column.byte_range();
+                    (acc.0.min(start), acc.1.max(start + len))
+                });
+            if start < stop {
+                let bytes = store.get_range(bucket, key, start..stop).await?;
+                ranges.push((start, Bytes::from(bytes)));
+            }
+        }
+        first_row += rows;
+    }
+
+    Ok(PageRanges {
+        metadata,
+        row_groups,
+        skip,
+        total: first_row,
+        chunks: FetchedRanges { size, ranges },
+    })
+}
+
+/// Byte ranges fetched from a Parquet object, served to the reader as if it were the whole file.
+/// Reads outside the fetched ranges fail.
+struct FetchedRanges {
+    size: u64,
+    /// `(start offset, bytes)`, in file order and not overlapping.
+    ranges: Vec<(u64, Bytes)>,
+}
+
+impl FetchedRanges {
+    fn slice(&self, start: u64, len: Option<usize>) -> ParquetResult<Bytes> {
+        let (range_start, bytes) = self
+            .ranges
+            .iter()
+            .find(|(range_start, bytes)| {
+                *range_start <= start && start < range_start + bytes.len() as u64
+            })
+            .ok_or_else(|| ParquetError::General(format!("offset {start} was not fetched")))?;
+        let from = (start - range_start) as usize;
+        let to = match len {
+            Some(len) if from + len <= bytes.len() => from + len,
+            Some(len) => {
+                return Err(ParquetError::General(format!(
+                    "range {start}+{len} was not fetched"
+                )))
+            }
+            None => bytes.len(),
+        };
+        Ok(bytes.slice(from..to))
+    }
+}
+
+impl Length for FetchedRanges {
+    fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+impl ChunkReader for FetchedRanges {
+    type T = bytes::buf::Reader<Bytes>;
+
+    fn get_read(&self, start: u64) -> ParquetResult<Self::T> {
+        Ok(self.slice(start, None)?.reader())
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> ParquetResult<Bytes> {
+        self.slice(start, Some(length))
+    }
+}
+
+fn decode_page(result: PageRanges, offset: u64, limit: u64) -> anyhow::Result<QueryJobPage> {
+    let PageRanges {
+        metadata,
+        row_groups,
+        skip,
+        total,
+        chunks,
+    } = result;
+    let metadata = ArrowReaderMetadata::try_new(Arc::new(metadata), ArrowReaderOptions::default())
+        .context("open parquet result")?;
+    let schema = metadata.schema().clone();
+    let columns = schema
+        .fields()
+        .iter()
+        .zip(format::column_type_names(&schema)?)
+        .map(|(field, r#type)| QueryColumnResponse {
+            name: field.name().clone(),
+            r#type,
+        })
+        .collect();
+    let reader = ParquetRecordBatchReaderBuilder::new_with_metadata(chunks, metadata)
+        .with_row_groups(row_groups)
+        .with_offset(usize::try_from(skip).unwrap_or(usize::MAX))
+        .with_limit(usize::try_from(limit).unwrap_or(usize::MAX))
+        .build()
+        .context("read parquet result")?;
+
+    let mut rows = Vec::new();
+    for batch in reader {
+        rows.extend(format::json_rows(&batch.context("decode result batch")?)?);
+    }
+    let end = offset.saturating_add(rows.len() as u64);
+    Ok(QueryJobPage {
+        offset,
+        columns,
+        rows,
+        next_offset: (end < total).then_some(end),
+    })
+}
+
+/// Mark succeeded jobs past `expires_at` as expired; returns how many changed.
+///
+/// Result objects are not deleted here; a lifecycle rule on the results prefix should remove them.
+pub async fn expire_query_jobs(pool: &PgPool) -> anyhow::Result<u64> {
+    let expired = sqlx::query(
+        r#"
+        UPDATE data.query_results
+        SET status = 'expired', updated_at = now()
+        WHERE status = 'succeeded' AND expires_at <= now()
+        "#,
+    )
+    .execute(pool)
+    .await
+    .context("expire query jobs")?;
+    Ok(expired.rows_affected())
+}
+
+/// Extend the lease on every queued or running job owned by this process; returns how many.
+pub async fn renew_job_leases(pool: &PgPool, jobs: &QueryJobs) -> anyhow::Result<u64> {
+    let renewed = sqlx::query(
+        r#"
+        UPDATE data.query_results
+        SET lease_expires_at = now() + make_interval(secs => $2)
+        WHERE owner_id = $1 AND status IN ('queued', 'running')
+        "#,
+    )
+    .bind(jobs.instance_id)
+    .bind(jobs.lease.as_secs_f64())
+    .execute(pool)
+    .await
+    .context("renew query job leases")?;
+    Ok(renewed.rows_affected())
+}
+
+/// Fail queued or running jobs whose owner stopped renewing their lease; returns how many changed.
+///
+/// A lapsed lease means the owning process died or lost the data DB, so the job will never
+/// finish. Jobs of live instances, including other replicas, are left alone.
+pub async fn fail_interrupted_jobs(pool: &PgPool) -> anyhow::Result<u64> {
+    let failed = sqlx::query(
+        r#"
+        UPDATE data.query_results
+        SET status = 'failed',
+            error_code = 'interrupted',
+            error_message = 'query job interrupted: its query service instance stopped',
+            finished_at = now(),
+            updated_at = now()
+        WHERE status IN ('queued', 'running')
+          AND (lease_expires_at IS NULL OR lease_expires_at <= now())
+        "#,
+    )
+    .execute(pool)
+    .await
+    .context("fail interrupted query jobs")?;
+    Ok(failed.rows_affected())
+}
+
+/// Run [`renew_job_leases`] for this process's jobs every third of the lease.
+pub async fn run_lease_renewer(pool: PgPool, jobs: QueryJobs) {
+    let mut ticker = tokio::time::interval(jobs.lease / 3);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = renew_job_leases(&pool, &jobs).await {
+            tracing::warn!(
+                event = "query_service.query_job.lease_renew_failed",
+                error = ?err,
+                "query job lease renewal failed"
+            );
+        }
+    }
+}
+
+/// Run [`fail_interrupted_jobs`] and [`expire_query_jobs`] every `interval`.
+pub async fn run_job_sweeper(pool: PgPool, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match fail_interrupted_jobs(&pool).await {
+            Ok(0) => {}
+            Ok(interrupted) => tracing::warn!(
+                event = "query_service.query_job.interrupted",
+                interrupted,
+                "failed query jobs whose instance stopped"
+            ),
+            Err(err) => tracing::warn!(
+                event = "query_service.query_job.interrupt_failed",
+                error = ?err,
+                "failing interrupted query jobs failed"
+            ),
+        }
+        match expire_query_jobs(&pool).await {
+            Ok(0) => {}
+            Ok(expired) => tracing::info!(
+                event = "query_service.query_job.expired",
+                expired,
+                "query job results expired"
+            ),
+            Err(err) => tracing::warn!(
+                event = "query_service.query_job.expire_failed",
+                error = ?err,
+                "query job expiry failed"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+    use trace_core::testing::MemoryObjectStore;
+
+    /// A 100-row result (`n` = 0..100) in row groups of 10.
+    async fn result_store() -> MemoryObjectStore {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from_iter_values(0..100))],
+        )
+        .unwrap();
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(10)
+            .build();
+        let mut out = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut out, schema, Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let store = MemoryObjectStore::default();
+        store
+            .put_bytes(
+                "results",
+                "job/result.parquet",
+                out,
+                "application/x-parquet",
+            )
+            .await
+            .unwrap();
+        store
+    }
+
+    async fn page(
+        store: &MemoryObjectStore,
+        offset: u64,
+        limit: u64,
+    ) -> (Vec<usize>, QueryJobPage) {
+        let ranges = fetch_page_ranges(store, "results", "job/result.parquet", offset, limit)
+            .await
+            .unwrap();
+        let row_groups = ranges.row_groups.clone();
+        (row_groups, decode_page(ranges, offset, limit).unwrap())
+    }
+
+    #[tokio::test]
+    async fn pages_fetch_only_their_row_groups() {
+        let store = result_store().await;
+
+        let (row_groups, page1) = page(&store, 25, 10).await;
+        assert_eq!(row_groups, vec![2, 3]);
+        assert_eq!(page1.columns.len(), 1);
+        assert_eq!(page1.columns[0].name, "n");
+        let rows: Vec<_> = (25..35).map(|n| vec![serde_json::json!(n)]).collect();
+        assert_eq!(page1.rows, rows);
+        assert_eq!(page1.next_offset, Some(35));
+
+        let (row_groups, last) = page(&store, 95, 10).await;
+        assert_eq!(row_groups, vec![9]);
+        assert_eq!(last.rows.len(), 5);
+        assert_eq!(last.next_offset, None);
+
+        let (row_groups, past_end) = page(&store, 200, 10).await;
+        assert!(row_groups.is_empty());
+        assert!(past_end.rows.is_empty());
+        assert_eq!(past_end.columns.len(), 1);
+        assert_eq!(past_end.next_offset, None);
+    }
+}
@@ -1,49 +1,96 @@
 //! Trace query service (Lite mode).
 //!
-//! Exposes a constrained `/v1/task/query` endpoint backed by DuckDB, intended for local/harness
-//! flows with a fail-closed SQL validator.
-
-use crate::config::QueryServiceConfig;
-use crate::duckdb::{DuckDbQueryError, DuckDbSandbox, QueryResultSet};
+//! Exposes constrained `/v1/task/query` and `/v1/query` endpoints backed by DuckDB, intended for
+//! local/harness flows with a fail-closed SQL validator.
+
+use crate::admission::{AdmissionController, AdmissionLimits, AdmissionStats, Rejection};
+use crate::attempts::AttemptCache;
+use crate::audit::{AuditPrincipal, QueryAudit};
+use crate::config::{QueryServiceConfig, ResultLimits};
+use crate::duckdb::{
+    DatasetView, DatasetViewSource, DuckDbLimits, DuckDbQueryError, DuckDbSandbox, MakeBatchSink,
+    QueryResultSet,
+};
+use crate::format::{ChunkWriter, ResultFormat};
+use crate::jobs::{QueryJob, QueryJobAccepted, QueryJobResponse, QueryJobStatus, QueryJobs};
+use crate::schema::{DatasetSchemaResponse, SchemaCache};
+use crate::user_jwt::UserJwtClaims;
 use anyhow::Context;
 use axum::{
-    extract::State,
-    http::{header, HeaderMap, StatusCode},
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     Json, Router,
 };
-use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::postgres::PgPoolOptions;
-use std::{sync::Arc, time::Duration};
-use trace_core::lite::jwt::{Hs256TaskCapabilityConfig, TaskCapability};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use trace_core::lite::jwks::read_public_keys;
+use trace_core::lite::jwt::{
+    Hs256TaskCapabilityConfig, TaskCapability, TaskCapabilityVerifierConfig,
+};
 use trace_core::lite::s3::parse_s3_uri;
 use trace_core::lite::s3::ObjectStore as LiteObjectStore;
-use trace_core::manifest::DatasetManifestV1;
+use trace_core::manifest::{
+    ColumnRange, DatasetManifest, DatasetManifestV1, DatasetManifestV2, ManifestObject,
+};
+use trace_core::query::{IntBounds, ValidatedQuery};
 use trace_core::ObjectStore as ObjectStoreTrait;
-use trace_core::Signer as SignerTrait;
 use trace_core::{DatasetGrant, DatasetStorageRef, S3Grants};
 use uuid::Uuid;
 
+mod admission;
+pub mod attempts;
+pub mod audit;
 pub mod config;
 mod duckdb;
+mod format;
+pub mod jobs;
+mod registry;
+mod schema;
+mod user_jwt;
+mod values;
+
+pub use user_jwt::UserJwtVerifier;
 
 pub const TASK_CAPABILITY_HEADER: &str = "X-Trace-Task-Capability";
 
-// Default and max per-request row limits (defense-in-depth against memory/CPU blowups).
-const DEFAULT_LIMIT: usize = 1000;
-const MAX_LIMIT: usize = 10_000;
+/// Response header carrying the row ceiling applied to a streamed result.
+pub const ROW_LIMIT_HEADER: &str = "x-trace-row-limit";
+
+// Default row limit for JSON results; every format is capped by the org's `ResultLimits`.
+const DEFAULT_LIMIT: u64 = 1000;
+// Encoded chunks buffered between the DuckDB worker and a slow client.
+const STREAM_BUFFER_CHUNKS: usize = 16;
+
+// Relation name for the single-dataset (`dataset_id`) request form.
+const DEFAULT_DATASET_ALIAS: &str = "dataset";
+// Upper bound on datasets attached by one query (each is authorized and attached separately).
+const MAX_QUERY_DATASETS: usize = 8;
+const MAX_DATASET_ALIAS_LEN: usize = 63;
 
 #[derive(Clone)]
 pub struct AppState {
     pub cfg: QueryServiceConfig,
     pub signer: TaskCapability,
     pub user_jwt: UserJwtVerifier,
+    /// Task attempt liveness lookups (capability revocation, see [`attempts`]).
+    pub attempts: AttemptCache,
     pub duckdb: DuckDbSandbox,
     pub data_pool: sqlx::PgPool,
+    /// State DB pool for dataset registry lookups; `None` disables multi-range resolution.
+    pub state_pool: Option<sqlx::PgPool>,
     pub object_store: Arc<dyn ObjectStoreTrait>,
+    /// Async query job executor (`/v1/query/jobs`).
+    pub jobs: QueryJobs,
+    /// Per-org query slots and per-user rate limits for synchronous queries.
+    pub admission: AdmissionController,
+    /// Described dataset versions (`/v1/datasets/{dataset_id}/schema`).
+    pub schemas: SchemaCache,
 }
 
 impl std::fmt::Debug for AppState {
@@ -51,12 +98,42 @@ impl std::fmt::Debug for AppState {
         f.debug_struct("AppState")
             .field("cfg", &self.cfg)
             .field("data_pool", &"<PgPool>")
+            .field("state_pool", &self.state_pool.as_ref().map(|_| "<PgPool>"))
             .field("signer", &"<TaskCapability>")
             .field("user_jwt", &"<UserJwtVerifier>")
             .finish()
     }
 }
 
+/// Verify capabilities with public keys when any are configured, else with the shared HS256
+/// secret (lite default).
+fn task_capability_verifier(cfg: &QueryServiceConfig) -> anyhow::Result<TaskCapability> {
+    if cfg.task_capability_public_keys.is_none() && cfg.task_capability_jwks_url.is_none() {
+        return Ok(TaskCapability::from_hs256_config(
+            Hs256TaskCapabilityConfig {
+                issuer: cfg.task_capability_iss.clone(),
+                audience: cfg.task_capability_aud.clone(),
+                current_kid: cfg.task_capability_kid.clone(),
+                current_secret: cfg.task_capability_secret.clone(),
+                next_kid: cfg.task_capability_next_kid.clone(),
+                next_secret: cfg.task_capability_next_secret.clone(),
+                ttl: Duration::from_secs(cfg.task_capability_ttl_secs),
+            },
+        )?);
+    }
+
+    let public_keys = match cfg.task_capability_public_keys.as_deref() {
+        Some(spec) => read_public_keys(spec).context("read TASK_CAPABILITY_PUBLIC_KEYS")?,
+        None => Vec::new(),
+    };
+    Ok(TaskCapability::verifier(TaskCapabilityVerifierConfig {
+        issuer: cfg.task_capability_iss.clone(),
+        audience: cfg.task_capability_aud.clone(),
+        public_keys,
+        jwks_url: cfg.task_capability_jwks_url.clone(),
+    })?)
+}
+
 pub async fn build_state(cfg: QueryServiceConfig) -> anyhow::Result<AppState> {
     let data_pool = PgPoolOptions::new()
         .max_connections(5)
@@ -64,30 +141,54 @@ pub async fn build_state(cfg: QueryServiceConfig) -> anyhow::Result<AppState> {
         .await
         .context("connect data db")?;
 
-    let signer = TaskCapability::from_hs256_config(Hs256TaskCapabilityConfig {
-        issuer: cfg.task_capability_iss.clone(),
-        audience: cfg.task_capability_aud.clone(),
-        current_kid: cfg.task_capability_kid.clone(),
-        current_secret: cfg.task_capability_secret.clone(),
-        next_kid: cfg.task_capability_next_kid.clone(),
-        next_secret: cfg.task_capability_next_secret.clone(),
-        ttl: Duration::from_secs(cfg.task_capability_ttl_secs),
-    })
-    .context("init task capability signer")?;
+    let signer = task_capability_verifier(&cfg).context("init task capability verifier")?;
+
+    let state_pool = match cfg.state_database_url.as_deref() {
+        Some(url) => Some(
+            PgPoolOptions::new()
+                .max_connections(5)
+                .connect(url)
+                .await
+                .context("connect state db")?,
+        ),
+        None => {
+            tracing::warn!(
+                event = "query_service.capability.attempt_check_unavailable",
+                "STATE_DATABASE_URL is not set; /v1/task/query rejects every request"
+            );
+            None
+        }
+    };
+    let attempts = AttemptCache::new(Duration::from_millis(cfg.task_attempt_cache_ms));
 
     let user_jwt = UserJwtVerifier::from_config(&cfg).context("init user jwt verifier")?;
+    user_jwt
+        .start_refresh()
+        .await
+        .context("start user jwt jwks refresh")?;
 
-    let duckdb = DuckDbSandbox::new();
+    let duckdb = DuckDbSandbox::new(DuckDbLimits::from_config(&cfg));
     let object_store: Arc<dyn ObjectStoreTrait> =
-        Arc::new(LiteObjectStore::new(&cfg.s3_endpoint).context("init object store")?);
+        Arc::new(LiteObjectStore::from_config(cfg.s3_config()?).context("init object store")?);
+    let jobs = QueryJobs::new(
+        cfg.max_running_jobs,
+        Duration::from_secs(cfg.job_lease_secs.max(1)),
+    );
+    let admission = AdmissionController::new(AdmissionLimits::from_config(&cfg));
+    let schemas = SchemaCache::new(cfg.schema_cache_entries);
 
     Ok(AppState {
         cfg,
         signer,
         user_jwt,
+        attempts,
         duckdb,
         data_pool,
+        state_pool,
         object_store,
+        jobs,
+        admission,
+        schemas,
     })
 }
 
@@ -96,14 +197,32 @@ pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/v1/task/query", post(task_query))
         .route("/v1/query", post(user_query))
+        .route("/v1/query/jobs", post(submit_query_job))
+        .route(
+            "/v1/query/jobs/:job_id",
+            get(get_query_job).delete(cancel_query_job),
+        )
+        .route("/v1/datasets/:dataset_id/schema", get(get_dataset_schema))
         .with_state(state)
 }
 
+/// Internal endpoints, served only on `QUERY_SERVICE_INTERNAL_BIND`.
+pub fn internal_router(state: AppState) -> Router {
+    Router::new()
+        .route("/internal/admission", get(admission_stats))
+        .with_state(Arc::new(state))
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TaskQueryRequest {
     pub task_id: Uuid,
     pub attempt: i64,
-    pub dataset_id: Uuid,
+    /// Single dataset, attached as the `dataset` relation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset_id: Option<Uuid>,
+    /// Datasets keyed by alias; each is attached as a relation named by its alias.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub datasets: BTreeMap<String, Uuid>,
     pub sql: String,
     pub limit: Option<u32>,
 }
@@ -117,7 +236,12 @@ pub struct TaskQueryResponse {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserQueryRequest {
-    pub dataset_id: Uuid,
+    /// Single dataset, attached as the `dataset` relation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset_id: Option<Uuid>,
+    /// Datasets keyed by alias; each is attached as a relation named by its alias.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub datasets: BTreeMap<String, Uuid>,
     pub sql: String,
     pub limit: Option<u32>,
 }
@@ -135,205 +259,618 @@ pub struct QueryColumnResponse {
     pub r#type: String,
 }
 
+/// Admission queue depth and rejection counters.
+async fn admission_stats(State(state): State<Arc<AppState>>) -> Json<AdmissionStats> {
+    Json(state.admission.stats())
+}
+
 async fn task_query(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Json(req): Json<TaskQueryRequest>,
-) -> Result<Json<TaskQueryResponse>, ApiError> {
-    let claims = require_task_capability(&state.signer, &headers, req.task_id, req.attempt)?;
-    let grant = require_dataset_grant(&claims, req.dataset_id)?;
+) -> Result<Response, ApiError> {
+    let claims = require_task_capability(&state.signer, &headers, req.task_id, req.attempt).await?;
+    require_current_attempt(&state, &claims).await?;
+    let format = ResultFormat::negotiate(&headers)?;
+    let datasets = requested_datasets(req.dataset_id, &req.datasets)?;
+    let grants = datasets
+        .iter()
+        .map(|(alias, dataset_id)| {
+            Ok((alias.clone(), require_dataset_grant(&claims, *dataset_id)?))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
 
-    trace_core::query::validate_sql(&req.sql).map_err(|err| {
-        tracing::info!(
-            event = "query_service.sql.rejected",
-            error = %err,
-            "sql rejected"
-        );
-        ApiError::bad_request("invalid sql")
-    })?;
+    let audit = QueryAudit::new(claims.org_id, AuditPrincipal::Task(req.task_id), &req.sql);
+    let validated = match validate_request_sql(&req.sql, &datasets) {
+        Ok(validated) => validated,
+        Err(err) => return Err(audit.rejected(&state.data_pool, &datasets, err).await),
+    };
+
+    let views = async {
+        // Task capabilities pin exactly one dataset_version per input.
+        let mut views = Vec::with_capacity(grants.len());
+        for (alias, grant) in grants {
+            let pruning = FilePruning::for_view(&validated, &alias);
+            views.push(DatasetView {
+                name: alias,
+                source: dataset_view_source(&state, &claims.s3, &[grant], pruning).await?,
+            });
+        }
+        Ok(views)
+    }
+    .await;
+
+    let limits = state.cfg.result_limits(claims.org_id);
+    let query = GrantedQuery {
+        audit,
+        datasets,
+        validated,
+        sql: req.sql,
+        format,
+        limit: result_row_limit(req.limit, format, limits),
+        max_bytes: limits.max_bytes,
+        timeout: Duration::from_secs(state.cfg.task_query_timeout_secs),
+    };
+    Ok(
+        match run_granted_query(&state, claims.org_id, query, views).await? {
+            QueryOutput::Rows(results) => Json(TaskQueryResponse {
+                columns: columns_to_response(&results),
+                truncated: results.truncated,
+                rows: results.rows,
+            })
+            .into_response(),
+            QueryOutput::Stream(response) => response,
+        },
+    )
+}
 
-    let storage_ref = authorize_dataset_storage(&state.cfg, &claims.s3, &grant)?;
+async fn user_query(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<UserQueryRequest>,
+) -> Result<Response, ApiError> {
+    let claims = require_user_bearer(&state.user_jwt, &headers).await?;
+    state
+        .admission
+        .check_rate(claims.org_id, &claims.sub)
+        .map_err(admission_error_to_api)?;
+    let format = ResultFormat::negotiate(&headers)?;
+    let (query, views) = grant_user_query(&state, &claims, req, format).await?;
+    Ok(
+        match run_granted_query(&state, claims.org_id, query, views).await? {
+            QueryOutput::Rows(results) => Json(UserQueryResponse {
+                columns: columns_to_response(&results),
+                truncated: results.truncated,
+                rows: results.rows,
+            })
+            .into_response(),
+            QueryOutput::Stream(response) => response,
+        },
+    )
+}
 
-    let limit = req
-        .limit
-        .map(|v| v as usize)
-        .unwrap_or(DEFAULT_LIMIT)
-        .clamp(1, MAX_LIMIT);
+/// Authorize and gate a user query, then resolve its dataset views.
+///
+/// View resolution errors are returned alongside the query so the caller can audit them.
+async fn grant_user_query(
+    state: &AppState,
+    claims: &UserJwtClaims,
+    req: UserQueryRequest,
+    format: ResultFormat,
+) -> Result<(GrantedQuery, Result<Vec<DatasetView>, ApiError>), ApiError> {
+    let datasets = requested_datasets(req.dataset_id, &req.datasets)?;
+    let grants = datasets
+        .iter()
+        .map(|(alias, dataset_id)| {
+            Ok((
+                alias.clone(),
+                require_dataset_grant_user(claims, *dataset_id)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
 
-    let mut results = match storage_ref {
-        DatasetStorageRef::S3 { bucket, prefix, .. } => {
-            let parquet_uris = resolve_parquet_uris_from_manifest(
-                &state.cfg,
-                state.object_store.as_ref(),
-                &claims.s3,
-                &grant,
-                &bucket,
-                &prefix,
-            )
-            .await
-            .map_err(|err| {
-                tracing::warn!(
-                    event = "query_service.manifest.error",
-                    kind = ?err.kind,
-                    error = %err,
-                    "dataset manifest resolution failed"
-                );
-                match err.kind {
-                    ManifestErrorKind::TooLarge => {
-                        ApiError::payload_too_large("dataset manifest too large")
-                    }
-                    ManifestErrorKind::InvalidJson | ManifestErrorKind::InvalidSchema => {
-                        ApiError::unprocessable("dataset manifest invalid")
-                    }
-                    ManifestErrorKind::Unauthorized => {
-                        ApiError::forbidden("dataset storage not authorized")
-                    }
-                    ManifestErrorKind::FetchFailed => {
-                        ApiError::internal("dataset manifest fetch failed")
-                    }
-                }
-            })?;
+    let audit = QueryAudit::new(
+        claims.org_id,
+        AuditPrincipal::User(claims.sub.clone()),
+        &req.sql,
+    );
+    let validated = match validate_request_sql(&req.sql, &datasets) {
+        Ok(validated) => validated,
+        Err(err) => return Err(audit.rejected(&state.data_pool, &datasets, err).await),
+    };
 
-            state
-                .duckdb
-                .query_with_s3_parquet_uris(&state.cfg, parquet_uris, req.sql, limit + 1)
-                .await
+    let views = async {
+        // Pin each dataset's range versions at query start (ADR 0009). Datasets the registry
+        // doesn't know about are read through the grant's own storage ref.
+        let mut views = Vec::with_capacity(grants.len());
+        for (alias, grant) in grants {
+            let versions = resolve_user_dataset_versions(state, &grant).await?;
+            let pruning = FilePruning::for_view(&validated, &alias);
+            views.push(DatasetView {
+                name: alias,
+                source: dataset_view_source(state, &claims.s3, &versions, pruning).await?,
+            });
         }
-        DatasetStorageRef::File { prefix, glob } => {
-            let scan = file_scan_target(&prefix, &glob).map_err(|err| {
-                tracing::warn!(
-                    event = "query_service.file_scan.invalid",
-                    error = %err,
-                    "invalid file scan target"
-                );
-                ApiError::unprocessable("dataset storage not authorized")
-            })?;
-            state
-                .duckdb
-                .query_with_file_scan(scan, req.sql, limit + 1)
-                .await
+        Ok(views)
+    }
+    .await;
+
+    let limits = state.cfg.result_limits(claims.org_id);
+    let query = GrantedQuery {
+        audit,
+        datasets,
+        validated,
+        sql: req.sql,
+        format,
+        limit: result_row_limit(req.limit, format, limits),
+        max_bytes: limits.max_bytes,
+        timeout: Duration::from_secs(state.cfg.user_query_timeout_secs),
+    };
+    Ok((query, views))
+}
+
+async fn submit_query_job(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<UserQueryRequest>,
+) -> Result<(StatusCode, Json<QueryJobAccepted>), ApiError> {
+    let claims = require_user_bearer(&state.user_jwt, &headers).await?;
+    state
+        .admission
+        .check_rate(claims.org_id, &claims.sub)
+        .map_err(admission_error_to_api)?;
+    let (mut query, views) = grant_user_query(&state, &claims, req, ResultFormat::Parquet).await?;
+    query.timeout = Duration::from_secs(state.cfg.job_timeout_secs);
+    let views = match views {
+        Ok(views) => views,
+        Err(err) => {
+            return Err(query
+                .audit
+                .failed(&state.data_pool, &query.datasets, &query.validated, err)
+                .await)
         }
+    };
+
+    let job_id = jobs::submit(
+        &state,
+        QueryJob {
+            org_id: claims.org_id,
+            user_sub: claims.sub,
+            query,
+            views,
+        },
+    )
+    .await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(QueryJobAccepted {
+            job_id,
+            status: QueryJobStatus::Queued,
+        }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryJobPageParams {
+    /// First result row to return.
+    #[serde(default)]
+    pub offset: u64,
+    pub limit: Option<u32>,
+}
+
+async fn get_query_job(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+    Query(params): Query<QueryJobPageParams>,
+) -> Result<Json<QueryJobResponse>, ApiError> {
+    let claims = require_user_bearer(&state.user_jwt, &headers).await?;
+    let (mut job, location) = jobs::load(&state.data_pool, job_id, claims.org_id, &claims.sub)
+        .await?
+        .ok_or_else(|| ApiError::not_found("query job not found"))?;
+
+    if let (QueryJobStatus::Succeeded, Some(location)) = (job.status, location) {
+        let limits = state.cfg.result_limits(claims.org_id);
+        let limit = result_row_limit(params.limit, ResultFormat::Json, limits);
+        job.page = Some(jobs::read_page(&state, &location, params.offset, limit).await?);
     }
-    .map_err(|err| match err {
-        DuckDbQueryError::Attach(err) => {
-            tracing::warn!(
-                event = "query_service.duckdb.attach_failed",
-                error = ?err,
-                "duckdb dataset attach failed"
-            );
-            ApiError::internal("query execution failed")
+    Ok(Json(job))
+}
+
+async fn cancel_query_job(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<QueryJobResponse>, ApiError> {
+    let claims = require_user_bearer(&state.user_jwt, &headers).await?;
+    let canceled = jobs::cancel(&state, job_id, claims.org_id, &claims.sub).await?;
+    let (job, _) = jobs::load(&state.data_pool, job_id, claims.org_id, &claims.sub)
+        .await?
+        .ok_or_else(|| ApiError::not_found("query job not found"))?;
+    if !canceled {
+        return Err(ApiError::conflict("query job is not active"));
+    }
+    Ok(Json(job))
+}
+
+async fn get_dataset_schema(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(dataset_id): Path<Uuid>,
+) -> Result<Json<DatasetSchemaResponse>, ApiError> {
+    let claims = require_user_bearer(&state.user_jwt, &headers).await?;
+    let grant = require_dataset_grant_user(&claims, dataset_id)?;
+    let versions = resolve_user_dataset_versions(&state, &grant).await?;
+    Ok(Json(
+        schema::describe(&state, &claims, dataset_id, versions).await?,
+    ))
+}
+
+/// A query whose datasets are granted and whose SQL passed the gate.
+struct GrantedQuery {
+    audit: QueryAudit,
+    datasets: Vec<(String, Uuid)>,
+    validated: ValidatedQuery,
+    sql: String,
+    format: ResultFormat,
+    /// Row ceiling for this response (see [`result_row_limit`]).
+    limit: u64,
+    /// Byte ceiling for this response's encoded rows.
+    max_bytes: u64,
+    /// Deadline for executing the query and writing its result.
+    timeout: Duration,
+}
+
+enum QueryOutput {
+    /// Buffered JSON rows.
+    Rows(QueryResultSet),
+    /// A streamed response that is still being written.
+    Stream(Response),
+}
+
+/// Row ceiling for a response: the requested `limit` (JSON defaults to [`DEFAULT_LIMIT`], streamed
+/// formats to the org ceiling), clamped to `[1, max_rows]`.
+fn result_row_limit(requested: Option<u32>, format: ResultFormat, limits: ResultLimits) -> u64 {
+    let max_rows = limits.max_rows.max(1);
+    let default = match format {
+        ResultFormat::Json => DEFAULT_LIMIT,
+        _ => max_rows,
+    };
+    requested
+        .map(u64::from)
+        .unwrap_or(default)
+        .clamp(1, max_rows)
+}
+
+/// Take one of `org_id`'s query slots, attach `views`, run the query, and audit the outcome.
+///
+/// Admission happens only once the query is validated and its views resolved, so rejected or
+/// unresolvable queries never hold a slot. The slot is held until the query finishes, including
+/// while a streamed result is written.
+async fn run_granted_query(
+    state: &Arc<AppState>,
+    org_id: Uuid,
+    query: GrantedQuery,
+    views: Result<Vec<DatasetView>, ApiError>,
+) -> Result<QueryOutput, ApiError> {
+    let GrantedQuery {
+        audit,
+        datasets,
+        validated,
+        sql,
+        format,
+        limit,
+        max_bytes,
+        timeout,
+    } = query;
+    let pool = &state.data_pool;
+
+    let views = match views {
+        Ok(views) => views,
+        Err(err) => return Err(audit.failed(pool, &datasets, &validated, err).await),
+    };
+    let permit = match state.admission.admit(org_id).await {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            let err = admission_error_to_api(rejection);
+            return Err(audit.failed(pool, &datasets, &validated, err).await);
         }
-        DuckDbQueryError::Query(_err) => {
-            // Avoid logging raw SQL; DuckDB errors may embed the statement text.
+    };
+
+    if format == ResultFormat::Json {
+        let results = match state
+            .duckdb
+            .query_with_dataset_views(&state.cfg, views, sql, limit, max_bytes, timeout)
+            .await
+        {
+            Ok(results) => results,
+            Err(err) => {
+                let err = duckdb_error_to_api(err);
+                return Err(audit.failed(pool, &datasets, &validated, err).await);
+            }
+        };
+        audit
+            .succeeded(
+                pool,
+                &datasets,
+                &validated,
+                results.rows.len() as u64,
+                &results.scans,
+            )
+            .await?;
+        return Ok(QueryOutput::Rows(results));
+    }
+
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    let writer = ChunkWriter::new(tx.clone(), max_bytes);
+    let make_sink: MakeBatchSink = Box::new(move |schema| format.encoder(schema, writer));
+    let stream = match state
+        .duckdb
+        .stream_with_dataset_views(&state.cfg, views, sql, limit, make_sink, timeout)
+        .await
+    {
+        Ok(stream) => stream,
+        Err(err) => {
+            let err = duckdb_error_to_api(err);
+            return Err(audit.failed(pool, &datasets, &validated, err).await);
+        }
+    };
+
+    // The response has started by the time the worker finishes, so errors past this point abort
+    // the body instead of changing the status, and audit write failures are only logged.
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        match stream.finish().await {
+            Ok(summary) => {
+                drop(tx);
+                let _ = audit
+                    .succeeded(&pool, &datasets, &validated, summary.rows, &summary.scans)
+                    .await;
+            }
+            Err(err) => {
+                let err = duckdb_error_to_api(err);
+                let _ = tx
+                    .send(Err(std::io::Error::other("query execution failed")))
+                    .await;
+                drop(tx);
+                let _ = audit.failed(&pool, &datasets, &validated, err).await;
+            }
+        }
+    });
+
+    let body = Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        ),
+        (
+            HeaderName::from_static(ROW_LIMIT_HEADER),
+            HeaderValue::from(limit),
+        ),
+    ];
+    Ok(QueryOutput::Stream((headers, body).into_response()))
+}
+
+/// Resolve the range versions a user query reads for `grant`.
+async fn resolve_user_dataset_versions(
+    state: &AppState,
+    grant: &DatasetGrant,
+) -> Result<Vec<DatasetGrant>, ApiError> {
+    let Some(state_pool) = state.state_pool.as_ref() else {
+        return Ok(vec![grant.clone()]);
+    };
+
+    let view = registry::resolve_dataset_view(state_pool, grant.dataset_uuid)
+        .await
+        .map_err(|err| {
             tracing::warn!(
-                event = "query_service.duckdb.query_failed",
-                "duckdb query failed"
+                event = "query_service.registry.error",
+                error = %err,
+                "dataset registry resolution failed"
             );
-            ApiError::internal("query execution failed")
-        }
-    })?;
+            ApiError::internal("dataset registry lookup failed")
+        })?;
+
+    let Some(view) = view else {
+        return Ok(vec![grant.clone()]);
+    };
 
-    let truncated = results.rows.len() > limit;
-    if truncated {
-        results.rows.truncate(limit);
+    if view.versions.len() > state.cfg.max_view_versions {
+        return Err(ApiError::payload_too_large("dataset has too many versions"));
     }
 
-    insert_query_audit(
-        &state.data_pool,
-        claims.org_id,
-        req.task_id,
-        req.dataset_id,
-        results.rows.len() as i64,
-    )
-    .await?;
+    tracing::debug!(
+        event = "query_service.registry.pinned",
+        dataset_uuid = %view.dataset_uuid,
+        config_hash = %view.config_hash,
+        versions = view.versions.len(),
+        "pinned dataset range versions"
+    );
+
+    Ok(view
+        .versions
+        .into_iter()
+        .map(|v| DatasetGrant {
+            dataset_uuid: view.dataset_uuid,
+            dataset_version: v.dataset_version,
+            storage_ref: Some(v.storage_ref),
+        })
+        .collect())
+}
+
+/// Normalize the request's dataset selection into `(alias, dataset_id)` pairs.
+///
+/// The legacy single `dataset_id` form is attached as `dataset`.
+fn requested_datasets(
+    dataset_id: Option<Uuid>,
+    datasets: &BTreeMap<String, Uuid>,
+) -> Result<Vec<(String, Uuid)>, ApiError> {
+    let requested: Vec<(String, Uuid)> = match dataset_id {
+        Some(_) if !datasets.is_empty() => {
+            return Err(ApiError::bad_request(
+                "specify either dataset_id or datasets",
+            ))
+        }
+        Some(dataset_id) => vec![(DEFAULT_DATASET_ALIAS.to_string(), dataset_id)],
+        None => datasets
+            .iter()
+            .map(|(alias, dataset_id)| (alias.clone(), *dataset_id))
+            .collect(),
+    };
+
+    if requested.is_empty() {
+        return Err(ApiError::bad_request("missing dataset"));
+    }
+    if requested.len() > MAX_QUERY_DATASETS {
+        return Err(ApiError::bad_request("too many datasets"));
+    }
+    if !requested
+        .iter()
+        .all(|(alias, _)| is_valid_dataset_alias(alias))
+    {
+        return Err(ApiError::bad_request("invalid dataset alias"));
+    }
 
-    Ok(Json(TaskQueryResponse {
-        columns: columns_to_response(&results),
-        rows: results.rows,
-        truncated,
-    }))
+    Ok(requested)
 }
 
-async fn user_query(
-    State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    Json(req): Json<UserQueryRequest>,
-) -> Result<Json<UserQueryResponse>, ApiError> {
-    let claims = require_user_bearer(&state.user_jwt, &headers)?;
-    let grant = require_dataset_grant_user(&claims, req.dataset_id)?;
+/// Aliases become SQL relation names: `[a-z_][a-z0-9_]*`, at most 63 bytes.
+fn is_valid_dataset_alias(alias: &str) -> bool {
+    let bytes = alias.as_bytes();
+    match bytes.first() {
+        Some(b) if b.is_ascii_lowercase() || *b == b'_' => {}
+        _ => return false,
+    }
+    bytes.len() <= MAX_DATASET_ALIAS_LEN
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'_')
+}
 
-    trace_core::query::validate_sql(&req.sql).map_err(|err| {
+/// Gate untrusted SQL: it may only reference the attached dataset views.
+fn validate_request_sql(
+    sql: &str,
+    datasets: &[(String, Uuid)],
+) -> Result<ValidatedQuery, ApiError> {
+    let relations: Vec<&str> = datasets.iter().map(|(alias, _)| alias.as_str()).collect();
+    trace_core::query::validate_sql_relations(sql, &relations).map_err(|err| {
         tracing::info!(
             event = "query_service.sql.rejected",
             error = %err,
             "sql rejected"
         );
         ApiError::bad_request("invalid sql")
-    })?;
-
-    let storage_ref = authorize_dataset_storage(&state.cfg, &claims.s3, &grant)?;
-
-    let limit = req
-        .limit
-        .map(|v| v as usize)
-        .unwrap_or(DEFAULT_LIMIT)
-        .clamp(1, MAX_LIMIT);
+    })
+}
 
-    let mut results = match storage_ref {
-        DatasetStorageRef::S3 { bucket, prefix, .. } => {
-            let parquet_uris = resolve_parquet_uris_from_manifest(
-                &state.cfg,
-                state.object_store.as_ref(),
-                &claims.s3,
-                &grant,
-                &bucket,
-                &prefix,
-            )
-            .await
-            .map_err(|err| {
-                tracing::warn!(
-                    event = "query_service.manifest.error",
-                    kind = ?err.kind,
-                    error = %err,
-                    "dataset manifest resolution failed"
-                );
-                match err.kind {
-                    ManifestErrorKind::TooLarge => {
-                        ApiError::payload_too_large("dataset manifest too large")
-                    }
-                    ManifestErrorKind::InvalidJson | ManifestErrorKind::InvalidSchema => {
-                        ApiError::unprocessable("dataset manifest invalid")
-                    }
-                    ManifestErrorKind::Unauthorized => {
-                        ApiError::forbidden("dataset storage not authorized")
-                    }
-                    ManifestErrorKind::FetchFailed => {
-                        ApiError::internal("dataset manifest fetch failed")
+/// Authorize `versions` (all of one dataset) and resolve the Parquet files its view reads.
+///
+/// Manifest objects that `pruning` rules out are not attached; if that rules out every object,
+/// one is still attached so the view keeps its columns (the query's filter matches none of it).
+async fn dataset_view_source(
+    state: &AppState,
+    s3: &S3Grants,
+    versions: &[DatasetGrant],
+    pruning: FilePruning,
+) -> Result<DatasetViewSource, ApiError> {
+    let mut s3_uris = Vec::new();
+    let mut pruned = Vec::new();
+    let mut file_scans = Vec::new();
+    for grant in versions {
+        match authorize_dataset_storage(&state.cfg, s3, grant)? {
+            DatasetStorageRef::S3 { bucket, prefix, .. } => {
+                let objects = resolve_parquet_uris_from_manifest(
+                    &state.cfg,
+                    state.object_store.as_ref(),
+                    s3,
+                    grant,
+                    &bucket,
+                    &prefix,
+                )
+                .await
+                .map_err(manifest_error_to_api)?;
+                for object in objects {
+                    if pruning.keeps(&object) {
+                        s3_uris.push(object.uri);
+                    } else {
+                        pruned.push(object.uri);
                     }
                 }
-            })?;
+            }
+            DatasetStorageRef::File { prefix, glob } => {
+                let scan = file_scan_target(&prefix, &glob).map_err(|err| {
+                    tracing::warn!(
+                        event = "query_service.file_scan.invalid",
+                        error = %err,
+                        "invalid file scan target"
+                    );
+                    ApiError::unprocessable("dataset storage not authorized")
+                })?;
+                file_scans.push(scan);
+            }
+        }
+    }
 
-            state
-                .duckdb
-                .query_with_s3_parquet_uris(&state.cfg, parquet_uris, req.sql, limit + 1)
-                .await
+    if !pruned.is_empty() {
+        tracing::debug!(
+            event = "query_service.manifest.pruned",
+            attached = s3_uris.len(),
+            pruned = pruned.len(),
+            "pruned parquet objects outside the query range"
+        );
+        if s3_uris.is_empty() && file_scans.is_empty() {
+            s3_uris.push(pruned.swap_remove(0));
         }
-        DatasetStorageRef::File { prefix, glob } => {
-            let scan = file_scan_target(&prefix, &glob).map_err(|err| {
-                tracing::warn!(
-                    event = "query_service.file_scan.invalid",
-                    error = %err,
-                    "invalid file scan target"
-                );
-                ApiError::unprocessable("dataset storage not authorized")
-            })?;
-            state
-                .duckdb
-                .query_with_file_scan(scan, req.sql, limit + 1)
-                .await
+    }
+
+    match (s3_uris.is_empty(), file_scans.is_empty()) {
+        (false, true) => Ok(DatasetViewSource::S3ParquetUris(s3_uris)),
+        (true, false) => Ok(DatasetViewSource::FileScans(file_scans)),
+        (false, false) => Err(ApiError::unprocessable(
+            "dataset versions use mixed storage schemes",
+        )),
+        (true, true) => Err(ApiError::forbidden("dataset storage not authorized")),
+    }
+}
+
+// Columns whose per-object ranges a v2 manifest records.
+const BLOCK_NUMBER_COLUMN: &str = "block_number";
+const TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// Ranges a query restricts a dataset view to, for skipping manifest objects outside them.
+#[derive(Debug, Clone, Copy, Default)]
+struct FilePruning {
+    block_number: Option<IntBounds>,
+    timestamp: Option<IntBounds>,
+}
+
+impl FilePruning {
+    /// Ranges every scan of the view `alias` in `validated` is restricted to.
+    fn for_view(validated: &ValidatedQuery, alias: &str) -> Self {
+        Self {
+            block_number: validated.column_bounds(alias, BLOCK_NUMBER_COLUMN),
+            timestamp: validated.column_bounds(alias, TIMESTAMP_COLUMN),
         }
     }
-    .map_err(|err| match err {
+
+    /// Objects without statistics for a bounded column are always kept.
+    fn keeps(&self, object: &ManifestParquetObject) -> bool {
+        let overlaps = |bounds: Option<IntBounds>, range: Option<ColumnRange>| match (bounds, range)
+        {
+            (Some(bounds), Some(range)) => bounds.overlaps(range.min, range.max),
+            _ => true,
+        };
+        overlaps(self.block_number, object.block_number)
+            && overlaps(self.timestamp, object.timestamp)
+    }
+}
+
+fn duckdb_error_to_api(err: DuckDbQueryError) -> ApiError {
+    match err {
         DuckDbQueryError::Attach(err) => {
             tracing::warn!(
                 event = "query_service.duckdb.attach_failed",
@@ -343,33 +880,51 @@ async fn user_query(
             ApiError::internal("query execution failed")
         }
         DuckDbQueryError::Query(_err) => {
+            // Avoid logging raw SQL; DuckDB errors may embed the statement text.
             tracing::warn!(
                 event = "query_service.duckdb.query_failed",
                 "duckdb query failed"
             );
             ApiError::internal("query execution failed")
         }
-    })?;
-
-    let truncated = results.rows.len() > limit;
-    if truncated {
-        results.rows.truncate(limit);
+        // Logged by the sandbox when it interrupts the query.
+        DuckDbQueryError::Timeout(_) => {
+            ApiError::gateway_timeout("query timeout exceeded").with_code("QUERY_TIMEOUT")
+        }
+        DuckDbQueryError::ResourceExhausted(_err) => {
+            tracing::info!(
+                event = "query_service.duckdb.resource_exhausted",
+                "duckdb query exceeded resource limits"
+            );
+            ApiError::unprocessable("query exceeded resource limits")
+                .with_code("QUERY_RESOURCE_EXHAUSTED")
+        }
+        // Nobody is waiting for this response; it only feeds the audit record.
+        DuckDbQueryError::Canceled => {
+            tracing::info!(
+                event = "query_service.duckdb.canceled",
+                "duckdb query canceled"
+            );
+            ApiError::internal("query canceled").with_code("QUERY_CANCELED")
+        }
     }
+}
 
-    insert_user_query_audit(
-        &state.data_pool,
-        claims.org_id,
-        &claims.sub,
-        req.dataset_id,
-        results.rows.len() as i64,
-    )
-    .await?;
-
-    Ok(Json(UserQueryResponse {
-        columns: columns_to_response(&results),
-        rows: results.rows,
-        truncated,
-    }))
+fn admission_error_to_api(rejection: Rejection) -> ApiError {
+    match rejection {
+        Rejection::QueueFull { retry_after } => {
+            ApiError::too_many_requests("too many concurrent queries", retry_after)
+                .with_code("QUERY_QUEUE_FULL")
+        }
+        Rejection::QueueTimeout { retry_after } => {
+            ApiError::too_many_requests("timed out waiting for a query slot", retry_after)
+                .with_code("QUERY_QUEUE_TIMEOUT")
+        }
+        Rejection::RateLimited { retry_after } => {
+            ApiError::too_many_requests("query rate limit exceeded", retry_after)
+                .with_code("RATE_LIMITED")
+        }
+    }
 }
 
 fn file_scan_target(prefix: &str, glob: &str) -> anyhow::Result<String> {
@@ -398,6 +953,31 @@ impl std::fmt::Display for ManifestError {
     }
 }
 
+fn manifest_error_to_api(err: ManifestError) -> ApiError {
+    tracing::warn!(
+        event = "query_service.manifest.error",
+        kind = ?err.kind,
+        error = %err,
+        "dataset manifest resolution failed"
+    );
+    match err.kind {
+        ManifestErrorKind::TooLarge => ApiError::payload_too_large("dataset manifest too large"),
+        ManifestErrorKind::InvalidJson | ManifestErrorKind::InvalidSchema => {
+            ApiError::unprocessable("dataset manifest invalid")
+        }
+        ManifestErrorKind::Unauthorized => ApiError::forbidden("dataset storage not authorized"),
+        ManifestErrorKind::FetchFailed => ApiError::internal("dataset manifest fetch failed"),
+    }
+}
+
+/// A Parquet object listed by a dataset manifest.
+#[derive(Debug, Clone)]
+struct ManifestParquetObject {
+    uri: String,
+    block_number: Option<ColumnRange>,
+    timestamp: Option<ColumnRange>,
+}
+
 async fn resolve_parquet_uris_from_manifest(
     cfg: &QueryServiceConfig,
     object_store: &dyn ObjectStoreTrait,
@@ -405,7 +985,7 @@ async fn resolve_parquet_uris_from_manifest(
     grant: &DatasetGrant,
     bucket: &str,
     prefix: &str,
-) -> Result<Vec<String>, ManifestError> {
+) -> Result<Vec<ManifestParquetObject>, ManifestError> {
     let manifest_key = manifest_key(prefix);
     let bytes = object_store
         .get_bytes(bucket, &manifest_key)
@@ -427,11 +1007,15 @@ async fn resolve_parquet_uris_from_manifest(
         });
     }
 
-    let manifest: DatasetManifestV1 = serde_json::from_slice(&bytes)
+    let manifest = DatasetManifest::from_json(&bytes)
         .context("decode dataset manifest json")
         .map_err(|err| ManifestError {
             kind: ManifestErrorKind::InvalidJson,
             inner: err,
+        })?
+        .ok_or_else(|| ManifestError {
+            kind: ManifestErrorKind::InvalidSchema,
+            inner: anyhow::anyhow!("unsupported manifest version"),
         })?;
 
     validate_manifest(cfg, grant, prefix, &manifest).map_err(|err| ManifestError {
@@ -439,8 +1023,21 @@ async fn resolve_parquet_uris_from_manifest(
         inner: err,
     })?;
 
-    let mut uris = Vec::with_capacity(manifest.parquet_keys.len());
-    for key in manifest.parquet_keys {
+    let objects: Vec<(String, Option<ColumnRange>, Option<ColumnRange>)> = match manifest {
+        DatasetManifest::V1(m) => m
+            .parquet_keys
+            .into_iter()
+            .map(|key| (key, None, None))
+            .collect(),
+        DatasetManifest::V2(m) => m
+            .objects
+            .into_iter()
+            .map(|o| (o.key, o.block_number, o.timestamp))
+            .collect(),
+    };
+
+    let mut resolved = Vec::with_capacity(objects.len());
+    for (key, block_number, timestamp) in objects {
         let key = key.trim_start_matches('/').to_string();
         let uri = format!("s3://{bucket}/{key}");
         if !s3_read_allowed(s3, &uri) {
@@ -449,10 +1046,14 @@ async fn resolve_parquet_uris_from_manifest(
                 inner: anyhow::anyhow!("manifest parquet object not authorized"),
             });
         }
-        uris.push(uri);
+        resolved.push(ManifestParquetObject {
+            uri,
+            block_number,
+            timestamp,
+        });
     }
 
-    Ok(uris)
+    Ok(resolved)
 }
 
 fn manifest_key(prefix: &str) -> String {
@@ -465,24 +1066,31 @@ fn validate_manifest(
     cfg: &QueryServiceConfig,
     grant: &DatasetGrant,
     prefix: &str,
-    manifest: &DatasetManifestV1,
+    manifest: &DatasetManifest,
 ) -> anyhow::Result<()> {
-    if manifest.version != DatasetManifestV1::VERSION {
-        anyhow::bail!("unsupported manifest version {}", manifest.version);
+    match manifest {
+        DatasetManifest::V1(m) if m.version != DatasetManifestV1::VERSION => {
+            anyhow::bail!("unsupported manifest version {}", m.version)
+        }
+        DatasetManifest::V2(m) if m.version != DatasetManifestV2::VERSION => {
+            anyhow::bail!("unsupported manifest version {}", m.version)
+        }
+        _ => {}
     }
-    if manifest.dataset_uuid != grant.dataset_uuid
-        || manifest.dataset_version != grant.dataset_version
+    if manifest.dataset_uuid() != grant.dataset_uuid
+        || manifest.dataset_version() != grant.dataset_version
     {
         anyhow::bail!("manifest does not match dataset grant");
     }
 
-    if manifest.parquet_keys.is_empty() {
+    let parquet_keys = manifest.parquet_keys();
+    if parquet_keys.is_empty() {
         anyhow::bail!("manifest contains no parquet objects");
     }
-    if manifest.parquet_keys.len() > cfg.max_manifest_objects {
+    if parquet_keys.len() > cfg.max_manifest_objects {
         anyhow::bail!(
             "manifest too many parquet objects ({} > {})",
-            manifest.parquet_keys.len(),
+            parquet_keys.len(),
             cfg.max_manifest_objects
         );
     }
@@ -492,7 +1100,7 @@ fn validate_manifest(
         anyhow::bail!("dataset prefix must not be empty");
     }
     let expected_prefix = format!("{expected_prefix}/");
-    for key in &manifest.parquet_keys {
+    for key in parquet_keys {
         if key.contains('\\') {
             anyhow::bail!("manifest parquet key contains backslash");
         }
@@ -505,6 +1113,30 @@ fn validate_manifest(
         }
     }
 
+    if let DatasetManifest::V2(m) = manifest {
+        m.objects.iter().try_for_each(validate_manifest_object)?;
+    }
+
+    Ok(())
+}
+
+fn validate_manifest_object(object: &ManifestObject) -> anyhow::Result<()> {
+    if object.sha256.len() != 64
+        || !object
+            .sha256
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    {
+        anyhow::bail!("manifest object sha256 is not lowercase hex");
+    }
+    for range in [object.block_number, object.timestamp]
+        .into_iter()
+        .flatten()
+    {
+        if range.min > range.max {
+            anyhow::bail!("manifest object range min exceeds max");
+        }
+    }
     Ok(())
 }
 
@@ -519,87 +1151,7 @@ fn columns_to_response(results: &QueryResultSet) -> Vec<QueryColumnResponse> {
         .collect()
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct UserJwtClaims {
-    iss: String,
-    aud: String,
-    sub: String,
-    exp: usize,
-    iat: usize,
-
-    org_id: Uuid,
-    datasets: Vec<DatasetGrant>,
-    s3: S3Grants,
-}
-
-#[derive(Clone)]
-pub struct UserJwtVerifier {
-    issuer: String,
-    audience: String,
-    current_kid: String,
-    next_kid: Option<String>,
-    current_decoding_key: DecodingKey,
-    next_decoding_key: Option<DecodingKey>,
-}
-
-impl std::fmt::Debug for UserJwtVerifier {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let next_decoding_key = self.next_decoding_key.as_ref().map(|_| "<redacted>");
-        f.debug_struct("UserJwtVerifier")
-            .field("issuer", &self.issuer)
-            .field("audience", &self.audience)
-            .field("current_kid", &self.current_kid)
-            .field("next_kid", &self.next_kid)
-            .field("current_decoding_key", &"<redacted>")
-            .field("next_decoding_key", &next_decoding_key)
-            .finish()
-    }
-}
-
-impl UserJwtVerifier {
-    pub fn from_config(cfg: &QueryServiceConfig) -> anyhow::Result<Self> {
-        if cfg.user_jwt_next_kid.is_some() != cfg.user_jwt_next_secret.is_some() {
-            anyhow::bail!("USER_JWT_NEXT_KID and USER_JWT_NEXT_SECRET must be set together");
-        }
-
-        Ok(Self {
-            issuer: cfg.user_jwt_iss.clone(),
-            audience: cfg.user_jwt_aud.clone(),
-            current_kid: cfg.user_jwt_kid.clone(),
-            next_kid: cfg.user_jwt_next_kid.clone(),
-            current_decoding_key: DecodingKey::from_secret(cfg.user_jwt_secret.as_bytes()),
-            next_decoding_key: cfg
-                .user_jwt_next_secret
-                .as_deref()
-                .map(|s| DecodingKey::from_secret(s.as_bytes())),
-        })
-    }
-
-    fn verify(&self, token: &str) -> anyhow::Result<UserJwtClaims> {
-        let header = decode_header(token).context("decode jwt header")?;
-        let kid = header.kid.as_deref().context("missing jwt kid")?;
-
-        let decoding_key = if kid == self.current_kid {
-            &self.current_decoding_key
-        } else if self.next_kid.as_deref() == Some(kid) {
-            self.next_decoding_key
-                .as_ref()
-                .context("next jwt key not configured")?
-        } else {
-            anyhow::bail!("invalid jwt kid");
-        };
-
-        let mut validation = Validation::new(Algorithm::HS256);
-        validation.set_issuer(std::slice::from_ref(&self.issuer));
-        validation.set_audience(std::slice::from_ref(&self.audience));
-
-        let data =
-            decode::<UserJwtClaims>(token, decoding_key, &validation).context("verify jwt")?;
-        Ok(data.claims)
-    }
-}
-
-fn require_user_bearer(
+async fn require_user_bearer(
     verifier: &UserJwtVerifier,
     headers: &HeaderMap,
 ) -> Result<UserJwtClaims, ApiError> {
@@ -612,7 +1164,7 @@ fn require_user_bearer(
         .strip_prefix("Bearer ")
         .ok_or_else(|| ApiError::unauthorized("invalid authorization"))?;
 
-    let claims = verifier.verify(token).map_err(|err| {
+    let claims = verifier.verify(token).await.map_err(|err| {
         tracing::warn!(
             event = "query_service.user_jwt.invalid",
             error = %err,
@@ -622,7 +1174,7 @@ fn require_user_bearer(
     })?;
 
     // Basic claim sanity checks (defense-in-depth; signature/exp are handled by `jsonwebtoken` validation).
-    if claims.exp < claims.iat || claims.iss.is_empty() || claims.aud.is_empty() {
+    if claims.exp < claims.iat || claims.iss.is_empty() || claims.sub.is_empty() {
         return Err(ApiError::unauthorized("invalid authorization"));
     }
 
@@ -650,8 +1202,8 @@ fn require_dataset_grant_user(
     Ok(grant)
 }
 
-fn require_task_capability(
-    signer: &dyn SignerTrait,
+async fn require_task_capability(
+    signer: &TaskCapability,
     headers: &HeaderMap,
     task_id: Uuid,
     attempt: i64,
@@ -661,7 +1213,7 @@ fn require_task_capability(
         .and_then(|v| v.to_str().ok())
         .ok_or_else(|| ApiError::unauthorized("missing capability token"))?;
 
-    let claims = signer.verify_task_capability(token).map_err(|err| {
+    let claims = signer.verify_with_refresh(token).await.map_err(|err| {
         tracing::warn!(
             event = "query_service.capability.invalid",
             error = %err,
@@ -682,6 +1234,44 @@ fn require_task_capability(
     Ok(claims)
 }
 
+/// Reject capabilities whose attempt is no longer the task's current running attempt.
+async fn require_current_attempt(
+    state: &AppState,
+    claims: &trace_core::TaskCapabilityClaims,
+) -> Result<(), ApiError> {
+    // Without the state DB a revoked attempt is indistinguishable from a live one: fail closed.
+    let Some(pool) = state.state_pool.as_ref() else {
+        return Err(ApiError::unavailable("task attempt check unavailable"));
+    };
+
+    let current = state
+        .attempts
+        .is_current(pool, claims.task_id, claims.attempt)
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                event = "query_service.capability.attempt_lookup_failed",
+                task_id = %claims.task_id,
+                attempt = claims.attempt,
+                error = ?err,
+                "task attempt lookup failed"
+            );
+            ApiError::unavailable("task attempt check unavailable")
+        })?;
+    if !current {
+        tracing::warn!(
+            event = "query_service.capability.stale_attempt",
+            task_id = %claims.task_id,
+            attempt = claims.attempt,
+            "capability attempt is no longer running"
+        );
+        return Err(ApiError::unauthorized(
+            "capability attempt is no longer current",
+        ));
+    }
+    Ok(())
+}
+
 fn require_dataset_grant(
     claims: &trace_core::TaskCapabilityClaims,
     dataset_id: Uuid,
@@ -775,82 +1365,14 @@ fn authorize_dataset_storage(
     Ok(storage_ref)
 }
 
-async fn insert_query_audit(
-    pool: &sqlx::PgPool,
-    org_id: Uuid,
-    task_id: Uuid,
-    dataset_id: Uuid,
-    result_row_count: i64,
-) -> Result<(), ApiError> {
-    sqlx::query(
-        r#"
-        INSERT INTO data.query_audit (
-          org_id,
-          task_id,
-          dataset_id,
-          columns_accessed,
-          result_row_count
-        ) VALUES ($1, $2, $3, NULL, $4)
-        "#,
-    )
-    .bind(org_id)
-    .bind(task_id)
-    .bind(dataset_id)
-    .bind(result_row_count)
-    .execute(pool)
-    .await
-    .map_err(|err| {
-        tracing::warn!(
-            event = "query_service.audit.insert_failed",
-            error = %err,
-            "audit insert failed"
-        );
-        ApiError::internal("audit insert failed")
-    })?;
-
-    Ok(())
-}
-
-async fn insert_user_query_audit(
-    pool: &sqlx::PgPool,
-    org_id: Uuid,
-    user_sub: &str,
-    dataset_id: Uuid,
-    result_row_count: i64,
-) -> Result<(), ApiError> {
-    sqlx::query(
-        r#"
-        INSERT INTO data.user_query_audit (
-          org_id,
-          user_sub,
-          dataset_id,
-          columns_accessed,
-          result_row_count
-        ) VALUES ($1, $2, $3, NULL, $4)
-        "#,
-    )
-    .bind(org_id)
-    .bind(user_sub)
-    .bind(dataset_id)
-    .bind(result_row_count)
-    .execute(pool)
-    .await
-    .map_err(|err| {
-        tracing::warn!(
-            event = "query_service.user_audit.insert_failed",
-            error = %err,
-            "user query audit insert failed"
-        );
-        ApiError::internal("audit insert failed")
-    })?;
-
-    Ok(())
-}
-
 #[derive(Debug)]
 pub struct ApiError {
     status: StatusCode,
     message: &'static str,
+    /// Machine-readable error code, for failures a client may want to handle specifically.
+    code: Option<&'static str>,
+    /// Sent as `Retry-After` (whole seconds, rounded up).
+    retry_after: Option<Duration>,
 }
 
 impl ApiError {
@@ -858,6 +1380,8 @@ impl ApiError {
         Self {
             status: StatusCode::BAD_REQUEST,
             message,
+            code: None,
+            retry_after: None,
         }
     }
 
@@ -865,6 +1389,8 @@ impl ApiError {
         Self {
             status: StatusCode::UNAUTHORIZED,
             message,
+            code: None,
+            retry_after: None,
         }
     }
 
@@ -872,6 +1398,26 @@ impl ApiError {
         Self {
             status: StatusCode::FORBIDDEN,
             message,
+            code: None,
+            retry_after: None,
+        }
+    }
+
+    fn not_found(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message,
+            code: None,
+            retry_after: None,
+        }
+    }
+
+    fn conflict(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message,
+            code: None,
+            retry_after: None,
         }
     }
 
@@ -879,6 +1425,17 @@ impl ApiError {
         Self {
             status: StatusCode::UNPROCESSABLE_ENTITY,
             message,
+            code: None,
+            retry_after: None,
+        }
+    }
+
+    fn not_acceptable(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::NOT_ACCEPTABLE,
+            message,
+            code: None,
+            retry_after: None,
         }
     }
 
@@ -886,6 +1443,35 @@ impl ApiError {
         Self {
             status: StatusCode::PAYLOAD_TOO_LARGE,
             message,
+            code: None,
+            retry_after: None,
+        }
+    }
+
+    fn gateway_timeout(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::GATEWAY_TIMEOUT,
+            message,
+            code: None,
+            retry_after: None,
+        }
+    }
+
+    fn too_many_requests(message: &'static str, retry_after: Duration) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message,
+            code: None,
+            retry_after: Some(retry_after),
+        }
+    }
+
+    fn unavailable(message: &'static str) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message,
+            code: None,
+            retry_after: None,
         }
     }
 
@@ -893,13 +1479,30 @@ impl ApiError {
         Self {
             status: StatusCode::INTERNAL_SERVER_ERROR,
             message,
+            code: None,
+            retry_after: None,
         }
     }
+
+    fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let body = Json(json!({ "error": self.message }));
-        (self.status, body).into_response()
+        let body = match self.code {
+            Some(code) => Json(json!({ "error": self.message, "code": code })),
+            None => Json(json!({ "error": self.message })),
+        };
+        let mut response = (self.status, body).into_response();
+        if let Some(retry_after) = self.retry_after {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+        response
     }
 }
@@ -1,11 +1,18 @@
 use anyhow::Context;
 use clap::Parser;
 use std::net::SocketAddr;
-use trace_query_service::{build_state, config::QueryServiceConfig, router};
+use std::time::Duration;
+use trace_query_service::audit::{self, AuditSummaryArgs, AuditSummaryFilter};
+use trace_query_service::{build_state, config::QueryServiceConfig, internal_router, jobs, router};
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Operator subcommand; handled before logging is set up so stdout stays pure JSON.
+    if std::env::args().nth(1).as_deref() == Some("audit-summary") {
+        return audit_summary(AuditSummaryArgs::parse_from(std::env::args_os().skip(1))).await;
+    }
+
     tracing_subscriber::fmt()
         .with_env_filter(
             EnvFilter::try_from_default_env()
@@ -15,8 +22,44 @@ async fn main() -> anyhow::Result<()> {
 
     let cfg = QueryServiceConfig::parse();
     let addr: SocketAddr = cfg.bind.parse().context("parse bind addr")?;
+    let internal_addr: Option<SocketAddr> = cfg
+        .internal_bind
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .context("parse internal bind addr")?;
 
     let state = build_state(cfg).await.context("build state")?;
+
+    // Jobs run in the process that accepted them: keep this process's leases fresh, and fail the
+    // jobs of any instance whose leases lapsed.
+    tokio::spawn(jobs::run_lease_renewer(
+        state.data_pool.clone(),
+        state.jobs.clone(),
+    ));
+    tokio::spawn(jobs::run_job_sweeper(
+        state.data_pool.clone(),
+        Duration::from_secs(60),
+    ));
+
+    if let Some(internal_addr) = internal_addr {
+        let listener = tokio::net::TcpListener::bind(internal_addr)
+            .await
+            .context("bind internal tcp listener")?;
+        let local = listener.local_addr().context("read internal local addr")?;
+        tracing::info!(addr = %local, "query service internal endpoints listening");
+        let internal = internal_router(state.clone());
+        tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, internal).await {
+                tracing::error!(
+                    event = "query_service.internal_listener.failed",
+                    error = %err,
+                    "internal listener failed"
+                );
+            }
+        });
+    }
+
     let app = router(state);
 
     let listener = tokio::net::TcpListener::bind(addr)
@@ -30,3 +73,23 @@ async fn main() -> anyhow::Result<()> {
         .context("serve query service")?;
     Ok(())
 }
+
+async fn audit_summary(args: AuditSummaryArgs) -> anyhow::Result<()> {
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&args.data_database_url)
+        .await
+        .context("connect data db")?;
+
+    let rows = audit::summarize(
+        &pool,
+        AuditSummaryFilter {
+            org_id: args.org,
+            since_hours: args.since_hours,
+            group_by: args.by,
+        },
+    )
+    .await?;
+    println!("{}", serde_json::to_string_pretty(&rows)?);
+    Ok(())
+}
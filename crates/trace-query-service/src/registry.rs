@@ -0,0 +1,185 @@
+//! Dataset registry lookups (Lite: `state.dataset_versions`).
+//!
+//! A chain_sync stream publishes one `dataset_version` per synced range. For user queries the
+//! Query Service resolves a `dataset_uuid` to the full set of completed, non-overlapping range
+//! versions for the dataset's current `config_hash` and attaches them as a single `dataset` view.
+//!
+//! Per ADR 0009 the set is resolved once, at query start, from a single read-only snapshot; the
+//! query then runs against that pinned file list even if new ranges are published meanwhile.
+
+use anyhow::Context;
+use sqlx::{PgPool, Row};
+use trace_core::DatasetStorageRef;
+use uuid::Uuid;
+
+/// One published range version of a logical dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PinnedDatasetVersion {
+    pub dataset_version: Uuid,
+    pub storage_ref: DatasetStorageRef,
+    /// Block range, end-exclusive: `[range_start, range_end)`.
+    pub range_start: i64,
+    pub range_end: i64,
+}
+
+/// The range versions a query reads, resolved at query start.
+#[derive(Debug, Clone)]
+pub(crate) struct PinnedDatasetView {
+    pub dataset_uuid: Uuid,
+    pub config_hash: String,
+    /// Ordered by `range_start`; ranges never overlap.
+    pub versions: Vec<PinnedDatasetVersion>,
+}
+
+/// Resolve `dataset_uuid` to its current set of range versions.
+///
+/// Returns `None` if the registry has no versions for the dataset (e.g. fixture datasets that are
+/// only reachable through the grant's `storage_ref`).
+pub(crate) async fn resolve_dataset_view(
+    pool: &PgPool,
+    dataset_uuid: Uuid,
+) -> anyhow::Result<Option<PinnedDatasetView>> {
+    let mut tx = pool.begin().await.context("begin registry snapshot tx")?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await
+        .context("set registry snapshot isolation")?;
+
+    // The stream definition owns the current config_hash; fall back to the most recent
+    // publication for datasets without a (registered) stream.
+    let config_hash: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT coalesce(
+          (
+            SELECT config_hash
+            FROM state.chain_sync_streams
+            WHERE dataset_uuid = $1
+            ORDER BY updated_at DESC
+            LIMIT 1
+          ),
+          (
+            SELECT config_hash
+            FROM state.dataset_versions
+            WHERE dataset_uuid = $1
+            ORDER BY created_at DESC
+            LIMIT 1
+          )
+        )
+        "#,
+    )
+    .bind(dataset_uuid)
+    .fetch_one(&mut *tx)
+    .await
+    .context("resolve current config_hash")?;
+
+    let Some(config_hash) = config_hash else {
+        tx.commit().await.context("commit registry snapshot tx")?;
+        return Ok(None);
+    };
+
+    let rows = sqlx::query(
+        r#"
+        SELECT dataset_version, storage_prefix, storage_glob, range_start, range_end
+        FROM state.dataset_versions
+        WHERE dataset_uuid = $1
+          AND config_hash = $2
+        ORDER BY range_start, range_end DESC, created_at, dataset_version
+        "#,
+    )
+    .bind(dataset_uuid)
+    .bind(&config_hash)
+    .fetch_all(&mut *tx)
+    .await
+    .context("list dataset versions")?;
+
+    tx.commit().await.context("commit registry snapshot tx")?;
+
+    let mut versions = Vec::with_capacity(rows.len());
+    for row in rows {
+        let dataset_version: Uuid = row.try_get("dataset_version").context("dataset_version")?;
+        let storage_prefix: String = row.try_get("storage_prefix").context("storage_prefix")?;
+        let storage_glob: String = row.try_get("storage_glob").context("storage_glob")?;
+        versions.push(PinnedDatasetVersion {
+            dataset_version,
+            storage_ref: DatasetStorageRef::from_uri(&storage_prefix, storage_glob).with_context(
+                || format!("parse storage_prefix dataset_version={dataset_version}"),
+            )?,
+            range_start: row.try_get("range_start").context("range_start")?,
+            range_end: row.try_get("range_end").context("range_end")?,
+        });
+    }
+
+    let versions = select_non_overlapping(versions);
+    if versions.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(PinnedDatasetView {
+        dataset_uuid,
+        config_hash,
+        versions,
+    }))
+}
+
+/// Keep a non-overlapping cover of the published ranges.
+///
+/// Input must be ordered by `range_start` then widest range first, so re-chunked publications of
+/// an already-covered span are skipped deterministically rather than double counted.
+fn select_non_overlapping(versions: Vec<PinnedDatasetVersion>) -> Vec<PinnedDatasetVersion> {
+    let mut out: Vec<PinnedDatasetVersion> = Vec::with_capacity(versions.len());
+    for version in versions {
+        if version.range_end <= version.range_start {
+            continue;
+        }
+        if let Some(last) = out.last() {
+            if version.range_start < last.range_end {
+                continue;
+            }
+        }
+        out.push(version);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(range_start: i64, range_end: i64) -> PinnedDatasetVersion {
+        PinnedDatasetVersion {
+            dataset_version: Uuid::new_v4(),
+            storage_ref: DatasetStorageRef::File {
+                prefix: format!("/data/{range_start}_{range_end}/"),
+                glob: "*.parquet".to_string(),
+            },
+            range_start,
+            range_end,
+        }
+    }
+
+    fn ranges(versions: &[PinnedDatasetVersion]) -> Vec<(i64, i64)> {
+        versions
+            .iter()
+            .map(|v| (v.range_start, v.range_end))
+            .collect()
+    }
+
+    #[test]
+    fn non_overlapping_ranges_are_kept() {
+        let out = select_non_overlapping(vec![version(0, 10), version(10, 20), version(30, 40)]);
+        assert_eq!(ranges(&out), vec![(0, 10), (10, 20), (30, 40)]);
+    }
+
+    #[test]
+    fn overlapping_ranges_keep_the_first_widest() {
+        // Ordered as the registry query returns them: range_start, then widest first.
+        let out = select_non_overlapping(vec![
+            version(0, 20),
+            version(0, 10),
+            version(10, 20),
+            version(15, 25),
+            version(20, 30),
+        ]);
+        assert_eq!(ranges(&out), vec![(0, 20), (20, 30)]);
+    }
+}
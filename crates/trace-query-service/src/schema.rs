@@ -0,0 +1,174 @@
+//! Dataset schema introspection (`GET /v1/datasets/{dataset_id}/schema`).
+//!
+//! The dataset is authorized and its range versions pinned exactly like `POST /v1/query`. Each
+//! version is attached as its own DuckDB view, and DuckDB reads the column types and Parquet
+//! footer metadata (no caller SQL runs). Dataset versions are immutable, so results are cached
+//! per `(dataset_uuid, dataset_version)`, up to `QUERY_SERVICE_SCHEMA_CACHE_ENTRIES` versions.
+//! Storage is re-authorized against the caller's grants on every request, cached or not.
+
+use crate::duckdb::{DatasetView, ParquetSchema, SchemaColumn};
+use crate::{
+    admission_error_to_api, authorize_dataset_storage, dataset_view_source, duckdb_error_to_api,
+    ApiError, AppState, FilePruning, UserJwtClaims, DEFAULT_DATASET_ALIAS,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use trace_core::DatasetGrant;
+use uuid::Uuid;
+
+// `(dataset_uuid, dataset_version)`
+type VersionKey = (Uuid, Uuid);
+
+/// Described dataset versions, keyed by `(dataset_uuid, dataset_version)`.
+#[derive(Clone)]
+pub struct SchemaCache {
+    capacity: usize,
+    entries: Arc<Mutex<HashMap<VersionKey, Arc<ParquetSchema>>>>,
+}
+
+impl SchemaCache {
+    /// A cache holding at most `capacity` versions; `0` disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of cached dataset versions.
+    pub fn len(&self) -> usize {
+        self.entries.lock().expect("schema cache poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, grant: &DatasetGrant) -> Option<Arc<ParquetSchema>> {
+        self.entries
+            .lock()
+            .expect("schema cache poisoned")
+            .get(&(grant.dataset_uuid, grant.dataset_version))
+            .cloned()
+    }
+
+    fn insert(&self, grant: &DatasetGrant, schema: Arc<ParquetSchema>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("schema cache poisoned");
+        // Any entry is as good to drop as another: they never go stale, only out of use.
+        if entries.len() >= self.capacity {
+            if let Some(key) = entries.keys().next().copied() {
+                entries.remove(&key);
+            }
+        }
+        entries.insert((grant.dataset_uuid, grant.dataset_version), schema);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatasetSchemaResponse {
+    pub dataset_id: Uuid,
+    /// Columns of the first pinned version; `nullable` if the column is nullable in any version.
+    pub columns: Vec<SchemaColumn>,
+    /// Rows across all pinned versions.
+    pub row_count: i64,
+    pub versions: Vec<DatasetVersionSchema>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatasetVersionSchema {
+    pub dataset_version: Uuid,
+    #[serde(flatten)]
+    pub schema: ParquetSchema,
+}
+
+/// Describe `versions` (the pinned versions of `dataset_id`), reading uncached ones through DuckDB.
+///
+/// Uncached versions are described under one of the caller's org query slots.
+pub(crate) async fn describe(
+    state: &AppState,
+    claims: &UserJwtClaims,
+    dataset_id: Uuid,
+    versions: Vec<DatasetGrant>,
+) -> Result<DatasetSchemaResponse, ApiError> {
+    let mut described = Vec::with_capacity(versions.len());
+    let mut misses = Vec::new();
+    for (idx, grant) in versions.iter().enumerate() {
+        authorize_dataset_storage(&state.cfg, &claims.s3, grant)?;
+        match state.schemas.get(grant) {
+            Some(schema) => described.push((idx, schema)),
+            None => misses.push(idx),
+        }
+    }
+
+    if !misses.is_empty() {
+        let _permit = state
+            .admission
+            .admit(claims.org_id)
+            .await
+            .map_err(admission_error_to_api)?;
+        let timeout = Duration::from_secs(state.cfg.user_query_timeout_secs);
+        for &idx in &misses {
+            let grant = &versions[idx];
+            let view = DatasetView {
+                name: DEFAULT_DATASET_ALIAS.to_string(),
+                source: dataset_view_source(
+                    state,
+                    &claims.s3,
+                    std::slice::from_ref(grant),
+                    FilePruning::default(),
+                )
+                .await?,
+            };
+            let schema = state
+                .duckdb
+                .describe_dataset_view(&state.cfg, view, state.cfg.schema_max_row_groups, timeout)
+                .await
+                .map_err(duckdb_error_to_api)?;
+            let schema = Arc::new(schema);
+            state.schemas.insert(grant, schema.clone());
+            described.push((idx, schema));
+        }
+    }
+    described.sort_by_key(|(idx, _)| *idx);
+
+    tracing::debug!(
+        event = "query_service.schema.described",
+        dataset_uuid = %dataset_id,
+        versions = versions.len(),
+        cache_misses = misses.len(),
+        "described dataset schema"
+    );
+
+    let mut columns = described
+        .first()
+        .map(|(_, schema)| schema.columns.clone())
+        .unwrap_or_default();
+    for column in &mut columns {
+        column.nullable = described.iter().any(|(_, schema)| {
+            schema
+                .columns
+                .iter()
+                .find(|c| c.name == column.name)
+                .is_none_or(|c| c.nullable)
+        });
+    }
+
+    Ok(DatasetSchemaResponse {
+        dataset_id,
+        columns,
+        row_count: described.iter().map(|(_, schema)| schema.row_count).sum(),
+        versions: versions
+            .iter()
+            .zip(described)
+            .map(|(grant, (_, schema))| DatasetVersionSchema {
+                dataset_version: grant.dataset_version,
+                schema: Arc::unwrap_or_clone(schema),
+            })
+            .collect(),
+    })
+}
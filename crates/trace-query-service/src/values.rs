@@ -0,0 +1,551 @@
+//! JSON rendering of DuckDB result values and SQL names for their types.
+//!
+//! Values are read from DuckDB's Arrow export (`duckdb::arrow`):
+//! - Nested lists, arrays, structs, maps, and unions become nested JSON.
+//! - Timestamps become RFC 3339 strings in UTC, with as many fractional digits as their unit carries.
+//! - Dates and times become ISO 8601 strings.
+//! - Blobs become `0x`-prefixed hex.
+//! - `HUGEINT`, `UHUGEINT`, `UBIGINT`, and decimals become exact decimal strings.
+//!
+//! Values outside what `chrono` can represent (e.g. `'infinity'::TIMESTAMP`) fall back to the raw
+//! integer DuckDB stores.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat};
+use duckdb::arrow::array::{
+    Array, AsArray, BinaryArray, BooleanArray, Date32Array, Date64Array, Decimal128Array,
+    FixedSizeBinaryArray, FixedSizeListArray, Float16Array, Float32Array, Float64Array, Int16Array,
+    Int32Array, Int64Array, Int8Array, IntervalDayTimeArray, IntervalMonthDayNanoArray,
+    IntervalYearMonthArray, LargeBinaryArray, LargeListArray, LargeStringArray, ListArray,
+    MapArray, StringArray, StringViewArray, StructArray, Time32MillisecondArray, Time32SecondArray,
+    Time64MicrosecondArray, Time64NanosecondArray, TimestampMicrosecondArray,
+    TimestampMillisecondArray, TimestampNanosecondArray, TimestampSecondArray, UInt16Array,
+    UInt32Array, UInt64Array, UInt8Array, UnionArray,
+};
+use duckdb::arrow::datatypes::{DataType, Field, IntervalUnit, TimeUnit};
+use duckdb::arrow::record_batch::RecordBatch;
+use duckdb::core::{LogicalTypeHandle, LogicalTypeId};
+use serde_json::{Map, Number, Value};
+use std::fmt::Write as _;
+
+// Field metadata DuckDB attaches to types Arrow has no native equivalent for.
+const EXTENSION_NAME_KEY: &str = "ARROW:extension:name";
+const EXTENSION_METADATA_KEY: &str = "ARROW:extension:metadata";
+const OPAQUE_EXTENSION: &str = "arrow.opaque";
+
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+/// Render row `row` of `batch` as one JSON value per column.
+pub(crate) fn row_json(batch: &RecordBatch, row: usize) -> Vec<Value> {
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, column)| value_json(field, column.as_ref(), row))
+        .collect()
+}
+
+/// SQL type name for a result column.
+///
+/// `logical` is the column's DuckDB logical type when the statement is at hand; it tells apart
+/// types that share an Arrow representation (`UUID` and `VARCHAR`, `HUGEINT` and `DECIMAL(38,0)`).
+pub(crate) fn column_type_name(field: &Field, logical: Option<&LogicalTypeHandle>) -> String {
+    if let Some(logical) = logical {
+        if let Some(alias) = logical.get_alias() {
+            return alias;
+        }
+        let name = match logical.id() {
+            LogicalTypeId::Hugeint => Some("HUGEINT"),
+            LogicalTypeId::UHugeint => Some("UHUGEINT"),
+            LogicalTypeId::Uuid => Some("UUID"),
+            LogicalTypeId::TimeTZ => Some("TIME WITH TIME ZONE"),
+            LogicalTypeId::Bit => Some("BIT"),
+            LogicalTypeId::Bignum => Some("BIGNUM"),
+            _ => None,
+        };
+        if let Some(name) = name {
+            return name.to_string();
+        }
+    }
+    type_name(field)
+}
+
+fn type_name(field: &Field) -> String {
+    match field.data_type() {
+        DataType::Null => "NULL".to_string(),
+        DataType::Boolean => "BOOLEAN".to_string(),
+        DataType::Int8 => "TINYINT".to_string(),
+        DataType::Int16 => "SMALLINT".to_string(),
+        DataType::Int32 => "INTEGER".to_string(),
+        DataType::Int64 => "BIGINT".to_string(),
+        DataType::UInt8 => "UTINYINT".to_string(),
+        DataType::UInt16 => "USMALLINT".to_string(),
+        DataType::UInt32 => "UINTEGER".to_string(),
+        DataType::UInt64 => "UBIGINT".to_string(),
+        DataType::Float16 | DataType::Float32 => "FLOAT".to_string(),
+        DataType::Float64 => "DOUBLE".to_string(),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            format!("DECIMAL({precision},{scale})")
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "VARCHAR".to_string(),
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => match opaque_type(field).as_deref() {
+            Some("uhugeint") => "UHUGEINT".to_string(),
+            Some("bignum") => "BIGNUM".to_string(),
+            _ => "BLOB".to_string(),
+        },
+        DataType::Date32 | DataType::Date64 => "DATE".to_string(),
+        DataType::Time32(_) | DataType::Time64(_) => "TIME".to_string(),
+        DataType::Timestamp(_, Some(_)) => "TIMESTAMP WITH TIME ZONE".to_string(),
+        DataType::Timestamp(unit, None) => match unit {
+            TimeUnit::Second => "TIMESTAMP_S",
+            TimeUnit::Millisecond => "TIMESTAMP_MS",
+            TimeUnit::Microsecond => "TIMESTAMP",
+            TimeUnit::Nanosecond => "TIMESTAMP_NS",
+        }
+        .to_string(),
+        DataType::Interval(_) | DataType::Duration(_) => "INTERVAL".to_string(),
+        DataType::List(child) | DataType::LargeList(child) => format!("{}[]", type_name(child)),
+        DataType::FixedSizeList(child, size) => format!("{}[{size}]", type_name(child)),
+        DataType::Struct(fields) => {
+            let members: Vec<String> = fields
+                .iter()
+                .map(|f| format!("{} {}", quote_identifier(f.name()), type_name(f)))
+                .collect();
+            format!("STRUCT({})", members.join(", "))
+        }
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(kv) if kv.len() == 2 => {
+                format!("MAP({}, {})", type_name(&kv[0]), type_name(&kv[1]))
+            }
+            _ => "MAP".to_string(),
+        },
+        DataType::Union(fields, _) => {
+            let members: Vec<String> = fields
+                .iter()
+                .map(|(_, f)| format!("{} {}", quote_identifier(f.name()), type_name(f)))
+                .collect();
+            format!("UNION({})", members.join(", "))
+        }
+        DataType::Dictionary(_, _) => "ENUM".to_string(),
+        other => other.to_string().to_ascii_uppercase(),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// DuckDB type name carried by an `arrow.opaque` extension field (e.g. `uhugeint`).
+fn opaque_type(field: &Field) -> Option<String> {
+    let metadata = field.metadata();
+    if metadata.get(EXTENSION_NAME_KEY).map(String::as_str) != Some(OPAQUE_EXTENSION) {
+        return None;
+    }
+    let extension: Value = serde_json::from_str(metadata.get(EXTENSION_METADATA_KEY)?).ok()?;
+    Some(extension.get("type_name")?.as_str()?.to_string())
+}
+
+fn value_json(field: &Field, array: &dyn Array, row: usize) -> Value {
+    if array.is_null(row) {
+        return Value::Null;
+    }
+    match array.data_type() {
+        DataType::Null => Value::Null,
+        DataType::Boolean => Value::Bool(downcast::<BooleanArray>(array).value(row)),
+        DataType::Int8 => Value::from(downcast::<Int8Array>(array).value(row)),
+        DataType::Int16 => Value::from(downcast::<Int16Array>(array).value(row)),
+        DataType::Int32 => Value::from(downcast::<Int32Array>(array).value(row)),
+        DataType::Int64 => Value::from(downcast::<Int64Array>(array).value(row)),
+        DataType::UInt8 => Value::from(downcast::<UInt8Array>(array).value(row)),
+        DataType::UInt16 => Value::from(downcast::<UInt16Array>(array).value(row)),
+        DataType::UInt32 => Value::from(downcast::<UInt32Array>(array).value(row)),
+        // Past 2^53 a JSON number loses precision in most clients.
+        DataType::UInt64 => Value::String(downcast::<UInt64Array>(array).value(row).to_string()),
+        DataType::Float16 => float_json(downcast::<Float16Array>(array).value(row).to_f64()),
+        DataType::Float32 => float_json(f64::from(downcast::<Float32Array>(array).value(row))),
+        DataType::Float64 => float_json(downcast::<Float64Array>(array).value(row)),
+        DataType::Decimal128(_, scale) => Value::String(decimal_string(
+            downcast::<Decimal128Array>(array).value(row),
+            *scale,
+        )),
+        DataType::Utf8 => Value::String(downcast::<StringArray>(array).value(row).to_string()),
+        DataType::LargeUtf8 => {
+            Value::String(downcast::<LargeStringArray>(array).value(row).to_string())
+        }
+        DataType::Utf8View => {
+            Value::String(downcast::<StringViewArray>(array).value(row).to_string())
+        }
+        DataType::Binary => blob_json(field, downcast::<BinaryArray>(array).value(row)),
+        DataType::LargeBinary => blob_json(field, downcast::<LargeBinaryArray>(array).value(row)),
+        DataType::FixedSizeBinary(_) => {
+            blob_json(field, downcast::<FixedSizeBinaryArray>(array).value(row))
+        }
+        DataType::Date32 => date_json(i64::from(downcast::<Date32Array>(array).value(row))),
+        DataType::Date64 => {
+            let millis = downcast::<Date64Array>(array).value(row);
+            date_json(millis.div_euclid(86_400_000))
+        }
+        DataType::Time32(TimeUnit::Second) => time_json(
+            i64::from(downcast::<Time32SecondArray>(array).value(row)),
+            TimeUnit::Second,
+        ),
+        DataType::Time32(_) => time_json(
+            i64::from(downcast::<Time32MillisecondArray>(array).value(row)),
+            TimeUnit::Millisecond,
+        ),
+        DataType::Time64(TimeUnit::Nanosecond) => time_json(
+            downcast::<Time64NanosecondArray>(array).value(row),
+            TimeUnit::Nanosecond,
+        ),
+        DataType::Time64(_) => time_json(
+            downcast::<Time64MicrosecondArray>(array).value(row),
+            TimeUnit::Microsecond,
+        ),
+        DataType::Timestamp(unit, _) => {
+            let value = match unit {
+                TimeUnit::Second => downcast::<TimestampSecondArray>(array).value(row),
+                TimeUnit::Millisecond => downcast::<TimestampMillisecondArray>(array).value(row),
+                TimeUnit::Microsecond => downcast::<TimestampMicrosecondArray>(array).value(row),
+                TimeUnit::Nanosecond => downcast::<TimestampNanosecondArray>(array).value(row),
+            };
+            timestamp_json(value, *unit)
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let v = downcast::<IntervalMonthDayNanoArray>(array).value(row);
+            interval_json(v.months, v.days, v.nanoseconds)
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let v = downcast::<IntervalDayTimeArray>(array).value(row);
+            interval_json(0, v.days, i64::from(v.milliseconds) * 1_000_000)
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            interval_json(downcast::<IntervalYearMonthArray>(array).value(row), 0, 0)
+        }
+        DataType::List(child) => list_json(child, downcast::<ListArray>(array).value(row).as_ref()),
+        DataType::LargeList(child) => {
+            list_json(child, downcast::<LargeListArray>(array).value(row).as_ref())
+        }
+        DataType::FixedSizeList(child, _) => list_json(
+            child,
+            downcast::<FixedSizeListArray>(array).value(row).as_ref(),
+        ),
+        DataType::Struct(fields) => {
+            let array = downcast::<StructArray>(array);
+            Value::Object(
+                fields
+                    .iter()
+                    .zip(array.columns())
+                    .map(|(f, column)| (f.name().clone(), value_json(f, column.as_ref(), row)))
+                    .collect(),
+            )
+        }
+        DataType::Map(entries, _) => {
+            let DataType::Struct(kv) = entries.data_type() else {
+                return display_json(array, row);
+            };
+            let entries = downcast::<MapArray>(array).value(row);
+            let mut object = Map::with_capacity(entries.len());
+            for i in 0..entries.len() {
+                // JSON keys are strings; other key types use their JSON text.
+                let key = match value_json(&kv[0], entries.column(0).as_ref(), i) {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                object.insert(key, value_json(&kv[1], entries.column(1).as_ref(), i));
+            }
+            Value::Object(object)
+        }
+        DataType::Union(fields, _) => {
+            let array = downcast::<UnionArray>(array);
+            let type_id = array.type_id(row);
+            let Some((_, member)) = fields.iter().find(|(id, _)| *id == type_id) else {
+                return Value::Null;
+            };
+            value_json(
+                member,
+                array.child(type_id).as_ref(),
+                array.value_offset(row),
+            )
+        }
+        DataType::Dictionary(_, values) => {
+            let dictionary = array.as_any_dictionary();
+            let key = dictionary.normalized_keys()[row];
+            let values_field = Field::new(field.name(), values.as_ref().clone(), true);
+            value_json(&values_field, dictionary.values().as_ref(), key)
+        }
+        _ => display_json(array, row),
+    }
+}
+
+fn downcast<T: 'static>(array: &dyn Array) -> &T {
+    array
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("arrow array matches its data type")
+}
+
+fn display_json(array: &dyn Array, row: usize) -> Value {
+    duckdb::arrow::util::display::array_value_to_string(array, row)
+        .map(Value::String)
+        .unwrap_or(Value::Null)
+}
+
+fn float_json(value: f64) -> Value {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+fn list_json(child: &Field, values: &dyn Array) -> Value {
+    Value::Array(
+        (0..values.len())
+            .map(|i| value_json(child, values, i))
+            .collect(),
+    )
+}
+
+/// Exact decimal text for an unscaled `value` with `scale` fractional digits.
+fn decimal_string(value: i128, scale: i8) -> String {
+    if scale <= 0 {
+        let mut out = value.to_string();
+        if value != 0 {
+            out.extend(std::iter::repeat_n('0', usize::from(scale.unsigned_abs())));
+        }
+        return out;
+    }
+    let scale = usize::from(scale.unsigned_abs());
+    let digits = value.unsigned_abs().to_string();
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    format!("{sign}{int}.{frac}")
+}
+
+fn blob_json(field: &Field, bytes: &[u8]) -> Value {
+    if opaque_type(field).as_deref() == Some("uhugeint") {
+        if let Ok(bytes) = <[u8; 16]>::try_from(bytes) {
+            return Value::String(u128::from_le_bytes(bytes).to_string());
+        }
+    }
+    let mut out = String::with_capacity(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    Value::String(out)
+}
+
+fn date_json(days: i64) -> Value {
+    i32::try_from(days)
+        .ok()
+        .and_then(|days| days.checked_add(UNIX_EPOCH_DAYS_FROM_CE))
+        .and_then(NaiveDate::from_num_days_from_ce_opt)
+        .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+        .unwrap_or_else(|| Value::from(days))
+}
+
+fn time_json(value: i64, unit: TimeUnit) -> Value {
+    let (per_second, format) = match unit {
+        TimeUnit::Second => (1, "%H:%M:%S"),
+        TimeUnit::Millisecond => (1_000, "%H:%M:%S%.3f"),
+        TimeUnit::Microsecond => (1_000_000, "%H:%M:%S%.6f"),
+        TimeUnit::Nanosecond => (1_000_000_000, "%H:%M:%S%.9f"),
+    };
+    let secs = u32::try_from(value.div_euclid(per_second)).ok();
+    let nanos = u32::try_from(value.rem_euclid(per_second) * (1_000_000_000 / per_second)).ok();
+    secs.zip(nanos)
+        .and_then(|(secs, nanos)| NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos))
+        .map(|time| Value::String(time.format(format).to_string()))
+        .unwrap_or_else(|| Value::from(value))
+}
+
+fn timestamp_json(value: i64, unit: TimeUnit) -> Value {
+    let (timestamp, format) = match unit {
+        TimeUnit::Second => (DateTime::from_timestamp(value, 0), SecondsFormat::Secs),
+        TimeUnit::Millisecond => (
+            DateTime::from_timestamp_millis(value),
+            SecondsFormat::Millis,
+        ),
+        TimeUnit::Microsecond => (
+            DateTime::from_timestamp_micros(value),
+            SecondsFormat::Micros,
+        ),
+        TimeUnit::Nanosecond => (
+            Some(DateTime::from_timestamp_nanos(value)),
+            SecondsFormat::Nanos,
+        ),
+    };
+    timestamp
+        .map(|ts| Value::String(ts.to_rfc3339_opts(format, true)))
+        .unwrap_or_else(|| Value::from(value))
+}
+
+fn interval_json(months: i32, days: i32, nanos: i64) -> Value {
+    serde_json::json!({ "months": months, "days": days, "nanos": nanos })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duckdb::Connection;
+
+    fn query(sql: &str) -> (Vec<String>, Vec<Value>) {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let batches: Vec<RecordBatch> = stmt.query_arrow([]).unwrap().collect();
+        let batch = &batches[0];
+        let row = row_json(batch, 0);
+        let schema = batch.schema();
+        let types = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(idx, field)| column_type_name(field, Some(&stmt.column_logical_type(idx))))
+            .collect();
+        (types, row)
+    }
+
+    #[test]
+    fn integers_and_decimals_are_exact() {
+        let (types, row) = query(
+            "SELECT 170141183460469231731687303715884105727::HUGEINT, \
+             340282366920938463463374607431768211455::UHUGEINT, \
+             18446744073709551615::UBIGINT, (-9223372036854775808)::BIGINT, \
+             (-0.05)::DECIMAL(10,2), 12345678901234567890.123456789012345678::DECIMAL(38,18)",
+        );
+        assert_eq!(
+            types,
+            [
+                "HUGEINT",
+                "UHUGEINT",
+                "UBIGINT",
+                "BIGINT",
+                "DECIMAL(10,2)",
+                "DECIMAL(38,18)"
+            ]
+        );
+        assert_eq!(
+            row,
+            [
+                Value::from("170141183460469231731687303715884105727"),
+                Value::from("340282366920938463463374607431768211455"),
+                Value::from("18446744073709551615"),
+                Value::from(i64::MIN),
+                Value::from("-0.05"),
+                Value::from("12345678901234567890.123456789012345678"),
+            ]
+        );
+    }
+
+    #[test]
+    fn temporal_values_are_iso_strings() {
+        let (types, row) = query(
+            "SELECT TIMESTAMP '2024-01-02 03:04:05.123456', \
+             TIMESTAMP_S '2024-01-02 03:04:05', TIMESTAMP_MS '2024-01-02 03:04:05.1', \
+             TIMESTAMP_NS '2024-01-02 03:04:05.123456789', \
+             TIMESTAMPTZ '2024-01-02 03:04:05+02', DATE '1969-12-31', TIME '03:04:05.5', \
+             INTERVAL '1 month 2 days 3 seconds'",
+        );
+        assert_eq!(
+            types,
+            [
+                "TIMESTAMP",
+                "TIMESTAMP_S",
+                "TIMESTAMP_MS",
+                "TIMESTAMP_NS",
+                "TIMESTAMP WITH TIME ZONE",
+                "DATE",
+                "TIME",
+                "INTERVAL"
+            ]
+        );
+        assert_eq!(
+            row,
+            [
+                Value::from("2024-01-02T03:04:05.123456Z"),
+                Value::from("2024-01-02T03:04:05Z"),
+                Value::from("2024-01-02T03:04:05.100Z"),
+                Value::from("2024-01-02T03:04:05.123456789Z"),
+                Value::from("2024-01-02T01:04:05.000000Z"),
+                Value::from("1969-12-31"),
+                Value::from("03:04:05.500000"),
+                serde_json::json!({ "months": 1, "days": 2, "nanos": 3_000_000_000i64 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn blobs_are_hex_and_nested_values_are_json() {
+        let (types, row) = query(
+            "SELECT '\\xDE\\xAD\\xBE\\xEF'::BLOB, \
+             '6ba7b810-9dad-11d1-80b4-00c04fd430c8'::UUID, \
+             [1, NULL, 3], [[1], []], [1, 2]::INTEGER[2], \
+             {'hash': '\\x01\\x02'::BLOB, 'n': 1, 'Tags': ['a']}, \
+             MAP {'k': 1}, MAP {1: 'x'}, union_value(num := 2), \
+             [1::UHUGEINT]",
+        );
+        assert_eq!(
+            types,
+            [
+                "BLOB",
+                "UUID",
+                "INTEGER[]",
+                "INTEGER[][]",
+                "INTEGER[2]",
+                "STRUCT(hash BLOB, n INTEGER, \"Tags\" VARCHAR[])",
+                "MAP(VARCHAR, INTEGER)",
+                "MAP(INTEGER, VARCHAR)",
+                "UNION(num INTEGER)",
+                "UHUGEINT[]",
+            ]
+        );
+        assert_eq!(
+            row,
+            [
+                Value::from("0xdeadbeef"),
+                Value::from("6ba7b810-9dad-11d1-80b4-00c04fd430c8"),
+                serde_json::json!([1, null, 3]),
+                serde_json::json!([[1], []]),
+                serde_json::json!([1, 2]),
+                serde_json::json!({ "hash": "0x0102", "n": 1, "Tags": ["a"] }),
+                serde_json::json!({ "k": 1 }),
+                serde_json::json!({ "1": "x" }),
+                Value::from(2),
+                serde_json::json!(["1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn enums_render_their_labels() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TYPE mood AS ENUM ('sad', 'ok')")
+            .unwrap();
+        let mut stmt = conn.prepare("SELECT 'ok'::mood").unwrap();
+        let batches: Vec<RecordBatch> = stmt.query_arrow([]).unwrap().collect();
+        assert_eq!(row_json(&batches[0], 0), [Value::from("ok")]);
+        assert_eq!(column_type_name(batches[0].schema().field(0), None), "ENUM");
+    }
+
+    #[test]
+    fn decimal_string_places_the_point() {
+        assert_eq!(decimal_string(0, 2), "0.00");
+        assert_eq!(decimal_string(5, 3), "0.005");
+        assert_eq!(decimal_string(-12345, 2), "-123.45");
+        assert_eq!(decimal_string(i128::MIN, 0), i128::MIN.to_string());
+    }
+}
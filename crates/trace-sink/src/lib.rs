@@ -110,7 +110,10 @@ impl Sink {
                 return Err(anyhow!("unsupported content_type={}", pointer.content_type));
             }
 
-            let (bucket, key) = parse_s3_uri(&pointer.batch_uri).context("parse batch_uri")?;
+            let (bucket, key) = self
+                .object_store
+                .locate_uri(&pointer.batch_uri)
+                .context("parse batch_uri")?;
             let bytes = self
                 .object_store
                 .get_bytes(&bucket, &key)
@@ -149,16 +152,6 @@ impl Sink {
     }
 }
 
-fn parse_s3_uri(uri: &str) -> anyhow::Result<(String, String)> {
-    let uri = uri
-        .strip_prefix("s3://")
-        .ok_or_else(|| anyhow!("batch_uri must start with s3://"))?;
-    let (bucket, key) = uri
-        .split_once('/')
-        .ok_or_else(|| anyhow!("s3 uri missing key"))?;
-    Ok((bucket.to_string(), key.to_string()))
-}
-
 fn parse_jsonl(bytes: &[u8]) -> anyhow::Result<Vec<AlertEventRow>> {
     let text = std::str::from_utf8(bytes).context("batch must be utf-8")?;
     let mut rows = Vec::new();
@@ -0,0 +1,30 @@
+-- Query audit metrics and outcomes (still no raw SQL stored).
+--
+-- Rejected and failed queries are audited too, so result_row_count is only set for `ok` rows.
+-- query_id groups the per-dataset rows written for one request.
+
+ALTER TABLE data.query_audit
+  ADD COLUMN IF NOT EXISTS query_id UUID NULL,
+  ADD COLUMN IF NOT EXISTS outcome TEXT NOT NULL DEFAULT 'ok'
+    CHECK (outcome IN ('ok', 'rejected', 'failed')),
+  ADD COLUMN IF NOT EXISTS sql_fingerprint TEXT NULL,
+  ADD COLUMN IF NOT EXISTS duration_ms BIGINT NULL,
+  ADD COLUMN IF NOT EXISTS bytes_scanned BIGINT NULL,
+  ADD COLUMN IF NOT EXISTS files_scanned BIGINT NULL,
+  ALTER COLUMN result_row_count DROP NOT NULL;
+
+ALTER TABLE data.user_query_audit
+  ADD COLUMN IF NOT EXISTS query_id UUID NULL,
+  ADD COLUMN IF NOT EXISTS outcome TEXT NOT NULL DEFAULT 'ok'
+    CHECK (outcome IN ('ok', 'rejected', 'failed')),
+  ADD COLUMN IF NOT EXISTS sql_fingerprint TEXT NULL,
+  ADD COLUMN IF NOT EXISTS duration_ms BIGINT NULL,
+  ADD COLUMN IF NOT EXISTS bytes_scanned BIGINT NULL,
+  ADD COLUMN IF NOT EXISTS files_scanned BIGINT NULL,
+  ALTER COLUMN result_row_count DROP NOT NULL;
+
+CREATE INDEX IF NOT EXISTS query_audit_dataset_time_idx
+  ON data.query_audit (dataset_id, query_time DESC);
+
+CREATE INDEX IF NOT EXISTS user_query_audit_dataset_time_idx
+  ON data.user_query_audit (dataset_id, query_time DESC);
@@ -0,0 +1,33 @@
+-- Query jobs and their results (ADR 0005; no raw SQL stored).
+
+CREATE TABLE IF NOT EXISTS data.query_results (
+  id              UUID PRIMARY KEY,
+  org_id          UUID NOT NULL,
+  user_sub        TEXT NOT NULL,
+  mode            TEXT NOT NULL CHECK (mode IN ('interactive', 'batch')),
+  status          TEXT NOT NULL
+    CHECK (status IN ('queued', 'running', 'succeeded', 'failed', 'canceled', 'expired')),
+  sql_fingerprint TEXT NULL,
+  output_format   TEXT NULL,
+  output_location TEXT NULL,
+  row_count       BIGINT NULL,
+  bytes           BIGINT NULL,
+  duration_ms     BIGINT NULL,
+  error_code      TEXT NULL,
+  error_message   TEXT NULL,
+  created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  started_at      TIMESTAMPTZ NULL,
+  finished_at     TIMESTAMPTZ NULL,
+  expires_at      TIMESTAMPTZ NULL
+);
+
+CREATE INDEX IF NOT EXISTS query_results_org_time_idx
+  ON data.query_results (org_id, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS query_results_user_time_idx
+  ON data.query_results (user_sub, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS query_results_active_idx
+  ON data.query_results (status)
+  WHERE status IN ('queued', 'running', 'succeeded');
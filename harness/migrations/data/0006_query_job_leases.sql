@@ -0,0 +1,6 @@
+-- Query job ownership: the Query Service instance running a job renews its lease while the job is
+-- queued or running. A job whose lease lapsed belongs to a dead instance and is failed as
+-- `interrupted` by any instance. Rows from before this migration have no lease and count as lapsed.
+ALTER TABLE data.query_results
+  ADD COLUMN IF NOT EXISTS owner_id UUID NULL,
+  ADD COLUMN IF NOT EXISTS lease_expires_at TIMESTAMPTZ NULL;
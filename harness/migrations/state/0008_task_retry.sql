@@ -0,0 +1,11 @@
+-- Bounded task retries.
+--
+-- Retryable failures and expired leases re-queue a task with exponential backoff until the
+-- operator's attempt budget is exhausted; the task then moves to the terminal `failed` status.
+-- `last_error` records the most recent failure reason for operators (`trace-dispatcher status`).
+
+ALTER TABLE state.tasks
+ADD COLUMN IF NOT EXISTS last_error TEXT NULL;
+
+CREATE INDEX IF NOT EXISTS tasks_status_updated_idx
+  ON state.tasks (status, updated_at);
@@ -0,0 +1,12 @@
+-- Terminal failure for chain_sync scheduled ranges.
+--
+-- A range whose task fails terminally (fatal_error, or retries exhausted) moves to
+-- status='failed' with the error recorded, freeing its max_inflight slot. Operators reschedule
+-- failed ranges with `trace-dispatcher chain-sync retry-ranges`, which re-queues the task under a
+-- fresh attempt; `retry_base_attempt` marks where the fresh retry budget starts.
+
+ALTER TABLE state.chain_sync_scheduled_ranges
+ADD COLUMN IF NOT EXISTS last_error TEXT NULL;
+
+ALTER TABLE state.tasks
+ADD COLUMN IF NOT EXISTS retry_base_attempt BIGINT NOT NULL DEFAULT 0;
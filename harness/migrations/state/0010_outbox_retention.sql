@@ -0,0 +1,9 @@
+-- Outbox per-row retries and retention.
+--
+-- The drainer selects due `pending` rows and the sweeper deletes aged `sent` rows; index both
+-- access paths so neither scans the (ever-growing) table. `dead` rows are listed by updated_at.
+CREATE INDEX IF NOT EXISTS outbox_status_available_idx
+  ON state.outbox (status, available_at);
+
+CREATE INDEX IF NOT EXISTS outbox_status_updated_idx
+  ON state.outbox (status, updated_at);
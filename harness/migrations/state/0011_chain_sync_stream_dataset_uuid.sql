@@ -0,0 +1,14 @@
+-- Chain sync stream dataset identity.
+--
+-- Query Service resolves a dataset_uuid to its current stream config_hash (and from there to the
+-- set of published range versions). Streams applied before this migration are backfilled on the
+-- next `apply`; until then the registry falls back to the most recently published config_hash.
+
+ALTER TABLE state.chain_sync_streams
+ADD COLUMN IF NOT EXISTS dataset_uuid UUID NULL;
+
+CREATE INDEX IF NOT EXISTS chain_sync_streams_dataset_uuid_idx
+  ON state.chain_sync_streams (dataset_uuid);
+
+CREATE INDEX IF NOT EXISTS dataset_versions_dataset_config_idx
+  ON state.dataset_versions (dataset_uuid, config_hash, range_start);
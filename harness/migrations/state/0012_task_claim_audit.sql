@@ -0,0 +1,22 @@
+-- Task claim audit.
+--
+-- Every authenticated `/internal/task-claim` attempt records which worker asked for which task and
+-- whether a capability was issued. Granted claims are written in the claim transaction; denials
+-- are written on their own. Unauthenticated attempts have no worker to attribute and are only
+-- logged, so anonymous callers cannot grow this table.
+
+CREATE TABLE IF NOT EXISTS state.task_claim_audit (
+  audit_id           BIGSERIAL PRIMARY KEY,
+  worker_id          TEXT NOT NULL,
+  task_id            UUID NOT NULL,
+  operator           TEXT NULL,
+  attempt            BIGINT NULL, -- set when granted
+  outcome            TEXT NOT NULL, -- granted|operator_denied|conflict
+  created_at         TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS task_claim_audit_task_idx
+  ON state.task_claim_audit (task_id, created_at);
+
+CREATE INDEX IF NOT EXISTS task_claim_audit_worker_idx
+  ON state.task_claim_audit (worker_id, created_at);
@@ -0,0 +1,10 @@
+-- Allowed task claim audit outcomes.
+--
+-- `inputs_unresolved` records claims denied because a declared input dataset version is not
+-- published (or the payload's inputs are malformed); the task stays queued.
+ALTER TABLE state.task_claim_audit
+DROP CONSTRAINT IF EXISTS task_claim_audit_outcome_check;
+
+ALTER TABLE state.task_claim_audit
+ADD CONSTRAINT task_claim_audit_outcome_check
+CHECK (outcome IN ('granted', 'operator_denied', 'inputs_unresolved', 'conflict'));
@@ -0,0 +1,7 @@
+-- Outbox drain claims.
+--
+-- The drainer claims due `pending` rows by setting `locked_until` in a short transaction, then
+-- publishes outside it; other drainers skip a row until its claim lapses. A drainer that dies
+-- mid-publish leaves the row to be re-published once `locked_until` passes (at-least-once).
+ALTER TABLE state.outbox
+  ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ NULL;
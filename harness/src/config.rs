@@ -1,4 +1,15 @@
+use anyhow::Context;
 use clap::Parser;
+use std::sync::Arc;
+use std::time::Duration;
+use trace_core::lite::fs::FsObjectStore;
+use trace_core::lite::jwks::read_public_keys;
+use trace_core::lite::jwt::{
+    AsymmetricTaskCapabilityConfig, Hs256TaskCapabilityConfig, TaskCapability,
+};
+use trace_core::lite::s3::{ObjectStore, S3Config, S3Credentials};
+use trace_core::multipart::MultipartConfig;
+use trace_core::ObjectStore as ObjectStoreTrait;
 use uuid::Uuid;
 
 /// Harness configuration.
@@ -55,10 +66,43 @@ pub struct HarnessConfig {
     #[arg(long, env = "LEASE_REAPER_POLL_MS", default_value_t = 200)]
     pub lease_reaper_poll_ms: u64,
 
+    /// Max attempts per task (including the first) before it is failed terminally.
+    #[arg(long, env = "TASK_MAX_ATTEMPTS", default_value_t = 5)]
+    pub task_max_attempts: i64,
+
+    /// Per-operator max attempts overrides (`operator=N,...`, e.g. `cryo_ingest=10`).
+    #[arg(long, env = "TASK_MAX_ATTEMPTS_BY_OPERATOR", default_value = "")]
+    pub task_max_attempts_by_operator: String,
+
+    /// Base retry backoff in milliseconds (doubles per attempt, with jitter).
+    #[arg(long, env = "TASK_RETRY_BASE_DELAY_MS", default_value_t = 200)]
+    pub task_retry_base_delay_ms: u64,
+
+    /// Max retry backoff in milliseconds.
+    #[arg(long, env = "TASK_RETRY_MAX_DELAY_MS", default_value_t = 30_000)]
+    pub task_retry_max_delay_ms: u64,
+
     /// Max outbox rows to drain per tick.
     #[arg(long, env = "OUTBOX_BATCH_SIZE", default_value_t = 50)]
     pub outbox_batch_size: i64,
 
+    /// Publish attempts per outbox row before it is marked `dead`.
+    #[arg(long, env = "OUTBOX_MAX_ATTEMPTS", default_value_t = 10)]
+    pub outbox_max_attempts: i64,
+
+    /// Backoff before re-publishing a failed outbox row; doubles per attempt.
+    #[arg(long, env = "OUTBOX_RETRY_BASE_DELAY_MS", default_value_t = 500)]
+    pub outbox_retry_base_delay_ms: u64,
+
+    /// Upper bound on outbox publish backoff.
+    #[arg(long, env = "OUTBOX_RETRY_MAX_DELAY_MS", default_value_t = 60_000)]
+    pub outbox_retry_max_delay_ms: u64,
+
+    /// How long `sent` outbox rows are retained before being swept (0 disables the sweeper).
+    /// Rows whose task is still running are kept regardless.
+    #[arg(long, env = "OUTBOX_SENT_RETENTION_SECS", default_value_t = 86_400)]
+    pub outbox_sent_retention_secs: u64,
+
     /// Queue name for task wake-up messages.
     #[arg(long, env = "TASK_WAKEUP_QUEUE", default_value = "task_wakeup")]
     pub task_wakeup_queue: String,
@@ -111,10 +155,38 @@ pub struct HarnessConfig {
     #[arg(long, env = "TASK_CAPABILITY_NEXT_SECRET")]
     pub task_capability_next_secret: Option<String>,
 
+    /// PKCS#8 PEM Ed25519 or P-256 private key. When set, task capabilities are signed with it
+    /// under `TASK_CAPABILITY_KID` instead of the HMAC secret, and verifiers only need the
+    /// dispatcher's public JWKS.
+    #[arg(long, env = "TASK_CAPABILITY_PRIVATE_KEY_FILE")]
+    pub task_capability_private_key_file: Option<String>,
+
+    /// Extra public keys to accept and publish alongside the private key, as
+    /// `kid=path.pem,...` (e.g. the previous signing key while its tokens are still live).
+    #[arg(long, env = "TASK_CAPABILITY_PUBLIC_KEYS")]
+    pub task_capability_public_keys: Option<String>,
+
     /// Task capability token TTL in seconds.
     #[arg(long, env = "TASK_CAPABILITY_TTL_SECS", default_value_t = 300)]
     pub task_capability_ttl_secs: u64,
 
+    /// Workers allowed to claim tasks, as `worker_id=token:op1|op2,...` (`*` allows any
+    /// operator). Each worker may only claim tasks whose payload `operator` is on its list.
+    #[arg(
+        long,
+        env = "DISPATCHER_WORKER_TOKENS",
+        default_value = "dev-worker=trace-harness-dev-worker-token:*"
+    )]
+    pub dispatcher_worker_tokens: String,
+
+    /// Worker token this process presents to `/internal/task-claim` (`X-Trace-Worker-Token`).
+    #[arg(
+        long,
+        env = "WORKER_TOKEN",
+        default_value = "trace-harness-dev-worker-token"
+    )]
+    pub worker_token: String,
+
     /// Worker poll interval in milliseconds.
     #[arg(long, env = "WORKER_POLL_MS", default_value_t = 200)]
     pub worker_poll_ms: u64,
@@ -127,6 +199,20 @@ pub struct HarnessConfig {
     #[arg(long, env = "WORKER_REQUEUE_DELAY_MS", default_value_t = 500)]
     pub worker_requeue_delay_ms: u64,
 
+    /// Interval between lease heartbeats while a worker holds a task claim (milliseconds).
+    ///
+    /// Keep this well below `LEASE_DURATION_SECS`.
+    #[arg(long, env = "TASK_HEARTBEAT_INTERVAL_MS", default_value_t = 3_000)]
+    pub task_heartbeat_interval_ms: u64,
+
+    /// Max random delay added to each heartbeat interval (milliseconds).
+    #[arg(long, env = "TASK_HEARTBEAT_JITTER_MS", default_value_t = 500)]
+    pub task_heartbeat_jitter_ms: u64,
+
+    /// Consecutive heartbeat failures (transport/5xx) before the worker abandons the claim.
+    #[arg(long, env = "TASK_HEARTBEAT_MAX_FAILURES", default_value_t = 3)]
+    pub task_heartbeat_max_failures: u32,
+
     /// Sink poll interval in milliseconds.
     #[arg(long, env = "SINK_POLL_MS", default_value_t = 200)]
     pub sink_poll_ms: u64,
@@ -143,6 +229,11 @@ pub struct HarnessConfig {
     #[arg(long, env = "SINK_MAX_DELIVERIES", default_value_t = 3)]
     pub sink_max_deliveries: i32,
 
+    /// Keep objects in this local directory instead of MinIO/S3 (the `S3_*` settings are unused,
+    /// except `S3_BUCKET`, which names the top-level directory).
+    #[arg(long, env = "TRACE_STORAGE_DIR")]
+    pub storage_dir: Option<String>,
+
     /// MinIO/S3 endpoint (used later by the pointer-buffer artifacts).
     #[arg(long, env = "S3_ENDPOINT", default_value = "http://localhost:9000")]
     pub s3_endpoint: String,
@@ -158,6 +249,26 @@ pub struct HarnessConfig {
 
     #[arg(long, env = "S3_REGION", default_value = "us-east-1")]
     pub s3_region: String,
+
+    /// `path` for MinIO; `vhost` for AWS.
+    #[arg(long, env = "S3_URL_STYLE", default_value = "path")]
+    pub s3_url_style: String,
+
+    /// Files larger than this are uploaded with S3 multipart uploads.
+    #[arg(long, env = "S3_MULTIPART_THRESHOLD_BYTES", default_value_t = 64 * 1024 * 1024)]
+    pub s3_multipart_threshold_bytes: u64,
+
+    /// Multipart part size (min 5 MiB).
+    #[arg(long, env = "S3_MULTIPART_PART_SIZE_BYTES", default_value_t = 16 * 1024 * 1024)]
+    pub s3_multipart_part_size_bytes: u64,
+
+    /// Multipart parts uploaded concurrently per file.
+    #[arg(long, env = "S3_MULTIPART_CONCURRENCY", default_value_t = 4)]
+    pub s3_multipart_concurrency: usize,
+
+    /// Attempts per multipart part before the upload is aborted (1 to 10).
+    #[arg(long, env = "S3_MULTIPART_PART_ATTEMPTS", default_value_t = 3)]
+    pub s3_multipart_part_attempts: u32,
 }
 
 impl std::fmt::Debug for HarnessConfig {
@@ -175,7 +286,24 @@ impl std::fmt::Debug for HarnessConfig {
             .field("lease_duration_secs", &self.lease_duration_secs)
             .field("outbox_poll_ms", &self.outbox_poll_ms)
             .field("lease_reaper_poll_ms", &self.lease_reaper_poll_ms)
+            .field("task_max_attempts", &self.task_max_attempts)
+            .field(
+                "task_max_attempts_by_operator",
+                &self.task_max_attempts_by_operator,
+            )
+            .field("task_retry_base_delay_ms", &self.task_retry_base_delay_ms)
+            .field("task_retry_max_delay_ms", &self.task_retry_max_delay_ms)
             .field("outbox_batch_size", &self.outbox_batch_size)
+            .field("outbox_max_attempts", &self.outbox_max_attempts)
+            .field(
+                "outbox_retry_base_delay_ms",
+                &self.outbox_retry_base_delay_ms,
+            )
+            .field("outbox_retry_max_delay_ms", &self.outbox_retry_max_delay_ms)
+            .field(
+                "outbox_sent_retention_secs",
+                &self.outbox_sent_retention_secs,
+            )
             .field("task_wakeup_queue", &self.task_wakeup_queue)
             .field("buffer_queue", &self.buffer_queue)
             .field("buffer_queue_dlq", &self.buffer_queue_dlq)
@@ -186,13 +314,32 @@ impl std::fmt::Debug for HarnessConfig {
             .field("task_capability_secret", &"<redacted>")
             .field("task_capability_next_kid", &self.task_capability_next_kid)
             .field("task_capability_next_secret", &task_capability_next_secret)
+            .field(
+                "task_capability_private_key_file",
+                &self.task_capability_private_key_file,
+            )
+            .field(
+                "task_capability_public_keys",
+                &self.task_capability_public_keys,
+            )
             .field("task_capability_ttl_secs", &self.task_capability_ttl_secs)
+            .field("dispatcher_worker_tokens", &"<redacted>")
+            .field("worker_token", &"<redacted>")
             .field("worker_poll_ms", &self.worker_poll_ms)
             .field(
                 "worker_visibility_timeout_secs",
                 &self.worker_visibility_timeout_secs,
             )
             .field("worker_requeue_delay_ms", &self.worker_requeue_delay_ms)
+            .field(
+                "task_heartbeat_interval_ms",
+                &self.task_heartbeat_interval_ms,
+            )
+            .field("task_heartbeat_jitter_ms", &self.task_heartbeat_jitter_ms)
+            .field(
+                "task_heartbeat_max_failures",
+                &self.task_heartbeat_max_failures,
+            )
             .field("sink_poll_ms", &self.sink_poll_ms)
             .field(
                 "sink_visibility_timeout_secs",
@@ -200,11 +347,26 @@ impl std::fmt::Debug for HarnessConfig {
             )
             .field("sink_retry_delay_ms", &self.sink_retry_delay_ms)
             .field("sink_max_deliveries", &self.sink_max_deliveries)
+            .field("storage_dir", &self.storage_dir)
             .field("s3_endpoint", &self.s3_endpoint)
             .field("s3_bucket", &self.s3_bucket)
             .field("s3_access_key", &"<redacted>")
             .field("s3_secret_key", &"<redacted>")
             .field("s3_region", &self.s3_region)
+            .field("s3_url_style", &self.s3_url_style)
+            .field(
+                "s3_multipart_threshold_bytes",
+                &self.s3_multipart_threshold_bytes,
+            )
+            .field(
+                "s3_multipart_part_size_bytes",
+                &self.s3_multipart_part_size_bytes,
+            )
+            .field("s3_multipart_concurrency", &self.s3_multipart_concurrency)
+            .field(
+                "s3_multipart_part_attempts",
+                &self.s3_multipart_part_attempts,
+            )
             .finish()
     }
 }
@@ -217,4 +379,71 @@ impl HarnessConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         Ok(Self::parse_from(["trace-harness"]))
     }
+
+    /// Object store settings; requests are signed with `S3_ACCESS_KEY` / `S3_SECRET_KEY`.
+    pub fn s3_config(&self) -> anyhow::Result<S3Config> {
+        Ok(S3Config {
+            endpoint: self.s3_endpoint.clone(),
+            region: self.s3_region.clone(),
+            url_style: self.s3_url_style.parse()?,
+            credentials: Some(S3Credentials {
+                access_key: self.s3_access_key.clone(),
+                secret_key: self.s3_secret_key.clone(),
+            }),
+            multipart: MultipartConfig {
+                threshold_bytes: self.s3_multipart_threshold_bytes,
+                part_size_bytes: self.s3_multipart_part_size_bytes,
+                concurrency: self.s3_multipart_concurrency,
+                part_attempts: self.s3_multipart_part_attempts,
+            },
+        })
+    }
+
+    /// The configured object store: a local directory if `TRACE_STORAGE_DIR` is set, else S3.
+    pub fn object_store(&self) -> anyhow::Result<Arc<dyn ObjectStoreTrait>> {
+        Ok(match &self.storage_dir {
+            Some(dir) => Arc::new(FsObjectStore::new(dir)?),
+            None => Arc::new(ObjectStore::from_config(self.s3_config()?)?),
+        })
+    }
+
+    /// The dispatcher's capability signer: Ed25519/ES256 if `TASK_CAPABILITY_PRIVATE_KEY_FILE`
+    /// is set, else HS256.
+    pub fn task_capability(&self) -> anyhow::Result<TaskCapability> {
+        let ttl = Duration::from_secs(self.task_capability_ttl_secs);
+        let Some(path) = &self.task_capability_private_key_file else {
+            anyhow::ensure!(
+                self.task_capability_public_keys.is_none(),
+                "TASK_CAPABILITY_PUBLIC_KEYS requires TASK_CAPABILITY_PRIVATE_KEY_FILE"
+            );
+            return Ok(TaskCapability::from_hs256_config(
+                Hs256TaskCapabilityConfig {
+                    issuer: self.task_capability_iss.clone(),
+                    audience: self.task_capability_aud.clone(),
+                    current_kid: self.task_capability_kid.clone(),
+                    current_secret: self.task_capability_secret.clone(),
+                    next_kid: self.task_capability_next_kid.clone(),
+                    next_secret: self.task_capability_next_secret.clone(),
+                    ttl,
+                },
+            )?);
+        };
+
+        let private_key_pem = std::fs::read_to_string(path)
+            .with_context(|| format!("read TASK_CAPABILITY_PRIVATE_KEY_FILE {path}"))?;
+        let public_keys = match &self.task_capability_public_keys {
+            Some(spec) => read_public_keys(spec).context("read TASK_CAPABILITY_PUBLIC_KEYS")?,
+            None => Vec::new(),
+        };
+        Ok(TaskCapability::from_private_key_config(
+            AsymmetricTaskCapabilityConfig {
+                issuer: self.task_capability_iss.clone(),
+                audience: self.task_capability_aud.clone(),
+                kid: self.task_capability_kid.clone(),
+                private_key_pem,
+                public_keys,
+                ttl,
+            },
+        )?)
+    }
 }
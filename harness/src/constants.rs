@@ -1,6 +1,7 @@
 use uuid::Uuid;
 
 pub const TASK_CAPABILITY_HEADER: &str = "X-Trace-Task-Capability";
+pub const WORKER_TOKEN_HEADER: &str = "X-Trace-Worker-Token";
 
 pub const CONTENT_TYPE_JSON: &str = "application/json";
 pub const CONTENT_TYPE_JSONL: &str = "application/jsonl";
@@ -1,21 +1,26 @@
 use crate::config::HarnessConfig;
-use crate::dispatcher_client::{CompleteRequest, DispatcherClient, WriteDisposition};
+use crate::dispatcher_client::{
+    CompleteRequest, DispatcherClient, HeartbeatRequest, WriteDisposition,
+};
 use crate::pgqueue::PgQueue;
-use crate::s3::ObjectStore;
 use anyhow::Context;
 use duckdb::Connection;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::postgres::PgPoolOptions;
 use std::{
     collections::BTreeSet,
+    future::Future,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, SystemTime},
 };
 use trace_core::{
-    manifest::DatasetManifestV1, DatasetPublication, DatasetStorageRef,
-    ObjectStore as ObjectStoreTrait, Queue as QueueTrait,
+    jitter,
+    manifest::{ColumnRange, DatasetManifestV2, ManifestObject},
+    DatasetPublication, DatasetStorageRef, ObjectStore as ObjectStoreTrait, Queue as QueueTrait,
 };
+use trace_dispatcher::grants::cryo_range_prefix;
 use uuid::Uuid;
 
 const CONTENT_TYPE_PARQUET: &str = "application/octet-stream";
@@ -110,29 +115,39 @@ pub struct CryoIngestPayload {
     pub rpc_pool: Option<String>,
 }
 
-pub fn derive_dataset_publication(bucket: &str, payload: &CryoIngestPayload) -> DatasetPublication {
+/// The publication for `payload`, stored under `bucket` in `object_store` (an `s3` or `file`
+/// storage ref, depending on the store).
+pub fn derive_dataset_publication(
+    object_store: &dyn ObjectStoreTrait,
+    bucket: &str,
+    payload: &CryoIngestPayload,
+) -> anyhow::Result<DatasetPublication> {
     let dataset_version = derive_dataset_version(payload);
+    // The dispatcher grants cryo_ingest tasks write access to this range prefix.
     let prefix = format!(
-        "cryo/{}/{}/{}_{}/{}/",
-        payload.chain_id,
-        payload.dataset_uuid,
-        payload.range_start,
-        payload.range_end,
-        dataset_version
+        "{}{dataset_version}/",
+        cryo_range_prefix(
+            payload.chain_id,
+            payload.dataset_uuid,
+            payload.range_start,
+            payload.range_end,
+        )
     );
 
-    DatasetPublication {
+    let storage_ref = DatasetStorageRef::from_uri(
+        &object_store.object_uri(bucket, &prefix),
+        "*.parquet".to_string(),
+    )
+    .context("derive dataset storage ref")?;
+
+    Ok(DatasetPublication {
         dataset_uuid: payload.dataset_uuid,
         dataset_version,
-        storage_ref: DatasetStorageRef::S3 {
-            bucket: bucket.to_string(),
-            prefix,
-            glob: "*.parquet".to_string(),
-        },
+        storage_ref,
         config_hash: payload.config_hash.clone(),
         range_start: payload.range_start,
         range_end: payload.range_end,
-    }
+    })
 }
 
 pub async fn run_task(
@@ -159,18 +174,48 @@ async fn run_task_with_caps(
     let payload: CryoIngestPayload =
         serde_json::from_value(claim.work_payload.clone()).context("decode cryo payload")?;
 
-    let pubd = derive_dataset_publication(&cfg.s3_bucket, &payload);
+    let pubd = derive_dataset_publication(object_store, &cfg.s3_bucket, &payload)?;
 
-    match write_dataset_artifacts(
+    // Keep the lease alive for the whole claim. If the fence goes stale (or the dispatcher is
+    // unreachable for too long) another worker may already own the task: stop immediately, which
+    // drops the artifact future and kills the cryo child process.
+    let heartbeat = HeartbeatSettings::from_config(cfg);
+    let heartbeat_req = HeartbeatRequest {
+        task_id: claim.task_id,
+        attempt: claim.attempt,
+        lease_token: claim.lease_token,
+    };
+    let work = write_dataset_artifacts(
         object_store,
         &pubd,
         &payload,
         claim.task_id,
         claim.attempt,
         caps,
-    )
-    .await
-    {
+    );
+    let res = tokio::select! {
+        res = work => Some(res),
+        lost = heartbeat_until_lease_lost(&heartbeat, || {
+            dispatcher.heartbeat(&claim.capability_token, &heartbeat_req)
+        }) => {
+            tracing::warn!(
+                event = "harness.cryo_worker.lease.lost",
+                task_id = %claim.task_id,
+                attempt = claim.attempt,
+                reason = %lost,
+                "lease lost; cancelling cryo task"
+            );
+            None
+        }
+    };
+
+    let Some(res) = res else {
+        let staging_dir = staging_dir_for_task(claim.task_id, claim.attempt);
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+        return Ok(None);
+    };
+
+    match res {
         Ok(()) => {
             let complete_req = CompleteRequest {
                 task_id: claim.task_id,
@@ -178,6 +223,7 @@ async fn run_task_with_caps(
                 lease_token: claim.lease_token,
                 outcome: "success",
                 datasets_published: vec![pubd.clone()],
+                error: None,
             };
 
             if dispatcher
@@ -191,12 +237,15 @@ async fn run_task_with_caps(
             Ok(Some(pubd))
         }
         Err(err) => {
+            let reason = match &err {
+                CryoArtifactError::Retryable(e) | CryoArtifactError::Fatal(e) => format!("{e:#}"),
+            };
             tracing::warn!(
                 event = "harness.cryo_worker.task.failed",
                 outcome = err.outcome(),
                 task_id = %claim.task_id,
                 attempt = claim.attempt,
-                error = %match &err { CryoArtifactError::Retryable(e) | CryoArtifactError::Fatal(e) => e },
+                error = %reason,
                 "cryo task failed"
             );
 
@@ -206,6 +255,7 @@ async fn run_task_with_caps(
                 lease_token: claim.lease_token,
                 outcome: err.outcome(),
                 datasets_published: Vec::new(),
+                error: Some(reason),
             };
 
             if dispatcher
@@ -221,6 +271,83 @@ async fn run_task_with_caps(
     }
 }
 
+#[derive(Debug, Clone)]
+struct HeartbeatSettings {
+    interval: Duration,
+    jitter: Duration,
+    max_failures: u32,
+}
+
+impl HeartbeatSettings {
+    fn from_config(cfg: &HarnessConfig) -> Self {
+        Self {
+            interval: Duration::from_millis(cfg.task_heartbeat_interval_ms),
+            jitter: Duration::from_millis(cfg.task_heartbeat_jitter_ms),
+            max_failures: cfg.task_heartbeat_max_failures.max(1),
+        }
+    }
+
+    fn next_delay(&self) -> Duration {
+        self.interval + self.jitter.mul_f64(jitter::unit())
+    }
+}
+
+#[derive(Debug)]
+enum LeaseLost {
+    StaleFence,
+    HeartbeatFailures {
+        failures: u32,
+        last_error: anyhow::Error,
+    },
+}
+
+impl std::fmt::Display for LeaseLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LeaseLost::StaleFence => write!(f, "stale task fence"),
+            LeaseLost::HeartbeatFailures {
+                failures,
+                last_error,
+            } => write!(
+                f,
+                "{failures} consecutive heartbeat failures (last: {last_error:#})"
+            ),
+        }
+    }
+}
+
+/// Heartbeat until the lease is lost; never returns while the lease is healthy.
+async fn heartbeat_until_lease_lost<F, Fut>(settings: &HeartbeatSettings, mut beat: F) -> LeaseLost
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<WriteDisposition>>,
+{
+    let mut failures = 0u32;
+    loop {
+        tokio::time::sleep(settings.next_delay()).await;
+
+        match beat().await {
+            Ok(WriteDisposition::Ok) => failures = 0,
+            Ok(WriteDisposition::Conflict) => return LeaseLost::StaleFence,
+            Err(err) => {
+                failures += 1;
+                tracing::warn!(
+                    event = "harness.cryo_worker.heartbeat.error",
+                    failures,
+                    error = %err,
+                    "task heartbeat failed"
+                );
+                if failures >= settings.max_failures {
+                    return LeaseLost::HeartbeatFailures {
+                        failures,
+                        last_error: err,
+                    };
+                }
+            }
+        }
+    }
+}
+
 pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
     let staging_root = staging_root();
     ensure_private_dir(&staging_root)
@@ -249,9 +376,9 @@ pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
         .context("connect state db")?;
     let queue: Arc<dyn QueueTrait> = Arc::new(PgQueue::new(pool));
 
-    let object_store: Arc<dyn ObjectStoreTrait> =
-        Arc::new(ObjectStore::new(&cfg.s3_endpoint).context("init object store")?);
-    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone());
+    let object_store = cfg.object_store().context("init object store")?;
+    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone())
+        .with_worker_token(cfg.worker_token.clone());
 
     let poll_interval = Duration::from_millis(cfg.worker_poll_ms);
     let visibility_timeout = Duration::from_secs(cfg.worker_visibility_timeout_secs);
@@ -365,31 +492,38 @@ async fn write_dataset_artifacts_fake(
     payload: &CryoIngestPayload,
     staging_dir: &Path,
 ) -> Result<(), CryoArtifactError> {
-    let (bucket, prefix_key) = match &pubd.storage_ref {
-        DatasetStorageRef::S3 { bucket, prefix, .. } => (bucket.clone(), prefix.clone()),
-        DatasetStorageRef::File { .. } => {
-            return Err(CryoArtifactError::Fatal(anyhow::anyhow!(
-                "cryo worker requires s3 storage ref"
-            )));
-        }
-    };
+    let (bucket, prefix_key) = storage_location(object_store, pubd)?;
 
     let file_name = format!("cryo_{}_{}.parquet", payload.range_start, payload.range_end);
     let parquet_key = join_key(&prefix_key, &file_name);
     let parquet_path = build_parquet_file(staging_dir, payload)
         .await
         .map_err(CryoArtifactError::Retryable)?;
+    let object = describe_parquet_object(&parquet_path, parquet_key.clone())
+        .await
+        .map_err(CryoArtifactError::Retryable)?;
     object_store
         .put_file(&bucket, &parquet_key, &parquet_path, CONTENT_TYPE_PARQUET)
         .await
         .context("upload parquet")
         .map_err(CryoArtifactError::Retryable)?;
 
-    write_manifest(object_store, pubd, &bucket, &prefix_key, vec![parquet_key]).await?;
+    write_manifest(object_store, pubd, &bucket, &prefix_key, vec![object]).await?;
 
     Ok(())
 }
 
+/// `(bucket, key prefix)` of the publication's storage ref within `object_store`.
+fn storage_location(
+    object_store: &dyn ObjectStoreTrait,
+    pubd: &DatasetPublication,
+) -> Result<(String, String), CryoArtifactError> {
+    object_store
+        .locate_uri(&pubd.storage_ref.uri())
+        .context("storage ref does not belong to the configured object store")
+        .map_err(CryoArtifactError::Fatal)
+}
+
 fn join_key(prefix: &str, leaf: &str) -> String {
     let prefix = prefix.trim_end_matches('/');
     format!("{prefix}/{leaf}")
@@ -437,14 +571,7 @@ async fn write_dataset_artifacts_real(
             ))
         })?;
 
-    let (bucket, prefix_key) = match &pubd.storage_ref {
-        DatasetStorageRef::S3 { bucket, prefix, .. } => (bucket.clone(), prefix.clone()),
-        DatasetStorageRef::File { .. } => {
-            return Err(CryoArtifactError::Fatal(anyhow::anyhow!(
-                "cryo worker requires s3 storage ref"
-            )));
-        }
-    };
+    let (bucket, prefix_key) = storage_location(object_store, pubd)?;
 
     run_cryo_cli(
         &cryo_bin,
@@ -472,7 +599,7 @@ async fn write_dataset_artifacts_real(
 
     enforce_parquet_caps(&parquet_files, caps)?;
 
-    let mut parquet_keys = Vec::with_capacity(parquet_files.len());
+    let mut objects = Vec::with_capacity(parquet_files.len());
     for file in parquet_files {
         let file_path = file.path;
         let rel = file_path
@@ -481,16 +608,19 @@ async fn write_dataset_artifacts_real(
         let rel = rel.to_string_lossy().replace('\\', "/");
         let key = join_key(&prefix_key, &rel);
 
+        let object = describe_parquet_object(&file_path, key.clone())
+            .await
+            .map_err(CryoArtifactError::Retryable)?;
         object_store
             .put_file(&bucket, &key, &file_path, CONTENT_TYPE_PARQUET)
             .await
             .with_context(|| format!("upload parquet object {key}"))
             .map_err(CryoArtifactError::Retryable)?;
 
-        parquet_keys.push(key);
+        objects.push(object);
     }
 
-    write_manifest(object_store, pubd, &bucket, &prefix_key, parquet_keys).await?;
+    write_manifest(object_store, pubd, &bucket, &prefix_key, objects).await?;
 
     Ok(())
 }
@@ -540,7 +670,7 @@ fn enforce_parquet_caps(
 fn dataset_name_from_config_hash(config_hash: &str) -> Option<&str> {
     // Expected harness format: `cryo_ingest.<dataset>:<version>`
     let without_prefix = config_hash.strip_prefix("cryo_ingest.")?;
-    Some(without_prefix.split(':').next()?)
+    without_prefix.split(':').next()
 }
 
 fn rpc_url_for_pool(pool: &str) -> Option<String> {
@@ -570,39 +700,32 @@ async fn run_cryo_cli(
     end_block: i64,
     output_dir: &Path,
 ) -> Result<(), CryoArtifactError> {
+    tokio::fs::create_dir_all(output_dir).await.map_err(|err| {
+        CryoArtifactError::Fatal(anyhow::Error::new(err).context("create output dir"))
+    })?;
+
     // Cryo's --blocks start:end syntax is end-exclusive, matching our range convention.
-    let out = tokio::task::spawn_blocking({
-        let cryo_bin = cryo_bin.to_string();
-        let dataset = dataset.to_string();
-        let rpc_url = rpc_url.to_string();
-        let output_dir = output_dir.to_path_buf();
-        move || {
-            std::fs::create_dir_all(&output_dir).map_err(|err| {
-                CryoArtifactError::Fatal(anyhow::Error::new(err).context("create output dir"))
-            })?;
-
-            std::process::Command::new(&cryo_bin)
-                .arg(dataset)
-                .arg("--rpc")
-                .arg(rpc_url)
-                .arg("--blocks")
-                .arg(format!("{}:{}", start_block, end_block))
-                .arg("--output-dir")
-                .arg(output_dir.to_string_lossy().to_string())
-                .output()
-                .map_err(|err| {
-                    let kind = err.kind();
-                    let wrapped = anyhow::Error::new(err).context("run cryo");
-                    if kind == std::io::ErrorKind::NotFound {
-                        CryoArtifactError::Fatal(wrapped)
-                    } else {
-                        CryoArtifactError::Retryable(wrapped)
-                    }
-                })
-        }
-    })
-    .await
-    .map_err(|err| CryoArtifactError::Retryable(anyhow::anyhow!(err)))??;
+    // `kill_on_drop` ensures the child dies if this future is cancelled (e.g. lease lost).
+    let out = tokio::process::Command::new(cryo_bin)
+        .arg(dataset)
+        .arg("--rpc")
+        .arg(rpc_url)
+        .arg("--blocks")
+        .arg(format!("{}:{}", start_block, end_block))
+        .arg("--output-dir")
+        .arg(output_dir.to_string_lossy().to_string())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|err| {
+            let kind = err.kind();
+            let wrapped = anyhow::Error::new(err).context("run cryo");
+            if kind == std::io::ErrorKind::NotFound {
+                CryoArtifactError::Fatal(wrapped)
+            } else {
+                CryoArtifactError::Retryable(wrapped)
+            }
+        })?;
 
     if out.status.success() {
         return Ok(());
@@ -790,21 +913,91 @@ async fn build_parquet_file(
     .context("join parquet builder")?
 }
 
+/// Describe a staged Parquet file for the manifest: size and SHA-256 from its bytes, row count
+/// and `block_number`/`timestamp` ranges from its footer statistics.
+async fn describe_parquet_object(path: &Path, key: String) -> anyhow::Result<ManifestObject> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)
+            .with_context(|| format!("open parquet file {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        let size_bytes = std::io::copy(&mut file, &mut hasher).context("hash parquet file")?;
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        let path_escaped = path.to_string_lossy().replace('\'', "''");
+        let conn = Connection::open_in_memory().context("open duckdb in-memory")?;
+        let row_count: i64 = conn
+            .query_row(
+                &format!(
+                    "SELECT coalesce(sum(row_group_num_rows), 0)::BIGINT FROM (\
+                     SELECT DISTINCT row_group_id, row_group_num_rows \
+                     FROM parquet_metadata('{path_escaped}'));"
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .context("read parquet row count")?;
+
+        Ok(ManifestObject {
+            key,
+            size_bytes,
+            row_count: u64::try_from(row_count).context("negative parquet row count")?,
+            sha256,
+            block_number: parquet_column_range(&conn, &path_escaped, "block_number")?,
+            timestamp: parquet_column_range(&conn, &path_escaped, "timestamp")?,
+        })
+    })
+    .await
+    .context("join parquet describe")?
+}
+
+/// `column`'s range across all row groups, or `None` if the column is missing or any row group
+/// lacks integer min/max statistics for it.
+fn parquet_column_range(
+    conn: &Connection,
+    path_escaped: &str,
+    column: &str,
+) -> anyhow::Result<Option<ColumnRange>> {
+    let (row_groups, unknown, min, max): (i64, i64, Option<i64>, Option<i64>) = conn
+        .query_row(
+            &format!(
+                "SELECT count(*), \
+                 count(*) FILTER (WHERE TRY_CAST(stats_min_value AS BIGINT) IS NULL \
+                   OR TRY_CAST(stats_max_value AS BIGINT) IS NULL), \
+                 min(TRY_CAST(stats_min_value AS BIGINT)), \
+                 max(TRY_CAST(stats_max_value AS BIGINT)) \
+                 FROM parquet_metadata('{path_escaped}') WHERE path_in_schema = ?;"
+            ),
+            [column],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .with_context(|| format!("read parquet {column} statistics"))?;
+
+    Ok(match (min, max) {
+        (Some(min), Some(max)) if row_groups > 0 && unknown == 0 => Some(ColumnRange { min, max }),
+        _ => None,
+    })
+}
+
 async fn write_manifest(
     object_store: &dyn ObjectStoreTrait,
     pubd: &DatasetPublication,
     bucket: &str,
     prefix_key: &str,
-    mut parquet_keys: Vec<String>,
+    mut objects: Vec<ManifestObject>,
 ) -> Result<(), CryoArtifactError> {
-    parquet_keys.sort();
-    parquet_keys.dedup();
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    objects.dedup_by(|a, b| a.key == b.key);
 
-    let manifest = DatasetManifestV1 {
-        version: DatasetManifestV1::VERSION,
+    let manifest = DatasetManifestV2 {
+        version: DatasetManifestV2::VERSION,
         dataset_uuid: pubd.dataset_uuid,
         dataset_version: pubd.dataset_version,
-        parquet_keys,
+        objects,
     };
     let bytes = serde_json::to_vec(&manifest)
         .context("encode manifest json")
@@ -824,7 +1017,7 @@ mod tests {
     use super::*;
     use async_trait::async_trait;
     use std::sync::{Arc, Mutex};
-    use trace_core::{Error, Result as CoreResult};
+    use trace_core::{Error, ObjectMeta, ObjectReader, Result as CoreResult};
 
     #[derive(Default, Clone)]
     struct RecordingObjectStore {
@@ -833,8 +1026,14 @@ mod tests {
 
     #[derive(Debug, Clone)]
     enum PutOp {
-        Bytes { key: String, content_type: String },
-        File { key: String },
+        Bytes {
+            key: String,
+            content_type: String,
+            bytes: Vec<u8>,
+        },
+        File {
+            key: String,
+        },
     }
 
     impl RecordingObjectStore {
@@ -854,7 +1053,7 @@ mod tests {
             &self,
             _bucket: &str,
             key: &str,
-            _bytes: Vec<u8>,
+            bytes: Vec<u8>,
             content_type: &str,
         ) -> CoreResult<()> {
             if content_type == CONTENT_TYPE_PARQUET {
@@ -868,6 +1067,7 @@ mod tests {
                 .push(PutOp::Bytes {
                     key: key.to_string(),
                     content_type: content_type.to_string(),
+                    bytes,
                 });
             Ok(())
         }
@@ -900,6 +1100,31 @@ mod tests {
         async fn get_bytes(&self, _bucket: &str, _key: &str) -> CoreResult<Vec<u8>> {
             Err(Error::msg("unexpected get_bytes in cryo worker test"))
         }
+
+        async fn get(&self, _bucket: &str, _key: &str) -> CoreResult<ObjectReader> {
+            Err(Error::msg("unexpected get in cryo worker test"))
+        }
+
+        async fn get_range(
+            &self,
+            _bucket: &str,
+            _key: &str,
+            _range: std::ops::Range<u64>,
+        ) -> CoreResult<Vec<u8>> {
+            Err(Error::msg("unexpected get_range in cryo worker test"))
+        }
+
+        async fn head(&self, _bucket: &str, _key: &str) -> CoreResult<Option<ObjectMeta>> {
+            Err(Error::msg("unexpected head in cryo worker test"))
+        }
+
+        async fn list_prefix(&self, _bucket: &str, _prefix: &str) -> CoreResult<Vec<ObjectMeta>> {
+            Err(Error::msg("unexpected list_prefix in cryo worker test"))
+        }
+
+        async fn delete(&self, _bucket: &str, _key: &str) -> CoreResult<()> {
+            Err(Error::msg("unexpected delete in cryo worker test"))
+        }
     }
 
     #[tokio::test]
@@ -915,7 +1140,7 @@ mod tests {
             cryo_dataset_name: Some("blocks".to_string()),
             rpc_pool: None,
         };
-        let pubd = derive_dataset_publication("test-bucket", &payload);
+        let pubd = derive_dataset_publication(&object_store, "test-bucket", &payload)?;
 
         let staging_dir = std::env::temp_dir().join(format!("trace-cryo-test-{}", Uuid::new_v4()));
         ensure_private_dir(&staging_dir).await?;
@@ -926,7 +1151,9 @@ mod tests {
         let ops = object_store.ops();
         for op in &ops {
             match op {
-                PutOp::Bytes { key, content_type } => {
+                PutOp::Bytes {
+                    key, content_type, ..
+                } => {
                     assert!(
                         content_type == CONTENT_TYPE_JSON,
                         "unexpected content type for put_bytes: {content_type}"
@@ -962,6 +1189,94 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn fake_artifact_manifest_records_parquet_statistics() -> anyhow::Result<()> {
+        let object_store = RecordingObjectStore::default();
+        let payload = CryoIngestPayload {
+            dataset_uuid: Uuid::new_v4(),
+            chain_id: 1,
+            range_start: 100,
+            range_end: 200,
+            config_hash: "cryo_ingest.blocks:1".to_string(),
+            dataset_key: Some("blocks".to_string()),
+            cryo_dataset_name: Some("blocks".to_string()),
+            rpc_pool: None,
+        };
+        let pubd = derive_dataset_publication(&object_store, "test-bucket", &payload)?;
+
+        let staging_dir = std::env::temp_dir().join(format!("trace-cryo-test-{}", Uuid::new_v4()));
+        ensure_private_dir(&staging_dir).await?;
+        let res = write_dataset_artifacts_fake(&object_store, &pubd, &payload, &staging_dir).await;
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+        res.map_err(|err| anyhow::anyhow!("{err:?}"))?;
+
+        let manifest = object_store
+            .ops()
+            .into_iter()
+            .find_map(|op| match op {
+                PutOp::Bytes { bytes, .. } => Some(bytes),
+                PutOp::File { .. } => None,
+            })
+            .context("manifest upload")?;
+        let manifest: DatasetManifestV2 = serde_json::from_slice(&manifest)?;
+        assert_eq!(manifest.version, DatasetManifestV2::VERSION);
+        assert_eq!(manifest.dataset_uuid, pubd.dataset_uuid);
+        assert_eq!(manifest.dataset_version, pubd.dataset_version);
+
+        let [object] = manifest.objects.as_slice() else {
+            panic!("expected one object: {:?}", manifest.objects);
+        };
+        assert!(
+            object.key.ends_with("/cryo_100_200.parquet"),
+            "{}",
+            object.key
+        );
+        assert!(object.size_bytes > 0);
+        assert_eq!(object.row_count, 3);
+        assert_eq!(object.sha256.len(), 64);
+        assert_eq!(
+            object.block_number,
+            Some(ColumnRange { min: 100, max: 199 })
+        );
+        assert_eq!(object.timestamp, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fake_artifacts_publish_file_storage_ref_for_fs_store() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("trace-cryo-fs-{}", Uuid::new_v4()));
+        let object_store = trace_core::lite::fs::FsObjectStore::new(&root)?;
+        let payload = CryoIngestPayload {
+            dataset_uuid: Uuid::new_v4(),
+            chain_id: 1,
+            range_start: 0,
+            range_end: 10,
+            config_hash: "cryo_ingest.blocks:1".to_string(),
+            dataset_key: Some("blocks".to_string()),
+            cryo_dataset_name: Some("blocks".to_string()),
+            rpc_pool: None,
+        };
+        let pubd = derive_dataset_publication(&object_store, "test-bucket", &payload)?;
+
+        let staging_dir = std::env::temp_dir().join(format!("trace-cryo-test-{}", Uuid::new_v4()));
+        ensure_private_dir(&staging_dir).await?;
+        let res = write_dataset_artifacts_fake(&object_store, &pubd, &payload, &staging_dir).await;
+        let _ = tokio::fs::remove_dir_all(&staging_dir).await;
+        res.map_err(|err| anyhow::anyhow!("{err:?}"))?;
+
+        let DatasetStorageRef::File { prefix, .. } = &pubd.storage_ref else {
+            panic!("expected file storage ref: {:?}", pubd.storage_ref);
+        };
+        let prefix = PathBuf::from(prefix);
+        assert!(prefix.starts_with(object_store.root().join("test-bucket")));
+        assert!(prefix.join("cryo_0_10.parquet").is_file());
+        assert!(prefix.join("_manifest.json").is_file());
+
+        let _ = tokio::fs::remove_dir_all(&root).await;
+        Ok(())
+    }
+
     #[test]
     fn parquet_caps_exceeded_is_fatal() {
         let caps = CryoArtifactCaps {
@@ -1012,4 +1327,103 @@ mod tests {
             other => panic!("expected fatal error, got {other:?}"),
         }
     }
+
+    fn fast_heartbeat(max_failures: u32) -> HeartbeatSettings {
+        HeartbeatSettings {
+            interval: Duration::from_millis(1),
+            jitter: Duration::ZERO,
+            max_failures,
+        }
+    }
+
+    #[tokio::test]
+    async fn heartbeat_stale_fence_reports_lease_lost() {
+        let calls = Arc::new(Mutex::new(0u32));
+        let lost = heartbeat_until_lease_lost(&fast_heartbeat(3), || {
+            let calls = calls.clone();
+            async move {
+                let mut n = calls.lock().expect("mutex poisoned");
+                *n += 1;
+                if *n < 3 {
+                    Ok(WriteDisposition::Ok)
+                } else {
+                    Ok(WriteDisposition::Conflict)
+                }
+            }
+        })
+        .await;
+
+        assert!(matches!(lost, LeaseLost::StaleFence), "got {lost:?}");
+        assert_eq!(*calls.lock().expect("mutex poisoned"), 3);
+    }
+
+    #[tokio::test]
+    async fn heartbeat_failure_threshold_counts_consecutive_errors() {
+        // err, ok (resets), err, err -> lost after the 4th call with max_failures=2.
+        let calls = Arc::new(Mutex::new(0u32));
+        let lost = heartbeat_until_lease_lost(&fast_heartbeat(2), || {
+            let calls = calls.clone();
+            async move {
+                let mut n = calls.lock().expect("mutex poisoned");
+                *n += 1;
+                if *n == 2 {
+                    Ok(WriteDisposition::Ok)
+                } else {
+                    Err(anyhow::anyhow!("dispatcher unavailable"))
+                }
+            }
+        })
+        .await;
+
+        match lost {
+            LeaseLost::HeartbeatFailures { failures, .. } => assert_eq!(failures, 2),
+            other => panic!("expected heartbeat failures, got {other:?}"),
+        }
+        assert_eq!(*calls.lock().expect("mutex poisoned"), 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cancelled_cryo_run_kills_child_process() -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("trace-cryo-kill-test-{}", Uuid::new_v4()));
+        ensure_private_dir(&dir).await?;
+        let pid_file = dir.join("pid");
+        let script = dir.join("fake-cryo.sh");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho $$ > '{}'\nexec sleep 30\n",
+                pid_file.display()
+            ),
+        )?;
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700))?;
+
+        let script = script.to_string_lossy();
+        let out_dir = dir.join("out");
+        let run = run_cryo_cli(&script, "blocks", "http://127.0.0.1:1", 0, 10, &out_dir);
+        let res = tokio::time::timeout(Duration::from_millis(500), run).await;
+        assert!(res.is_err(), "fake cryo should still be running");
+
+        let pid = std::fs::read_to_string(&pid_file)?.trim().to_string();
+        let proc_stat = PathBuf::from(format!("/proc/{pid}/stat"));
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            // Killed children may linger briefly as zombies until reaped.
+            let alive = std::fs::read_to_string(&proc_stat)
+                .map(|stat| !stat.contains(") Z "))
+                .unwrap_or(false);
+            if !alive {
+                break;
+            }
+            if tokio::time::Instant::now() > deadline {
+                anyhow::bail!("cryo child {pid} still running after cancellation");
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        Ok(())
+    }
 }
@@ -1,13 +1,11 @@
 use crate::config::HarnessConfig;
-use crate::jwt::{Hs256TaskCapabilityConfig, TaskCapability};
 use anyhow::Context;
 use sqlx::PgPool;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
-use trace_core::fixtures::{
-    ALERTS_FIXTURE_DATASET_ID, ALERTS_FIXTURE_DATASET_STORAGE_PREFIX,
-    ALERTS_FIXTURE_DATASET_VERSION,
-};
-use trace_core::{DatasetGrant, DatasetStorageRef, S3Grants, Signer as SignerTrait};
+use trace_core::Signer as SignerTrait;
+use trace_dispatcher::retry::{parse_operator_max_attempts, RetryPolicy, TaskRetryConfig};
+use trace_dispatcher::worker_auth::WorkerAuth;
+use trace_dispatcher::BackgroundLoops;
 
 #[derive(Debug)]
 pub struct DispatcherServer {
@@ -24,22 +22,40 @@ impl DispatcherServer {
         enable_chain_sync_planner: bool,
         enable_lease_reaper: bool,
     ) -> anyhow::Result<Self> {
-        let capability = TaskCapability::from_hs256_config(Hs256TaskCapabilityConfig {
-            issuer: cfg.task_capability_iss.clone(),
-            audience: cfg.task_capability_aud.clone(),
-            current_kid: cfg.task_capability_kid.clone(),
-            current_secret: cfg.task_capability_secret.clone(),
-            next_kid: cfg.task_capability_next_kid.clone(),
-            next_secret: cfg.task_capability_next_secret.clone(),
-            ttl: Duration::from_secs(cfg.task_capability_ttl_secs),
-        })
-        .context("init task capability")?;
+        let capability = cfg.task_capability().context("init task capability")?;
 
         let queue = Arc::new(crate::pgqueue::PgQueue::new(pool.clone()));
 
-        let (bucket, prefix) =
-            trace_core::lite::s3::parse_s3_uri(ALERTS_FIXTURE_DATASET_STORAGE_PREFIX)
-                .context("parse fixture dataset storage prefix")?;
+        let artifact_root = cfg
+            .object_store()
+            .context("init object store")?
+            .object_uri(&cfg.s3_bucket, "");
+
+        let default_retry = RetryPolicy {
+            max_attempts: cfg.task_max_attempts,
+            base_delay: Duration::from_millis(cfg.task_retry_base_delay_ms),
+            max_delay: Duration::from_millis(cfg.task_retry_max_delay_ms),
+        };
+        let per_operator = parse_operator_max_attempts(&cfg.task_max_attempts_by_operator)
+            .context("parse TASK_MAX_ATTEMPTS_BY_OPERATOR")?
+            .into_iter()
+            .map(|(op, max_attempts)| {
+                (
+                    op,
+                    RetryPolicy {
+                        max_attempts,
+                        ..default_retry.clone()
+                    },
+                )
+            })
+            .collect();
+
+        let worker_auth = WorkerAuth::parse(&cfg.dispatcher_worker_tokens)
+            .context("parse DISPATCHER_WORKER_TOKENS")?;
+        anyhow::ensure!(
+            !worker_auth.is_empty(),
+            "DISPATCHER_WORKER_TOKENS is empty; no worker could claim tasks"
+        );
 
         let dispatcher_cfg = trace_dispatcher::DispatcherConfig {
             org_id: cfg.org_id,
@@ -49,19 +65,18 @@ impl DispatcherServer {
             outbox_batch_size: cfg.outbox_batch_size,
             task_wakeup_queue: cfg.task_wakeup_queue.clone(),
             buffer_queue: cfg.buffer_queue.clone(),
-            default_datasets: vec![DatasetGrant {
-                dataset_uuid: ALERTS_FIXTURE_DATASET_ID,
-                dataset_version: ALERTS_FIXTURE_DATASET_VERSION,
-                storage_ref: Some(DatasetStorageRef::S3 {
-                    bucket,
-                    prefix,
-                    glob: "*.parquet".to_string(),
-                }),
-            }],
-            default_s3: S3Grants {
-                read_prefixes: vec![ALERTS_FIXTURE_DATASET_STORAGE_PREFIX.to_string()],
-                write_prefixes: Vec::new(),
+            artifact_root,
+            retry: TaskRetryConfig {
+                default: default_retry,
+                per_operator,
+            },
+            outbox_retry: RetryPolicy {
+                max_attempts: cfg.outbox_max_attempts,
+                base_delay: Duration::from_millis(cfg.outbox_retry_base_delay_ms),
+                max_delay: Duration::from_millis(cfg.outbox_retry_max_delay_ms),
             },
+            outbox_sent_retention_secs: cfg.outbox_sent_retention_secs,
+            worker_auth,
         };
 
         let inner = trace_dispatcher::DispatcherServer::start(
@@ -70,9 +85,11 @@ impl DispatcherServer {
             Arc::new(capability) as Arc<dyn SignerTrait>,
             queue,
             bind,
-            enable_outbox,
-            enable_chain_sync_planner,
-            enable_lease_reaper,
+            BackgroundLoops {
+                outbox: enable_outbox,
+                chain_sync_planner: enable_chain_sync_planner,
+                lease_reaper: enable_lease_reaper,
+            },
         )
         .await?;
 
@@ -1,4 +1,4 @@
-use crate::constants::TASK_CAPABILITY_HEADER;
+use crate::constants::{TASK_CAPABILITY_HEADER, WORKER_TOKEN_HEADER};
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use reqwest::Url;
@@ -17,6 +17,13 @@ pub struct TaskClaimResponse {
     pub work_payload: Value,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatRequest {
+    pub task_id: Uuid,
+    pub attempt: i64,
+    pub lease_token: Uuid,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct BufferPublishRequest {
     pub task_id: Uuid,
@@ -37,6 +44,10 @@ pub struct CompleteRequest {
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub datasets_published: Vec<DatasetPublication>,
+
+    /// Error reason for non-success outcomes (recorded as the task's `last_error`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,25 +56,46 @@ pub enum WriteDisposition {
     Conflict,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct DispatcherClient {
     base_url: String,
+    worker_token: Option<String>,
     http: reqwest::Client,
 }
 
+impl std::fmt::Debug for DispatcherClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let worker_token = self.worker_token.as_deref().map(|_| "<redacted>");
+        f.debug_struct("DispatcherClient")
+            .field("base_url", &self.base_url)
+            .field("worker_token", &worker_token)
+            .finish()
+    }
+}
+
 impl DispatcherClient {
     pub fn new(base_url: String) -> Self {
         Self {
             base_url,
+            worker_token: None,
             http: reqwest::Client::new(),
         }
     }
 
+    /// Worker token presented to `/internal/task-claim`; clients without one can only use the
+    /// capability-authenticated task endpoints.
+    pub fn with_worker_token(mut self, worker_token: String) -> Self {
+        self.worker_token = Some(worker_token);
+        self
+    }
+
     pub async fn task_claim(&self, task_id: Uuid) -> anyhow::Result<Option<TaskClaimResponse>> {
         let url = self.url("/internal/task-claim")?;
-        let resp = self
-            .http
-            .post(url)
+        let mut req = self.http.post(url);
+        if let Some(token) = &self.worker_token {
+            req = req.header(WORKER_TOKEN_HEADER, token);
+        }
+        let resp = req
             .json(&serde_json::json!({ "task_id": task_id }))
             .send()
             .await
@@ -81,6 +113,30 @@ impl DispatcherClient {
         ))
     }
 
+    /// Extend the task lease. `Conflict` means the fence is stale (lease lost or superseded).
+    pub async fn heartbeat(
+        &self,
+        capability_token: &str,
+        req: &HeartbeatRequest,
+    ) -> anyhow::Result<WriteDisposition> {
+        let url = self.url("/v1/task/heartbeat")?;
+        let resp = self
+            .http
+            .post(url)
+            .header(TASK_CAPABILITY_HEADER, capability_token)
+            .json(req)
+            .send()
+            .await
+            .context("POST /v1/task/heartbeat")?;
+
+        if resp.status() == reqwest::StatusCode::CONFLICT {
+            return Ok(WriteDisposition::Conflict);
+        }
+
+        resp.error_for_status().context("heartbeat status")?;
+        Ok(WriteDisposition::Ok)
+    }
+
     pub async fn buffer_publish(
         &self,
         capability_token: &str,
@@ -3,7 +3,6 @@ use crate::{
     dispatcher_client::{DispatcherClient, TaskClaimResponse},
     pgqueue::PgQueue,
     runner::FakeRunner,
-    s3::ObjectStore,
 };
 use anyhow::Context;
 use serde::Deserialize;
@@ -29,8 +28,7 @@ pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
         .await
         .context("connect state db")?;
     let queue: Arc<dyn QueueTrait> = Arc::new(PgQueue::new(pool));
-    let object_store: Arc<dyn ObjectStoreTrait> =
-        Arc::new(ObjectStore::new(&cfg.s3_endpoint).context("init object store")?);
+    let object_store = cfg.object_store().context("init object store")?;
 
     let runner = FakeRunner::new(
         cfg.dispatcher_url.clone(),
@@ -39,7 +37,8 @@ pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
         object_store.clone(),
     );
 
-    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone());
+    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone())
+        .with_worker_token(cfg.worker_token.clone());
 
     let poll_interval = Duration::from_millis(cfg.worker_poll_ms);
     let visibility_timeout = Duration::from_secs(cfg.worker_visibility_timeout_secs);
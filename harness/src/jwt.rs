@@ -1,4 +1,7 @@
 #[doc(inline)]
-pub use trace_core::lite::jwt::{Hs256TaskCapabilityConfig, TaskCapability};
+pub use trace_core::lite::jwt::{
+    AsymmetricTaskCapabilityConfig, Hs256TaskCapabilityConfig, TaskCapability,
+    TaskCapabilityVerifierConfig,
+};
 #[doc(inline)]
 pub use trace_core::{DatasetGrant, S3Grants, TaskCapabilityClaims};
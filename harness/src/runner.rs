@@ -1,4 +1,3 @@
-use crate::s3::parse_s3_uri;
 use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -13,6 +12,7 @@ use crate::dispatcher_client::{
 
 use async_trait::async_trait;
 use trace_core::runtime::RuntimeInvoker;
+use trace_dispatcher::grants::attempt_output_prefix;
 
 fn default_payload() -> Value {
     Value::Object(Map::new())
@@ -147,10 +147,10 @@ impl FakeRunner {
         };
 
         let key = format!(
-            "batches/{}/{}/udf.jsonl",
-            invocation.task_id, invocation.attempt
+            "{}udf.jsonl",
+            attempt_output_prefix(invocation.task_id, invocation.attempt)
         );
-        let batch_uri = format!("s3://{}/{}", self.bucket, key);
+        let batch_uri = self.object_store.object_uri(&self.bucket, &key);
         let mut bytes = serde_json::to_vec(&row).context("encode alert event row")?;
         bytes.push(b'\n');
 
@@ -186,10 +186,10 @@ impl FakeRunner {
         };
 
         let key = format!(
-            "batches/{}/{}/alert_evaluate.jsonl",
-            invocation.task_id, invocation.attempt
+            "{}alert_evaluate.jsonl",
+            attempt_output_prefix(invocation.task_id, invocation.attempt)
         );
-        let batch_uri = format!("s3://{}/{}", self.bucket, key);
+        let batch_uri = self.object_store.object_uri(&self.bucket, &key);
 
         let bytes = if spec.emit_malformed_output {
             let bad_line = serde_json::json!({
@@ -272,7 +272,7 @@ impl FakeRunner {
         let mut rows = Vec::new();
         for (idx, row) in body.rows.into_iter().enumerate() {
             let chain_id = row
-                .get(0)
+                .first()
                 .and_then(|v| v.as_i64())
                 .with_context(|| format!("row {} chain_id", idx + 1))?;
             let block_number = row
@@ -292,8 +292,8 @@ impl FakeRunner {
     }
 
     async fn fetch_bundle_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
-        if url.starts_with("s3://") {
-            let (bucket, key) = parse_s3_uri(url)?;
+        if url.starts_with("s3://") || url.starts_with("file://") {
+            let (bucket, key) = self.object_store.locate_uri(url)?;
             return Ok(self.object_store.get_bytes(&bucket, &key).await?);
         }
 
@@ -346,6 +346,7 @@ impl FakeRunner {
             lease_token: invocation.lease_token,
             outcome,
             datasets_published: Vec::new(),
+            error: None,
         };
 
         if self
@@ -1,2 +1,2 @@
 #[doc(inline)]
-pub use trace_core::lite::s3::{parse_s3_uri, ObjectStore};
+pub use trace_core::lite::s3::{parse_s3_uri, ObjectStore, S3Config, S3Credentials, UrlStyle};
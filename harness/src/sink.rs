@@ -1,8 +1,8 @@
-use crate::{config::HarnessConfig, pgqueue::PgQueue, s3::ObjectStore};
+use crate::{config::HarnessConfig, pgqueue::PgQueue};
 use anyhow::Context;
 use sqlx::postgres::PgPoolOptions;
 use std::{sync::Arc, time::Duration};
-use trace_core::{ObjectStore as ObjectStoreTrait, Queue as QueueTrait};
+use trace_core::Queue as QueueTrait;
 use trace_sink::{Sink, SinkConfig};
 
 pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
@@ -18,8 +18,7 @@ pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
         .context("connect data db")?;
 
     let queue: Arc<dyn QueueTrait> = Arc::new(PgQueue::new(state_pool));
-    let object_store: Arc<dyn ObjectStoreTrait> =
-        Arc::new(ObjectStore::new(&cfg.s3_endpoint).context("init object store")?);
+    let object_store = cfg.object_store().context("init object store")?;
 
     let sink_cfg = SinkConfig {
         buffer_queue: cfg.buffer_queue.clone(),
@@ -5,7 +5,6 @@ use crate::{
         WriteDisposition,
     },
     pgqueue::PgQueue,
-    s3::ObjectStore,
 };
 use anyhow::Context;
 use chrono::{DateTime, Utc};
@@ -14,6 +13,7 @@ use serde_json::Value;
 use sqlx::postgres::PgPoolOptions;
 use std::{sync::Arc, time::Duration};
 use trace_core::{ObjectStore as ObjectStoreTrait, Queue as QueueTrait};
+use trace_dispatcher::grants::attempt_output_prefix;
 use uuid::Uuid;
 
 use crate::constants::{CONTENT_TYPE_JSONL, DEFAULT_ALERT_DEFINITION_ID};
@@ -42,9 +42,9 @@ pub async fn run(cfg: &HarnessConfig) -> anyhow::Result<()> {
         .await
         .context("connect state db")?;
     let queue: Arc<dyn QueueTrait> = Arc::new(PgQueue::new(pool));
-    let object_store: Arc<dyn ObjectStoreTrait> =
-        Arc::new(ObjectStore::new(&cfg.s3_endpoint).context("init object store")?);
-    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone());
+    let object_store = cfg.object_store().context("init object store")?;
+    let dispatcher = DispatcherClient::new(cfg.dispatcher_url.clone())
+        .with_worker_token(cfg.worker_token.clone());
 
     let poll_interval = Duration::from_millis(cfg.worker_poll_ms);
     let visibility_timeout = Duration::from_secs(cfg.worker_visibility_timeout_secs);
@@ -114,10 +114,15 @@ async fn handle_message(
             return Ok(());
         };
 
-        let (batch_uri, batch_bytes) = build_batch(cfg, &claim)?;
-        let (bucket, key) = crate::s3::parse_s3_uri(&batch_uri).context("parse batch uri")?;
+        let (key, batch_bytes) = build_batch(cfg, &claim)?;
+        let batch_uri = object_store.object_uri(&cfg.s3_bucket, &key);
         object_store
-            .put_bytes(&bucket, &key, batch_bytes.clone(), CONTENT_TYPE_JSONL)
+            .put_bytes(
+                &cfg.s3_bucket,
+                &key,
+                batch_bytes.clone(),
+                CONTENT_TYPE_JSONL,
+            )
             .await
             .context("upload batch")?;
 
@@ -146,6 +151,7 @@ async fn handle_message(
             lease_token: claim.lease_token,
             outcome: "success",
             datasets_published: Vec::new(),
+            error: None,
         };
         if dispatcher
             .complete(&claim.capability_token, &complete_req)
@@ -190,10 +196,12 @@ fn build_batch(
         }),
     };
 
-    let key = format!("batches/{}/{}.jsonl", claim.task_id, claim.attempt);
-    let batch_uri = format!("s3://{}/{}", cfg.s3_bucket, key);
+    let key = format!(
+        "{}harness.jsonl",
+        attempt_output_prefix(claim.task_id, claim.attempt)
+    );
 
     let mut bytes = serde_json::to_vec(&row).context("encode alert event row")?;
     bytes.push(b'\n');
-    Ok((batch_uri, bytes))
+    Ok((key, bytes))
 }
@@ -20,8 +20,10 @@ use trace_core::{
     TaskCapabilityIssueRequest,
 };
 use trace_dispatcher::chain_sync::{apply_chain_sync_yaml, derive_dataset_uuid};
+use trace_dispatcher::worker_auth::WorkerAuth;
 use trace_harness::constants::{
     CONTENT_TYPE_JSON, CONTENT_TYPE_JSONL, DEFAULT_ALERT_DEFINITION_ID, TASK_CAPABILITY_HEADER,
+    WORKER_TOKEN_HEADER,
 };
 use trace_harness::jwt::{Hs256TaskCapabilityConfig, TaskCapability};
 use trace_harness::{
@@ -166,8 +168,37 @@ async fn build_alerts_fixture_parquet_bytes() -> anyhow::Result<Vec<u8>> {
     .context("join parquet builder")?
 }
 
+/// A `cryo_ingest` task payload as the chain sync planner writes it.
+fn cryo_task_payload(payload: &CryoIngestPayload) -> anyhow::Result<serde_json::Value> {
+    let mut value = serde_json::to_value(payload).context("encode cryo payload")?;
+    value["operator"] = serde_json::json!("cryo_ingest");
+    Ok(value)
+}
+
+/// Record `attempt` of `task_id` as running, so Query Service accepts capabilities minted for it
+/// outside a dispatcher claim.
+async fn insert_running_attempt(
+    state_pool: &sqlx::PgPool,
+    task_id: Uuid,
+    attempt: i64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO state.tasks (task_id, status, attempt, lease_token, lease_expires_at)
+        VALUES ($1, 'running', $2, $3, now() + interval '5 minutes')
+        "#,
+    )
+    .bind(task_id)
+    .bind(attempt)
+    .bind(Uuid::new_v4())
+    .execute(state_pool)
+    .await
+    .context("insert running task attempt")?;
+    Ok(())
+}
+
 async fn seed_alerts_fixture_dataset(cfg: &HarnessConfig) -> anyhow::Result<()> {
-    let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
+    let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
     let (bucket, prefix_key) =
         parse_s3_uri(ALERTS_FIXTURE_DATASET_STORAGE_PREFIX).context("parse storage prefix")?;
 
@@ -192,6 +223,28 @@ async fn seed_alerts_fixture_dataset(cfg: &HarnessConfig) -> anyhow::Result<()>
     Ok(())
 }
 
+#[tokio::test]
+async fn lite_object_store_conforms() -> anyhow::Result<()> {
+    let cfg = HarnessConfig::from_env().context("load harness config")?;
+    let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
+    trace_core::testing::conformance::object_store(&object_store, &cfg.s3_bucket).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn pg_queue_conforms() -> anyhow::Result<()> {
+    let cfg = HarnessConfig::from_env().context("load harness config")?;
+    migrate::run(&cfg).await.context("run migrations")?;
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&cfg.state_database_url)
+        .await
+        .context("connect state db")?;
+    trace_core::testing::conformance::queue(&PgQueue::new(pool), &trace_core::testing::SystemClock)
+        .await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn duplicate_claims_do_not_double_run() -> anyhow::Result<()> {
     let cfg = migrated_config().await?;
@@ -218,10 +271,12 @@ async fn duplicate_claims_do_not_double_run() -> anyhow::Result<()> {
     let (r1, r2) = tokio::join!(
         client
             .post(format!("{base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": task_id }))
             .send(),
         client
             .post(format!("{base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": task_id }))
             .send()
     );
@@ -268,6 +323,7 @@ async fn capability_token_required() -> anyhow::Result<()> {
 
     let claim = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -325,6 +381,7 @@ async fn wrong_capability_token_rejected() -> anyhow::Result<()> {
 
     let claim_a = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_a }))
         .send()
         .await?
@@ -334,6 +391,7 @@ async fn wrong_capability_token_rejected() -> anyhow::Result<()> {
 
     let claim_b = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_b }))
         .send()
         .await?
@@ -391,6 +449,7 @@ async fn wrong_lease_token_rejected() -> anyhow::Result<()> {
 
     let claim = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -454,6 +513,7 @@ async fn next_key_token_accepted_during_overlap() -> anyhow::Result<()> {
 
     let claim = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -544,6 +604,7 @@ async fn stale_attempt_fencing_rejects_old_complete() -> anyhow::Result<()> {
 
     let claim1 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -558,6 +619,7 @@ async fn stale_attempt_fencing_rejects_old_complete() -> anyhow::Result<()> {
 
     let claim2 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -616,6 +678,7 @@ async fn stale_attempt_fencing_rejects_old_buffer_publish() -> anyhow::Result<()
 
     let claim1 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -630,6 +693,7 @@ async fn stale_attempt_fencing_rejects_old_buffer_publish() -> anyhow::Result<()
 
     let claim2 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -646,7 +710,7 @@ async fn stale_attempt_fencing_rejects_old_buffer_publish() -> anyhow::Result<()
             "task_id": task_id,
             "attempt": attempt1,
             "lease_token": lease1,
-            "batch_uri": format!("s3://{}/batches/{task_id}/{attempt1}.jsonl", cfg.s3_bucket),
+            "batch_uri": format!("s3://{}/batches/{task_id}/{attempt1}/test.jsonl", cfg.s3_bucket),
             "content_type": CONTENT_TYPE_JSONL,
             "batch_size_bytes": 1,
             "dedupe_scope": "test",
@@ -689,6 +753,7 @@ async fn buffer_publish_is_idempotent_for_same_attempt_and_uri() -> anyhow::Resu
 
     let claim = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -701,7 +766,10 @@ async fn buffer_publish_is_idempotent_for_same_attempt_and_uri() -> anyhow::Resu
         .as_str()
         .context("capability_token")?;
 
-    let batch_uri = format!("s3://{}/batches/{task_id}/{attempt}.jsonl", cfg.s3_bucket);
+    let batch_uri = format!(
+        "s3://{}/batches/{task_id}/{attempt}/test.jsonl",
+        cfg.s3_bucket
+    );
     for _ in 0..2 {
         client
             .post(format!("{base}/v1/task/buffer-publish"))
@@ -764,6 +832,7 @@ async fn dispatcher_restart_recovers_outbox() -> anyhow::Result<()> {
 
     let claim = client
         .post(format!("{base1}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -784,7 +853,7 @@ async fn dispatcher_restart_recovers_outbox() -> anyhow::Result<()> {
             "task_id": task_id,
             "attempt": attempt,
             "lease_token": lease_token,
-            "batch_uri": format!("s3://{}/batches/{task_id}/{attempt}.jsonl", cfg.s3_bucket),
+            "batch_uri": format!("s3://{}/batches/{task_id}/{attempt}/test.jsonl", cfg.s3_bucket),
             "content_type": CONTENT_TYPE_JSONL,
             "batch_size_bytes": 1,
             "dedupe_scope": "test",
@@ -893,6 +962,7 @@ async fn worker_crash_triggers_retry() -> anyhow::Result<()> {
 
     let claim1 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -931,6 +1001,7 @@ async fn worker_crash_triggers_retry() -> anyhow::Result<()> {
 
     let claim2 = client
         .post(format!("{base}/internal/task-claim"))
+        .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
         .json(&serde_json::json!({ "task_id": task_id }))
         .send()
         .await?
@@ -961,7 +1032,7 @@ async fn poison_batch_goes_to_dlq_without_partial_writes() -> anyhow::Result<()>
         .await
         .context("connect data db")?;
     let pgq = PgQueue::new(state_pool);
-    let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
+    let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
 
     let dedupe_key = format!("poison_test:{}", Uuid::new_v4());
     let valid = serde_json::json!({
@@ -1071,7 +1142,7 @@ async fn runner_claim_invoke_sink_inserts_once() -> anyhow::Result<()> {
     let sink_task = tokio::spawn(async move { trace_harness::sink::run(&sink_cfg).await });
 
     let result: anyhow::Result<()> = async {
-        let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
+        let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
         let object_store_arc: Arc<dyn ObjectStoreTrait> = Arc::new(object_store.clone());
         let runner = FakeRunner::new(
             base.clone(),
@@ -1131,6 +1202,7 @@ async fn runner_claim_invoke_sink_inserts_once() -> anyhow::Result<()> {
 
         let claim: ClaimResponse = client
             .post(format!("{base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": task_id }))
             .send()
             .await?
@@ -1243,7 +1315,7 @@ async fn dispatcher_dataset_grant_allows_task_query_and_emits_audit() -> anyhow:
         .context("connect data db")?;
 
     let server = DispatcherServer::start(
-        state_pool,
+        state_pool.clone(),
         cfg.clone(),
         "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
         false,
@@ -1254,6 +1326,7 @@ async fn dispatcher_dataset_grant_allows_task_query_and_emits_audit() -> anyhow:
 
     let mut qs_cfg = QueryServiceConfig::from_env().context("load query service config")?;
     qs_cfg.data_database_url = cfg.data_database_url.clone();
+    qs_cfg.state_database_url = Some(cfg.state_database_url.clone());
     qs_cfg.task_capability_iss = cfg.task_capability_iss.clone();
     qs_cfg.task_capability_aud = cfg.task_capability_aud.clone();
     qs_cfg.task_capability_kid = cfg.task_capability_kid.clone();
@@ -1291,8 +1364,41 @@ async fn dispatcher_dataset_grant_allows_task_query_and_emits_audit() -> anyhow:
         let client = reqwest::Client::new();
         let task_id = Uuid::new_v4();
 
+        // The capability only grants the datasets a task declares as inputs.
+        sqlx::query(
+            r#"
+            INSERT INTO state.dataset_versions (
+              dataset_version, dataset_uuid, storage_prefix, config_hash, range_start, range_end
+            ) VALUES ($1, $2, $3, 'alerts_fixture', 0, 0)
+            ON CONFLICT (dataset_version) DO NOTHING
+            "#,
+        )
+        .bind(ALERTS_FIXTURE_DATASET_VERSION)
+        .bind(ALERTS_FIXTURE_DATASET_ID)
+        .bind(ALERTS_FIXTURE_DATASET_STORAGE_PREFIX)
+        .execute(&state_pool)
+        .await
+        .context("register fixture dataset version")?;
+        sqlx::query(
+            r#"
+            INSERT INTO state.tasks (task_id, status, payload)
+            VALUES ($1, 'queued', $2)
+            "#,
+        )
+        .bind(task_id)
+        .bind(serde_json::json!({
+            "inputs": [{
+                "dataset_uuid": ALERTS_FIXTURE_DATASET_ID,
+                "dataset_version": ALERTS_FIXTURE_DATASET_VERSION,
+            }],
+        }))
+        .execute(&state_pool)
+        .await
+        .context("insert task")?;
+
         let claim = client
             .post(format!("{dispatcher_base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": task_id }))
             .send()
             .await?
@@ -1303,7 +1409,8 @@ async fn dispatcher_dataset_grant_allows_task_query_and_emits_audit() -> anyhow:
         let req = TaskQueryRequest {
             task_id,
             attempt: claim.attempt,
-            dataset_id: ALERTS_FIXTURE_DATASET_ID,
+            dataset_id: Some(ALERTS_FIXTURE_DATASET_ID),
+            datasets: Default::default(),
             sql: "SELECT dedupe_key FROM dataset ORDER BY dedupe_key".to_string(),
             limit: None,
         };
@@ -1342,7 +1449,8 @@ async fn dispatcher_dataset_grant_allows_task_query_and_emits_audit() -> anyhow:
         let unauthorized_req = TaskQueryRequest {
             task_id,
             attempt: claim.attempt,
-            dataset_id: unauthorized_dataset_id,
+            dataset_id: Some(unauthorized_dataset_id),
+            datasets: Default::default(),
             sql: "SELECT 1".to_string(),
             limit: None,
         };
@@ -1482,7 +1590,11 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
         cryo_dataset_name: None,
         rpc_pool: None,
     };
-    let dataset_pubd = derive_dataset_publication(&cfg.s3_bucket, &dataset_payload);
+    let dataset_pubd = derive_dataset_publication(
+        &ObjectStore::from_config(cfg.s3_config()?)?,
+        &cfg.s3_bucket,
+        &dataset_payload,
+    )?;
 
     let capability: Arc<dyn SignerTrait> = Arc::new(TaskCapability::from_hs256_config(
         Hs256TaskCapabilityConfig {
@@ -1497,14 +1609,6 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
     )?);
 
     let queue: Arc<dyn trace_core::Queue> = Arc::new(PgQueue::new(state_pool.clone()));
-    let dataset_storage_prefix = match &dataset_pubd.storage_ref {
-        trace_core::DatasetStorageRef::S3 { bucket, prefix, .. } => {
-            format!("s3://{bucket}/{prefix}")
-        }
-        trace_core::DatasetStorageRef::File { .. } => {
-            anyhow::bail!("expected s3 dataset storage ref")
-        }
-    };
     let dispatcher_cfg = trace_dispatcher::DispatcherConfig {
         org_id: cfg.org_id,
         lease_duration_secs: cfg.lease_duration_secs,
@@ -1513,15 +1617,11 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
         outbox_batch_size: cfg.outbox_batch_size,
         task_wakeup_queue: cfg.task_wakeup_queue.clone(),
         buffer_queue: cfg.buffer_queue.clone(),
-        default_datasets: vec![DatasetGrant {
-            dataset_uuid: dataset_pubd.dataset_uuid,
-            dataset_version: dataset_pubd.dataset_version,
-            storage_ref: Some(dataset_pubd.storage_ref.clone()),
-        }],
-        default_s3: S3Grants {
-            read_prefixes: vec![dataset_storage_prefix],
-            write_prefixes: Vec::new(),
-        },
+        artifact_root: format!("s3://{}/", cfg.s3_bucket),
+        retry: Default::default(),
+        outbox_retry: Default::default(),
+        outbox_sent_retention_secs: 0,
+        worker_auth: WorkerAuth::parse(&cfg.dispatcher_worker_tokens)?,
     };
 
     let dispatcher = trace_dispatcher::DispatcherServer::start(
@@ -1530,15 +1630,17 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
         capability,
         queue,
         "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
-        true,
-        false,
-        false,
+        trace_dispatcher::BackgroundLoops {
+            outbox: true,
+            ..Default::default()
+        },
     )
     .await?;
     let dispatcher_base = format!("http://{}", dispatcher.addr);
 
     let mut qs_cfg = QueryServiceConfig::from_env().context("load query service config")?;
     qs_cfg.data_database_url = cfg.data_database_url.clone();
+    qs_cfg.state_database_url = Some(cfg.state_database_url.clone());
     qs_cfg.task_capability_iss = cfg.task_capability_iss.clone();
     qs_cfg.task_capability_aud = cfg.task_capability_aud.clone();
     qs_cfg.task_capability_kid = cfg.task_capability_kid.clone();
@@ -1574,7 +1676,7 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
     let sink_task = tokio::spawn(async move { trace_harness::sink::run(&sink_cfg).await });
 
     let result: anyhow::Result<()> = async {
-        let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
+        let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
         let object_store_arc: Arc<dyn ObjectStoreTrait> = Arc::new(object_store.clone());
         let runner = FakeRunner::new(
             dispatcher_base.clone(),
@@ -1582,7 +1684,8 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
             cfg.s3_bucket.clone(),
             object_store_arc,
         );
-        let dispatcher_client = DispatcherClient::new(dispatcher_base.clone());
+        let dispatcher_client = DispatcherClient::new(dispatcher_base.clone())
+            .with_worker_token(cfg.worker_token.clone());
 
         // Produce a real Parquet dataset version via the trusted Cryo worker path.
         let cryo_task_id = Uuid::new_v4();
@@ -1599,7 +1702,7 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
             "#,
         )
         .bind(cryo_task_id)
-        .bind(serde_json::to_value(&dataset_payload).context("encode cryo payload")?)
+        .bind(cryo_task_payload(&dataset_payload)?)
         .execute(&state_pool)
         .await
         .context("insert cryo task")?;
@@ -1630,6 +1733,10 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
         .bind(eval_task_id)
         .bind(serde_json::json!({
             "bundle_url": format!("s3://{}/bundles/{}.json", cfg.s3_bucket, eval_task_id),
+            "inputs": [{
+                "dataset_uuid": dataset_pubd.dataset_uuid,
+                "dataset_version": dataset_pubd.dataset_version,
+            }],
             "alert_evaluate": {
                 "alert_definition_id": DEFAULT_ALERT_DEFINITION_ID,
                 "dataset_id": dataset_pubd.dataset_uuid,
@@ -1644,6 +1751,7 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
 
         let claim1: ClaimResponse = reqwest::Client::new()
             .post(format!("{dispatcher_base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": eval_task_id }))
             .send()
             .await?
@@ -1670,6 +1778,7 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
 
         let claim2: ClaimResponse = reqwest::Client::new()
             .post(format!("{dispatcher_base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": eval_task_id }))
             .send()
             .await?
@@ -1774,6 +1883,7 @@ async fn alert_evaluate_over_parquet_dataset_emits_idempotent_events_and_rejects
 
         let bad_claim: ClaimResponse = reqwest::Client::new()
             .post(format!("{dispatcher_base}/internal/task-claim"))
+            .header(WORKER_TOKEN_HEADER, &cfg.worker_token)
             .json(&serde_json::json!({ "task_id": bad_task_id }))
             .send()
             .await?
@@ -1866,8 +1976,8 @@ async fn cryo_worker_registers_dataset_version_idempotently() -> anyhow::Result<
     let base = format!("http://{}", server.addr);
 
     let result: anyhow::Result<()> = async {
-        let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
-        let dispatcher = DispatcherClient::new(base);
+        let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
+        let dispatcher = DispatcherClient::new(base).with_worker_token(cfg.worker_token.clone());
 
         let payload = CryoIngestPayload {
             dataset_uuid: Uuid::new_v4(),
@@ -1879,7 +1989,7 @@ async fn cryo_worker_registers_dataset_version_idempotently() -> anyhow::Result<
             cryo_dataset_name: None,
             rpc_pool: None,
         };
-        let expected = derive_dataset_publication(&cfg.s3_bucket, &payload);
+        let expected = derive_dataset_publication(&object_store, &cfg.s3_bucket, &payload)?;
 
         for task_id in [Uuid::new_v4(), Uuid::new_v4()] {
             sqlx::query(
@@ -1895,7 +2005,7 @@ async fn cryo_worker_registers_dataset_version_idempotently() -> anyhow::Result<
                 "#,
             )
             .bind(task_id)
-            .bind(serde_json::to_value(&payload).context("encode payload")?)
+            .bind(cryo_task_payload(&payload)?)
             .execute(&state_pool)
             .await
             .context("insert cryo task")?;
@@ -2051,6 +2161,12 @@ async fn query_service_attaches_local_parquet_via_file_storage_ref() -> anyhow::
     let task_id = Uuid::new_v4();
     let dataset_id = Uuid::new_v4();
     let dataset_version = Uuid::new_v4();
+    let state_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&cfg.state_database_url)
+        .await
+        .context("connect state db")?;
+    insert_running_attempt(&state_pool, task_id, 1).await?;
     let token = signer.issue_task_capability(&TaskCapabilityIssueRequest {
         org_id: cfg.org_id,
         task_id,
@@ -2068,6 +2184,7 @@ async fn query_service_attaches_local_parquet_via_file_storage_ref() -> anyhow::
 
     let mut qs_cfg = QueryServiceConfig::from_env().context("load query service config")?;
     qs_cfg.data_database_url = cfg.data_database_url.clone();
+    qs_cfg.state_database_url = Some(cfg.state_database_url.clone());
     qs_cfg.task_capability_iss = cfg.task_capability_iss.clone();
     qs_cfg.task_capability_aud = cfg.task_capability_aud.clone();
     qs_cfg.task_capability_kid = cfg.task_capability_kid.clone();
@@ -2107,7 +2224,8 @@ async fn query_service_attaches_local_parquet_via_file_storage_ref() -> anyhow::
         .json(&TaskQueryRequest {
             task_id,
             attempt: 1,
-            dataset_id,
+            dataset_id: Some(dataset_id),
+            datasets: Default::default(),
             sql: "SELECT dedupe_key FROM dataset ORDER BY dedupe_key".to_string(),
             limit: None,
         })
@@ -2211,8 +2329,9 @@ streams:
             );
         }
 
-        let object_store = ObjectStore::new(&cfg.s3_endpoint)?;
-        let dispatcher = DispatcherClient::new(base.clone());
+        let object_store = ObjectStore::from_config(cfg.s3_config()?)?;
+        let dispatcher =
+            DispatcherClient::new(base.clone()).with_worker_token(cfg.worker_token.clone());
         let queue = PgQueue::new(state_pool.clone());
 
         let visibility_timeout = Duration::from_secs(cfg.worker_visibility_timeout_secs);
@@ -2375,9 +2494,11 @@ streams:
             },
         };
         let token = signer.issue_task_capability(&issue_req)?;
+        insert_running_attempt(&state_pool, query_task_id, 1).await?;
 
         let mut qs_cfg = QueryServiceConfig::from_env().context("load query service config")?;
         qs_cfg.data_database_url = cfg.data_database_url.clone();
+        qs_cfg.state_database_url = Some(cfg.state_database_url.clone());
         qs_cfg.task_capability_iss = cfg.task_capability_iss.clone();
         qs_cfg.task_capability_aud = cfg.task_capability_aud.clone();
         qs_cfg.task_capability_kid = cfg.task_capability_kid.clone();
@@ -2410,7 +2531,8 @@ streams:
             let req = TaskQueryRequest {
                 task_id: query_task_id,
                 attempt: 1,
-                dataset_id: blocks_uuid,
+                dataset_id: Some(blocks_uuid),
+                datasets: Default::default(),
                 sql: "SELECT count(*) FROM dataset".to_string(),
                 limit: None,
             };